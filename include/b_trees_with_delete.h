@@ -0,0 +1,53 @@
+/* C interface to b_trees_with_delete, built with the `ffi` feature.
+ * Regenerate with `cbindgen --lang c --crate b_trees_with_delete`; see
+ * src/ffi.rs for what each function does. */
+
+#ifndef B_TREES_WITH_DELETE_H
+#define B_TREES_WITH_DELETE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A tree of uint64_t keys. */
+typedef struct BTreeHandle BTreeHandle;
+
+/* The keys a tree held when the iterator was made, in ascending order. */
+typedef struct BTreeIter BTreeIter;
+
+/* A new empty tree, or NULL if branch_factor is below 2. */
+BTreeHandle *btree_new(size_t branch_factor);
+
+/* Free a tree. Iterators made from it stay valid. */
+void btree_free(BTreeHandle *tree);
+
+/* Insert key, keeping duplicates. 0 on success, -1 on failure. */
+int btree_insert_u64(BTreeHandle *tree, uint64_t key);
+
+/* 1 if key is in the tree, 0 if not, -1 on failure. */
+int btree_search_u64(const BTreeHandle *tree, uint64_t key);
+
+/* Remove one copy of key: 1 if there was one, 0 if not, -1 on failure. */
+int btree_delete_u64(BTreeHandle *tree, uint64_t key);
+
+/* The number of keys in the tree, 0 for NULL. */
+size_t btree_len(const BTreeHandle *tree);
+
+/* An iterator over the tree's keys as they are now, or NULL on failure. */
+BTreeIter *btree_iter_new(const BTreeHandle *tree);
+
+/* Store the next key in *key and return 1, or return 0 at the end and -1
+ * on failure. */
+int btree_iter_next(BTreeIter *iter, uint64_t *key);
+
+/* Free an iterator. */
+void btree_iter_free(BTreeIter *iter);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
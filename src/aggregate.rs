@@ -0,0 +1,291 @@
+//! Trees that keep a summary of every subtree (a sum, a count, a maximum)
+//! in its root node, so that the summary of any range of keys takes
+//! O(log n) nodes to work out rather than a scan.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::ops::{Add, Bound, RangeBounds};
+
+use crate::allocator::Allocator;
+use crate::{BTree, BTreeProps, Context, Node};
+
+/// How to summarize keys: a value for one key, and an associative way to
+/// combine two values, with `empty` combining as a no-op.
+pub trait Aggregate<T> {
+    type Value: Clone;
+
+    fn empty() -> Self::Value;
+    fn key(key: &T) -> Self::Value;
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// No summary, which is what a plain `BTree` keeps.
+impl<T> Aggregate<T> for () {
+    type Value = ();
+
+    fn empty() {}
+    fn key(_: &T) {}
+    fn combine(_: &(), _: &()) {}
+}
+
+/// The number of keys.
+pub struct Count;
+
+impl<T> Aggregate<T> for Count {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+    fn key(_: &T) -> usize {
+        1
+    }
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// The sum of the keys, starting from `T::default()`.
+pub struct Sum;
+
+impl<T: Copy + Default + Add<Output = T>> Aggregate<T> for Sum {
+    type Value = T;
+
+    fn empty() -> T {
+        T::default()
+    }
+    fn key(key: &T) -> T {
+        *key
+    }
+    fn combine(left: &T, right: &T) -> T {
+        *left + *right
+    }
+}
+
+/// The smallest key, `None` for no keys.
+pub struct Min;
+
+impl<T: Ord + Copy> Aggregate<T> for Min {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+    fn key(key: &T) -> Option<T> {
+        Some(*key)
+    }
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(*left.min(right)),
+            _ => left.or(*right),
+        }
+    }
+}
+
+/// The largest key, `None` for no keys.
+pub struct Max;
+
+impl<T: Ord + Copy> Aggregate<T> for Max {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+    fn key(key: &T) -> Option<T> {
+        Some(*key)
+    }
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(*left.max(right)),
+            _ => left.or(*right),
+        }
+    }
+}
+
+impl<T, A> BTree<T, A>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+{
+    /// `BTree::new` for a tree whose nodes each hold `A`'s summary of their
+    /// subtree, kept up to date through splits, merges and borrows.
+    pub fn with_aggregate(branch_factor: usize) -> Self {
+        let degree = 2 * branch_factor;
+        BTree {
+            root: Arc::new(Node::new(degree, None, None)),
+            props: BTreeProps::new(degree),
+            ctx: Context::new(),
+        }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+
+    /// The summary of every key.
+    pub fn aggregate(&self) -> A::Value {
+        self.root.summary.clone()
+    }
+
+    // Keys in order, leaving out every subtree whose summary `visit`
+    // rejects.
+    pub(crate) fn pruned<V: Fn(&A::Value) -> bool>(&self, visit: V) -> Pruned<'_, T, A, M, V> {
+        let mut pruned = Pruned { stack: Vec::new(), visit };
+        pruned.descend(&self.root);
+        pruned
+    }
+
+    /// The summary of the keys in `range`. Subtrees entirely inside it
+    /// contribute their stored summary, so only the nodes along its two
+    /// ends are looked into.
+    pub fn range_aggregate<R: RangeBounds<T>>(&self, range: R) -> A::Value {
+        range_aggregate(&self.root, &range, None, None)
+    }
+}
+
+pub(crate) struct Pruned<'a, T, A: Aggregate<T>, M: Allocator + Clone, V> {
+    // Nodes on the path to the next key, with the index of that key.
+    stack: Vec<(&'a Node<T, A, M>, usize)>,
+    visit: V,
+}
+
+impl<'a, T, A: Aggregate<T>, M: Allocator + Clone, V: Fn(&A::Value) -> bool> Pruned<'a, T, A, M, V> {
+    fn descend(&mut self, mut node: &'a Node<T, A, M>) {
+        while (self.visit)(&node.summary) {
+            self.stack.push((node, 0));
+            match node.children.first() {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a, T, A: Aggregate<T>, M: Allocator + Clone, V: Fn(&A::Value) -> bool> Iterator for Pruned<'a, T, A, M, V> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some((node, index)) = self.stack.pop() {
+            if index < node.keys.len() {
+                self.stack.push((node, index + 1));
+                if let Some(child) = node.children.get(index + 1) {
+                    self.descend(child);
+                }
+                return Some(&node.keys[index]);
+            }
+        }
+        None
+    }
+}
+
+// `low` and `high` are the separators around `node` in its parent, which
+// bound every key in it; `None` past the ends of the tree.
+fn range_aggregate<T: Ord, A: Aggregate<T>, M: Allocator + Clone, R: RangeBounds<T>>(
+    node: &Node<T, A, M>,
+    range: &R,
+    low: Option<&T>,
+    high: Option<&T>,
+) -> A::Value {
+    let starts_before = match range.start_bound() {
+        Bound::Unbounded => true,
+        Bound::Included(start) => low.is_some_and(|low| start <= low),
+        Bound::Excluded(start) => low.is_some_and(|low| start < low),
+    };
+    let ends_after = match range.end_bound() {
+        Bound::Unbounded => true,
+        Bound::Included(end) => high.is_some_and(|high| high <= end),
+        Bound::Excluded(end) => high.is_some_and(|high| high < end),
+    };
+    if starts_before && ends_after {
+        return node.summary.clone();
+    }
+
+    let mut summary = A::empty();
+    for (index, key) in node.keys.iter().enumerate() {
+        let child_low = index.checked_sub(1).map(|before| &node.keys[before]).or(low);
+        if let Some(child) = node.children.get(index) {
+            if overlaps(range, child_low, Some(key)) {
+                summary = A::combine(&summary, &range_aggregate(child, range, child_low, Some(key)));
+            }
+        }
+        if range.contains(key) {
+            summary = A::combine(&summary, &A::key(key));
+        }
+    }
+    if let Some(child) = node.children.last() {
+        let child_low = node.keys.last().or(low);
+        if overlaps(range, child_low, high) {
+            summary = A::combine(&summary, &range_aggregate(child, range, child_low, high));
+        }
+    }
+    summary
+}
+
+// Whether `range` can hold any key between `low` and `high`.
+fn overlaps<T: Ord, R: RangeBounds<T>>(range: &R, low: Option<&T>, high: Option<&T>) -> bool {
+    let after_start = match (range.start_bound(), high) {
+        (Bound::Included(start), Some(high)) => start <= high,
+        (Bound::Excluded(start), Some(high)) => start < high,
+        _ => true,
+    };
+    let before_end = match (range.end_bound(), low) {
+        (Bound::Included(end), Some(low)) => low <= end,
+        (Bound::Excluded(end), Some(low)) => low < end,
+        _ => true,
+    };
+    after_start && before_end
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Count, Max, Sum};
+    use crate::{BTree, SplitPolicy};
+
+    #[test]
+    fn test_range_aggregate() {
+        let mut sums: BTree<u64, Sum> = BTree::with_aggregate(2);
+        let mut maxes: BTree<u64, Max> = BTree::with_aggregate(3);
+        maxes.set_split_policy(SplitPolicy::TowardInsert);
+        let mut keys = Vec::new();
+        for key in 0..600u64 {
+            let key = (key * 7919) % 1000;
+            sums.insert(key);
+            maxes.insert(key);
+            keys.push(key);
+        }
+        // relaxed, the deletes leave underfull nodes for `settle` to refill
+        sums.enable_history();
+        sums.checkpoint("filled");
+        sums.set_relaxed(true);
+        for &key in keys.iter().step_by(3) {
+            assert!(sums.delete(key) && maxes.delete(key));
+        }
+        sums.set_relaxed(false);
+        let filled: Vec<u64> = keys.clone();
+        keys = keys.into_iter().enumerate().filter(|(index, _)| index % 3 != 0).map(|(_, key)| key).collect();
+
+        assert_eq!(sums.aggregate(), keys.iter().sum::<u64>());
+        let ends = (0..1100).step_by(37).chain([13, 14, 999, 2000]);
+        for (start, end) in ends.clone().flat_map(|start| ends.clone().map(move |end| (start, end))) {
+            let expected = keys.iter().filter(|&&key| (start..end).contains(&key));
+            assert_eq!(sums.range_aggregate(start..end), expected.clone().sum::<u64>());
+            assert_eq!(maxes.range_aggregate(start..=end), keys.iter().filter(|&&key| (start..=end).contains(&key)).max().copied());
+        }
+        assert_eq!(sums.range_aggregate(..), sums.aggregate());
+
+        // undoing puts back the nodes, summaries and all
+        assert!(sums.undo_to("filled"));
+        assert_eq!(sums.range_aggregate(..500), filled.iter().filter(|&&key| key < 500).sum::<u64>());
+
+        let mut counts: BTree<u32, Count> = BTree::with_aggregate(2);
+        for key in [5, 5, 5, 6] {
+            counts.insert(key);
+        }
+        assert_eq!((counts.range_aggregate(5..=5), counts.range_aggregate(6..)), (3, 1));
+    }
+}
@@ -0,0 +1,119 @@
+//! Where nodes live. With the `allocator_api` feature, which needs a
+//! nightly compiler, a tree's nodes and the key and child buffers in them
+//! come from an allocator the caller picks, an arena, a bump allocator, a
+//! shared-memory segment, through `BTree::new_in`. Without it the allocator
+//! can only be `Global`.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+#[cfg(feature = "allocator_api")]
+pub use alloc::alloc::{Allocator, Global};
+
+#[cfg(not(feature = "allocator_api"))]
+pub use stable::{Allocator, Global};
+
+#[cfg(not(feature = "allocator_api"))]
+mod stable {
+    /// Stands in for `core::alloc::Allocator`, which needs nightly. Only
+    /// `Global` implements it.
+    pub trait Allocator {}
+
+    /// The global allocator.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Global;
+
+    impl Allocator for Global {}
+}
+
+// `Vec` and `Arc` with their memory in `M`, named once for both builds: on
+// stable `M` is always `Global` and they are the plain ones. The projection
+// is on `In` rather than on `M` so that `M` can still be inferred from them.
+pub(crate) struct In;
+
+pub(crate) trait Storage<M> {
+    type Vec<T>;
+    type Arc<T>;
+}
+
+#[cfg(feature = "allocator_api")]
+impl<M: Allocator> Storage<M> for In {
+    type Vec<T> = Vec<T, M>;
+    type Arc<T> = Arc<T, M>;
+}
+
+#[cfg(not(feature = "allocator_api"))]
+impl<M> Storage<M> for In {
+    type Vec<T> = Vec<T>;
+    type Arc<T> = Arc<T>;
+}
+
+pub(crate) type VecIn<T, M> = <In as Storage<M>>::Vec<T>;
+pub(crate) type ArcIn<T, M> = <In as Storage<M>>::Arc<T>;
+
+#[cfg(feature = "allocator_api")]
+pub(crate) fn vec_in<T, M: Allocator + Clone>(capacity: usize, alloc: &M) -> VecIn<T, M> {
+    Vec::with_capacity_in(capacity, alloc.clone())
+}
+
+#[cfg(not(feature = "allocator_api"))]
+pub(crate) fn vec_in<T, M: Allocator + Clone>(capacity: usize, _: &M) -> VecIn<T, M> {
+    Vec::with_capacity(capacity)
+}
+
+#[cfg(feature = "allocator_api")]
+pub(crate) fn arc_in<T, M: Allocator + Clone>(value: T, alloc: &M) -> ArcIn<T, M> {
+    Arc::new_in(value, alloc.clone())
+}
+
+#[cfg(not(feature = "allocator_api"))]
+pub(crate) fn arc_in<T, M: Allocator + Clone>(value: T, _: &M) -> ArcIn<T, M> {
+    Arc::new(value)
+}
+
+#[cfg(all(test, feature = "allocator_api"))]
+mod test {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    use crate::BTree;
+
+    // Counts live allocations so the test can see the tree uses it for
+    // everything and returns it all.
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a Cell<isize>);
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_custom_allocator() {
+        let live = Cell::new(0);
+        let mut tree = BTree::new_in(2, Counting(&live));
+        for key in 0..500u32 {
+            tree.insert((key * 7919) % 500);
+        }
+        assert!(live.get() > 100);
+        for key in (0..500u32).filter(|key| key % 2 == 0) {
+            assert!(tree.delete(key));
+        }
+        assert!(!tree.delete(0));
+        for key in 0..500u32 {
+            assert_eq!(tree.search(key), key % 2 == 1);
+        }
+        assert_eq!(tree.len(), 250);
+        assert!(std::ptr::eq(tree.allocator().0, &live));
+        drop(tree);
+        assert_eq!(live.get(), 0);
+    }
+}
@@ -0,0 +1,131 @@
+//! `DiskBTree` for async code.
+//!
+//! Page reads and writes block, on the file and on the buffer pool's lock,
+//! so each operation runs on tokio's blocking thread pool and the task
+//! awaiting it yields until it is done: a cache miss never stalls an
+//! executor thread. This is what `tokio::fs` does for file IO, with a whole
+//! operation per trip instead of a read or write.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::task::{self, JoinError};
+
+use crate::codec::{KeyCodec, Ordered};
+use crate::disk::{DiskBTree, DiskOptions};
+
+/// A handle to a `DiskBTree` whose operations are `async`. Clones share the
+/// tree, and their operations take turns with each other.
+pub struct AsyncDiskBTree<T = u64, C = Ordered> {
+    tree: Arc<Mutex<DiskBTree<T, C>>>,
+}
+
+impl<T, C> Clone for AsyncDiskBTree<T, C> {
+    fn clone(&self) -> Self {
+        AsyncDiskBTree { tree: Arc::clone(&self.tree) }
+    }
+}
+
+impl<T, C> AsyncDiskBTree<T, C>
+where
+    T: Ord + Send + 'static,
+    C: KeyCodec<T> + Send + 'static,
+{
+    /// `DiskBTree::open`, including any WAL recovery, on a blocking thread.
+    pub async fn open<P: AsRef<Path>>(path: P, options: DiskOptions) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let tree = task::spawn_blocking(move || DiskBTree::open(path, options)).await.map_err(joined)??;
+        Ok(AsyncDiskBTree { tree: Arc::new(Mutex::new(tree)) })
+    }
+
+    pub async fn len(&self) -> io::Result<u64> {
+        self.run(|tree| Ok(tree.len())).await
+    }
+
+    pub async fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    pub async fn search(&self, key: T) -> io::Result<bool> {
+        self.run(move |tree| tree.search(key)).await
+    }
+
+    pub async fn insert(&self, key: T) -> io::Result<()> {
+        self.run(move |tree| tree.insert(key)).await
+    }
+
+    pub async fn delete(&self, key: T) -> io::Result<bool> {
+        self.run(move |tree| tree.delete(key)).await
+    }
+
+    /// Write all dirty pages and the header to disk.
+    pub async fn flush(&self) -> io::Result<()> {
+        self.run(|tree| tree.flush()).await
+    }
+
+    // Run `op` on the tree on a blocking thread.
+    async fn run<R: Send + 'static>(
+        &self,
+        op: impl FnOnce(&mut DiskBTree<T, C>) -> io::Result<R> + Send + 'static,
+    ) -> io::Result<R> {
+        let tree = Arc::clone(&self.tree);
+        task::spawn_blocking(move || op(&mut tree.lock())).await.map_err(joined)?
+    }
+}
+
+// A panic in the blocking task carries on in the task that awaited it; the
+// runtime shutting down before the task ran is an error.
+fn joined(error: JoinError) -> io::Error {
+    match error.try_into_panic() {
+        Ok(panic) => std::panic::resume_unwind(panic),
+        Err(error) => io::Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncDiskBTree;
+    use crate::disk::DiskOptions;
+
+    #[test]
+    fn test_async_disk() {
+        let path = std::env::temp_dir().join(format!("disk-btree-async-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions { branch_factor: 3, cache_pages: 4, ..DiskOptions::default() };
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let tree: AsyncDiskBTree = AsyncDiskBTree::open(&path, options()).await.unwrap();
+            let tasks: Vec<_> = (0..4u64)
+                .map(|task| {
+                    let tree = tree.clone();
+                    tokio::spawn(async move {
+                        for key in (0..200).map(|key| key * 4 + task) {
+                            tree.insert(key).await.unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(tree.len().await.unwrap(), 800);
+            for key in (0..800).step_by(2) {
+                assert!(tree.delete(key).await.unwrap());
+            }
+            tree.flush().await.unwrap();
+        });
+        drop(runtime);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let tree: AsyncDiskBTree = AsyncDiskBTree::open(&path, options()).await.unwrap();
+            assert_eq!(tree.len().await.unwrap(), 400);
+            for key in 0..800 {
+                assert_eq!(tree.search(key).await.unwrap(), key % 2 == 1, "{key}");
+            }
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+//! A `DiskBTree` with a maintenance thread that writes it back to disk
+//! every so often, so a crash without a WAL loses at most the last
+//! interval's changes, and a WAL is checkpointed, emptied into the data
+//! file, without waiting for it to reach its size limit.
+//!
+//! Deletes give a disk tree's pages back to its free list as they empty, so
+//! unlike a `TombstoneBTree` there is nothing for the thread to compact.
+
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::codec::{KeyCodec, Ordered};
+use crate::disk::DiskBTree;
+
+struct State<T, C> {
+    tree: DiskBTree<T, C>,
+    // Changed since the last flush.
+    dirty: bool,
+    stopping: bool,
+    // What went wrong in the thread, for the next `flush` or `close`.
+    failed: Option<io::Error>,
+}
+
+struct Shared<T, C> {
+    state: Mutex<State<T, C>>,
+    wake: Condvar,
+}
+
+/// A `DiskBTree` shared with a thread that calls `flush` on it every
+/// interval in which it changed. Operations take turns with each other and
+/// with the thread.
+pub struct BackgroundDiskBTree<T = u64, C = Ordered> {
+    shared: Arc<Shared<T, C>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T, C> BackgroundDiskBTree<T, C>
+where
+    T: Ord + Send + 'static,
+    C: KeyCodec<T> + Send + 'static,
+{
+    /// Hand `tree` over to a maintenance thread that flushes it every
+    /// `interval`.
+    pub fn new(tree: DiskBTree<T, C>, interval: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { tree, dirty: false, stopping: false, failed: None }),
+            wake: Condvar::new(),
+        });
+        let thread = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || maintain(&shared, interval))
+        };
+        BackgroundDiskBTree { shared, thread: Some(thread) }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.shared.state.lock().tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn search(&self, key: T) -> io::Result<bool> {
+        self.shared.state.lock().tree.search(key)
+    }
+
+    pub fn insert(&self, key: T) -> io::Result<()> {
+        let mut state = self.shared.state.lock();
+        state.dirty = true;
+        state.tree.insert(key)
+    }
+
+    pub fn delete(&self, key: T) -> io::Result<bool> {
+        let mut state = self.shared.state.lock();
+        state.dirty = true;
+        state.tree.delete(key)
+    }
+
+    /// Flush now rather than at the end of the interval. Fails with the
+    /// error of a failed background flush, if there was one since the last
+    /// call, before trying again.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.shared.state.lock();
+        if let Some(error) = state.failed.take() {
+            return Err(error);
+        }
+        state.dirty = false;
+        state.tree.flush()
+    }
+
+    /// Stop the thread, flush one last time and close the file. Dropping
+    /// does the same but can't report a failure.
+    pub fn close(mut self) -> io::Result<()> {
+        self.stop();
+        let result = self.flush();
+        drop(self);
+        result
+    }
+}
+
+impl<T, C> BackgroundDiskBTree<T, C> {
+    fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.shared.state.lock().stopping = true;
+        self.shared.wake.notify_one();
+        // a panic in the thread has already been reported on stderr
+        let _ = thread.join();
+    }
+}
+
+impl<T, C> Drop for BackgroundDiskBTree<T, C> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn maintain<T: Ord, C: KeyCodec<T>>(shared: &Shared<T, C>, interval: Duration) {
+    let mut state = shared.state.lock();
+    while !state.stopping {
+        shared.wake.wait_for(&mut state, interval);
+        if state.dirty && !state.stopping {
+            state.dirty = false;
+            if let Err(error) = state.tree.flush() {
+                state.failed.get_or_insert(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::BackgroundDiskBTree;
+    use crate::disk::{DiskBTree, DiskOptions};
+
+    #[test]
+    fn test_background_flush() {
+        let path = std::env::temp_dir().join(format!("disk-btree-background-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions { branch_factor: 3, cache_pages: 64, ..DiskOptions::default() };
+        let tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+        let tree = BackgroundDiskBTree::new(tree, Duration::from_millis(5));
+        for key in 0..300 {
+            tree.insert(key).unwrap();
+        }
+        // the key count in the header on disk
+        let on_disk = || u64::from_le_bytes(std::fs::read(&path).unwrap()[20..28].try_into().unwrap());
+        let start = Instant::now();
+        while on_disk() != 300 {
+            assert!(start.elapsed() < Duration::from_secs(10), "never flushed");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(tree.delete(7).unwrap());
+        tree.close().unwrap();
+
+        let mut tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+        assert_eq!(tree.len(), 299);
+        assert!(!tree.search(7).unwrap() && tree.search(8).unwrap());
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
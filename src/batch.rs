@@ -0,0 +1,157 @@
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+
+#[cfg(feature = "tracing")]
+use crate::trace::{key_range, node_id};
+use crate::trace::event;
+use crate::{node_mut, BTree, BTreeProps, DuplicatePolicy, Node};
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Insert many keys at once.
+    ///
+    /// The batch is sorted and split by the subtree each key belongs to, so
+    /// every node on the way is visited once per batch instead of once per
+    /// key. Nodes that receive more keys than fit are split into as many
+    /// siblings as needed after the keys are in.
+    pub fn insert_batch(&mut self, mut keys: Vec<T>) {
+        let count = keys.len();
+        self.ctx.version += 1;
+        self.ctx.begin_nested(&self.root);
+        keys.sort_unstable();
+        if self.props.duplicates != DuplicatePolicy::Allow {
+            keys.dedup();
+            keys.retain(|&key| !self.take_duplicate(key));
+        }
+        insert_sorted(&self.props, node_mut(&mut self.root), &keys);
+        for key in &keys {
+            self.ctx.inserted(key);
+        }
+        self.ctx.end_nested();
+        while self.root.keys.len() > self.props.max_keys {
+            let new_root = Arc::new(Node::new(self.props.degree, None, None));
+            let old_root = mem::replace(&mut self.root, new_root);
+            let root = node_mut(&mut self.root);
+            root.children.push(old_root);
+            split_overfull(&self.props, root, 0);
+            root.recount();
+            event!("root grew", root = node_id(&self.root));
+        }
+        self.record_whole(|| format!("insert a batch of {count} keys"));
+    }
+}
+
+// Insert sorted keys below `node`, leaving `node` itself possibly overfull.
+fn insert_sorted<T: Ord + Copy + Debug>(props: &BTreeProps, node: &mut Node<T>, keys: &[T]) {
+    if keys.is_empty() {
+        return;
+    }
+    node.len += keys.len();
+    if node.is_leaf() {
+        let mut merged = Vec::with_capacity(node.keys.len() + keys.len());
+        let (mut old, mut new) = (node.keys.iter().peekable(), keys.iter().peekable());
+        while let (Some(&&a), Some(&&b)) = (old.peek(), new.peek()) {
+            if b <= a {
+                merged.push(b);
+                new.next();
+            } else {
+                merged.push(a);
+                old.next();
+            }
+        }
+        merged.extend(old.chain(new));
+        node.keys = merged;
+        return;
+    }
+
+    // Children from right to left, so splitting one doesn't shift the
+    // indexes of those still to do.
+    let mut end = keys.len();
+    for index in (0..node.children.len()).rev() {
+        let start = match index {
+            0 => 0,
+            _ => keys[..end].partition_point(|key| *key <= node.keys[index - 1]),
+        };
+        if start < end {
+            insert_sorted(props, node_mut(&mut node.children[index]), &keys[start..end]);
+            split_overfull(props, node, index);
+        }
+        end = start;
+    }
+}
+
+// Split the child at `index` into as many siblings as it takes for each to
+// fit, lifting the keys between them into `parent`.
+fn split_overfull<T: Ord + Copy + Debug>(props: &BTreeProps, parent: &mut Node<T>, index: usize) {
+    let len = parent.children[index].keys.len();
+    if len <= props.max_keys {
+        return;
+    }
+    let child = node_mut(&mut parent.children[index]);
+    let count = (len + 1).div_ceil(props.max_keys + 1);
+    let per_node = (len + 1 - count) / count;
+    let extra = (len + 1 - count) % count;
+
+    let mut keys = mem::take(&mut child.keys).into_iter();
+    let mut children = mem::take(&mut child.children).into_iter();
+    let mut pieces = Vec::with_capacity(count);
+    let mut separators = Vec::with_capacity(count - 1);
+    for piece in 0..count {
+        let size = per_node + usize::from(piece < extra);
+        let piece_keys: Vec<T> = keys.by_ref().take(size).collect();
+        let piece_children = if children.len() == 0 {
+            None
+        } else {
+            Some(children.by_ref().take(size + 1).collect())
+        };
+        pieces.push(Node::new(props.degree, Some(piece_keys), piece_children));
+        if piece + 1 < count {
+            separators.push(keys.next().unwrap());
+        }
+    }
+
+    let mut pieces = pieces.into_iter().map(Arc::new);
+    parent.children[index] = pieces.next().unwrap();
+    parent.children.splice(index + 1..index + 1, pieces);
+    parent.keys.splice(index..index, separators);
+    props.counters.split(count - 1);
+    event!(
+        "split",
+        parent = node_id(parent),
+        pieces = count,
+        keys = ?(parent.keys[index..index + count - 1].first(), parent.keys[index..index + count - 1].last()),
+        first_keys = ?key_range(&parent.children[index]),
+        last_keys = ?key_range(&parent.children[index + count - 1]),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_insert_batch() {
+        let mut tree = BTree::new(2);
+        for key in (0..1000).step_by(10) {
+            tree.insert(key);
+        }
+        // every leaf gets several times what it can hold and splits many ways
+        tree.insert_batch((0..1000).filter(|key| key % 10 != 0).rev().collect());
+        check_node(&tree.root, &tree.props, true);
+        tree.insert_batch(vec![5, 5, 5]);
+        let mut expected: Vec<i32> = (0..1000).collect();
+        expected.extend([5, 5, 5]);
+        expected.sort();
+        assert_eq!(tree.iter_snapshot().collect::<Vec<_>>(), expected);
+        for key in expected {
+            assert!(tree.delete(key));
+        }
+        assert!(!tree.search(5));
+    }
+}
@@ -0,0 +1,190 @@
+//! `btree bench`: timed insert/search/delete mixes against `BTreeSet`.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use b_trees_with_delete::BTree;
+
+pub const USAGE: &str = "\
+usage: btree bench [--degrees <n>,...] [--sizes <n>,...] [--mix <insert>:<search>:<delete>]
+
+Fills each tree with <size> keys, then times <size> random operations drawn
+in the given proportions (default 1:1:1), for every degree and size (default
+degrees 4,16,64 and sizes 10000,100000). std's BTreeSet runs the same
+operations as a baseline.";
+
+thread_local! {
+    static COMPARISONS: Cell<u64> = const { Cell::new(0) };
+}
+
+// A key that counts how often it is compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Counted(u64);
+
+impl PartialOrd for Counted {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Counted {
+    fn cmp(&self, other: &Self) -> Ordering {
+        COMPARISONS.with(|count| count.set(count.get() + 1));
+        self.0.cmp(&other.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Insert(Counted),
+    Search(Counted),
+    Delete(Counted),
+}
+
+pub struct Config {
+    pub degrees: Vec<usize>,
+    pub sizes: Vec<usize>,
+    // Relative weights of inserts, searches and deletes.
+    pub mix: [u64; 3],
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            degrees: vec![4, 16, 64],
+            sizes: vec![10_000, 100_000],
+            mix: [1, 1, 1],
+        }
+    }
+}
+
+pub struct Run {
+    pub name: String,
+    pub size: usize,
+    pub ops_per_sec: f64,
+    pub comparisons_per_op: f64,
+}
+
+pub fn parse_args(mut words: impl Iterator<Item = String>) -> Result<Config, String> {
+    let mut config = Config::default();
+    while let Some(word) = words.next() {
+        let value = words.next().ok_or(format!("{word} needs a value"))?;
+        let numbers: Result<Vec<usize>, _> = value.split([',', ':']).map(str::parse).collect();
+        let numbers = numbers.map_err(|_| format!("{word} takes whole numbers, not {value}"))?;
+        match word.as_str() {
+            "--degrees" if numbers.iter().all(|&degree| degree >= 4 && degree % 2 == 0) => config.degrees = numbers,
+            "--degrees" => return Err(String::from("degrees must be even numbers of at least 4")),
+            "--sizes" => config.sizes = numbers,
+            "--mix" => match numbers[..] {
+                [insert, search, delete] if insert + search + delete > 0 => {
+                    config.mix = [insert as u64, search as u64, delete as u64];
+                }
+                _ => return Err(String::from("--mix takes three weights, such as 2:1:1")),
+            },
+            _ => return Err(format!("unknown option {word}")),
+        }
+    }
+    Ok(config)
+}
+
+pub fn run(config: &Config, out: &mut impl Write) -> io::Result<Vec<Run>> {
+    writeln!(out, "{:<10} {:>10} {:>14} {:>8}", "tree", "size", "ops/sec", "cmp/op")?;
+    let mut runs = Vec::new();
+    for &size in &config.sizes {
+        let (fill, ops) = workload(size, config.mix);
+        let mut results = Vec::new();
+        for &degree in &config.degrees {
+            let mut tree = BTree::new(degree / 2);
+            for &key in &fill {
+                tree.insert(key);
+            }
+            results.push(measure(format!("degree {degree}"), size, &ops, |op| match op {
+                Op::Insert(key) => tree.insert(key),
+                Op::Search(key) => _ = tree.search(key),
+                Op::Delete(key) => _ = tree.delete(key),
+            }));
+        }
+        let mut set: BTreeSet<Counted> = fill.iter().copied().collect();
+        results.push(measure(String::from("BTreeSet"), size, &ops, |op| match op {
+            Op::Insert(key) => _ = set.insert(key),
+            Op::Search(key) => _ = set.contains(&key),
+            Op::Delete(key) => _ = set.remove(&key),
+        }));
+
+        for result in results {
+            writeln!(
+                out,
+                "{:<10} {:>10} {:>14.0} {:>8.1}",
+                result.name, result.size, result.ops_per_sec, result.comparisons_per_op
+            )?;
+            runs.push(result);
+        }
+    }
+    Ok(runs)
+}
+
+// `size` distinct keys to start from, and `size` operations on keys from
+// a range twice as wide, so about half of searches and deletes hit.
+fn workload(size: usize, mix: [u64; 3]) -> (Vec<Counted>, Vec<Op>) {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let span = 2 * size.max(1) as u64;
+    let fill = (0..size as u64).map(|key| Counted(key * 2)).collect();
+    let total = mix.iter().sum::<u64>();
+    let ops = (0..size)
+        .map(|_| {
+            let key = Counted(next() % span);
+            let pick = next() % total;
+            if pick < mix[0] {
+                Op::Insert(key)
+            } else if pick < mix[0] + mix[1] {
+                Op::Search(key)
+            } else {
+                Op::Delete(key)
+            }
+        })
+        .collect();
+    (fill, ops)
+}
+
+fn measure(name: String, size: usize, ops: &[Op], mut apply: impl FnMut(Op)) -> Run {
+    COMPARISONS.with(|count| count.set(0));
+    let start = Instant::now();
+    for &op in ops {
+        apply(op);
+    }
+    let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    let comparisons = COMPARISONS.with(Cell::get);
+    Run {
+        name,
+        size,
+        ops_per_sec: ops.len() as f64 / seconds,
+        comparisons_per_op: comparisons as f64 / ops.len().max(1) as f64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_args, run};
+
+    #[test]
+    fn test_bench() {
+        let words = ["--degrees", "4,8", "--sizes", "500", "--mix", "2:1:1"];
+        let config = parse_args(words.into_iter().map(String::from)).unwrap();
+        let mut out = Vec::new();
+        let runs = run(&config, &mut out).unwrap();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[2].name, "BTreeSet");
+        assert!(runs.iter().all(|run| run.ops_per_sec > 0.0 && run.comparisons_per_op > 1.0));
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 4);
+        assert!(parse_args(["--mix", "1:1"].into_iter().map(String::from)).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! A tree that holds at most a fixed number of keys, evicting one whenever
+//! an insert would go past it: a bounded sorted cache, or the top k of a
+//! stream.
+
+use alloc::boxed::Box;
+use core::fmt::Debug;
+
+use crate::BTree;
+
+type Chooser<T> = Box<dyn FnMut(&BTree<T>) -> T + Send + Sync>;
+
+/// Which key a full `BoundedBTree` gives up.
+pub enum Eviction<T> {
+    /// The smallest, keeping the largest keys seen.
+    Smallest,
+    /// The largest, keeping the smallest keys seen.
+    Largest,
+    /// The key the callback picks, given the tree with the new key already
+    /// in. It must pick a key in the tree.
+    With(Chooser<T>),
+}
+
+/// A `BTree` of at most `capacity` keys. See the module docs.
+pub struct BoundedBTree<T> {
+    tree: BTree<T>,
+    capacity: usize,
+    eviction: Eviction<T>,
+}
+
+impl<T> BoundedBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Panics if `capacity` is 0.
+    pub fn new(branch_factor: usize, capacity: usize, eviction: Eviction<T>) -> Self {
+        assert!(capacity > 0, "a bounded tree must have room for a key");
+        BoundedBTree {
+            tree: BTree::new(branch_factor),
+            capacity,
+            eviction,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The keys held, for reading.
+    pub fn tree(&self) -> &BTree<T> {
+        &self.tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Insert `key`, returning the key evicted to make room if the tree was
+    /// full; that may be `key` itself.
+    pub fn insert(&mut self, key: T) -> Option<T> {
+        self.tree.insert(key);
+        if self.tree.len() <= self.capacity {
+            return None;
+        }
+        let victim = match &mut self.eviction {
+            Eviction::Smallest => *self.tree.nth(0).unwrap(),
+            Eviction::Largest => *self.tree.nth(self.tree.len() - 1).unwrap(),
+            Eviction::With(choose) => choose(&self.tree),
+        };
+        assert!(self.tree.delete(victim), "the eviction callback picked {victim:?}, which is not in the tree");
+        Some(victim)
+    }
+
+    pub fn delete(&mut self, key: T) -> bool {
+        self.tree.delete(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BoundedBTree, Eviction};
+    use crate::BTree;
+
+    #[test]
+    fn test_eviction() {
+        let mut largest = BoundedBTree::new(2, 10, Eviction::Smallest);
+        let mut smallest = BoundedBTree::new(2, 10, Eviction::Largest);
+        // evict whichever key is closest to 500
+        let mut far = BoundedBTree::new(
+            2,
+            10,
+            Eviction::With(Box::new(|tree: &BTree<u32>| {
+                let rank = tree.rank(500).min(tree.len() - 1);
+                let after = *tree.nth(rank).unwrap();
+                match rank.checked_sub(1).map(|before| *tree.nth(before).unwrap()) {
+                    Some(before) if 500 - before < after.abs_diff(500) => before,
+                    _ => after,
+                }
+            })),
+        );
+        let mut evicted = 0;
+        for key in 0..1000u32 {
+            let key = (key * 7919) % 1000;
+            evicted += largest.insert(key).is_some() as usize;
+            smallest.insert(key);
+            far.insert(key);
+            assert!(largest.len() <= 10 && far.len() <= 10);
+        }
+        assert_eq!(evicted, 990);
+        assert!(largest.tree().iter_snapshot().eq(990..1000));
+        assert!(smallest.tree().iter_snapshot().eq(0..10));
+        assert!(far.len() == 10 && far.tree().iter_snapshot().all(|key| key.abs_diff(500) >= 495));
+        assert_eq!(smallest.insert(3), Some(9));
+        assert_eq!(smallest.insert(50), Some(50));
+    }
+}
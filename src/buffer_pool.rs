@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::pager::{Page, PageId, Pager, PAGE_SIZE};
+use crate::wal::Wal;
+
+// Checkpoint once the log grows past this many bytes.
+const WAL_CHECKPOINT_BYTES: u64 = 4 << 20;
+
+struct Frame {
+    data: Box<Page>,
+    dirty: bool,
+    // The current contents are in the WAL, so the page may be written back.
+    logged: bool,
+    last_used: u64,
+}
+
+/// Page cache in front of a `Pager`.
+///
+/// Holds at most `capacity` pages and evicts the least recently used one when
+/// full. Modified pages are only written back when they are evicted or on
+/// `flush`, so a hot upper level of the tree is read from disk once.
+///
+/// With a `Wal` attached, pages modified since the last `commit` are pinned
+/// in memory: they reach the data file only after being logged.
+pub struct BufferPool {
+    pager: Pager,
+    wal: Option<Wal>,
+    capacity: usize,
+    frames: HashMap<PageId, Frame>,
+    // last_used tick -> page, oldest first
+    lru: BTreeMap<u64, PageId>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl BufferPool {
+    pub fn new(pager: Pager, capacity: usize) -> Self {
+        BufferPool::build(pager, None, capacity)
+    }
+
+    pub fn with_wal(pager: Pager, wal: Wal, capacity: usize) -> Self {
+        BufferPool::build(pager, Some(wal), capacity)
+    }
+
+    fn build(pager: Pager, wal: Option<Wal>, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        BufferPool {
+            pager,
+            wal,
+            capacity,
+            frames: HashMap::with_capacity(capacity),
+            lru: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of pages currently cached.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn page_count(&self) -> u64 {
+        self.pager.page_count()
+    }
+
+    /// Page requests served from memory.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Page requests that had to read the file.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn page(&mut self, id: PageId) -> io::Result<&Page> {
+        let frame = self.fetch(id)?;
+        Ok(&frame.data)
+    }
+
+    /// Like `page`, but marks the page dirty so it is written back later.
+    pub fn page_mut(&mut self, id: PageId) -> io::Result<&mut Page> {
+        let frame = self.fetch(id)?;
+        frame.dirty = true;
+        frame.logged = false;
+        Ok(&mut frame.data)
+    }
+
+    /// Add a zeroed page at the end of the file and cache it.
+    pub fn allocate(&mut self) -> io::Result<PageId> {
+        let id = self.pager.allocate()?;
+        self.make_room()?;
+        self.insert_frame(id, Box::new([0; PAGE_SIZE]), false);
+        Ok(id)
+    }
+
+    /// Log every page changed since the last commit as one atomic batch.
+    /// Does nothing without a WAL.
+    pub fn commit(&mut self) -> io::Result<()> {
+        let wal = match self.wal {
+            Some(ref mut wal) => wal,
+            None => return Ok(()),
+        };
+        let mut unlogged: Vec<PageId> = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.dirty && !frame.logged)
+            .map(|(&id, _)| id)
+            .collect();
+        if unlogged.is_empty() {
+            return Ok(());
+        }
+        unlogged.sort_unstable();
+        for id in unlogged.iter() {
+            let image = self.pager.log_image(*id, &self.frames[id].data)?;
+            wal.append_page(*id, &image)?;
+        }
+        wal.commit()?;
+        for id in unlogged {
+            self.frames.get_mut(&id).unwrap().logged = true;
+        }
+        if wal.len() > WAL_CHECKPOINT_BYTES {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write every dirty page back and sync the file. With a WAL this
+    /// commits first and empties the log afterwards (a checkpoint).
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.commit()?;
+        let mut dirty: Vec<PageId> = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.dirty)
+            .map(|(&id, _)| id)
+            .collect();
+        dirty.sort_unstable();
+        for id in dirty {
+            let frame = self.frames.get_mut(&id).unwrap();
+            self.pager.write_page(id, &frame.data)?;
+            frame.dirty = false;
+        }
+        self.pager.sync()?;
+        if let Some(ref mut wal) = self.wal {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    fn fetch(&mut self, id: PageId) -> io::Result<&mut Frame> {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(frame) = self.frames.get_mut(&id) {
+            self.hits += 1;
+            self.lru.remove(&frame.last_used);
+            self.lru.insert(tick, id);
+            frame.last_used = tick;
+        } else {
+            self.misses += 1;
+            let mut data = Box::new([0; PAGE_SIZE]);
+            self.pager.read_page(id, &mut data)?;
+            self.make_room()?;
+            self.insert_frame(id, data, false);
+        }
+        Ok(self.frames.get_mut(&id).unwrap())
+    }
+
+    fn insert_frame(&mut self, id: PageId, data: Box<Page>, dirty: bool) {
+        self.tick += 1;
+        self.lru.insert(self.tick, id);
+        self.frames.insert(
+            id,
+            Frame {
+                data,
+                dirty,
+                logged: false,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn make_room(&mut self) -> io::Result<()> {
+        while self.frames.len() >= self.capacity {
+            let has_wal = self.wal.is_some();
+            let frames = &self.frames;
+            // Unlogged pages must stay put; go over capacity if nothing else is left.
+            let (tick, victim) = match self.lru.iter().find(|(_, id)| {
+                let frame = &frames[*id];
+                !has_wal || !frame.dirty || frame.logged
+            }) {
+                Some((&tick, &id)) => (tick, id),
+                None => break,
+            };
+            self.lru.remove(&tick);
+            let frame = self.frames.remove(&victim).unwrap();
+            if frame.dirty {
+                self.pager.write_page(victim, &frame.data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BufferPool;
+    use crate::pager::Pager;
+
+    #[test]
+    fn test_eviction_writes_back() {
+        let path = std::env::temp_dir().join(format!("buffer-pool-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut pool = BufferPool::new(Pager::open(&path).unwrap(), 2);
+            for value in 0..4u8 {
+                let id = pool.allocate().unwrap();
+                pool.page_mut(id).unwrap()[0] = value + 1;
+            }
+            assert_eq!(pool.len(), 2);
+            pool.reset_stats();
+            assert_eq!(pool.page(3).unwrap()[0], 4);
+            assert_eq!(pool.hits(), 1);
+            // page 0 was evicted long ago, so this has to come from the file
+            assert_eq!(pool.page(0).unwrap()[0], 1);
+            assert_eq!(pool.misses(), 1);
+        }
+        let mut pool = BufferPool::new(Pager::open(&path).unwrap(), 8);
+        for id in 0..4 {
+            assert_eq!(pool.page(id).unwrap()[0], id as u8 + 1);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::mem;
+
+use crate::{BTreeProps, Refill};
+
+// Every key is stored once, with the number of times it was inserted, and
+// all keys live in leaves. Internal nodes hold pivots (keys >= pivot `i` go
+// right of it) and a buffer of pending count changes, sorted by key.
+enum Node<T> {
+    Leaf(Vec<(T, usize)>),
+    Internal {
+        pivots: Vec<T>,
+        children: Vec<Node<T>>,
+        buffer: Vec<(T, isize)>,
+    },
+}
+
+impl<T: Ord + Copy> Node<T> {
+    fn empty_internal(child: Node<T>) -> Self {
+        Node::Internal {
+            pivots: Vec::new(),
+            children: vec![child],
+            buffer: Vec::new(),
+        }
+    }
+
+    // Keys in a leaf, pivots in an internal node.
+    fn size(&self) -> usize {
+        match self {
+            Node::Leaf(entries) => entries.len(),
+            Node::Internal { pivots, .. } => pivots.len(),
+        }
+    }
+}
+
+/// A write-optimized B-tree (a Bε-tree) where changes trickle down in
+/// batches.
+///
+/// An insert or delete just records a message in the root's buffer. When a
+/// buffer overflows, the messages bound for the child with the most of them
+/// move down in one go, so each node is rewritten once per batch rather than
+/// once per change. Messages for the same key are combined on the way.
+/// Lookups check the buffers along their path as well as the leaf. Nodes
+/// are split and refilled the way `BTree`'s are.
+pub struct BufferedBTree<T> {
+    root: Node<T>,
+    props: BTreeProps,
+    buffer_capacity: usize,
+    len: usize,
+}
+
+impl<T> BufferedBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// `buffer_capacity` is the number of distinct keys an internal node
+    /// buffers before flushing; larger buffers batch more work per flush.
+    pub fn new(branch_factor: usize, buffer_capacity: usize) -> Self {
+        BufferedBTree {
+            root: Node::Leaf(Vec::new()),
+            props: BTreeProps::new(2 * branch_factor),
+            buffer_capacity: buffer_capacity.max(1),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, key: T) {
+        self.apply(key, 1);
+        self.len += 1;
+    }
+
+    /// Pending deletes are only recorded for keys that are present, which
+    /// takes a lookup; the tree itself is changed lazily like an insert.
+    pub fn delete(&mut self, key: T) -> bool {
+        if !self.search(key) {
+            return false;
+        }
+        self.apply(key, -1);
+        self.len -= 1;
+        true
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        let mut pending = 0;
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let stored = match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                        Ok(index) => entries[index].1 as isize,
+                        Err(_) => 0,
+                    };
+                    return stored + pending > 0;
+                }
+                Node::Internal { pivots, children, buffer } => {
+                    if let Ok(index) = buffer.binary_search_by(|(k, _)| k.cmp(&key)) {
+                        pending += buffer[index].1;
+                    }
+                    node = &children[pivots.partition_point(|p| *p <= key)];
+                }
+            }
+        }
+    }
+
+    /// Apply every buffered message, rebuilding the tree with evenly
+    /// filled nodes.
+    pub fn flush(&mut self) {
+        let mut entries = Vec::new();
+        collect_entries(&self.root, &[], &mut entries);
+        self.root = build(&self.props, entries);
+    }
+
+    fn apply(&mut self, key: T, delta: isize) {
+        match &mut self.root {
+            Node::Leaf(entries) => apply_to_leaf(entries, &[(key, delta)]),
+            Node::Internal { buffer, .. } => merge_messages(buffer, &[(key, delta)]),
+        }
+        self.settle_root(&key);
+    }
+
+    // Flush the root while its buffer is over capacity, then restore the
+    // root's shape: split it if overfull, drop it if it has a single child.
+    // `key` is the one just changed, for the split policy.
+    fn settle_root(&mut self, key: &T) {
+        let props = Props { nodes: &self.props, buffer_capacity: self.buffer_capacity };
+        while can_flush(&props, &self.root) {
+            flush_one(&props, &mut self.root);
+        }
+        while self.root.size() > props.nodes.max_keys {
+            let old_root = mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+            self.root = Node::empty_internal(old_root);
+            split_child(&props, &mut self.root, 0, key);
+        }
+        while let Node::Internal { pivots, children, buffer } = &mut self.root {
+            if !pivots.is_empty() {
+                break;
+            }
+            let messages = mem::take(buffer);
+            push_down(&props, &mut children[0], &messages);
+            if children[0].size() > props.nodes.max_keys {
+                split_child(&props, &mut self.root, 0, key);
+            } else {
+                let child = children.pop().unwrap();
+                self.root = child;
+            }
+        }
+    }
+}
+
+// What flushing goes by: node sizes and policies from the tree's
+// `BTreeProps`, and how many messages a buffer holds.
+struct Props<'a> {
+    nodes: &'a BTreeProps,
+    buffer_capacity: usize,
+}
+
+// Merge sorted messages into a sorted buffer, combining messages for the
+// same key and dropping those that cancel out.
+fn merge_messages<T: Ord + Copy>(buffer: &mut Vec<(T, isize)>, messages: &[(T, isize)]) {
+    let old = mem::take(buffer);
+    let (mut a, mut b) = (old.into_iter().peekable(), messages.iter().copied().peekable());
+    loop {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => match x.0.cmp(&y.0) {
+                Ordering::Less => a.next().unwrap(),
+                Ordering::Greater => b.next().unwrap(),
+                Ordering::Equal => {
+                    let (key, delta) = a.next().unwrap();
+                    (key, delta + b.next().unwrap().1)
+                }
+            },
+            (Some(_), None) => a.next().unwrap(),
+            (None, Some(_)) => b.next().unwrap(),
+            (None, None) => break,
+        };
+        if next.1 != 0 {
+            buffer.push(next);
+        }
+    }
+}
+
+fn apply_to_leaf<T: Ord + Copy>(entries: &mut Vec<(T, usize)>, messages: &[(T, isize)]) {
+    let mut merged: Vec<(T, isize)> = entries.iter().map(|&(key, count)| (key, count as isize)).collect();
+    merge_messages(&mut merged, messages);
+    // Deletes are only issued for keys that are present, so counts never go
+    // negative.
+    *entries = merged.into_iter().map(|(key, count)| (key, count as usize)).collect();
+}
+
+// Hand messages to a node, flushing it in turn if its buffer overflows.
+fn push_down<T: Ord + Copy>(props: &Props, node: &mut Node<T>, messages: &[(T, isize)]) {
+    match node {
+        Node::Leaf(entries) => apply_to_leaf(entries, messages),
+        Node::Internal { buffer, .. } => merge_messages(buffer, messages),
+    }
+    while can_flush(props, node) {
+        flush_one(props, node);
+    }
+}
+
+// A node left with a single child by merges below it stops flushing until
+// its parent has merged it with a sibling: a child that underflows under it
+// would have no sibling to merge with.
+fn can_flush<T>(props: &Props, node: &Node<T>) -> bool {
+    matches!(node, Node::Internal { pivots, buffer, .. }
+        if buffer.len() > props.buffer_capacity && !pivots.is_empty())
+}
+
+// Move the messages bound for the child that has the most of them.
+fn flush_one<T: Ord + Copy>(props: &Props, node: &mut Node<T>) {
+    let Node::Internal { pivots, children, buffer } = node else { return };
+    let mut bounds = Vec::with_capacity(children.len() + 1);
+    bounds.push(0);
+    for pivot in pivots.iter() {
+        bounds.push(buffer.partition_point(|(key, _)| key < pivot));
+    }
+    bounds.push(buffer.len());
+    let index = (0..children.len()).max_by_key(|&i| bounds[i + 1] - bounds[i]).unwrap();
+    let messages: Vec<_> = buffer.drain(bounds[index]..bounds[index + 1]).collect();
+    push_down(props, &mut children[index], &messages);
+    // the largest key moved stands for the batch in the split policy
+    let key = messages[messages.len() - 1].0;
+    fix_child(props, node, index, &key);
+}
+
+// Every key with its count once all pending messages are applied, in order.
+fn collect_entries<T: Ord + Copy>(node: &Node<T>, pending: &[(T, isize)], out: &mut Vec<(T, usize)>) {
+    match node {
+        Node::Leaf(entries) => {
+            let mut merged: Vec<(T, isize)> = entries.iter().map(|&(key, count)| (key, count as isize)).collect();
+            merge_messages(&mut merged, pending);
+            out.extend(merged.into_iter().map(|(key, count)| (key, count as usize)));
+        }
+        Node::Internal { pivots, children, buffer } => {
+            // Messages higher up are newer, but counts just add up, so the
+            // order they are combined in doesn't matter.
+            let mut messages = buffer.clone();
+            merge_messages(&mut messages, pending);
+            let mut start = 0;
+            for (index, child) in children.iter().enumerate() {
+                let end = match pivots.get(index) {
+                    Some(pivot) => messages.partition_point(|(key, _)| key < pivot),
+                    None => messages.len(),
+                };
+                collect_entries(child, &messages[start..end], out);
+                start = end;
+            }
+        }
+    }
+}
+
+// Bottom-up build, with nodes filled as evenly as the entry count allows.
+fn build<T: Ord + Copy>(props: &BTreeProps, entries: Vec<(T, usize)>) -> Node<T> {
+    // (smallest key, node) for every node of the level being built
+    let mut level: Vec<(Option<T>, Node<T>)> = even_chunks(entries, props.max_keys)
+        .into_iter()
+        .map(|chunk| (chunk.first().map(|entry| entry.0), Node::Leaf(chunk)))
+        .collect();
+    while level.len() > 1 {
+        level = even_chunks(level, props.max_keys + 1)
+            .into_iter()
+            .map(|chunk| {
+                let first = chunk[0].0;
+                let pivots = chunk[1..].iter().map(|(key, _)| key.unwrap()).collect();
+                let children = chunk.into_iter().map(|(_, node)| node).collect();
+                let node = Node::Internal {
+                    pivots,
+                    children,
+                    buffer: Vec::new(),
+                };
+                (first, node)
+            })
+            .collect();
+    }
+    level.pop().unwrap().1
+}
+
+fn even_chunks<E>(items: Vec<E>, max: usize) -> Vec<Vec<E>> {
+    let count = items.len().div_ceil(max).max(1);
+    let (per_chunk, extra) = (items.len() / count, items.len() % count);
+    let mut items = items.into_iter();
+    (0..count)
+        .map(|index| items.by_ref().take(per_chunk + usize::from(index < extra)).collect())
+        .collect()
+}
+
+// Bring the child at `index` back within its size limits after a flush:
+// split where the split policy says for `key`, or refill it as `BTree`
+// refills an underfull child, as many times as it takes.
+fn fix_child<T: Ord + Copy>(props: &Props, node: &mut Node<T>, mut index: usize, key: &T) {
+    if child_size(node, index) > props.nodes.max_keys {
+        split_child(props, node, index, key);
+        return;
+    }
+    while child_size(node, index) < props.nodes.min_keys {
+        let Node::Internal { children, .. } = &*node else { return };
+        match props.nodes.refill_from(index, children.len(), |child| children[child].size()) {
+            Some(Refill::FromRight) => borrow_from_right(node, index),
+            Some(Refill::FromLeft) => borrow_from_left(node, index),
+            Some(Refill::MergeRight) => merge_children(node, index),
+            Some(Refill::MergeLeft) => {
+                index -= 1;
+                merge_children(node, index);
+            }
+            None => return,
+        }
+    }
+}
+
+// Split the child at `index` where the split policy says for `key`,
+// repeatedly if it is far over the limit.
+fn split_child<T: Ord + Copy>(props: &Props, node: &mut Node<T>, index: usize, key: &T) {
+    let Node::Internal { pivots, children, .. } = node else { return };
+    let (right, pivot) = match &mut children[index] {
+        Node::Leaf(entries) => {
+            let at = props.nodes.split_index(&entries[0].0, &entries[entries.len() - 1].0, key);
+            let right = entries.split_off(at);
+            let pivot = right[0].0;
+            (Node::Leaf(right), pivot)
+        }
+        Node::Internal { pivots, children, buffer } => {
+            let at = props.nodes.split_index(&pivots[0], &pivots[pivots.len() - 1], key);
+            let right_pivots = pivots.split_off(at + 1);
+            let pivot = pivots.pop().unwrap();
+            let right_children = children.split_off(at + 1);
+            let right_buffer = buffer.split_off(buffer.partition_point(|(key, _)| *key < pivot));
+            (
+                Node::Internal {
+                    pivots: right_pivots,
+                    children: right_children,
+                    buffer: right_buffer,
+                },
+                pivot,
+            )
+        }
+    };
+    pivots.insert(index, pivot);
+    children.insert(index + 1, right);
+    for index in [index + 1, index] {
+        if child_size(node, index) > props.nodes.max_keys {
+            split_child(props, node, index, key);
+        }
+    }
+}
+
+// Move the first entry, or the first child with the messages buffered for
+// it, of the child at `index + 1` to the end of the one at `index`.
+fn borrow_from_right<T: Ord + Copy>(node: &mut Node<T>, index: usize) {
+    let Node::Internal { pivots, children, .. } = node else { return };
+    let (left, right) = children.split_at_mut(index + 1);
+    match (&mut left[index], &mut right[0]) {
+        (Node::Leaf(child), Node::Leaf(sibling)) => {
+            child.push(sibling.remove(0));
+            pivots[index] = sibling[0].0;
+        }
+        (
+            Node::Internal { pivots: child_pivots, children: child_children, buffer },
+            Node::Internal {
+                pivots: sibling_pivots,
+                children: sibling_children,
+                buffer: sibling_buffer,
+            },
+        ) => {
+            let pivot = sibling_pivots.remove(0);
+            child_pivots.push(mem::replace(&mut pivots[index], pivot));
+            child_children.push(sibling_children.remove(0));
+            let moved = sibling_buffer.partition_point(|(key, _)| *key < pivot);
+            buffer.extend(sibling_buffer.drain(..moved));
+        }
+        _ => unreachable!("siblings are always at the same depth"),
+    }
+}
+
+// Move the last entry, or the last child with the messages buffered for
+// it, of the child at `index - 1` to the front of the one at `index`.
+fn borrow_from_left<T: Ord + Copy>(node: &mut Node<T>, index: usize) {
+    let Node::Internal { pivots, children, .. } = node else { return };
+    let (left, right) = children.split_at_mut(index);
+    match (&mut left[index - 1], &mut right[0]) {
+        (Node::Leaf(sibling), Node::Leaf(child)) => {
+            let entry = sibling.pop().unwrap();
+            pivots[index - 1] = entry.0;
+            child.insert(0, entry);
+        }
+        (
+            Node::Internal {
+                pivots: sibling_pivots,
+                children: sibling_children,
+                buffer: sibling_buffer,
+            },
+            Node::Internal { pivots: child_pivots, children: child_children, buffer },
+        ) => {
+            let pivot = sibling_pivots.pop().unwrap();
+            child_pivots.insert(0, mem::replace(&mut pivots[index - 1], pivot));
+            child_children.insert(0, sibling_children.pop().unwrap());
+            let moved = sibling_buffer.partition_point(|(key, _)| *key < pivot);
+            buffer.splice(..0, sibling_buffer.drain(moved..));
+        }
+        _ => unreachable!("siblings are always at the same depth"),
+    }
+}
+
+fn child_size<T: Ord + Copy>(node: &Node<T>, index: usize) -> usize {
+    match node {
+        Node::Internal { children, .. } => children[index].size(),
+        Node::Leaf(_) => 0,
+    }
+}
+
+// Merge the child at `index + 1` into the one at `index`.
+fn merge_children<T: Ord + Copy>(node: &mut Node<T>, index: usize) {
+    let Node::Internal { pivots, children, .. } = node else { return };
+    let pivot = pivots.remove(index);
+    let right = children.remove(index + 1);
+    match (&mut children[index], right) {
+        (Node::Leaf(left), Node::Leaf(mut right)) => left.append(&mut right),
+        (
+            Node::Internal { pivots, children, buffer },
+            Node::Internal {
+                pivots: mut right_pivots,
+                children: mut right_children,
+                buffer: mut right_buffer,
+            },
+        ) => {
+            pivots.push(pivot);
+            pivots.append(&mut right_pivots);
+            children.append(&mut right_children);
+            // every key buffered on the left is below the pivot
+            buffer.append(&mut right_buffer);
+        }
+        _ => unreachable!("siblings are always at the same depth"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BufferedBTree, Node};
+
+    // Every node but the root within its size limits, each child's keys
+    // within its pivots, and leaves all at one depth, which is returned.
+    fn check_shape(node: &Node<u32>, min: usize, max: usize, is_root: bool, bounds: (Option<u32>, Option<u32>)) -> usize {
+        assert!(node.size() <= max && (is_root || node.size() >= min), "node of {} keys", node.size());
+        let within = |key: u32| bounds.0.is_none_or(|low| low <= key) && bounds.1.is_none_or(|high| key < high);
+        match node {
+            Node::Leaf(entries) => {
+                assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0) && entries.iter().all(|&(key, _)| within(key)));
+                0
+            }
+            Node::Internal { pivots, children, buffer } => {
+                assert!(pivots.iter().all(|&pivot| within(pivot)) && buffer.iter().all(|&(key, _)| within(key)));
+                let depths: Vec<usize> = children
+                    .iter()
+                    .enumerate()
+                    .map(|(index, child)| {
+                        let low = index.checked_sub(1).map(|before| pivots[before]).or(bounds.0);
+                        let high = pivots.get(index).copied().or(bounds.1);
+                        check_shape(child, min, max, false, (low, high))
+                    })
+                    .collect();
+                assert!(depths.windows(2).all(|pair| pair[0] == pair[1]));
+                depths[0] + 1
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_plain_tree() {
+        let mut tree = BufferedBTree::new(2, 8);
+        let mut plain = crate::BTree::new(2);
+        for step in 0..3000u32 {
+            let key = (step * 7919) % 400;
+            if step % 3 == 0 {
+                assert_eq!(tree.delete(key), plain.delete(key));
+            } else {
+                tree.insert(key);
+                plain.insert(key);
+            }
+            check_shape(&tree.root, tree.props.min_keys, tree.props.max_keys, true, (None, None));
+        }
+        for key in 0..400 {
+            assert_eq!(tree.search(key), plain.search(key));
+        }
+        tree.flush();
+        for key in 0..400 {
+            assert_eq!(tree.search(key), plain.search(key));
+        }
+        assert_eq!(tree.len(), plain.iter_snapshot().count());
+
+        // batches of deletes leave children far underfull at once
+        for key in (0..400).filter(|key| key % 5 != 0) {
+            while tree.delete(key) {}
+        }
+        check_shape(&tree.root, tree.props.min_keys, tree.props.max_keys, true, (None, None));
+        for key in 0..400 {
+            assert_eq!(tree.search(key), key % 5 == 0 && plain.search(key));
+        }
+    }
+}
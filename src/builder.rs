@@ -0,0 +1,142 @@
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+use crate::{BTree, ConfigError, DuplicatePolicy, RebalancePolicy};
+
+/// The branch factor of `BTree::default` and of a builder left alone: 15
+/// keys to a node.
+pub const DEFAULT_BRANCH_FACTOR: usize = 8;
+
+/// Configuration for a new `BTree`, checked all at once by `build`.
+///
+/// ```
+/// use b_trees_with_delete::{BTree, DuplicatePolicy};
+///
+/// let mut tree = BTree::builder().branch_factor(8).duplicate_policy(DuplicatePolicy::Reject).build().unwrap();
+/// tree.insert(1);
+/// tree.insert(1);
+/// assert_eq!(tree.len(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BTreeBuilder<T> {
+    size: Size,
+    duplicates: DuplicatePolicy,
+    rebalance: Option<RebalancePolicy>,
+    _keys: PhantomData<T>,
+}
+
+// However the caller gave it, so errors name what they passed.
+#[derive(Clone, Copy, Debug)]
+enum Size {
+    BranchFactor(usize),
+    Degree(usize),
+}
+
+impl<T> BTreeBuilder<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Nodes hold up to `2 * branch_factor - 1` keys, as with `BTree::new`.
+    /// Replaces any `degree` set before.
+    pub fn branch_factor(mut self, branch_factor: usize) -> Self {
+        self.size = Size::BranchFactor(branch_factor);
+        self
+    }
+
+    /// Nodes have up to `degree` children, as with `BTree::with_degree`.
+    /// Replaces any `branch_factor` set before.
+    pub fn degree(mut self, degree: usize) -> Self {
+        self.size = Size::Degree(degree);
+        self
+    }
+
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Defaults to borrowing first, with the most `min_keys` the degree
+    /// allows.
+    pub fn rebalance_policy(mut self, policy: RebalancePolicy) -> Self {
+        self.rebalance = Some(policy);
+        self
+    }
+
+    pub fn build(self) -> Result<BTree<T>, ConfigError> {
+        let mut tree = match self.size {
+            Size::BranchFactor(branch_factor) => BTree::with_branch_factor(branch_factor)?,
+            Size::Degree(degree) => BTree::with_degree(degree)?,
+        };
+        tree.set_duplicate_policy(self.duplicates);
+        if let Some(policy) = self.rebalance {
+            tree.set_rebalance_policy(policy)?;
+        }
+        Ok(tree)
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn builder() -> BTreeBuilder<T> {
+        BTreeBuilder {
+            size: Size::BranchFactor(DEFAULT_BRANCH_FACTOR),
+            duplicates: DuplicatePolicy::default(),
+            rebalance: None,
+            _keys: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// An empty tree with `DEFAULT_BRANCH_FACTOR`.
+    fn default() -> Self {
+        BTree::new(DEFAULT_BRANCH_FACTOR)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{check_node, Tagged};
+    use crate::{BTree, ConfigError, DuplicatePolicy, Finger, RebalancePolicy, Strategy};
+
+    #[test]
+    fn test_builder() {
+        let tree: BTree<u32> = BTree::default();
+        assert_eq!(tree.degree(), 16);
+        let tree = BTree::<u32>::builder().degree(6).branch_factor(2).build().unwrap();
+        assert_eq!(tree.degree(), 4);
+        assert_eq!(BTree::<u32>::builder().branch_factor(1).build().err(), Some(ConfigError::BranchFactorTooSmall(1)));
+        assert_eq!(BTree::<u32>::builder().degree(7).build().err(), Some(ConfigError::OddDegree(7)));
+        let policy = RebalancePolicy { min_keys: 1, strategy: Strategy::PreferMerge };
+        let tree = BTree::<u32>::builder().degree(4).rebalance_policy(policy).build().unwrap();
+        assert_eq!(tree.rebalance_policy(), policy);
+
+        for policy in [DuplicatePolicy::Allow, DuplicatePolicy::Reject, DuplicatePolicy::Replace] {
+            let mut tree = BTree::builder().branch_factor(2).duplicate_policy(policy).build().unwrap();
+            let mut finger: Option<Finger<Tagged>> = None;
+            for round in 0..3u8 {
+                for key in 0..100u32 {
+                    let key = Tagged(key, char::from(b'a' + round));
+                    match key.0 % 3 {
+                        0 => tree.insert(key),
+                        1 => tree.insert_hint(finger.get_or_insert_with(|| tree.finger(key)), key),
+                        _ => tree.insert_batch(vec![key, key]),
+                    }
+                }
+                tree.insert_max(Tagged(99, char::from(b'a' + round))).unwrap();
+                check_node(&tree.root, &tree.props, true);
+            }
+            let keys: Vec<(u32, char)> = tree.iter_snapshot().map(|key| (key.0, key.1)).collect();
+            match policy {
+                DuplicatePolicy::Allow => assert_eq!(keys.len(), 3 * (100 + 33 + 1)),
+                DuplicatePolicy::Reject => assert!(keys.into_iter().eq((0..100).map(|key| (key, 'a')))),
+                DuplicatePolicy::Replace => assert!(keys.into_iter().eq((0..100).map(|key| (key, 'c')))),
+            }
+        }
+    }
+}
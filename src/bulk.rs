@@ -0,0 +1,194 @@
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::allocator::{arc_in, vec_in, Allocator, ArcIn, Global};
+use crate::{Aggregate, BTree, BTreeProps, Node};
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// Rebuild the tree from its keys with nodes about `fill` full, from 0.0
+    /// (as empty as the minimum allows) to 1.0 (full).
+    ///
+    /// After many deletes a tree is left with lots of half-empty nodes;
+    /// rebuilding packs the keys into fewer, fuller ones. Leaving some room
+    /// (say 0.7) makes the next inserts less likely to split.
+    pub fn rebuild(&mut self, fill: f64) {
+        let keys: Vec<T> = self.iter_snapshot().collect();
+        self.rebuild_from(keys, fill);
+        self.record_whole(|| format!("rebuild {fill} full"));
+    }
+
+    // Replace the tree with one holding `keys`, which must be sorted, in
+    // nodes about `fill` full.
+    pub(crate) fn rebuild_from(&mut self, keys: Vec<T>, fill: f64) {
+        // rounded by hand: `f64::round` needs std
+        let target = (self.props.max_keys as f64 * fill.clamp(0.0, 1.0) + 0.5) as usize;
+        let per_node = target.clamp(self.props.min_keys.max(1), self.props.max_keys);
+        self.root = arc_in(build(&self.props, &self.ctx.alloc, keys, per_node), &self.ctx.alloc);
+        self.ctx.version += 1;
+    }
+
+    /// Rebuild with full nodes and no spare capacity in them.
+    pub fn shrink_to_fit(&mut self) {
+        self.rebuild(1.0);
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// A tree of the default branch factor holding `keys`, built bottom-up
+    /// in one pass with full nodes, as `shrink_to_fit` leaves them. Panics
+    /// if `keys` isn't sorted.
+    pub fn from_sorted_vec(keys: Vec<T>) -> Self {
+        assert!(keys.is_sorted(), "keys must be sorted");
+        let mut tree = BTree::default();
+        tree.root = Arc::new(build(&tree.props, &Global, keys, tree.props.max_keys));
+        tree
+    }
+}
+
+impl<T> From<BTreeSet<T>> for BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    fn from(set: BTreeSet<T>) -> Self {
+        BTree::from_sorted_vec(set.into_iter().collect())
+    }
+}
+
+/// Every key in order, duplicates included.
+impl<T> From<BTree<T>> for Vec<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    fn from(tree: BTree<T>) -> Self {
+        tree.iter_snapshot().collect()
+    }
+}
+
+/// Every key, with duplicates kept once.
+impl<T> From<BTree<T>> for BTreeSet<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    fn from(tree: BTree<T>) -> Self {
+        tree.iter_snapshot().collect()
+    }
+}
+
+// Build bottom-up, one level at a time: split the level's keys into nodes
+// of about `per_node` keys, and pass the key between each pair of nodes up
+// to the next level.
+fn build<T: Ord + Copy, A: Aggregate<T>, M: Allocator + Clone>(props: &BTreeProps, alloc: &M, mut keys: Vec<T>, per_node: usize) -> Node<T, A, M> {
+    let mut children: Vec<ArcIn<Node<T, A, M>, M>> = Vec::new();
+    loop {
+        let len = keys.len();
+        // about `per_node` keys per node, but never fewer than the minimum
+        let count = (len + 1)
+            .div_ceil(per_node + 1)
+            .min((len + 1) / (props.min_keys + 1));
+        if count <= 1 {
+            return node_in(alloc, keys, children);
+        }
+        let per = (len + 1 - count) / count;
+        let extra = (len + 1 - count) % count;
+
+        let mut level_keys = keys.into_iter();
+        let mut level_children = children.into_iter();
+        let is_leaf = level_children.len() == 0;
+        keys = Vec::with_capacity(count - 1);
+        children = Vec::with_capacity(count);
+        for index in 0..count {
+            let size = per + usize::from(index < extra);
+            let node_keys: Vec<T> = level_keys.by_ref().take(size).collect();
+            let node_children: Vec<ArcIn<Node<T, A, M>, M>> = match is_leaf {
+                true => Vec::new(),
+                false => level_children.by_ref().take(size + 1).collect(),
+            };
+            children.push(arc_in(node_in(alloc, node_keys, node_children), alloc));
+            if index + 1 < count {
+                keys.push(level_keys.next().unwrap());
+            }
+        }
+    }
+}
+
+// A node holding `keys` and `children`, with no spare room, in `alloc`.
+fn node_in<T: Ord, A: Aggregate<T>, M: Allocator + Clone>(alloc: &M, keys: Vec<T>, children: Vec<ArcIn<Node<T, A, M>, M>>) -> Node<T, A, M> {
+    let mut node_keys = vec_in(keys.len(), alloc);
+    node_keys.extend(keys);
+    let mut node_children = vec_in(children.len(), alloc);
+    node_children.extend(children);
+    Node::from_parts(node_keys, node_children)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::test::check_node;
+    use crate::{BTree, Node};
+
+    fn count_nodes(node: &Node<u32>) -> usize {
+        1 + node.children.iter().map(|child| count_nodes(child)).sum::<usize>()
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut tree = BTree::new(4);
+        for key in 0..2000u32 {
+            tree.insert(key);
+        }
+        for key in (0..2000u32).filter(|key| key % 4 != 0) {
+            tree.delete(key);
+        }
+        let before = count_nodes(&tree.root);
+
+        tree.rebuild(0.5);
+        check_node(&tree.root, &tree.props, true);
+        assert_eq!(tree.iter_snapshot().collect::<Vec<_>>(), (0..2000).step_by(4).collect::<Vec<u32>>());
+
+        tree.shrink_to_fit();
+        check_node(&tree.root, &tree.props, true);
+        let packed = count_nodes(&tree.root);
+        // 500 keys at up to 7 per node and one between each pair of leaves
+        assert!(packed < before && packed <= 500 / 7 + 2);
+        assert!(tree.root.keys.capacity() == tree.root.keys.len());
+        assert!(tree.search(1996) && !tree.search(1997));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let set: BTreeSet<u32> = (0..5000).map(|key| key * 3).collect();
+        let mut tree = BTree::from(set.clone());
+        check_node(&tree.root, &tree.props, true);
+        assert_eq!(tree.len(), 5000);
+        assert!(tree.iter_snapshot().eq(set.iter().copied()));
+        tree.insert(3);
+        tree.insert(4);
+        check_node(&tree.root, &tree.props, true);
+
+        let mut expected = set.clone();
+        expected.insert(4);
+        assert_eq!(BTreeSet::from(tree), expected);
+        let mut tree = BTree::from(set);
+        tree.insert(3);
+        let keys: Vec<u32> = tree.into();
+        assert_eq!((keys.len(), &keys[..4]), (5001, &[0, 3, 3, 6][..]));
+
+        for len in [0, 1, 15, 16, 17, 300] {
+            let tree = BTree::from_sorted_vec((0..len).collect::<Vec<u32>>());
+            check_node(&tree.root, &tree.props, true);
+            assert!(tree.iter_snapshot().eq(0..len));
+        }
+    }
+}
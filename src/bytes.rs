@@ -0,0 +1,63 @@
+//! Prefix scans over byte-string keys, for dictionaries and routing tables.
+//!
+//! `BTree` keys are `Copy`, so byte strings go in borrowed: `&[u8]`, `&str`
+//! or anything else that is `AsRef<[u8]>` and orders the way its bytes do,
+//! typically pointing into an arena or interned data that outlives the tree.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{BTree, SnapshotIter};
+
+/// The smallest byte string greater than every string starting with
+/// `prefix`, or `None` if there is none (`prefix` is empty or all `0xff`).
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&byte| byte != 0xff)?;
+    let mut bound = prefix[..=last].to_vec();
+    bound[last] += 1;
+    Some(bound)
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default + AsRef<[u8]>,
+{
+    /// The keys starting with `prefix`, in order: everything from the first
+    /// key not below `prefix` up to `prefix_upper_bound(prefix)`.
+    pub fn iter_prefix(&self, prefix: &[u8]) -> impl Iterator<Item = T> {
+        let end = prefix_upper_bound(prefix);
+        SnapshotIter::seek(Arc::clone(&self.root), |key: &T| key.as_ref() < prefix)
+            .take_while(move |key| end.as_ref().is_none_or(|end| key.as_ref() < end.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::prefix_upper_bound;
+    use crate::BTree;
+
+    #[test]
+    fn test_iter_prefix() {
+        assert_eq!(prefix_upper_bound(b"foo"), Some(b"fop".to_vec()));
+        assert_eq!(prefix_upper_bound(b"a\xff\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_upper_bound(b"\xff"), None);
+        assert_eq!(prefix_upper_bound(b""), None);
+
+        let words = ["fo", "foo", "foo/bar", "foobar", "fop", "fo\u{ff}", "bar", "zap", "foo"];
+        let mut tree = BTree::new(2);
+        for word in words {
+            tree.insert(word);
+        }
+        assert_eq!(tree.iter_prefix(b"foo").collect::<Vec<_>>(), ["foo", "foo", "foo/bar", "foobar"]);
+        assert_eq!(tree.iter_prefix(b"fo").count(), 7);
+        assert_eq!(tree.iter_prefix(b"").count(), words.len());
+        assert_eq!(tree.iter_prefix(b"q").count(), 0);
+
+        let mut routes: BTree<&[u8]> = BTree::new(3);
+        for route in [&b"\x0a\x00"[..], b"\x0a\xff", b"\x0a\xff\xff", b"\x0b", b"\x09\xff"] {
+            routes.insert(route);
+        }
+        assert_eq!(routes.iter_prefix(b"\x0a\xff").collect::<Vec<_>>(), [&b"\x0a\xff"[..], b"\x0a\xff\xff"]);
+    }
+}
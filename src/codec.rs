@@ -0,0 +1,268 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Turns keys into bytes for the on-disk formats.
+///
+/// Implementations must be order preserving: comparing two encodings as byte
+/// strings gives the same answer as comparing the keys. The formats keep
+/// track of the length of every encoding, so from an intact file `decode`
+/// gets exactly the bytes `encode` produced; from a damaged or foreign one
+/// it may get anything, and returns `None` for bytes no key encodes to.
+pub trait KeyCodec<T> {
+    /// Recorded in file headers, so a file is never read back with another
+    /// codec. Built-in codecs use ids below 128.
+    const ID: u8;
+    /// `Some(n)` if every encoding is exactly `n` bytes; lets formats drop
+    /// the per-key lengths.
+    const FIXED_WIDTH: Option<usize>;
+    /// For variable-width codecs whose encodings say how long they are:
+    /// the length of the encoding at the start of a byte string. Lets the
+    /// disk format drop the per-key lengths, as `FIXED_WIDTH` does.
+    const DELIMITED_LEN: Option<fn(&[u8]) -> usize> = None;
+
+    fn encode(key: &T, out: &mut Vec<u8>);
+    fn decode(bytes: &[u8]) -> Option<T>;
+}
+
+/// The built-in order-preserving codec: big-endian integers (signed ones with
+/// the sign bit flipped), UTF-8 strings and raw byte strings.
+pub struct Ordered;
+
+macro_rules! unsigned_codec {
+    ($($t:ty => $id:expr),* $(,)?) => {
+        $(
+            impl KeyCodec<$t> for Ordered {
+                const ID: u8 = $id;
+                const FIXED_WIDTH: Option<usize> = Some(core::mem::size_of::<$t>());
+
+                fn encode(key: &$t, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&key.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<$t> {
+                    Some(<$t>::from_be_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_codec {
+    ($($t:ty as $u:ty => $id:expr),* $(,)?) => {
+        $(
+            impl KeyCodec<$t> for Ordered {
+                const ID: u8 = $id;
+                const FIXED_WIDTH: Option<usize> = Some(core::mem::size_of::<$t>());
+
+                fn encode(key: &$t, out: &mut Vec<u8>) {
+                    // Flipping the sign bit moves negatives below positives.
+                    let flipped = (*key as $u) ^ (1 << (<$u>::BITS - 1));
+                    out.extend_from_slice(&flipped.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<$t> {
+                    let flipped = <$u>::from_be_bytes(bytes.try_into().ok()?);
+                    Some((flipped ^ (1 << (<$u>::BITS - 1))) as $t)
+                }
+            }
+        )*
+    };
+}
+
+unsigned_codec! {
+    u8 => 1, u16 => 2, u32 => 3, u64 => 4, u128 => 5,
+}
+
+signed_codec! {
+    i8 as u8 => 6, i16 as u16 => 7, i32 as u32 => 8, i64 as u64 => 9, i128 as u128 => 10,
+}
+
+impl KeyCodec<String> for Ordered {
+    const ID: u8 = 11;
+    const FIXED_WIDTH: Option<usize> = None;
+
+    // UTF-8 byte order is code point order, which is how `String` compares.
+    fn encode(key: &String, out: &mut Vec<u8>) {
+        out.extend_from_slice(key.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<String> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl KeyCodec<Vec<u8>> for Ordered {
+    const ID: u8 = 12;
+    const FIXED_WIDTH: Option<usize> = None;
+
+    fn encode(key: &Vec<u8>, out: &mut Vec<u8>) {
+        out.extend_from_slice(key);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Vec<u8>> {
+        Some(bytes.to_vec())
+    }
+}
+
+/// Order-preserving variable-length integers: a tag byte giving the sign
+/// and the number of bytes that follow, then the integer's low bytes big
+/// endian. Small keys take a byte or two instead of the full width, so more
+/// of them fit in a disk page.
+///
+/// Unsigned integers are tagged with their length, so shorter encodings are
+/// smaller numbers. Signed ones count tags up from `width + 1` for zero and
+/// positives, and down from `width` for negatives by the length of `!key`,
+/// which keeps large negatives lowest.
+pub struct Varint;
+
+macro_rules! unsigned_varint {
+    ($($t:ty => $id:expr),* $(,)?) => {
+        $(
+            impl KeyCodec<$t> for Varint {
+                const ID: u8 = $id;
+                const FIXED_WIDTH: Option<usize> = None;
+                const DELIMITED_LEN: Option<fn(&[u8]) -> usize> =
+                    Some(|bytes| 1 + bytes.first().map_or(0, |&tag| tag as usize));
+
+                fn encode(key: &$t, out: &mut Vec<u8>) {
+                    let len = (<$t>::BITS - key.leading_zeros()).div_ceil(8) as usize;
+                    out.push(len as u8);
+                    out.extend_from_slice(&key.to_be_bytes()[core::mem::size_of::<$t>() - len..]);
+                }
+
+                fn decode(bytes: &[u8]) -> Option<$t> {
+                    let (&len, rest) = bytes.split_first()?;
+                    if len as usize > core::mem::size_of::<$t>() || rest.len() != len as usize {
+                        return None;
+                    }
+                    Some(rest.iter().fold(0, |value, &byte| value << 8 | byte as $t))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_varint {
+    ($($t:ty as $u:ty => $id:expr),* $(,)?) => {
+        $(
+            impl KeyCodec<$t> for Varint {
+                const ID: u8 = $id;
+                const FIXED_WIDTH: Option<usize> = None;
+                const DELIMITED_LEN: Option<fn(&[u8]) -> usize> = Some(|bytes| {
+                    const WIDTH: usize = core::mem::size_of::<$t>();
+                    match bytes.first().map_or(WIDTH + 1, |&tag| tag as usize) {
+                        tag if tag > WIDTH => tag - WIDTH,
+                        tag => 1 + WIDTH - tag,
+                    }
+                });
+
+                fn encode(key: &$t, out: &mut Vec<u8>) {
+                    const WIDTH: usize = core::mem::size_of::<$t>();
+                    // A negative's low bytes, past the leading all-ones
+                    // bytes, are the complement of `!key`'s.
+                    let magnitude = (if *key < 0 { !*key } else { *key }) as $u;
+                    let len = (<$u>::BITS - magnitude.leading_zeros()).div_ceil(8) as usize;
+                    out.push((if *key < 0 { WIDTH - len } else { WIDTH + 1 + len }) as u8);
+                    out.extend_from_slice(&key.to_be_bytes()[WIDTH - len..]);
+                }
+
+                fn decode(bytes: &[u8]) -> Option<$t> {
+                    const WIDTH: usize = core::mem::size_of::<$t>();
+                    let (&tag, rest) = bytes.split_first()?;
+                    let negative = tag as usize <= WIDTH;
+                    let len = if negative { WIDTH - tag as usize } else { tag as usize - WIDTH - 1 };
+                    if len > WIDTH || rest.len() != len {
+                        return None;
+                    }
+                    let start: $u = if negative { !0 } else { 0 };
+                    Some(rest.iter().fold(start, |value, &byte| value << 8 | byte as $u) as $t)
+                }
+            }
+        )*
+    };
+}
+
+unsigned_varint! {
+    u16 => 15, u32 => 16, u64 => 17, u128 => 18,
+}
+
+signed_varint! {
+    i16 as u16 => 19, i32 as u32 => 20, i64 as u64 => 21, i128 as u128 => 22,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KeyCodec, Ordered, Varint};
+
+    fn encoded<T>(key: &T) -> Vec<u8>
+    where
+        Ordered: KeyCodec<T>,
+    {
+        let mut out = Vec::new();
+        Ordered::encode(key, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_order_preserving() {
+        let ints = [i32::MIN, -70_000, -1, 0, 1, 255, 256, i32::MAX];
+        for pair in ints.windows(2) {
+            assert!(encoded(&pair[0]) < encoded(&pair[1]));
+        }
+        for key in ints {
+            assert_eq!(<Ordered as KeyCodec<i32>>::decode(&encoded(&key)), Some(key));
+        }
+        assert!(encoded(&1u64) < encoded(&256u64));
+
+        let words = ["", "a", "ab", "b", "é", "中"].map(String::from);
+        for pair in words.windows(2) {
+            assert!(encoded(&pair[0]) < encoded(&pair[1]));
+        }
+        assert_eq!(<Ordered as KeyCodec<String>>::decode(&encoded(&words[5])).as_ref(), Some(&words[5]));
+
+        // bytes no key encodes to, as a damaged file might hold
+        assert_eq!(<Ordered as KeyCodec<i32>>::decode(&[0, 1, 2]), None);
+        assert_eq!(<Ordered as KeyCodec<String>>::decode(&[b'a', 0xff]), None);
+    }
+
+    #[test]
+    fn test_varint() {
+        fn varint<T>(key: T) -> Vec<u8>
+        where
+            Varint: KeyCodec<T>,
+        {
+            let mut out = Vec::new();
+            Varint::encode(&key, &mut out);
+            let delimited_len = <Varint as KeyCodec<T>>::DELIMITED_LEN.unwrap();
+            out.extend_from_slice(b"trailing");
+            out.truncate(delimited_len(&out));
+            out
+        }
+
+        let ints = [i64::MIN, -1 << 40, -70_000, -256, -255, -1, 0, 1, 255, 256, 70_000, i64::MAX];
+        for pair in ints.windows(2) {
+            assert!(varint(pair[0]) < varint(pair[1]), "{pair:?}");
+        }
+        for key in ints {
+            assert_eq!(<Varint as KeyCodec<i64>>::decode(&varint(key)), Some(key));
+        }
+        assert_eq!(varint(0i64).len(), 1);
+        assert_eq!(varint(-1i64).len(), 1);
+        assert_eq!(varint(200i64).len(), 2);
+
+        let unsigned = [0u32, 1, 255, 256, 65_535, 65_536, u32::MAX];
+        for pair in unsigned.windows(2) {
+            assert!(varint(pair[0]) < varint(pair[1]), "{pair:?}");
+        }
+        for key in unsigned {
+            assert_eq!(<Varint as KeyCodec<u32>>::decode(&varint(key)), Some(key));
+        }
+        assert_eq!(varint(u128::MAX).len(), 17);
+
+        // empty, truncated, too long for the type, and past any tag's length
+        for bytes in [&[][..], &[2, 1], &[5, 1, 2, 3, 4, 5], &[2, 1, 2, 3]] {
+            assert_eq!(<Varint as KeyCodec<u32>>::decode(bytes), None);
+        }
+        assert_eq!(<Varint as KeyCodec<i64>>::decode(&[18]), None);
+    }
+}
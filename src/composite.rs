@@ -0,0 +1,70 @@
+//! Keys made of several parts, such as `(user, timestamp)`, scanned by
+//! their leading part. Keeping `(indexed value, primary key)` pairs in a
+//! tree this way makes a simple secondary index.
+
+use alloc::sync::Arc;
+use core::fmt::Debug;
+
+use crate::{BTree, SnapshotIter};
+
+/// A key that sorts by a leading part first, as tuples do.
+pub trait Composite {
+    type Prefix: Ord;
+
+    fn prefix(&self) -> &Self::Prefix;
+}
+
+impl<A: Ord, B> Composite for (A, B) {
+    type Prefix = A;
+
+    fn prefix(&self) -> &A {
+        &self.0
+    }
+}
+
+impl<A: Ord, B, C> Composite for (A, B, C) {
+    type Prefix = A;
+
+    fn prefix(&self) -> &A {
+        &self.0
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default + Composite,
+{
+    /// The keys whose leading part is `prefix`, in order. Goes straight to
+    /// the first of them, so costs a descent plus the keys returned.
+    pub fn range_prefix(&self, prefix: T::Prefix) -> impl Iterator<Item = T> {
+        SnapshotIter::seek(Arc::clone(&self.root), |key: &T| *key.prefix() < prefix)
+            .take_while(move |key| *key.prefix() == prefix)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BTree;
+
+    #[test]
+    fn test_range_prefix() {
+        let mut tree = BTree::new(2);
+        for user in 0..50u64 {
+            for time in 0..(user % 7) {
+                tree.insert((user * 3 % 50, time * 10));
+            }
+        }
+        for user in 0..50u64 {
+            let times: Vec<u64> = tree.range_prefix(user).map(|(_, time)| time).collect();
+            let expected: Vec<u64> = (0..(user * 17 % 50 % 7)).map(|time| time * 10).collect();
+            assert_eq!(times, expected, "user {user}");
+        }
+        assert_eq!(tree.range_prefix(99).count(), 0);
+
+        let mut index = BTree::new(3);
+        for (id, name) in [(1u32, "ann"), (2, "bob"), (3, "ann"), (4, "cy")] {
+            index.insert((name, id, ()));
+        }
+        assert_eq!(index.range_prefix("ann").map(|(_, id, _)| id).collect::<Vec<_>>(), [1, 3]);
+    }
+}
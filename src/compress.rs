@@ -0,0 +1,131 @@
+//! Compression of the leaf pages of a `DiskBTree`.
+//!
+//! Each page records the codec it was written with, so a file can be read
+//! whatever a page ended up as. A leaf is stored compressed when that is
+//! smaller, and a tree that compresses lets a leaf hold up to twice the keys
+//! that fit in a page uncompressed, so long as they compress into one: on
+//! keys that share a lot, such as log lines or paths, that comes near to
+//! halving the pages the leaves take.
+
+// Built without any codec, only `Compression::None` is left to match.
+#![cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables, unreachable_code))]
+
+use std::io;
+
+/// How a `DiskBTree` compresses its leaves, chosen when the file is created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// LZ4: fast, for trees that are read and written in equal measure.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Zstandard at the given level, 1 to 22: smaller than LZ4 for more
+    /// time spent writing.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+// The codec byte of a page and in the file header.
+pub(crate) const CODEC_NONE: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+const CODEC_ZSTD: u8 = 2;
+
+impl Compression {
+    pub(crate) fn codec(self) -> u8 {
+        match self {
+            Compression::None => CODEC_NONE,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => CODEC_LZ4,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => CODEC_ZSTD,
+        }
+    }
+
+    // What a file's header says it compresses with. Only the codec is
+    // recorded: new pages use the default level.
+    pub(crate) fn from_codec(codec: u8) -> io::Result<Compression> {
+        match codec {
+            CODEC_NONE => Ok(Compression::None),
+            #[cfg(feature = "lz4")]
+            CODEC_LZ4 => Ok(Compression::Lz4),
+            #[cfg(feature = "zstd")]
+            CODEC_ZSTD => Ok(Compression::Zstd(zstd::DEFAULT_COMPRESSION_LEVEL)),
+            _ => Err(unsupported(codec)),
+        }
+    }
+
+    /// `None` if compressing doesn't make `raw` smaller.
+    pub(crate) fn compress(self, raw: &[u8]) -> Option<Vec<u8>> {
+        let compressed: Vec<u8> = match self {
+            Compression::None => return None,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::compress(raw),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => zstd::bulk::compress(raw, level).ok()?,
+        };
+        (compressed.len() < raw.len()).then_some(compressed)
+    }
+}
+
+/// Undo `compress` by the codec a page was written with, given how long
+/// the page was before.
+pub(crate) fn decompress(codec: u8, compressed: &[u8], raw_len: usize) -> io::Result<Vec<u8>> {
+    let raw: Vec<u8> = match codec {
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => lz4_flex::block::decompress(compressed, raw_len).map_err(io::Error::other)?,
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD => zstd::bulk::decompress(compressed, raw_len)?,
+        _ => return Err(unsupported(codec)),
+    };
+    if raw.len() != raw_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "page decompressed to the wrong length"));
+    }
+    Ok(raw)
+}
+
+fn unsupported(codec: u8) -> io::Error {
+    let message = match codec {
+        CODEC_LZ4 => "file is compressed with LZ4, which needs the lz4 feature",
+        CODEC_ZSTD => "file is compressed with Zstandard, which needs the zstd feature",
+        _ => "unknown page compression",
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_compressed_leaves() {
+        use super::Compression;
+        use crate::disk::{DiskBTree, DiskOptions};
+
+        let key = |key: u32| format!("users/{:05}/settings", (key * 7919) % 5000);
+        let mut pages = Vec::new();
+        for compression in [Compression::None, Compression::Lz4] {
+            let path = std::env::temp_dir().join(format!("disk-btree-{compression:?}-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let options = || DiskOptions { branch_factor: 48, compression, ..DiskOptions::default() };
+            {
+                let mut tree: DiskBTree<String> = DiskBTree::open(&path, options()).unwrap();
+                for n in 0..5000 {
+                    tree.insert(key(n)).unwrap();
+                }
+                pages.push(tree.buffer_pool().page_count());
+                for n in (0..5000).step_by(3) {
+                    assert!(tree.delete(key(n)).unwrap());
+                }
+            }
+            // the file says how its pages are compressed, whatever is asked for
+            let mut tree: DiskBTree<String> = DiskBTree::open(&path, DiskOptions::default()).unwrap();
+            assert_eq!(tree.len(), 3333);
+            for n in 0..5000 {
+                assert_eq!(tree.search(key(n)).unwrap(), n % 3 != 0, "{}", key(n));
+            }
+            drop(tree);
+            std::fs::remove_file(&path).unwrap();
+        }
+        assert!(pages[1] * 3 < pages[0], "{pages:?}");
+    }
+}
@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard};
+use parking_lot::{RawRwLock, RwLock};
+
+type NodeRef<T> = Arc<RwLock<Node<T>>>;
+type ReadGuard<T> = ArcRwLockReadGuard<RawRwLock, Node<T>>;
+type WriteGuard<T> = ArcRwLockWriteGuard<RawRwLock, Node<T>>;
+
+struct Node<T> {
+    keys: Vec<T>,
+    children: Vec<NodeRef<T>>,
+}
+
+impl<T> Node<T> {
+    fn new_ref(keys: Vec<T>, children: Vec<NodeRef<T>>) -> NodeRef<T> {
+        Arc::new(RwLock::new(Node { keys, children }))
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// A B-tree that can be shared between threads, with a lock per node.
+///
+/// Operations descend hand over hand: a node is locked before its parent is
+/// released. Inserts split full nodes and deletes top up minimal nodes on the
+/// way down, so a writer never has to go back up and holds at most a node,
+/// its child and the child's sibling at once. Readers and writers working in
+/// different subtrees don't wait for each other.
+pub struct ConcurrentBTree<T> {
+    // Locked while the root itself might be replaced.
+    root: RwLock<NodeRef<T>>,
+    max_keys: usize,
+    min_keys: usize,
+    len: AtomicUsize,
+}
+
+impl<T: Ord> ConcurrentBTree<T> {
+    pub fn new(branch_factor: usize) -> Self {
+        let degree = 2 * branch_factor;
+        ConcurrentBTree {
+            root: RwLock::new(Node::new_ref(Vec::new(), Vec::new())),
+            max_keys: degree - 1,
+            min_keys: (degree - 1) / 2,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        let mut node: ReadGuard<T> = self.root.read().read_arc();
+        loop {
+            let index = node.keys.partition_point(|k| *k < key);
+            if index < node.keys.len() && node.keys[index] == key {
+                return true;
+            }
+            if node.is_leaf() {
+                return false;
+            }
+            node = node.children[index].read_arc();
+        }
+    }
+
+    pub fn insert(&self, key: T) {
+        let mut root = self.root.write();
+        let mut node = root.write_arc();
+        if node.keys.len() == self.max_keys {
+            let new_root = Node::new_ref(Vec::new(), vec![root.clone()]);
+            let mut parent = new_root.write_arc();
+            self.split_child(&mut parent, 0, &mut node);
+            *root = new_root;
+            node = parent;
+        }
+        drop(root);
+
+        loop {
+            let mut index = node.keys.partition_point(|k| *k < key);
+            if node.is_leaf() {
+                node.keys.insert(index, key);
+                break;
+            }
+            let mut child = node.children[index].write_arc();
+            if child.keys.len() == self.max_keys {
+                self.split_child(&mut node, index, &mut child);
+                if node.keys[index] < key {
+                    index += 1;
+                    child = node.children[index].write_arc();
+                }
+            }
+            node = child;
+        }
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Move the upper half of the full `child` into a new sibling, lifting its
+    // middle key into `parent`.
+    fn split_child(&self, parent: &mut Node<T>, index: usize, child: &mut Node<T>) {
+        let mid = self.max_keys / 2;
+        let right_keys = child.keys.split_off(mid + 1);
+        let middle = child.keys.pop().unwrap();
+        let right_children = if child.is_leaf() {
+            Vec::new()
+        } else {
+            child.children.split_off(mid + 1)
+        };
+        parent.keys.insert(index, middle);
+        parent.children.insert(index + 1, Node::new_ref(right_keys, right_children));
+    }
+
+    pub fn delete(&self, key: T) -> bool {
+        let mut root = Some(self.root.write());
+        let mut node = root.as_ref().unwrap().write_arc();
+        loop {
+            let index = node.keys.partition_point(|k| *k < key);
+            let found = index < node.keys.len() && node.keys[index] == key;
+            if node.is_leaf() {
+                if found {
+                    node.keys.remove(index);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                }
+                return found;
+            }
+
+            let child = if found {
+                let mut left = node.children[index].write_arc();
+                let mut right = node.children[index + 1].write_arc();
+                if left.keys.len() > self.min_keys {
+                    drop((right, root));
+                    node.keys[index] = self.pop_max(left);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    return true;
+                }
+                if right.keys.len() > self.min_keys {
+                    drop((left, root));
+                    node.keys[index] = self.pop_min(right);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    return true;
+                }
+                // Both children are minimal: merge them around the key and
+                // delete it from the merged node.
+                let separator = node.keys.remove(index);
+                node.children.remove(index + 1);
+                left.keys.push(separator);
+                left.keys.append(&mut right.keys);
+                left.children.append(&mut right.children);
+                left
+            } else {
+                self.lock_child_for_delete(&mut node, index)
+            };
+
+            if let Some(mut root) = root.take() {
+                if node.keys.is_empty() {
+                    *root = node.children[0].clone();
+                }
+            }
+            node = child;
+        }
+    }
+
+    fn pop_max(&self, mut node: WriteGuard<T>) -> T {
+        loop {
+            if node.is_leaf() {
+                return node.keys.pop().unwrap();
+            }
+            let last = node.children.len() - 1;
+            node = self.lock_child_for_delete(&mut node, last);
+        }
+    }
+
+    fn pop_min(&self, mut node: WriteGuard<T>) -> T {
+        loop {
+            if node.is_leaf() {
+                return node.keys.remove(0);
+            }
+            node = self.lock_child_for_delete(&mut node, 0);
+        }
+    }
+
+    // Lock the child at `index` for a descent that may remove a key from it,
+    // first giving it a key from a sibling or merging it with one if it only
+    // has the minimum. Returns the node to continue in.
+    fn lock_child_for_delete(&self, node: &mut Node<T>, index: usize) -> WriteGuard<T> {
+        let mut child = node.children[index].write_arc();
+        if child.keys.len() > self.min_keys {
+            return child;
+        }
+        if index > 0 {
+            let mut left = node.children[index - 1].write_arc();
+            if left.keys.len() > self.min_keys {
+                let borrowed = left.keys.pop().unwrap();
+                let separator = std::mem::replace(&mut node.keys[index - 1], borrowed);
+                child.keys.insert(0, separator);
+                if let Some(grandchild) = left.children.pop() {
+                    child.children.insert(0, grandchild);
+                }
+                return child;
+            }
+            let separator = node.keys.remove(index - 1);
+            node.children.remove(index);
+            left.keys.push(separator);
+            left.keys.append(&mut child.keys);
+            left.children.append(&mut child.children);
+            return left;
+        }
+        let mut right = node.children[index + 1].write_arc();
+        if right.keys.len() > self.min_keys {
+            let borrowed = right.keys.remove(0);
+            let separator = std::mem::replace(&mut node.keys[index], borrowed);
+            child.keys.push(separator);
+            if !right.is_leaf() {
+                child.children.push(right.children.remove(0));
+            }
+            return child;
+        }
+        let separator = node.keys.remove(index);
+        node.children.remove(index + 1);
+        child.keys.push(separator);
+        child.keys.append(&mut right.keys);
+        child.children.append(&mut right.children);
+        child
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConcurrentBTree;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_insert_delete() {
+        let tree = Arc::new(ConcurrentBTree::new(2));
+        let writers: Vec<_> = (0..4u64)
+            .map(|t| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for key in (t..2000).step_by(4) {
+                        tree.insert(key);
+                    }
+                    for key in (t..2000).step_by(4).filter(|key| key % 2 == 0) {
+                        assert!(tree.delete(key));
+                    }
+                })
+            })
+            .collect();
+        let reader = {
+            let tree = Arc::clone(&tree);
+            // keys 1, 3, 5, ... are never deleted, so once seen they stay
+            thread::spawn(move || {
+                for key in (1..2000u64).step_by(2) {
+                    while !tree.search(key) {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+        for handle in writers {
+            handle.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        assert_eq!(tree.len(), 1000);
+        for key in 0..2000u64 {
+            assert_eq!(tree.search(key), key % 2 == 1);
+        }
+        for key in (1..2000u64).step_by(2) {
+            assert!(tree.delete(key));
+        }
+        assert!(tree.is_empty());
+        assert!(!tree.delete(1));
+    }
+}
@@ -0,0 +1,190 @@
+//! Comparing two trees key by key, for syncing one with the other, and
+//! node by node, for telling whether two ways of building a tree end up
+//! with the same shape.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::allocator::{Allocator, ArcIn};
+use crate::{Aggregate, BTree, FrozenBTree, Node, SnapshotIter};
+
+/// The keys two trees don't have in common. See `BTree::diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeDiff<T> {
+    /// In order, with a key held more times here than there listed as
+    /// many times more.
+    pub only_in_self: Vec<T>,
+    pub only_in_other: Vec<T>,
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// The keys in only one of the two trees, found by walking both in
+    /// order together. Subtrees the two share, as a tree shares them with
+    /// its snapshots, are skipped without looking at their keys, and where
+    /// both trees split the same keys into the same children they are
+    /// compared child by child. With the `merkle` feature, subtrees whose
+    /// hashes `root_hash` has worked out are also skipped if the hashes
+    /// match.
+    pub fn diff(&self, other: &BTree<T, A, M>) -> TreeDiff<T> {
+        diff_roots(&self.root, &other.root)
+    }
+
+    /// `diff` against a snapshot, typically one of this tree: what changed
+    /// since it was taken, found in time proportional to the nodes changed.
+    pub fn diff_snapshot(&self, snapshot: &FrozenBTree<T, A, M>) -> TreeDiff<T> {
+        diff_roots(&self.root, &snapshot.root)
+    }
+
+    /// Whether the two trees hold the same keys in the same nodes, not only
+    /// the same keys in order as `==` asks: `from_sorted_vec` and inserting
+    /// the same keys one by one give equal trees that are rarely
+    /// structurally equal. Branch factors aren't compared, only the nodes.
+    pub fn structurally_equal(&self, other: &BTree<T, A, M>) -> bool {
+        same_nodes(&self.root, &other.root)
+    }
+}
+
+/// Trees are equal when they hold the same keys, each as many times,
+/// whatever their nodes; see `structurally_equal` for the nodes too.
+impl<T, A, M> PartialEq for BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter_snapshot().eq(other.iter_snapshot())
+    }
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> Eq for BTree<T, A, M> where T: Ord + Copy + Debug + Default {}
+
+fn same_nodes<T: Ord, A: Aggregate<T>, M: Allocator + Clone>(ours: &ArcIn<Node<T, A, M>, M>, theirs: &ArcIn<Node<T, A, M>, M>) -> bool {
+    Arc::ptr_eq(ours, theirs)
+        || ours.keys == theirs.keys
+            && ours.children.len() == theirs.children.len()
+            && ours.children.iter().zip(&theirs.children).all(|(ours, theirs)| same_nodes(ours, theirs))
+}
+
+pub(crate) fn diff_roots<T: Ord + Copy + Default, A: Aggregate<T>, M: Allocator + Clone>(ours: &ArcIn<Node<T, A, M>, M>, theirs: &ArcIn<Node<T, A, M>, M>) -> TreeDiff<T> {
+    let mut diff = TreeDiff::default();
+    diff_nodes(ours, theirs, &mut diff);
+    // A key equal to a separator can sit on either side of it, so
+    // comparing child by child can list one copy on each side.
+    let mut merged = TreeDiff::default();
+    merge_diff(diff.only_in_self.into_iter(), diff.only_in_other.into_iter(), &mut merged);
+    merged
+}
+
+fn diff_nodes<T: Ord + Copy, A: Aggregate<T>, M: Allocator + Clone>(ours: &ArcIn<Node<T, A, M>, M>, theirs: &ArcIn<Node<T, A, M>, M>, diff: &mut TreeDiff<T>) {
+    if Arc::ptr_eq(ours, theirs) {
+        return;
+    }
+    #[cfg(feature = "merkle")]
+    if ours.hash.get().is_some_and(|hash| theirs.hash.get() == Some(hash)) {
+        return;
+    }
+    if !ours.is_leaf() && ours.keys == theirs.keys && ours.children.len() == theirs.children.len() {
+        for (ours, theirs) in ours.children.iter().zip(&theirs.children) {
+            diff_nodes(ours, theirs, diff);
+        }
+        return;
+    }
+    let ours = SnapshotIter::at(Arc::clone(ours), 0);
+    let theirs = SnapshotIter::at(Arc::clone(theirs), 0);
+    merge_diff(ours, theirs, diff);
+}
+
+// Walk two sorted runs together, adding what only one of them has.
+fn merge_diff<T: Ord>(mut ours: impl Iterator<Item = T>, mut theirs: impl Iterator<Item = T>, diff: &mut TreeDiff<T>) {
+    let (mut a, mut b) = (ours.next(), theirs.next());
+    loop {
+        match (a.take(), b.take()) {
+            (Some(x), Some(y)) if x < y => {
+                diff.only_in_self.push(x);
+                (a, b) = (ours.next(), Some(y));
+            }
+            (Some(x), Some(y)) if y < x => {
+                diff.only_in_other.push(y);
+                (a, b) = (Some(x), theirs.next());
+            }
+            (Some(_), Some(_)) => (a, b) = (ours.next(), theirs.next()),
+            (Some(x), None) => {
+                diff.only_in_self.push(x);
+                diff.only_in_self.extend(ours);
+                return;
+            }
+            (None, Some(y)) => {
+                diff.only_in_other.push(y);
+                diff.only_in_other.extend(theirs);
+                return;
+            }
+            (None, None) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TreeDiff;
+    use crate::BTree;
+
+    #[test]
+    fn test_diff() {
+        let mut ours = BTree::new(2);
+        for key in 0..3000u32 {
+            ours.insert((key * 7919) % 3000);
+        }
+        let snapshot = ours.snapshot();
+        let mut theirs = BTree::new(3);
+        for key in (0..3000u32).rev().filter(|key| key % 100 != 7) {
+            theirs.insert(key);
+        }
+        theirs.insert(42);
+        theirs.insert(9999);
+        let diff = ours.diff(&theirs);
+        assert_eq!(diff.only_in_self, (7..3000).step_by(100).collect::<Vec<_>>());
+        assert_eq!(diff.only_in_other, [42, 9999]);
+        assert_eq!(theirs.diff(&ours).only_in_self, diff.only_in_other);
+
+        ours.delete(100);
+        ours.insert(100);
+        ours.insert(100);
+        ours.delete(2999);
+        let changes = TreeDiff { only_in_self: vec![100], only_in_other: vec![2999] };
+        assert_eq!(ours.diff_snapshot(&snapshot), changes);
+        assert_eq!(ours.diff(&BTree::from_sorted_vec(snapshot.iter().collect())), changes);
+        assert_eq!(BTree::<u32>::new(2).diff(&BTree::new(5)), TreeDiff::default());
+    }
+
+    #[test]
+    fn test_structurally_equal() {
+        let keys: Vec<u32> = (0..500).collect();
+        let bulk = BTree::from_sorted_vec(keys.clone());
+        let mut inserted = BTree::new(bulk.degree() / 2);
+        for &key in &keys {
+            inserted.insert(key);
+        }
+        assert!(bulk == inserted && !bulk.structurally_equal(&inserted));
+
+        // the same inserts in the same order give the same nodes
+        let mut again = BTree::new(bulk.degree() / 2);
+        for &key in &keys {
+            again.insert(key);
+        }
+        assert!(inserted.structurally_equal(&again));
+        let snapshot = again.snapshot();
+        again.delete(250);
+        assert!(!inserted.structurally_equal(&again) && inserted != again);
+        again.insert(250);
+        assert!(inserted == again);
+        assert!(BTree::from_sorted_vec(snapshot.iter().collect()).structurally_equal(&bulk));
+        assert!(BTree::<u32>::new(2).structurally_equal(&BTree::new(9)));
+    }
+}
@@ -0,0 +1,975 @@
+use std::ffi::OsString;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::buffer_pool::BufferPool;
+use crate::codec::{KeyCodec, Ordered};
+use crate::compress::{decompress, Compression, CODEC_NONE};
+use crate::pager::{Page, PageId, Pager, PAGE_PAYLOAD};
+use crate::wal::Wal;
+
+pub(crate) const MAGIC: &[u8; 4] = b"BTDK";
+const VERSION: u32 = 5;
+
+const HEADER_PAGE: PageId = 0;
+const NO_PAGE: PageId = u64::MAX;
+
+const KIND_FREE: u8 = 0;
+const KIND_LEAF: u8 = 1;
+const KIND_INTERNAL: u8 = 2;
+
+// kind (1) + codec (1) + key count (2)
+const NODE_HEADER: usize = 4;
+// kind (1) + codec (1) + key count onwards
+const BODY: usize = PAGE_PAYLOAD - 2;
+// kind (1) + codec (1) + length before compression (2) + after it (2)
+const COMPRESSED_HEADER: usize = 6;
+// length prefix of a variable-width key
+const KEY_LEN: usize = 2;
+
+pub struct DiskOptions {
+    /// Only used when creating a new file; an existing file keeps its own.
+    /// A full node must fit in a page, so the larger the branch factor the
+    /// shorter keys have to be: with 8-byte keys it can go up to 127, and
+    /// with `Varint` keys below 2^16, three bytes each, up to 184.
+    pub branch_factor: usize,
+    /// Number of pages the buffer pool keeps in memory.
+    pub cache_pages: usize,
+    /// Log every operation to `<path>-wal` before touching the data file,
+    /// so a crash can never leave a half-applied split or merge behind.
+    /// Costs one fsync per `insert`/`delete`.
+    pub wal: bool,
+    /// Only used when creating a new file, like `branch_factor`.
+    pub compression: Compression,
+    /// Encrypt every page of the file and its WAL with this key; see
+    /// `Pager`. A file created with a key can only be opened with it.
+    #[cfg(feature = "encryption")]
+    pub encryption_key: Option<[u8; 32]>,
+}
+
+impl Default for DiskOptions {
+    fn default() -> Self {
+        DiskOptions {
+            branch_factor: 64,
+            cache_pages: 256,
+            wal: false,
+            compression: Compression::None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+        }
+    }
+}
+
+struct DiskNode<T> {
+    keys: Vec<T>,
+    children: Vec<PageId>,
+}
+
+impl<T> DiskNode<T> {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    // After the kind and codec bytes comes the body, compressed or not: the
+    // key count, then keys packed back to back for fixed-width and
+    // self-delimiting codecs and with a length prefix otherwise, then child
+    // page ids.
+    fn decode<C: KeyCodec<T>>(page: &Page) -> io::Result<Self> {
+        let kind = page[0];
+        if kind != KIND_LEAF && kind != KIND_INTERNAL {
+            return Err(invalid_data("page does not hold a tree node"));
+        }
+        let decompressed;
+        let body = match page[1] {
+            CODEC_NONE => &page[2..PAGE_PAYLOAD],
+            codec => {
+                let raw_len = u16::from_le_bytes([page[2], page[3]]) as usize;
+                let len = u16::from_le_bytes([page[4], page[5]]) as usize;
+                let compressed = page[..PAGE_PAYLOAD]
+                    .get(COMPRESSED_HEADER..COMPRESSED_HEADER + len)
+                    .ok_or_else(|| invalid_data("compressed node runs past the end of the page"))?;
+                decompressed = decompress(codec, compressed, raw_len)?;
+                &decompressed[..]
+            }
+        };
+        let past_end = || invalid_data("node keys run past the end of the page");
+        let count = u16::from_le_bytes(body.get(..2).ok_or_else(past_end)?.try_into().unwrap()) as usize;
+        let mut offset = 2;
+        let mut keys = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = match (C::FIXED_WIDTH, C::DELIMITED_LEN) {
+                (Some(width), _) => width,
+                (None, Some(delimited_len)) => delimited_len(body.get(offset..).ok_or_else(past_end)?),
+                (None, None) => {
+                    offset += KEY_LEN;
+                    let len = body.get(offset - KEY_LEN..offset).ok_or_else(past_end)?;
+                    u16::from_le_bytes([len[0], len[1]]) as usize
+                }
+            };
+            let bytes = body.get(offset..offset + len).ok_or_else(past_end)?;
+            keys.push(C::decode(bytes).ok_or_else(|| invalid_data("node holds a key its codec can't decode"))?);
+            offset += len;
+        }
+        let mut children = Vec::new();
+        if kind == KIND_INTERNAL {
+            if offset + 8 * (count + 1) > body.len() {
+                return Err(invalid_data("node children run past the end of the page"));
+            }
+            children.reserve(count + 1);
+            for _ in 0..=count {
+                children.push(u64::from_le_bytes(body[offset..offset + 8].try_into().unwrap()));
+                offset += 8;
+            }
+        }
+        Ok(DiskNode { keys, children })
+    }
+
+    // The page image of the node, its leaves compressed if that makes them
+    // smaller, or `None` if the body is over `max_body` bytes or the node
+    // doesn't fit in a page.
+    fn encode<C: KeyCodec<T>>(&self, compression: Compression, max_body: usize) -> Option<Vec<u8>> {
+        self.encode_first::<C>(self.keys.len(), compression, max_body)
+    }
+
+    // `encode` with only the first `count` keys, of a leaf.
+    fn encode_first<C: KeyCodec<T>>(&self, count: usize, compression: Compression, max_body: usize) -> Option<Vec<u8>> {
+        let mut body = Vec::with_capacity(BODY);
+        body.extend_from_slice(&(count as u16).to_le_bytes());
+        for key in self.keys[..count].iter() {
+            if !prefixed::<T, C>() {
+                C::encode(key, &mut body);
+            } else {
+                let start = body.len();
+                body.extend_from_slice(&[0; KEY_LEN]);
+                C::encode(key, &mut body);
+                let len = (body.len() - start - KEY_LEN) as u16;
+                body[start..start + KEY_LEN].copy_from_slice(&len.to_le_bytes());
+            }
+        }
+        for child in self.children.iter() {
+            body.extend_from_slice(&child.to_le_bytes());
+        }
+        if body.len() > max_body {
+            return None;
+        }
+        let kind = if self.is_leaf() { KIND_LEAF } else { KIND_INTERNAL };
+        let compressed = match self.is_leaf() {
+            true => compression.compress(&body).filter(|compressed| COMPRESSED_HEADER + compressed.len() < 2 + body.len()),
+            false => None,
+        };
+        let mut bytes = Vec::with_capacity(PAGE_PAYLOAD);
+        match compressed {
+            Some(compressed) => {
+                bytes.extend_from_slice(&[kind, compression.codec()]);
+                bytes.extend_from_slice(&(body.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(&(compressed.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(&compressed);
+            }
+            None => {
+                bytes.extend_from_slice(&[kind, CODEC_NONE]);
+                bytes.extend_from_slice(&body);
+            }
+        }
+        (bytes.len() <= PAGE_PAYLOAD).then_some(bytes)
+    }
+}
+
+/// A B-tree stored in a page file, with keys encoded by a `KeyCodec`.
+///
+/// Every node occupies one page, page 0 holds the header. Pages are read and
+/// written through a `BufferPool`, so without a WAL nothing is guaranteed to
+/// be on disk until `flush` (or drop) returns. With `DiskOptions::wal` every
+/// `insert` and `delete` is durable once it returns.
+pub struct DiskBTree<T = u64, C = Ordered> {
+    pool: BufferPool,
+    degree: usize,
+    max_keys: usize,
+    min_keys: usize,
+    // Longest encoded key a full node has room for.
+    max_key_len: usize,
+    root: PageId,
+    len: u64,
+    free_head: PageId,
+    codec_id: u8,
+    compression: Compression,
+    // The longest body a leaf may have before it splits, with compression
+    // on; a leaf without it splits once it has `max_keys` keys.
+    max_leaf_body: usize,
+    // Keys taken out of a leaf to make it fit its page again, to put back
+    // once the operation is done.
+    spilled: Vec<T>,
+    _keys: PhantomData<(T, C)>,
+}
+
+impl<T, C> DiskBTree<T, C>
+where
+    T: Ord,
+    C: KeyCodec<T>,
+{
+    pub fn open<P: AsRef<Path>>(path: P, options: DiskOptions) -> io::Result<Self> {
+        #[cfg(feature = "encryption")]
+        let mut pager = match &options.encryption_key {
+            Some(key) => Pager::open_encrypted(&path, key)?,
+            None => Pager::open(&path)?,
+        };
+        #[cfg(not(feature = "encryption"))]
+        let mut pager = Pager::open(&path)?;
+        let wal_path = wal_path(path.as_ref());
+        // Recover even when the WAL is now off: the last run may have used it.
+        let wal = if options.wal || wal_path.exists() {
+            let mut wal = Wal::open(&wal_path)?;
+            wal.recover(&mut pager)?;
+            Some(wal)
+        } else {
+            None
+        };
+        let mut pool = match wal {
+            Some(wal) if options.wal => BufferPool::with_wal(pager, wal, options.cache_pages),
+            _ => {
+                if wal_path.exists() {
+                    std::fs::remove_file(&wal_path)?;
+                }
+                BufferPool::new(pager, options.cache_pages)
+            }
+        };
+        if pool.page_count() == 0 {
+            if options.branch_factor < 2 || max_key_len::<T, C>(2 * options.branch_factor).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "branch factor must be at least 2 and leave room in a page for the keys",
+                ));
+            }
+            let header = pool.allocate()?;
+            debug_assert_eq!(header, HEADER_PAGE);
+            let root = pool.allocate()?;
+            let mut tree = DiskBTree::with_degree(pool, 2 * options.branch_factor, root, 0, NO_PAGE, options.compression);
+            tree.write_node(root, &DiskNode { keys: Vec::new(), children: Vec::new() })?;
+            tree.commit()?;
+            return Ok(tree);
+        }
+
+        let header = pool.page(HEADER_PAGE)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid_data("not a disk b-tree file"));
+        }
+        if read_u32(header, 4) != VERSION {
+            return Err(invalid_data("unsupported disk b-tree version"));
+        }
+        let degree = read_u32(header, 8) as usize;
+        let root = read_u64(header, 12);
+        let len = read_u64(header, 20);
+        let free_head = read_u64(header, 28);
+        if header[36] != C::ID {
+            return Err(invalid_data("file was written with a different key codec"));
+        }
+        let compression = Compression::from_codec(header[37])?;
+        if degree < 4 || max_key_len::<T, C>(degree).is_none() {
+            return Err(invalid_data("corrupt header: bad degree"));
+        }
+        Ok(DiskBTree::with_degree(pool, degree, root, len, free_head, compression))
+    }
+
+    fn with_degree(
+        pool: BufferPool,
+        degree: usize,
+        root: PageId,
+        len: u64,
+        free_head: PageId,
+        compression: Compression,
+    ) -> Self {
+        let max_key_len = max_key_len::<T, C>(degree).unwrap();
+        // Up to twice a page, less enough that when a leaf splits in two
+        // each half fits in a page uncompressed.
+        let longest_key = max_key_len + if prefixed::<T, C>() { KEY_LEN } else { 0 };
+        let max_leaf_body = if compression == Compression::None {
+            BODY
+        } else {
+            (2 * BODY.saturating_sub(2 * longest_key + 8)).max(BODY)
+        };
+        DiskBTree {
+            pool,
+            degree,
+            max_keys: degree - 1,
+            min_keys: (degree - 1) / 2,
+            max_key_len,
+            root,
+            len,
+            free_head,
+            codec_id: C::ID,
+            compression,
+            max_leaf_body,
+            spilled: Vec::new(),
+            _keys: PhantomData,
+        }
+    }
+
+    /// Longest encoded key `insert` accepts.
+    pub fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    pub fn buffer_pool_mut(&mut self) -> &mut BufferPool {
+        &mut self.pool
+    }
+
+    /// Write all dirty pages and the header to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.pool.flush()
+    }
+
+    pub fn search(&mut self, key: T) -> io::Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// The stored key equal to `key`, for keys that carry more than what
+    /// they compare by.
+    pub fn get(&mut self, key: T) -> io::Result<Option<T>> {
+        let mut id = self.root;
+        loop {
+            let mut node = self.read_node(id)?;
+            let index = node.keys.partition_point(|k| *k < key);
+            if index < node.keys.len() && node.keys[index] == key {
+                return Ok(Some(node.keys.swap_remove(index)));
+            }
+            if node.is_leaf() {
+                return Ok(None);
+            }
+            id = node.children[index];
+        }
+    }
+
+    /// Call `visit` with every key in order, stopping at its first error.
+    /// Holds the nodes of one root-to-leaf path in memory at a time.
+    pub fn for_each(&mut self, mut visit: impl FnMut(T) -> io::Result<()>) -> io::Result<()> {
+        self.visit_node(self.root, &mut visit)
+    }
+
+    fn visit_node<F: FnMut(T) -> io::Result<()>>(&mut self, id: PageId, visit: &mut F) -> io::Result<()> {
+        let node = self.read_node(id)?;
+        let mut children = node.children.into_iter();
+        for key in node.keys {
+            if let Some(child) = children.next() {
+                self.visit_node(child, visit)?;
+            }
+            visit(key)?;
+        }
+        match children.next() {
+            Some(child) => self.visit_node(child, visit),
+            None => Ok(()),
+        }
+    }
+
+    pub fn insert(&mut self, key: T) -> io::Result<()> {
+        let mut encoded = Vec::new();
+        C::encode(&key, &mut encoded);
+        if encoded.len() > self.max_key_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("encoded key is {} bytes, this tree allows {}", encoded.len(), self.max_key_len),
+            ));
+        }
+        self.insert_key(key)?;
+        self.len += 1;
+        self.commit()
+    }
+
+    fn insert_key(&mut self, key: T) -> io::Result<()> {
+        let root = self.read_node(self.root)?;
+        if self.is_full(&root) {
+            // Grow a level: the old root becomes the only child of a new one.
+            let new_root = self.allocate()?;
+            let mut node = DiskNode {
+                keys: Vec::new(),
+                children: vec![self.root],
+            };
+            self.split_child(&mut node, 0)?;
+            self.write_node(new_root, &node)?;
+            self.root = new_root;
+        }
+        if let Some((middle_key, right_id)) = self.insert_non_full(self.root, key)? {
+            // A compressed root leaf outgrew its page.
+            let new_root = self.allocate()?;
+            let node = DiskNode {
+                keys: vec![middle_key],
+                children: vec![self.root, right_id],
+            };
+            self.write_node(new_root, &node)?;
+            self.root = new_root;
+        }
+        Ok(())
+    }
+
+    // Whether a node must be split before an insert goes into it. With
+    // compression, leaves are split once an insert has made them too big
+    // instead, since how many more keys one takes depends on the keys.
+    fn is_full(&self, node: &DiskNode<T>) -> bool {
+        node.keys.len() >= self.max_keys && !(node.is_leaf() && self.compression != Compression::None)
+    }
+
+    // Returns the middle key and new right half if the leaf the key went
+    // into had to split, for the caller to insert into the parent.
+    fn insert_non_full(&mut self, id: PageId, key: T) -> io::Result<Option<(T, PageId)>> {
+        let mut node = self.read_node(id)?;
+        let mut index = node.keys.partition_point(|k| *k < key);
+        if node.is_leaf() {
+            node.keys.insert(index, key);
+            return match node.encode::<C>(self.compression, self.max_leaf_body) {
+                Some(bytes) => {
+                    self.write_bytes(id, &bytes)?;
+                    Ok(None)
+                }
+                None => self.split_leaf(id, node).map(Some),
+            };
+        }
+        let child = self.read_node(node.children[index])?;
+        if self.is_full(&child) {
+            self.split_child(&mut node, index)?;
+            self.write_node(id, &node)?;
+            if node.keys[index] < key {
+                index += 1;
+            }
+        }
+        if let Some((middle_key, right_id)) = self.insert_non_full(node.children[index], key)? {
+            node.keys.insert(index, middle_key);
+            node.children.insert(index + 1, right_id);
+            self.write_node(id, &node)?;
+        }
+        Ok(None)
+    }
+
+    // Split a leaf too big for its page where half its bytes are on either
+    // side.
+    fn split_leaf(&mut self, id: PageId, mut node: DiskNode<T>) -> io::Result<(T, PageId)> {
+        let mut encoded = Vec::new();
+        let sizes: Vec<usize> = node
+            .keys
+            .iter()
+            .map(|key| {
+                encoded.clear();
+                C::encode(key, &mut encoded);
+                encoded.len()
+            })
+            .collect();
+        let half = sizes.iter().sum::<usize>() / 2;
+        let (mut mid, mut left) = (0, 0);
+        while left + sizes[mid] <= half {
+            left += sizes[mid];
+            mid += 1;
+        }
+        let mid = mid.clamp(1, node.keys.len() - 2);
+        let right = DiskNode { keys: node.keys.split_off(mid + 1), children: Vec::new() };
+        let middle_key = node.keys.pop().unwrap();
+        let right_id = self.allocate()?;
+        self.write_node(id, &node)?;
+        self.write_node(right_id, &right)?;
+        Ok((middle_key, right_id))
+    }
+
+    // Split the full child at `index`, moving its middle key into `parent`.
+    // The caller writes `parent` back.
+    fn split_child(&mut self, parent: &mut DiskNode<T>, index: usize) -> io::Result<()> {
+        let child_id = parent.children[index];
+        let mut child = self.read_node(child_id)?;
+        let mid = self.max_keys / 2;
+        let right_keys = child.keys.split_off(mid + 1);
+        let middle_key = child.keys.pop().unwrap();
+        let right_children = if child.is_leaf() {
+            Vec::new()
+        } else {
+            child.children.split_off(mid + 1)
+        };
+        let right_id = self.allocate()?;
+        self.write_node(child_id, &child)?;
+        self.write_node(
+            right_id,
+            &DiskNode {
+                keys: right_keys,
+                children: right_children,
+            },
+        )?;
+        parent.keys.insert(index, middle_key);
+        parent.children.insert(index + 1, right_id);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: T) -> io::Result<bool> {
+        let found = self.delete_from(self.root, key)?;
+        let root = self.read_node(self.root)?;
+        if root.keys.is_empty() && !root.is_leaf() {
+            // The root lost its last key to a merge; its only child takes over.
+            let old_root = self.root;
+            self.root = root.children[0];
+            self.free(old_root)?;
+        }
+        for key in std::mem::take(&mut self.spilled) {
+            self.insert_key(key)?;
+        }
+        if found {
+            self.len -= 1;
+        }
+        self.commit()?;
+        Ok(found)
+    }
+
+    // Top-down delete: before descending into a child, make sure it can
+    // afford to lose a key, so no fix-ups are needed on the way back up.
+    fn delete_from(&mut self, id: PageId, key: T) -> io::Result<bool> {
+        let mut node = self.read_node(id)?;
+        let index = node.keys.partition_point(|k| *k < key);
+        let in_node = index < node.keys.len() && node.keys[index] == key;
+
+        if node.is_leaf() {
+            if in_node {
+                node.keys.remove(index);
+                self.write_node(id, &node)?;
+            }
+            return Ok(in_node);
+        }
+
+        if in_node {
+            let left = self.read_node(node.children[index])?;
+            if left.keys.len() > self.min_keys {
+                node.keys[index] = self.delete_max(node.children[index])?;
+                self.write_node(id, &node)?;
+                return Ok(true);
+            }
+            let right = self.read_node(node.children[index + 1])?;
+            if right.keys.len() > self.min_keys {
+                node.keys[index] = self.delete_min(node.children[index + 1])?;
+                self.write_node(id, &node)?;
+                return Ok(true);
+            }
+            let merged = self.merge_children(&mut node, index)?;
+            self.write_node(id, &node)?;
+            return self.delete_from(merged, key);
+        }
+
+        let child = self.make_child_deletable(&mut node, id, index)?;
+        self.delete_from(child, key)
+    }
+
+    fn delete_max(&mut self, id: PageId) -> io::Result<T> {
+        let mut node = self.read_node(id)?;
+        if node.is_leaf() {
+            let key = node.keys.pop().unwrap();
+            self.write_node(id, &node)?;
+            return Ok(key);
+        }
+        let last = node.children.len() - 1;
+        let child = self.make_child_deletable(&mut node, id, last)?;
+        self.delete_max(child)
+    }
+
+    fn delete_min(&mut self, id: PageId) -> io::Result<T> {
+        let mut node = self.read_node(id)?;
+        if node.is_leaf() {
+            let key = node.keys.remove(0);
+            self.write_node(id, &node)?;
+            return Ok(key);
+        }
+        let child = self.make_child_deletable(&mut node, id, 0)?;
+        self.delete_min(child)
+    }
+
+    // Give the child at `index` more than `min_keys` keys, borrowing from a
+    // sibling or merging with one. Returns the page to descend into.
+    fn make_child_deletable(&mut self, node: &mut DiskNode<T>, id: PageId, index: usize) -> io::Result<PageId> {
+        let child_id = node.children[index];
+        let mut child = self.read_node(child_id)?;
+        if child.keys.len() > self.min_keys {
+            return Ok(child_id);
+        }
+
+        if index > 0 {
+            let left_id = node.children[index - 1];
+            let mut left = self.read_node(left_id)?;
+            if left.keys.len() > self.min_keys {
+                let separator = std::mem::replace(&mut node.keys[index - 1], left.keys.pop().unwrap());
+                child.keys.insert(0, separator);
+                if !left.is_leaf() {
+                    child.children.insert(0, left.children.pop().unwrap());
+                }
+                self.write_node(left_id, &left)?;
+                self.write_node(child_id, &child)?;
+                self.write_node(id, node)?;
+                return Ok(child_id);
+            }
+        }
+        if index < node.keys.len() {
+            let right_id = node.children[index + 1];
+            let mut right = self.read_node(right_id)?;
+            if right.keys.len() > self.min_keys {
+                let separator = std::mem::replace(&mut node.keys[index], right.keys.remove(0));
+                child.keys.push(separator);
+                if !right.is_leaf() {
+                    child.children.push(right.children.remove(0));
+                }
+                self.write_node(right_id, &right)?;
+                self.write_node(child_id, &child)?;
+                self.write_node(id, node)?;
+                return Ok(child_id);
+            }
+        }
+
+        let merged = if index < node.keys.len() {
+            self.merge_children(node, index)?
+        } else {
+            self.merge_children(node, index - 1)?
+        };
+        self.write_node(id, node)?;
+        Ok(merged)
+    }
+
+    // Fold child `index + 1` and the separator between them into child
+    // `index`. The caller writes `node` back.
+    fn merge_children(&mut self, node: &mut DiskNode<T>, index: usize) -> io::Result<PageId> {
+        let left_id = node.children[index];
+        let right_id = node.children.remove(index + 1);
+        let separator = node.keys.remove(index);
+        let mut left = self.read_node(left_id)?;
+        let mut right = self.read_node(right_id)?;
+        left.keys.push(separator);
+        left.keys.append(&mut right.keys);
+        left.children.append(&mut right.children);
+        self.write_node(left_id, &left)?;
+        self.free(right_id)?;
+        Ok(left_id)
+    }
+
+    fn read_node(&mut self, id: PageId) -> io::Result<DiskNode<T>> {
+        DiskNode::decode::<C>(self.pool.page(id)?)
+    }
+
+    fn write_node(&mut self, id: PageId, node: &DiskNode<T>) -> io::Result<()> {
+        // Key lengths are checked on insert, so a full node always fits, as
+        // does each half of a compressed leaf that split.
+        match node.encode::<C>(self.compression, usize::MAX) {
+            Some(bytes) => self.write_bytes(id, &bytes),
+            None => self.spill(id, node),
+        }
+    }
+
+    // Taking a key out of a compressed leaf can leave the rest compressing
+    // worse, and no longer fitting its page. Its largest keys then come out
+    // until it fits, to go back in by way of `insert` once the delete is
+    // done, splitting the leaf if need be.
+    fn spill(&mut self, id: PageId, node: &DiskNode<T>) -> io::Result<()> {
+        // Only leaves are compressed, so only a leaf can outgrow its page.
+        if !node.is_leaf() {
+            return Err(invalid_data("internal node overflows its page"));
+        }
+        let mut count = node.keys.len();
+        let bytes = loop {
+            count -= 1;
+            if let Some(bytes) = node.encode_first::<C>(count, self.compression, usize::MAX) {
+                break bytes;
+            }
+        };
+        let mut encoded = Vec::new();
+        for key in &node.keys[count..] {
+            encoded.clear();
+            C::encode(key, &mut encoded);
+            self.spilled.push(C::decode(&encoded).ok_or_else(|| invalid_data("codec can't decode its own encoding"))?);
+        }
+        self.write_bytes(id, &bytes)
+    }
+
+    fn write_bytes(&mut self, id: PageId, bytes: &[u8]) -> io::Result<()> {
+        let page = self.pool.page_mut(id)?;
+        page.fill(0);
+        page[..bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> io::Result<PageId> {
+        if self.free_head == NO_PAGE {
+            return self.pool.allocate();
+        }
+        let id = self.free_head;
+        let page = self.pool.page(id)?;
+        if page[0] != KIND_FREE {
+            return Err(invalid_data("free list points at a live page"));
+        }
+        self.free_head = read_u64(page, 1);
+        Ok(id)
+    }
+
+    fn free(&mut self, id: PageId) -> io::Result<()> {
+        let page = self.pool.page_mut(id)?;
+        page.fill(0);
+        page[0] = KIND_FREE;
+        page[1..9].copy_from_slice(&self.free_head.to_le_bytes());
+        self.free_head = id;
+        Ok(())
+    }
+
+    // End of an operation: the header and every page touched go to the WAL
+    // as one batch.
+    fn commit(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.pool.commit()
+    }
+}
+
+impl<T, C> DiskBTree<T, C> {
+    fn write_header(&mut self) -> io::Result<()> {
+        let page = self.pool.page_mut(HEADER_PAGE)?;
+        page.fill(0);
+        page[0..4].copy_from_slice(MAGIC);
+        page[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        page[8..12].copy_from_slice(&(self.degree as u32).to_le_bytes());
+        page[12..20].copy_from_slice(&self.root.to_le_bytes());
+        page[20..28].copy_from_slice(&self.len.to_le_bytes());
+        page[28..36].copy_from_slice(&self.free_head.to_le_bytes());
+        page[36] = self.codec_id;
+        page[37] = self.compression.codec();
+        Ok(())
+    }
+}
+
+impl<T, C> Drop for DiskBTree<T, C> {
+    fn drop(&mut self) {
+        let _ = self.write_header();
+    }
+}
+
+// Room per key in a full node of the given degree, or None if not even the
+// shortest key fits.
+fn max_key_len<T, C: KeyCodec<T>>(degree: usize) -> Option<usize> {
+    let max_keys = degree - 1;
+    let children = 8 * degree;
+    let per_key = PAGE_PAYLOAD.checked_sub(NODE_HEADER + children)? / max_keys;
+    match C::FIXED_WIDTH {
+        Some(width) if width <= per_key => Some(width),
+        Some(_) => None,
+        None if !prefixed::<T, C>() && per_key > 0 => Some(per_key.min(u16::MAX as usize)),
+        None if per_key > KEY_LEN => Some((per_key - KEY_LEN).min(u16::MAX as usize)),
+        None => None,
+    }
+}
+
+// Whether nodes store a length before each key.
+fn prefixed<T, C: KeyCodec<T>>() -> bool {
+    C::FIXED_WIDTH.is_none() && C::DELIMITED_LEN.is_none()
+}
+
+fn wal_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push("-wal");
+    PathBuf::from(name)
+}
+
+fn read_u32(page: &Page, offset: usize) -> u32 {
+    u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(page: &Page, offset: usize) -> u64 {
+    u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DiskBTree, DiskOptions};
+    use crate::codec::Varint;
+
+    #[test]
+    fn test_insert_delete_reopen() {
+        let path = std::env::temp_dir().join(format!("disk-btree-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions {
+            branch_factor: 2,
+            cache_pages: 8,
+            ..DiskOptions::default()
+        };
+        {
+            let mut tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+            for key in 0..500u64 {
+                tree.insert((key * 7919) % 500).unwrap();
+            }
+            for key in (0..500u64).step_by(2) {
+                assert!(tree.delete(key).unwrap());
+            }
+            assert!(!tree.delete(0).unwrap());
+            tree.flush().unwrap();
+        }
+        let mut tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+        assert_eq!(tree.len(), 250);
+        for key in 0..500u64 {
+            assert_eq!(tree.search(key).unwrap(), key % 2 == 1);
+        }
+        // freed pages are reused instead of growing the file
+        let pages = tree.buffer_pool().page_count();
+        for key in (0..500u64).step_by(2) {
+            tree.insert(key).unwrap();
+        }
+        assert!(tree.buffer_pool().page_count() <= pages + 2);
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_wal_survives_crash() {
+        let path = std::env::temp_dir().join(format!("disk-btree-wal-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions {
+            branch_factor: 2,
+            cache_pages: 4,
+            wal: true,
+            ..DiskOptions::default()
+        };
+        let mut tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+        for key in 0..200u64 {
+            tree.insert(key).unwrap();
+        }
+        for key in 0..50u64 {
+            tree.delete(key).unwrap();
+        }
+        // Crash: nothing is flushed, most pages only exist in the pool and the log.
+        std::mem::forget(tree);
+
+        let mut tree: DiskBTree = DiskBTree::open(&path, options()).unwrap();
+        assert_eq!(tree.len(), 150);
+        for key in 0..200u64 {
+            assert_eq!(tree.search(key).unwrap(), key >= 50);
+        }
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(super::wal_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_hot_pages_stay_cached() {
+        let path = std::env::temp_dir().join(format!("disk-btree-cache-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut tree: DiskBTree = DiskBTree::open(&path, DiskOptions::default()).unwrap();
+        for key in 0..20_000u64 {
+            tree.insert(key).unwrap();
+        }
+        tree.buffer_pool_mut().reset_stats();
+        for _ in 0..10 {
+            assert!(tree.search(12_345).unwrap());
+        }
+        // only the first lookup can miss
+        assert!(tree.buffer_pool().misses() <= 3);
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_string_keys() {
+        let path = std::env::temp_dir().join(format!("disk-btree-strings-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions {
+            branch_factor: 8,
+            ..DiskOptions::default()
+        };
+        {
+            let mut tree: DiskBTree<String> = DiskBTree::open(&path, options()).unwrap();
+            for key in 0..300 {
+                tree.insert(format!("key-{}", key)).unwrap();
+            }
+            assert!(tree.delete("key-42".to_string()).unwrap());
+            let too_long = "x".repeat(tree.max_key_len() + 1);
+            assert!(tree.insert(too_long).is_err());
+        }
+        let mut tree: DiskBTree<String> = DiskBTree::open(&path, options()).unwrap();
+        assert_eq!(tree.len(), 299);
+        assert!(tree.search("key-299".to_string()).unwrap());
+        assert!(!tree.search("key-42".to_string()).unwrap());
+        drop(tree);
+        // the header records the codec, so the file can't be read as u64 keys
+        assert!(DiskBTree::<u64>::open(&path, options()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_varint_keys() {
+        let path = std::env::temp_dir().join(format!("disk-btree-varint-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = || DiskOptions {
+            branch_factor: 184,
+            ..DiskOptions::default()
+        };
+        // fixed-width keys don't fit a node this wide in a page
+        assert!(DiskBTree::<u64>::open(&path, options()).is_err());
+        {
+            let mut tree: DiskBTree<u64, Varint> = DiskBTree::open(&path, options()).unwrap();
+            assert_eq!(tree.max_key_len(), 3);
+            for key in 0..20_000 {
+                tree.insert((key * 7919) % 20_000).unwrap();
+            }
+            assert!(tree.insert(1 << 16).is_err());
+            assert!(tree.delete(42).unwrap());
+        }
+        let mut tree: DiskBTree<u64, Varint> = DiskBTree::open(&path, options()).unwrap();
+        assert_eq!(tree.len(), 19_999);
+        assert!(tree.search(19_999).unwrap());
+        assert!(!tree.search(42).unwrap());
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_pages() {
+        let path = std::env::temp_dir().join(format!("disk-btree-encrypted-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = |key| DiskOptions {
+            branch_factor: 4,
+            cache_pages: 4,
+            wal: true,
+            encryption_key: Some(key),
+            ..DiskOptions::default()
+        };
+        let mut tree: DiskBTree<String> = DiskBTree::open(&path, options([7; 32])).unwrap();
+        for key in 0..100 {
+            tree.insert(format!("secret-{key}")).unwrap();
+        }
+        // some pages only in the WAL, none of them readable
+        std::mem::forget(tree);
+        for file in [path.clone(), super::wal_path(&path)] {
+            let bytes = std::fs::read(file).unwrap();
+            assert!(!bytes.windows(7).any(|window| window == b"secret-"));
+        }
+
+        let mut tree: DiskBTree<String> = DiskBTree::open(&path, options([7; 32])).unwrap();
+        assert_eq!(tree.len(), 100);
+        assert!(tree.search("secret-42".to_string()).unwrap());
+        drop(tree);
+        assert!(DiskBTree::<String>::open(&path, options([8; 32])).is_err());
+        assert!(DiskBTree::<String>::open(&path, DiskOptions::default()).is_err());
+        // the header can be told apart from other files without the key
+        assert!(crate::verify::verify_file(&path).is_err());
+        assert_eq!(&std::fs::read(&path).unwrap()[..4], super::MAGIC);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[crate::pager::PAGE_SIZE + 10] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut tree: DiskBTree<String> = DiskBTree::open(&path, options([7; 32])).unwrap();
+        assert!((0..100).any(|key| tree.search(format!("secret-{key}")).is_err()));
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
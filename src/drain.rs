@@ -0,0 +1,293 @@
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+
+use crate::{node_mut, BTree, BTreeProps, Context, Node, SnapshotIter};
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Remove the keys in `range`, yielding them in order as they go. The
+    /// whole range is removed even if the iterator is dropped part way,
+    /// like `Vec::drain`.
+    ///
+    /// The keys come from a snapshot taken at the start, so each delete
+    /// costs what `delete` does, plus copying a node shared with the
+    /// snapshot the first time one is changed.
+    pub fn drain_range<R: RangeBounds<T>>(&mut self, range: R) -> DrainRange<'_, T> {
+        let start = range.start_bound().cloned();
+        let before = move |key: &T| match start {
+            Bound::Included(start) => *key < start,
+            Bound::Excluded(start) => *key <= start,
+            Bound::Unbounded => false,
+        };
+        self.ctx.begin_nested(&self.root);
+        DrainRange {
+            keys: Some(SnapshotIter::seek(Arc::clone(&self.root), before)),
+            end: range.end_bound().cloned(),
+            tree: self,
+        }
+    }
+
+    /// Remove the `n` smallest keys, or all of them if there are fewer,
+    /// and return them smallest first. Subtrees that hold only keys being
+    /// removed are cut off whole instead of emptied key by key, so this
+    /// costs about the keys removed plus a walk down the left edge, however
+    /// large `n` is.
+    pub fn delete_min(&mut self, n: usize) -> Vec<T> {
+        self.delete_edge(n, Edge::Min)
+    }
+
+    /// Remove the `n` largest keys, or all of them if there are fewer, and
+    /// return them largest first, as `delete_min` does from the other end.
+    pub fn delete_max(&mut self, n: usize) -> Vec<T> {
+        self.delete_edge(n, Edge::Max)
+    }
+
+    fn delete_edge(&mut self, n: usize, edge: Edge) -> Vec<T> {
+        let n = n.min(self.len());
+        let mut removed = Vec::with_capacity(n);
+        if n == 0 {
+            return removed;
+        }
+        self.ctx.begin(&self.root);
+        self.ctx.version += 1;
+        take_edge(node_mut(&mut self.root), n, edge, &mut removed, &mut self.ctx);
+        // Only nodes down the edge can be short of keys now. Each pass
+        // refills them bottom up; a merge can leave a node's one child short,
+        // which the next pass reaches once the level above has merged.
+        // Whole children cut off can leave the root with no keys over one
+        // child, settled or not, so it is shrunk before every check.
+        loop {
+            while self.root.keys.is_empty() && !self.root.is_leaf() {
+                let child = Arc::clone(&self.root.children[0]);
+                let old_root = mem::replace(&mut self.root, child);
+                self.ctx.version += 1;
+                if !self.props.shrink_on_merge {
+                    self.ctx.free.put(old_root);
+                }
+            }
+            if edge_settled(&self.root, &self.props, edge, true) {
+                break;
+            }
+            settle_edge(&self.props, node_mut(&mut self.root), edge, &mut self.ctx);
+        }
+        for key in &removed {
+            self.ctx.removed(key);
+        }
+        let name = match edge {
+            Edge::Min => "delete_min",
+            Edge::Max => "delete_max",
+        };
+        self.record_whole(|| format!("{name} {n}"));
+        removed
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Min,
+    Max,
+}
+
+impl Edge {
+    fn child<T>(self, node: &Node<T>) -> usize {
+        match self {
+            Edge::Min => 0,
+            Edge::Max => node.children.len() - 1,
+        }
+    }
+}
+
+// Moves the `n` keys at `edge` of the subtree into `removed`, in the order
+// they are taken: whole children at the edge with the keys beside them,
+// then what is left to take from the child that is at the edge after them.
+// Fill is left to `settle_edge`.
+fn take_edge<T: Ord + Copy>(node: &mut Node<T>, mut n: usize, edge: Edge, removed: &mut Vec<T>, ctx: &mut Context<T>) {
+    node.len -= n;
+    if node.is_leaf() {
+        match edge {
+            Edge::Min => removed.extend(node.keys.drain(..n)),
+            Edge::Max => removed.extend(node.keys.drain(node.keys.len() - n..).rev()),
+        }
+        return;
+    }
+    while node.children.len() > 1 && n > node.children[edge.child(node)].len {
+        let (child, key) = match edge {
+            Edge::Min => (node.children.remove(0), node.keys.remove(0)),
+            Edge::Max => (node.children.pop().unwrap(), node.keys.pop().unwrap()),
+        };
+        let keys = SnapshotIter::at(Arc::clone(&child), 0);
+        match edge {
+            Edge::Min => removed.extend(keys),
+            Edge::Max => removed.extend(keys.collect::<Vec<T>>().into_iter().rev()),
+        }
+        removed.push(key);
+        n -= child.len + 1;
+        ctx.free.put(child);
+    }
+    if n > 0 {
+        let index = edge.child(node);
+        take_edge(node_mut(&mut node.children[index]), n, edge, removed, ctx);
+    }
+}
+
+fn edge_settled<T: Ord>(node: &Node<T>, props: &BTreeProps, edge: Edge, is_root: bool) -> bool {
+    (is_root || node.keys.len() >= props.fewest_keys())
+        && (node.is_leaf() || edge_settled(&node.children[edge.child(node)], props, edge, false))
+}
+
+fn settle_edge<T: Ord + Copy + Debug>(props: &BTreeProps, node: &mut Node<T>, edge: Edge, ctx: &mut Context<T>) {
+    if node.is_leaf() {
+        return;
+    }
+    let index = edge.child(node);
+    settle_edge(props, node_mut(&mut node.children[index]), edge, ctx);
+    while node.children.len() > 1 {
+        let index = edge.child(node);
+        if node.children[index].keys.len() >= props.fewest_keys() {
+            break;
+        }
+        props.refill_child(node, index, ctx);
+    }
+}
+
+/// Removes and yields a range of keys. See `BTree::drain_range`.
+pub struct DrainRange<'a, T: Ord + Copy + Debug + Default> {
+    tree: &'a mut BTree<T>,
+    // The tree as it was, from the first key in the range; `None` once past
+    // the end of it.
+    keys: Option<SnapshotIter<T>>,
+    end: Bound<T>,
+}
+
+impl<T: Ord + Copy + Debug + Default> Iterator for DrainRange<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let key = self.keys.as_mut()?.next()?;
+        let in_range = match self.end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            self.keys = None;
+            return None;
+        }
+        // one copy of `key` per time the snapshot holds it
+        self.tree.delete(key);
+        Some(key)
+    }
+}
+
+impl<T: Ord + Copy + Debug + Default> Drop for DrainRange<'_, T> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+        self.tree.ctx.end_nested();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_drain_range() {
+        let mut tree = BTree::new(2);
+        for key in 0..1000u32 {
+            tree.insert((key * 7919) % 1000);
+        }
+        tree.insert(500);
+        let snapshot = tree.snapshot();
+
+        let drained: Vec<u32> = tree.drain_range(400..600).collect();
+        assert!(drained.iter().copied().eq((400..=500).chain(500..600)));
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.iter_snapshot().eq((0..400).chain(600..1000)));
+        assert_eq!(snapshot.iter().count(), 1001);
+
+        // dropped after two keys, the rest of the range still goes
+        assert!(tree.drain_range(..=100).take(2).eq([0, 1]));
+        let mut drain = tree.drain_range((std::ops::Bound::Excluded(900), std::ops::Bound::Unbounded));
+        assert_eq!(drain.next(), Some(901));
+        drop(drain);
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.iter_snapshot().eq((101..400).chain(600..=900)));
+        assert_eq!(tree.drain_range(..).count(), 299 + 301);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_delete_min_max() {
+        let mut tree = BTree::new(2);
+        for key in 0..2000u32 {
+            tree.insert((key * 7919) % 2000);
+        }
+        tree.insert(1500);
+        let snapshot = tree.snapshot();
+
+        assert!(tree.delete_min(0).is_empty());
+        assert_eq!(tree.delete_min(3), [0, 1, 2]);
+        assert!(tree.delete_min(700).into_iter().eq(3..703));
+        check_node(&tree.root, &tree.props, true);
+        let mut largest: Vec<u32> = (1400..2000).chain([1500]).collect();
+        largest.sort_by(|a, b| b.cmp(a));
+        assert_eq!(tree.delete_max(601), largest);
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.iter_snapshot().eq(703..1400));
+        assert_eq!((tree.len(), tree.audit_counts().len()), (697, 0));
+        assert_eq!(snapshot.iter().count(), 2001);
+
+        for n in [1, 5, 40, 100] {
+            let smallest = tree.iter_snapshot().next().unwrap();
+            assert!(tree.delete_min(n).into_iter().eq(smallest..smallest + n as u32));
+            check_node(&tree.root, &tree.props, true);
+        }
+        assert_eq!(tree.delete_max(1000).len(), 551);
+        assert!(tree.is_empty() && tree.root.is_leaf());
+    }
+
+    #[test]
+    fn test_delete_min_max_small() {
+        for branch_factor in 2..=4 {
+            for len in 0..=6 * branch_factor as u32 {
+                for n in 0..=len as usize {
+                    for min in [true, false] {
+                        let mut tree = BTree::new(branch_factor);
+                        for key in 0..len {
+                            tree.insert(key);
+                        }
+                        let removed = match min {
+                            true => tree.delete_min(n),
+                            false => tree.delete_max(n),
+                        };
+                        assert_eq!(removed.len(), n);
+                        check_node(&tree.root, &tree.props, true);
+                        assert!(tree.root.is_leaf() || !tree.root.keys.is_empty());
+                        let left = match min {
+                            true => n as u32..len,
+                            false => 0..len - n as u32,
+                        };
+                        assert!(tree.iter_snapshot().eq(left.clone()));
+
+                        #[cfg(feature = "std")]
+                        {
+                            let path = std::env::temp_dir().join(format!("drain-edge-{}.snap", std::process::id()));
+                            tree.save_to(&path).unwrap();
+                            let loaded: BTree<u32> = BTree::load_from(&path).unwrap();
+                            std::fs::remove_file(&path).unwrap();
+                            check_node(&loaded.root, &loaded.props, true);
+                            assert!(loaded.iter_snapshot().eq(left));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use core::fmt::{self, Debug, Display};
+
+/// Why one of the `try_` methods of `BTree`, which never panic, failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A tree shape the constructors refuse.
+    Config(ConfigError),
+    /// The key to delete is not in the tree.
+    NotFound,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(error) => write!(f, "{error}"),
+            Error::NotFound => write!(f, "key not found"),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Config(error) => Some(error),
+            Error::NotFound => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::Config(error)
+    }
+}
+
+/// A tree shape the constructors refuse: small or odd sizes leave nodes
+/// with no room for keys once split, or split them unevenly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// A branch factor below 2.
+    BranchFactorTooSmall(usize),
+    /// A degree below 4.
+    DegreeTooSmall(usize),
+    /// An odd degree; a node of degree `d` splits around its middle key
+    /// into two of `d / 2 - 1`.
+    OddDegree(usize),
+    /// A `RebalancePolicy::min_keys` outside `1..=most`.
+    MinKeysOutOfRange { min_keys: usize, most: usize },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::BranchFactorTooSmall(branch_factor) => {
+                write!(f, "branch factor {branch_factor} is too small, it must be at least 2")
+            }
+            ConfigError::DegreeTooSmall(degree) => write!(f, "degree {degree} is too small, it must be at least 4"),
+            ConfigError::OddDegree(degree) => write!(f, "degree {degree} is odd, it must be even"),
+            ConfigError::MinKeysOutOfRange { min_keys, most } => {
+                write!(f, "a minimum of {min_keys} keys per node is out of range, it must be from 1 to {most}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ConfigError {}
+
+/// What `BTree::try_insert` returns when an equal key is already stored.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OccupiedError<'a, T> {
+    /// The key already in the tree.
+    pub existing: &'a T,
+    /// The key that was not inserted.
+    pub key: T,
+}
+
+impl<T: Debug> Display for OccupiedError<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "key {:?} is already stored as {:?}", self.key, self.existing)
+    }
+}
+
+impl<T: Debug> core::error::Error for OccupiedError<'_, T> {}
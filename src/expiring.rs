@@ -0,0 +1,127 @@
+//! Keys that expire: each has a time after which reads stop seeing it, and
+//! `sweep_expired` takes out everything past its time in one go.
+//!
+//! Times are plain `u64`s in whatever unit the caller likes (seconds,
+//! milliseconds, ticks), with the current time always passed in, so the
+//! tree never reads a clock.
+
+use core::fmt::Debug;
+
+use crate::BTree;
+
+/// A set of keys with expiry times. See the module docs.
+///
+/// Keys are kept as `(key, expires at)` and, for sweeping, in a second tree
+/// as `(expires at, key)`, so finding what has expired is a scan from the
+/// front of that tree.
+pub struct ExpiringBTree<T> {
+    keys: BTree<(T, u64)>,
+    by_expiry: BTree<(u64, T)>,
+}
+
+impl<T> ExpiringBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize) -> Self {
+        ExpiringBTree {
+            keys: BTree::new(branch_factor),
+            by_expiry: BTree::new(branch_factor),
+        }
+    }
+
+    /// The keys stored, counting expired ones not yet swept.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Add `key` with no expiry, or take away the expiry it had.
+    pub fn insert(&mut self, key: T) {
+        self.insert_until(key, u64::MAX);
+    }
+
+    /// Add `key`, or move its expiry, to expire `ttl` after `now`.
+    pub fn insert_with_ttl(&mut self, key: T, ttl: u64, now: u64) {
+        self.insert_until(key, now.saturating_add(ttl));
+    }
+
+    fn insert_until(&mut self, key: T, expires: u64) {
+        self.remove(key);
+        self.keys.insert((key, expires));
+        self.by_expiry.insert((expires, key));
+    }
+
+    /// When `key` expires, if it is stored, expired or not. `u64::MAX` for
+    /// keys that never do.
+    pub fn expiry(&self, key: T) -> Option<u64> {
+        self.keys.range_prefix(key).next().map(|(_, expires)| expires)
+    }
+
+    /// Whether `key` is stored and has not expired by `now`.
+    pub fn contains(&self, key: T, now: u64) -> bool {
+        self.expiry(key).is_some_and(|expires| expires > now)
+    }
+
+    pub fn remove(&mut self, key: T) -> bool {
+        let Some(expires) = self.expiry(key) else {
+            return false;
+        };
+        self.keys.delete((key, expires));
+        self.by_expiry.delete((expires, key));
+        true
+    }
+
+    /// The keys not expired by `now`, in order.
+    pub fn iter(&self, now: u64) -> impl Iterator<Item = T> {
+        self.keys.iter_snapshot().filter(move |&(_, expires)| expires > now).map(|(key, _)| key)
+    }
+
+    /// Remove every key expired by `now`, returning how many there were.
+    /// They are at the front of the expiry tree, and go from it as one
+    /// drained range.
+    pub fn sweep_expired(&mut self, now: u64) -> usize {
+        let expired = self.by_expiry.iter_snapshot().take_while(|&(expires, _)| expires <= now).last();
+        let Some(last) = expired else {
+            return 0;
+        };
+        let mut count = 0;
+        for (expires, key) in self.by_expiry.drain_range(..=last) {
+            self.keys.delete((key, expires));
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExpiringBTree;
+
+    #[test]
+    fn test_expiring_keys() {
+        let mut tree = ExpiringBTree::new(2);
+        for key in 0..200u32 {
+            tree.insert_with_ttl(key, u64::from(key % 10) * 100, 1000);
+        }
+        tree.insert(500);
+        // a second insert moves the expiry rather than adding a copy
+        tree.insert_with_ttl(0, 5000, 1000);
+        tree.insert(1);
+        assert_eq!((tree.len(), tree.expiry(0), tree.expiry(1)), (201, Some(6000), Some(u64::MAX)));
+
+        assert!(tree.contains(19, 1899) && !tree.contains(19, 1900) && !tree.contains(10, 1000));
+        assert_eq!(tree.iter(1450).count(), 200 / 10 * 5 + 1 + 2);
+        assert_eq!(tree.sweep_expired(1450), 100 - 2);
+        assert_eq!(tree.len(), 103);
+        assert_eq!(tree.sweep_expired(1450), 0);
+        assert!(tree.iter(0).eq(tree.iter(1450)));
+
+        assert!(tree.remove(15) && !tree.remove(15) && !tree.remove(10));
+        assert_eq!(tree.sweep_expired(u64::MAX - 1), 100);
+        assert!(tree.iter(u64::MAX - 1).eq([1, 500]));
+    }
+}
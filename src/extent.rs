@@ -0,0 +1,205 @@
+//! Free space kept as extents, as file systems keep their free blocks:
+//! disjoint runs `(start, len)` of integers, handed out by `allocate` and
+//! given back by `free`, which joins a freed run to the free runs on either
+//! side of it so that free space never fragments more than it has to.
+//!
+//! Extents are kept twice, by start and by length, so that both finding a
+//! run's neighbours and finding the smallest run that fits take a descent.
+
+use core::fmt;
+
+use crate::BTree;
+
+/// Which free extent `allocate` carves from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fit {
+    /// The one that starts lowest, found by scanning extents in order
+    /// until one is long enough. Tends to keep the top of the space free.
+    #[default]
+    First,
+    /// The shortest one long enough, the lowest of those if several are,
+    /// found with one descent. Tends to keep long extents whole.
+    Best,
+}
+
+/// Free extents of `u64` units. See the module docs.
+///
+/// ```
+/// use b_trees_with_delete::ExtentTree;
+///
+/// let mut space = ExtentTree::new(8);
+/// space.free(0, 100);
+/// let a = space.allocate(30).unwrap();
+/// let b = space.allocate(30).unwrap();
+/// assert_eq!((a, b), (0, 30));
+/// space.free(a, 30);
+/// space.free(b, 30);
+/// assert_eq!(space.extents().collect::<Vec<_>>(), [(0, 100)]);
+/// ```
+pub struct ExtentTree {
+    by_start: BTree<(u64, u64)>,
+    // The same extents as `(len, start)`.
+    by_len: BTree<(u64, u64)>,
+    fit: Fit,
+    free_units: u64,
+}
+
+impl ExtentTree {
+    /// No free space; add some with `free`.
+    pub fn new(branch_factor: usize) -> Self {
+        ExtentTree { by_start: BTree::new(branch_factor), by_len: BTree::new(branch_factor), fit: Fit::First, free_units: 0 }
+    }
+
+    pub fn fit(&self) -> Fit {
+        self.fit
+    }
+
+    pub fn set_fit(&mut self, fit: Fit) {
+        self.fit = fit;
+    }
+
+    /// The number of free extents.
+    pub fn len(&self) -> usize {
+        self.by_start.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_start.is_empty()
+    }
+
+    /// The units free in all extents together.
+    pub fn free_units(&self) -> u64 {
+        self.free_units
+    }
+
+    /// The free extents as `(start, len)`, lowest first.
+    pub fn extents(&self) -> impl Iterator<Item = (u64, u64)> {
+        self.by_start.iter_snapshot()
+    }
+
+    /// Take `len` units from a free extent chosen by the fit policy,
+    /// returning where they start, or `None` if no extent is long enough.
+    /// They come from the start of the extent; the rest stays free. Panics
+    /// if `len` is 0.
+    pub fn allocate(&mut self, len: u64) -> Option<u64> {
+        assert!(len > 0, "allocate(0): extents can't be empty");
+        let (start, extent_len) = match self.fit {
+            Fit::First => self.by_start.iter_snapshot().find(|&(_, extent_len)| extent_len >= len)?,
+            Fit::Best => {
+                let &(extent_len, start) = self.by_len.nth(self.by_len.rank((len, 0)))?;
+                (start, extent_len)
+            }
+        };
+        self.remove((start, extent_len));
+        if extent_len > len {
+            self.add((start + len, extent_len - len));
+        }
+        self.free_units -= len;
+        Some(start)
+    }
+
+    /// Give back the `len` units from `start`, joining them to any free
+    /// extents they touch. Panics if any of them are already free: freeing
+    /// twice is a bug in the caller that this catches while it is cheap to.
+    pub fn free(&mut self, start: u64, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let end = start.checked_add(len).expect("extent runs past u64::MAX");
+        let mut extent = (start, len);
+        let before = self.by_start.rank((start, 0)).checked_sub(1);
+        if let Some(&before) = before.and_then(|index| self.by_start.nth(index)) {
+            assert!(before.0 + before.1 <= start, "freeing {}, which overlaps free {}", Span(extent), Span(before));
+            if before.0 + before.1 == start {
+                self.remove(before);
+                extent = (before.0, before.1 + len);
+            }
+        }
+        if let Some(&after) = self.by_start.nth(self.by_start.rank((start, 0))) {
+            assert!(end <= after.0, "freeing {}, which overlaps free {}", Span((start, len)), Span(after));
+            if end == after.0 {
+                self.remove(after);
+                extent.1 += after.1;
+            }
+        }
+        self.add(extent);
+        self.free_units += len;
+    }
+
+    fn add(&mut self, (start, len): (u64, u64)) {
+        self.by_start.insert((start, len));
+        self.by_len.insert((len, start));
+    }
+
+    fn remove(&mut self, (start, len): (u64, u64)) {
+        self.by_start.delete((start, len));
+        self.by_len.delete((len, start));
+    }
+}
+
+impl Default for ExtentTree {
+    fn default() -> Self {
+        ExtentTree { by_start: BTree::default(), by_len: BTree::default(), fit: Fit::First, free_units: 0 }
+    }
+}
+
+// An extent written as the range it covers, for panic messages.
+struct Span((u64, u64));
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, len) = self.0;
+        write!(f, "{}..{}", start, start + len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+    use std::panic::AssertUnwindSafe;
+
+    use super::{ExtentTree, Fit};
+    use crate::testing::Rng;
+
+    #[test]
+    fn test_extents() {
+        let mut space = ExtentTree::new(2);
+        assert_eq!(space.allocate(1), None);
+        space.free(0, 1000);
+        // carve the space up, then free every other piece
+        let pieces: Vec<u64> = (1..=20).map(|len| space.allocate(len).unwrap()).collect();
+        assert_eq!(pieces[..4], [0, 1, 3, 6]);
+        assert_eq!(space.free_units(), 1000 - 210);
+        for (index, &start) in pieces.iter().enumerate().filter(|(index, _)| index % 2 == 1) {
+            space.free(start, index as u64 + 1);
+        }
+        assert_eq!(space.len(), 10);
+
+        assert_eq!(space.allocate(5), Some(15));
+        space.set_fit(Fit::Best);
+        assert_eq!(space.allocate(5), Some(28));
+        assert_eq!(space.allocate(9), Some(45));
+        space.set_fit(Fit::First);
+        assert_eq!(space.allocate(1000), None);
+
+        let panic = std::panic::catch_unwind(AssertUnwindSafe(|| space.free(200, 5))).unwrap_err();
+        assert_eq!(panic.downcast_ref::<String>().unwrap(), "freeing 200..205, which overlaps free 190..1000");
+
+        // freeing everything in any order coalesces back to one extent
+        let mut taken: Vec<(u64, u64)> = Vec::new();
+        let mut at = 0;
+        for (start, len) in space.extents().collect::<Vec<_>>() {
+            if at < start {
+                taken.push((at, start - at));
+            }
+            at = start + len;
+        }
+        let mut rng = Rng::new(7);
+        while !taken.is_empty() {
+            let (start, len) = taken.swap_remove(rng.below(taken.len() as u64) as usize);
+            space.free(start, len);
+        }
+        assert_eq!(space.extents().collect::<Vec<_>>(), [(0, 1000)]);
+        assert_eq!(space.free_units(), 1000);
+    }
+}
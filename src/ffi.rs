@@ -0,0 +1,191 @@
+//! A C interface to a tree of `u64` keys, declared in
+//! `include/b_trees_with_delete.h` (which cbindgen regenerates from this
+//! file). Build a library C can link with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or
+//! `staticlib`).
+//!
+//! Handles are opaque pointers owned by the caller, released with the
+//! matching `_free`. No panic crosses into C: a function that panics
+//! returns its error value instead, `-1` or null, and an argument that is
+//! null is treated the same way.
+
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::frozen::SnapshotIter;
+use crate::BTree;
+
+/// A tree of `u64` keys.
+pub struct BTreeHandle(BTree<u64>);
+
+/// The keys a tree held when the iterator was made, in ascending order.
+/// Later changes to the tree don't affect it.
+pub struct BTreeIter(SnapshotIter<u64>);
+
+// Run `f`, or return `error` if it panics.
+fn guard<R>(error: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(error)
+}
+
+/// A new empty tree, or null if `branch_factor` is below 2.
+#[no_mangle]
+pub extern "C" fn btree_new(branch_factor: usize) -> *mut BTreeHandle {
+    guard(ptr::null_mut(), || match BTree::with_branch_factor(branch_factor) {
+        Ok(tree) => Box::into_raw(Box::new(BTreeHandle(tree))),
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+/// Free a tree. Iterators made from it stay valid.
+///
+/// # Safety
+///
+/// `tree` is null or came from `btree_new` and has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn btree_free(tree: *mut BTreeHandle) {
+    if !tree.is_null() {
+        guard((), || drop(Box::from_raw(tree)));
+    }
+}
+
+/// Insert `key`, keeping duplicates. 0 on success, -1 on failure.
+///
+/// # Safety
+///
+/// `tree` is null or a live tree not in use by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn btree_insert_u64(tree: *mut BTreeHandle, key: u64) -> c_int {
+    let Some(tree) = tree.as_mut() else {
+        return -1;
+    };
+    guard(-1, || {
+        tree.0.insert(key);
+        0
+    })
+}
+
+/// 1 if `key` is in the tree, 0 if not, -1 on failure.
+///
+/// # Safety
+///
+/// `tree` is null or a live tree not being changed by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn btree_search_u64(tree: *const BTreeHandle, key: u64) -> c_int {
+    let Some(tree) = tree.as_ref() else {
+        return -1;
+    };
+    guard(-1, || c_int::from(tree.0.search(key)))
+}
+
+/// Remove one copy of `key`: 1 if there was one, 0 if not, -1 on failure.
+///
+/// # Safety
+///
+/// As for `btree_insert_u64`.
+#[no_mangle]
+pub unsafe extern "C" fn btree_delete_u64(tree: *mut BTreeHandle, key: u64) -> c_int {
+    let Some(tree) = tree.as_mut() else {
+        return -1;
+    };
+    guard(-1, || c_int::from(tree.0.delete(key)))
+}
+
+/// The number of keys in the tree, 0 for null.
+///
+/// # Safety
+///
+/// As for `btree_search_u64`.
+#[no_mangle]
+pub unsafe extern "C" fn btree_len(tree: *const BTreeHandle) -> usize {
+    tree.as_ref().map_or(0, |tree| tree.0.len())
+}
+
+/// An iterator over the tree's keys as they are now, or null on failure.
+///
+/// # Safety
+///
+/// As for `btree_search_u64`.
+#[no_mangle]
+pub unsafe extern "C" fn btree_iter_new(tree: *const BTreeHandle) -> *mut BTreeIter {
+    let Some(tree) = tree.as_ref() else {
+        return ptr::null_mut();
+    };
+    guard(ptr::null_mut(), || Box::into_raw(Box::new(BTreeIter(tree.0.iter_snapshot()))))
+}
+
+/// Store the next key in `*key` and return 1, or return 0 at the end and
+/// -1 on failure.
+///
+/// # Safety
+///
+/// `iter` is null or came from `btree_iter_new` and has not been freed;
+/// `key` is null or points to writable memory for a `uint64_t`.
+#[no_mangle]
+pub unsafe extern "C" fn btree_iter_next(iter: *mut BTreeIter, key: *mut u64) -> c_int {
+    let (Some(iter), false) = (iter.as_mut(), key.is_null()) else {
+        return -1;
+    };
+    match guard(Err(()), || Ok(iter.0.next())) {
+        Ok(Some(next)) => {
+            key.write(next);
+            1
+        }
+        Ok(None) => 0,
+        Err(()) => -1,
+    }
+}
+
+/// Free an iterator.
+///
+/// # Safety
+///
+/// `iter` is null or came from `btree_iter_new` and has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn btree_iter_free(iter: *mut BTreeIter) {
+    if !iter.is_null() {
+        guard((), || drop(Box::from_raw(iter)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+
+    use super::{
+        btree_delete_u64, btree_free, btree_insert_u64, btree_iter_free, btree_iter_new, btree_iter_next, btree_len,
+        btree_new, btree_search_u64,
+    };
+
+    #[test]
+    fn test_c_interface() {
+        assert!(btree_new(1).is_null());
+        unsafe {
+            let tree = btree_new(3);
+            for key in (0..100u64).rev() {
+                assert_eq!(btree_insert_u64(tree, key * 2), 0);
+            }
+            assert_eq!(btree_len(tree), 100);
+            assert_eq!(btree_search_u64(tree, 42), 1);
+            assert_eq!(btree_search_u64(tree, 43), 0);
+            assert_eq!(btree_delete_u64(tree, 42), 1);
+            assert_eq!(btree_delete_u64(tree, 42), 0);
+
+            let iter = btree_iter_new(tree);
+            btree_free(tree);
+            let mut keys = Vec::new();
+            let mut key = 0;
+            while btree_iter_next(iter, &mut key) == 1 {
+                keys.push(key);
+            }
+            assert_eq!(btree_iter_next(iter, &mut key), 0);
+            assert_eq!(btree_iter_next(iter, ptr::null_mut()), -1);
+            btree_iter_free(iter);
+            assert_eq!(keys.len(), 99);
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+            assert_eq!(btree_insert_u64(ptr::null_mut(), 1), -1);
+            assert_eq!(btree_search_u64(ptr::null(), 1), -1);
+        }
+    }
+}
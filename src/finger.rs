@@ -0,0 +1,194 @@
+//! Finger search: lookups and inserts that start from where the last one
+//! ended instead of from the root.
+//!
+//! A `Finger` remembers the path to a node and the range of keys below each
+//! node on it. The next key is looked for from the lowest node whose range
+//! holds it, so a key close to the last costs a handful of comparisons
+//! however big the tree is. Getting back down to that node follows the
+//! remembered child indexes, which takes no comparisons.
+//!
+//! A finger stays good across inserts and deletes that only touch leaves.
+//! Anything that moves keys between nodes (splits, merges, borrows,
+//! replacing a separator, rebuilds) makes it stale, and it is found again
+//! from the root on next use.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{node_mut, BTree, Node};
+
+/// A remembered position in a tree. See the module docs.
+#[derive(Clone, Debug)]
+pub struct Finger<T> {
+    version: u64,
+    // The child index taken at each node on the path.
+    path: Vec<usize>,
+    // For the root and each node on the path, the separators on either
+    // side of it, `None` past the ends of the tree.
+    bounds: Vec<(Option<T>, Option<T>)>,
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// A finger at the node where `key` is or would be.
+    pub fn finger(&self, key: T) -> Finger<T> {
+        let mut finger = Finger {
+            version: self.ctx.version,
+            path: Vec::new(),
+            bounds: vec![(None, None)],
+        };
+        self.find_from(&mut finger, 0, key);
+        finger
+    }
+
+    /// `search`, starting from `finger` and leaving it at `key`.
+    pub fn search_near(&self, finger: &mut Finger<T>, key: T) -> bool {
+        if finger.version != self.ctx.version {
+            *finger = self.finger(key);
+            return self.search(key);
+        }
+        match self.depth_holding(finger, key, false) {
+            Ok(depth) => self.find_from(finger, depth, key),
+            // `key` is one of the separators
+            Err(()) => true,
+        }
+    }
+
+    /// `insert`, starting from `finger` and leaving it near `key`.
+    pub fn insert_hint(&mut self, finger: &mut Finger<T>, key: T) {
+        self.ctx.begin(&self.root);
+        if self.take_duplicate(key) {
+            return;
+        }
+        let depth = match finger.version == self.ctx.version {
+            true => self.depth_holding(finger, key, true).ok(),
+            false => None,
+        };
+        // The lowest node that holds `key` and has room: it takes the key
+        // without any node above it changing.
+        let depth = depth.and_then(|deepest| {
+            let mut node = &*self.root;
+            let mut roomy = None;
+            for depth in 0..=deepest {
+                if !self.props.is_maxed_out(node) {
+                    roomy = Some(depth);
+                }
+                if depth < deepest {
+                    node = &node.children[finger.path[depth]];
+                }
+            }
+            roomy
+        });
+        let Some(depth) = depth else {
+            self.insert(key);
+            *finger = self.finger(key);
+            return;
+        };
+
+        self.insert_down(&finger.path[..depth], key);
+        if finger.version != self.ctx.version {
+            *finger = self.finger(key);
+        }
+    }
+
+    // Insert `key` into the subtree of the node `path` leads to, which must
+    // have room for it and be where `key` belongs.
+    pub(crate) fn insert_down(&mut self, path: &[usize], key: T) {
+        let mut node = node_mut(&mut self.root);
+        for &index in path {
+            self.ctx.descend(index);
+            node.len += 1;
+            node = node_mut(&mut node.children[index]);
+        }
+        self.props.insert_non_full(node, key, &mut self.ctx);
+        self.props.counters.depth(self.height());
+        if let Some(recording) = &mut self.ctx.recording {
+            recording.finish(format!("insert {key:?}"), &self.root, &self.root);
+        }
+        self.ctx.inserted(&key);
+    }
+
+    // The depth of the lowest node on the finger's path whose key range
+    // holds `key`, or an error if `key` is a separator on the path and
+    // `inclusive` is false.
+    fn depth_holding(&self, finger: &Finger<T>, key: T, inclusive: bool) -> Result<usize, ()> {
+        for (depth, &(low, high)) in finger.bounds.iter().enumerate().rev() {
+            self.props.counters.compare(usize::from(low.is_some()) + usize::from(high.is_some()));
+            let above_low = low.is_none_or(|low| low < key || (inclusive && low == key));
+            let below_high = high.is_none_or(|high| key < high || (inclusive && key == high));
+            if above_low && below_high {
+                return Ok(depth);
+            }
+            if !inclusive && (low == Some(key) || high == Some(key)) {
+                return Err(());
+            }
+        }
+        // the root holds every key
+        Ok(0)
+    }
+
+    // Search down from the node at `depth` on the finger's path, extending
+    // the path as it goes.
+    fn find_from(&self, finger: &mut Finger<T>, depth: usize, key: T) -> bool {
+        let mut node: &Node<T> = self.root.node_at(&finger.path[..depth]);
+        finger.path.truncate(depth);
+        finger.bounds.truncate(depth + 1);
+        let result = node.locate(key, Some(&self.props.counters));
+        for &index in &result.path {
+            let (low, high) = finger.bounds[finger.bounds.len() - 1];
+            let low = index.checked_sub(1).map(|before| node.keys[before]).or(low);
+            let high = node.keys.get(index).copied().or(high);
+            finger.path.push(index);
+            finger.bounds.push((low, high));
+            node = &node.children[index];
+        }
+        result.found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Finger;
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_finger_search() {
+        let mut tree = BTree::new(3);
+        let mut finger: Option<Finger<u32>> = None;
+        for key in 0..2000u32 {
+            let key = (key % 40) * 50 + key / 40;
+            let finger = finger.get_or_insert_with(|| tree.finger(key));
+            tree.insert_hint(finger, key);
+            check_node(&tree.root, &tree.props, true);
+        }
+        assert!(tree.iter_snapshot().eq(0..2000));
+
+        let mut finger = tree.finger(0);
+        for key in 0..2500u32 {
+            assert_eq!(tree.search_near(&mut finger, key), key < 2000, "{key}");
+        }
+        #[cfg(feature = "metrics")]
+        {
+            tree.reset_metrics();
+            for key in 1000..1100 {
+                tree.search(key);
+            }
+            let from_root = tree.metrics().comparisons;
+            tree.reset_metrics();
+            for key in 1000..1100 {
+                tree.search_near(&mut finger, key);
+            }
+            assert!(tree.metrics().comparisons * 2 < from_root);
+        }
+        for key in (0..1000u32).map(|key| key * 2) {
+            assert!(tree.delete(key));
+            assert!(!tree.search_near(&mut finger, key));
+            assert!(tree.search_near(&mut finger, key + 1));
+        }
+    }
+}
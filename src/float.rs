@@ -0,0 +1,156 @@
+//! Floating-point keys. `f64` and `f32` are only partially ordered, so they
+//! go into a tree wrapped in `OrdF64` or `OrdF32`, which order by
+//! `total_cmp`: `-inf < ... < -0.0 < 0.0 < ... < inf`, with NaNs sorting
+//! below everything or above everything by their sign bit.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+
+use crate::codec::{KeyCodec, Ordered};
+
+/// What `OrdF64::with_policy` and `OrdF32::with_policy` do with NaN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Keep NaNs as they are, ordered by `total_cmp`: NaNs with different
+    /// signs or payloads are different keys.
+    #[default]
+    Total,
+    /// Replace every NaN with the standard positive NaN, so they are all
+    /// one key, sorting after infinity.
+    Canonical,
+    /// Refuse NaNs.
+    Reject,
+}
+
+macro_rules! ord_float {
+    ($($name:ident($float:ty, $bits:ty) => $id:expr),* $(,)?) => {
+        $(
+            #[doc = concat!("An `", stringify!($float), "` ordered by `total_cmp`, usable as a tree key.")]
+            #[derive(Clone, Copy, Debug, Default)]
+            pub struct $name(pub $float);
+
+            impl $name {
+                /// Wraps `value` as it is, NaN or not.
+                pub fn new(value: $float) -> Self {
+                    $name(value)
+                }
+
+                /// Wraps `value`, treating a NaN as `policy` says; `None`
+                /// only for a NaN under `NanPolicy::Reject`.
+                pub fn with_policy(value: $float, policy: NanPolicy) -> Option<Self> {
+                    match policy {
+                        _ if !value.is_nan() => Some($name(value)),
+                        NanPolicy::Total => Some($name(value)),
+                        NanPolicy::Canonical => Some($name(<$float>::NAN)),
+                        NanPolicy::Reject => None,
+                    }
+                }
+
+                pub fn get(self) -> $float {
+                    self.0
+                }
+            }
+
+            impl From<$float> for $name {
+                fn from(value: $float) -> Self {
+                    $name(value)
+                }
+            }
+
+            impl From<$name> for $float {
+                fn from(key: $name) -> $float {
+                    key.0
+                }
+            }
+
+            impl PartialEq for $name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.0.to_bits() == other.0.to_bits()
+                }
+            }
+
+            impl Eq for $name {}
+
+            impl PartialOrd for $name {
+                fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                    Some(self.cmp(other))
+                }
+            }
+
+            impl Ord for $name {
+                fn cmp(&self, other: &Self) -> Ordering {
+                    self.0.total_cmp(&other.0)
+                }
+            }
+
+            impl Hash for $name {
+                fn hash<H: Hasher>(&self, state: &mut H) {
+                    self.0.to_bits().hash(state);
+                }
+            }
+
+            impl KeyCodec<$name> for Ordered {
+                const ID: u8 = $id;
+                const FIXED_WIDTH: Option<usize> = Some(core::mem::size_of::<$float>());
+
+                // The same mapping `total_cmp` uses: flipping every bit of
+                // a negative and just the sign bit of a positive makes the
+                // bits compare as unsigned integers in total order.
+                fn encode(key: &$name, out: &mut Vec<u8>) {
+                    let bits = key.0.to_bits();
+                    let sign = 1 << (<$bits>::BITS - 1);
+                    let flipped = if bits & sign != 0 { !bits } else { bits ^ sign };
+                    out.extend_from_slice(&flipped.to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Option<$name> {
+                    let flipped = <$bits>::from_be_bytes(bytes.try_into().ok()?);
+                    let sign = 1 << (<$bits>::BITS - 1);
+                    let bits = if flipped & sign != 0 { flipped ^ sign } else { !flipped };
+                    Some($name(<$float>::from_bits(bits)))
+                }
+            }
+        )*
+    };
+}
+
+ord_float! {
+    OrdF64(f64, u64) => 13,
+    OrdF32(f32, u32) => 14,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NanPolicy, OrdF32, OrdF64};
+    use crate::codec::{KeyCodec, Ordered};
+    use crate::BTree;
+
+    #[test]
+    fn test_float_keys() {
+        let values = [f64::INFINITY, 1.5, -0.0, f64::NAN, 0.0, -f64::NAN, -2.0, f64::NEG_INFINITY, 1.5, f64::MIN_POSITIVE];
+        let mut tree = BTree::new(2);
+        for value in values {
+            tree.insert(OrdF64::from(value));
+        }
+        let sorted: Vec<u64> = tree.iter_snapshot().map(|key| key.get().to_bits()).collect();
+        let expected = [-f64::NAN, f64::NEG_INFINITY, -2.0, -0.0, 0.0, f64::MIN_POSITIVE, 1.5, 1.5, f64::INFINITY, f64::NAN];
+        assert_eq!(sorted, expected.map(f64::to_bits));
+        assert!(tree.search(OrdF64(f64::NAN)) && !tree.search(OrdF64(-1.0)));
+
+        let odd_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        assert_ne!(OrdF64::new(odd_nan), OrdF64::new(f64::NAN));
+        assert_eq!(OrdF64::with_policy(odd_nan, NanPolicy::Canonical), Some(OrdF64(f64::NAN)));
+        assert_eq!(OrdF64::with_policy(-f64::NAN, NanPolicy::Canonical), Some(OrdF64(f64::NAN)));
+        assert_eq!(OrdF32::with_policy(f32::NAN, NanPolicy::Reject), None);
+        assert_eq!(OrdF32::with_policy(2.5, NanPolicy::Reject), Some(OrdF32(2.5)));
+
+        for pair in expected.windows(2) {
+            let (mut low, mut high) = (Vec::new(), Vec::new());
+            Ordered::encode(&OrdF64(pair[0]), &mut low);
+            Ordered::encode(&OrdF64(pair[1]), &mut high);
+            assert!(low <= high);
+            assert_eq!(<Ordered as KeyCodec<OrdF64>>::decode(&low).unwrap().get().to_bits(), pair[0].to_bits());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::allocator::{arc_in, Allocator, ArcIn, Global};
+use crate::{Aggregate, Node};
+
+// Most nodes kept for reuse; beyond this, freed nodes are dropped so a mass
+// delete doesn't leave the tree holding its old size in spare nodes. Only
+// `reserve` goes past it.
+const MAX_FREE: usize = 64;
+
+// Nodes freed by merges and root collapses, kept with their `Arc` and key
+// and child buffers allocated so that later splits can reuse them.
+pub(crate) struct FreeList<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    nodes: Vec<ArcIn<Node<T, A, M>, M>>,
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> FreeList<T, A, M> {
+    pub(crate) fn new() -> Self {
+        FreeList { nodes: Vec::new() }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // An empty node that only the caller holds.
+    pub(crate) fn take(&mut self, degree: usize, alloc: &M) -> ArcIn<Node<T, A, M>, M>
+    where
+        T: Ord,
+    {
+        self.nodes.pop().unwrap_or_else(|| arc_in(Node::new_in(degree, alloc), alloc))
+    }
+
+    // Make sure at least `count` empty nodes are on hand.
+    pub(crate) fn reserve(&mut self, count: usize, degree: usize, alloc: &M)
+    where
+        T: Ord,
+    {
+        let missing = count.saturating_sub(self.nodes.len());
+        self.nodes.reserve(missing);
+        self.nodes.extend((0..missing).map(|_| arc_in(Node::new_in(degree, alloc), alloc)));
+    }
+
+    // Keep `node` if nothing else (a snapshot) still holds it.
+    pub(crate) fn put(&mut self, mut node: ArcIn<Node<T, A, M>, M>) {
+        if self.nodes.len() >= MAX_FREE {
+            return;
+        }
+        if let Some(free) = Arc::get_mut(&mut node) {
+            free.keys.clear();
+            free.children.clear();
+            free.len = 0;
+            free.summary = A::empty();
+            #[cfg(feature = "merkle")]
+            free.hash.take();
+            self.nodes.push(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_merges_feed_splits() {
+        let mut tree = BTree::new(2);
+        for key in 0..100u32 {
+            tree.insert(key);
+        }
+        for key in 0..50u32 {
+            tree.delete(key);
+        }
+        assert!(tree.ctx.free.len() > 0);
+
+        // nodes still held by a snapshot aren't recycled from under it
+        let snapshot = tree.snapshot();
+        for key in 50..100u32 {
+            tree.delete(key);
+        }
+
+        for key in 0..200u32 {
+            tree.insert(key);
+            check_node(&tree.root, &tree.props, true);
+        }
+        assert_eq!(tree.ctx.free.len(), 0);
+        assert_eq!(tree.iter_snapshot().collect::<Vec<_>>(), (0..200).collect::<Vec<_>>());
+        assert_eq!(snapshot.iter().collect::<Vec<_>>(), (50..100).collect::<Vec<_>>());
+    }
+}
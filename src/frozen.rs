@@ -0,0 +1,241 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::iter::FusedIterator;
+use core::ops::{Bound, RangeBounds};
+
+use crate::allocator::{Allocator, ArcIn, Global};
+use crate::{Aggregate, BTree, Node};
+
+type NodeRef<T, A, M> = ArcIn<Node<T, A, M>, M>;
+
+/// A read-only, point-in-time copy of a `BTree`.
+///
+/// Taking one is O(1): it shares every node with the tree it came from.
+/// Later changes to the tree copy the nodes on their path before touching
+/// them, so the snapshot keeps seeing exactly the keys it was taken with,
+/// and can be moved to another thread while the tree keeps changing.
+pub struct FrozenBTree<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    pub(crate) root: ArcIn<Node<T, A, M>, M>,
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> Clone for FrozenBTree<T, A, M> {
+    fn clone(&self) -> Self {
+        FrozenBTree { root: Arc::clone(&self.root) }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    pub fn snapshot(&self) -> FrozenBTree<T, A, M> {
+        FrozenBTree {
+            root: Arc::clone(&self.root),
+        }
+    }
+
+    /// Iterate over the keys as they are now, in order. The iterator holds
+    /// its own snapshot, so the tree can be changed while it is in use.
+    pub fn iter_snapshot(&self) -> SnapshotIter<T, A, M> {
+        self.snapshot().iter()
+    }
+
+    /// `iter_snapshot` over just the keys in `range`, found with one
+    /// descent to its start rather than by skipping the keys before it.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> impl Iterator<Item = T> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let before = move |key: &T| match start {
+            Bound::Included(start) => *key < start,
+            Bound::Excluded(start) => *key <= start,
+            Bound::Unbounded => false,
+        };
+        SnapshotIter::seek(Arc::clone(&self.root), before).take_while(move |key| match end {
+            Bound::Included(end) => *key <= end,
+            Bound::Excluded(end) => *key < end,
+            Bound::Unbounded => true,
+        })
+    }
+}
+
+impl<T, A, M> FrozenBTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    pub fn search(&self, key: T) -> bool {
+        self.root.search(key, None)
+    }
+
+    pub fn iter(&self) -> SnapshotIter<T, A, M> {
+        let mut iter = SnapshotIter { stack: Vec::new(), remaining: self.root.len };
+        iter.descend(Arc::clone(&self.root));
+        iter
+    }
+}
+
+/// In-order iterator over a frozen tree. See `BTree::iter_snapshot`.
+///
+/// Knows how many keys it has left from the subtree counts, so `len` and
+/// `size_hint` are exact.
+pub struct SnapshotIter<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    // Nodes on the path to the next key, with the index of that key.
+    stack: Vec<(NodeRef<T, A, M>, usize)>,
+    remaining: usize,
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> SnapshotIter<T, A, M> {
+    // Starting at the key in position `rank` under `node`, or empty past
+    // the end.
+    pub(crate) fn at(mut node: ArcIn<Node<T, A, M>, M>, mut rank: usize) -> Self
+    where
+        T: Ord,
+    {
+        let mut iter = SnapshotIter { stack: Vec::new(), remaining: node.len.saturating_sub(rank) };
+        if rank >= node.len {
+            return iter;
+        }
+        loop {
+            if node.is_leaf() {
+                iter.stack.push((node, rank));
+                return iter;
+            }
+            // the child holding `rank`, or the key before the next child
+            let mut index = 0;
+            while rank >= node.children[index].len {
+                rank -= node.children[index].len;
+                if rank == 0 {
+                    iter.stack.push((node, index));
+                    return iter;
+                }
+                rank -= 1;
+                index += 1;
+            }
+            let child = Arc::clone(&node.children[index]);
+            iter.stack.push((node, index));
+            node = child;
+        }
+    }
+
+    // Starting at the first key for which `before` is false; `before` must
+    // hold for every key up to some point in sorted order and none after.
+    pub(crate) fn seek(mut node: ArcIn<Node<T, A, M>, M>, before: impl Fn(&T) -> bool) -> Self {
+        let mut iter = SnapshotIter { stack: Vec::new(), remaining: node.len };
+        loop {
+            let index = node.keys.partition_point(&before);
+            // the keys left behind: those before `index` and the subtrees
+            // to their left
+            iter.remaining -= index + node.children.iter().take(index).map(|child| child.len).sum::<usize>();
+            let child = node.children.get(index).cloned();
+            iter.stack.push((node, index));
+            match child {
+                Some(child) => node = child,
+                None => return iter,
+            }
+        }
+    }
+
+    fn descend(&mut self, mut node: ArcIn<Node<T, A, M>, M>) {
+        loop {
+            let child = node.children.first().cloned();
+            self.stack.push((node, 0));
+            match child {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T: Copy, A: Aggregate<T>, M: Allocator + Clone> Iterator for SnapshotIter<T, A, M> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        while let Some((node, index)) = self.stack.pop() {
+            if index < node.keys.len() {
+                let key = node.keys[index];
+                let next_child = node.children.get(index + 1).cloned();
+                self.stack.push((node, index + 1));
+                if let Some(child) = next_child {
+                    self.descend(child);
+                }
+                self.remaining -= 1;
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Copy, A: Aggregate<T>, M: Allocator + Clone> ExactSizeIterator for SnapshotIter<T, A, M> {}
+
+// Once the stack is empty it stays empty.
+impl<T: Copy, A: Aggregate<T>, M: Allocator + Clone> FusedIterator for SnapshotIter<T, A, M> {}
+
+#[cfg(test)]
+mod test {
+    use super::SnapshotIter;
+    use crate::BTree;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_is_isolated() {
+        let mut tree = BTree::new(2);
+        for key in 0..200 {
+            tree.insert(key);
+        }
+        let frozen = tree.snapshot();
+        for key in 0..100 {
+            assert!(tree.delete(key));
+        }
+        for key in 200..300 {
+            tree.insert(key);
+        }
+        for key in 0..300 {
+            assert_eq!(frozen.search(key), key < 200);
+            assert_eq!(tree.search(key), key >= 100);
+        }
+        // only the path to the new key is copied
+        let frozen = tree.snapshot();
+        tree.insert(1000);
+        assert!(Arc::ptr_eq(&tree.root.children[0], &frozen.root.children[0]));
+        assert!(!Arc::ptr_eq(&tree.root, &frozen.root));
+    }
+
+    #[test]
+    fn test_iter_snapshot_during_writes() {
+        let mut tree = BTree::new(2);
+        for key in (0..100).rev() {
+            tree.insert(key);
+        }
+        let mut seen = Vec::new();
+        for key in tree.iter_snapshot() {
+            // neither change shows up in, or disturbs, the running iteration
+            tree.delete(key);
+            tree.insert(key + 1000);
+            seen.push(key);
+        }
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+        assert_eq!(tree.iter_snapshot().collect::<Vec<_>>(), (1000..1100).collect::<Vec<_>>());
+
+        let mut iter = tree.iter_snapshot();
+        assert_eq!(iter.len(), 100);
+        iter.nth(39);
+        assert_eq!(iter.size_hint(), (60, Some(60)));
+        let seek = |from| SnapshotIter::seek(Arc::clone(&tree.root), |key| *key < from);
+        assert_eq!((seek(1050).len(), seek(0).len(), seek(2000).len()), (50, 100, 0));
+        assert_eq!(seek(1077).len(), seek(1077).count());
+        assert_eq!(SnapshotIter::at(Arc::clone(&tree.root), 90).len(), 10);
+        assert!(tree.range(1050..1053).eq([1050, 1051, 1052]));
+        assert!(tree.range(..=1001).eq([1000, 1001]));
+        assert_eq!(tree.range(1098..).count(), 2);
+    }
+}
@@ -0,0 +1,88 @@
+//! How numeric keys are spread: the narrowest and widest gaps between
+//! neighbouring keys and the most crowded window, each found in one pass
+//! over the keys in order. A tree of free block numbers, say, finds its
+//! largest run of used blocks with `max_gap`.
+
+use core::fmt::Debug;
+use core::ops::Sub;
+
+use crate::BTree;
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default + Sub<Output = T>,
+{
+    /// The neighbouring keys closest together, the first such pair if
+    /// several are; their gap is `b - a`. Equal keys are a gap of zero.
+    /// `None` with fewer than two keys.
+    pub fn min_gap(&self) -> Option<(T, T)> {
+        self.best_gap(|gap, best| gap < best)
+    }
+
+    /// The neighbouring keys furthest apart, the first such pair if several
+    /// are. `None` with fewer than two keys.
+    pub fn max_gap(&self) -> Option<(T, T)> {
+        self.best_gap(|gap, best| gap > best)
+    }
+
+    /// The start of the `width` wide window, `start..start + width`, that
+    /// holds the most keys, and how many it holds. Windows start at a key,
+    /// the lowest such key if several hold as many. `None` for an empty
+    /// tree.
+    pub fn densest_range(&self, width: T) -> Option<(T, usize)> {
+        let mut ahead = self.iter_snapshot().peekable();
+        let mut best: Option<(T, usize)> = None;
+        // keys from the start of the window up to `ahead`
+        let mut count = 0;
+        for start in self.iter_snapshot() {
+            count += core::iter::from_fn(|| ahead.next_if(|&key| start <= key && key - start < width)).count();
+            if best.is_none_or(|(_, most)| count > most) {
+                best = Some((start, count));
+            }
+            // with no width, nothing was counted and `ahead` stays put
+            count = count.saturating_sub(1);
+        }
+        best
+    }
+
+    // The first neighbouring pair whose gap `better` prefers to every
+    // other's.
+    fn best_gap(&self, better: impl Fn(T, T) -> bool) -> Option<(T, T)> {
+        let mut keys = self.iter_snapshot();
+        let mut last = keys.next()?;
+        let mut best: Option<(T, T)> = None;
+        for key in keys {
+            if best.is_none_or(|(a, b)| better(key - last, b - a)) {
+                best = Some((last, key));
+            }
+            last = key;
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BTree;
+
+    #[test]
+    fn test_gaps() {
+        let mut tree = BTree::new(2);
+        assert_eq!((tree.min_gap(), tree.max_gap(), tree.densest_range(5)), (None, None, None));
+        tree.insert(7);
+        assert_eq!((tree.min_gap(), tree.densest_range(5)), (None, Some((7, 1))));
+        for key in [1, 4, 20, 21, 23, 24, 40, 80, 81, 82, 83, 84] {
+            tree.insert(key);
+        }
+        assert_eq!(tree.min_gap(), Some((20, 21)));
+        assert_eq!(tree.max_gap(), Some((40, 80)));
+        assert_eq!(tree.densest_range(5), Some((80, 5)));
+        assert_eq!(tree.densest_range(2), Some((20, 2)));
+        assert_eq!(tree.densest_range(1), Some((1, 1)));
+        assert_eq!(tree.densest_range(100), Some((1, 13)));
+        assert_eq!(tree.densest_range(0), Some((1, 0)));
+        tree.insert(4);
+        assert_eq!(tree.min_gap(), Some((4, 4)));
+        assert_eq!(tree.densest_range(1), Some((4, 2)));
+    }
+}
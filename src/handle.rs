@@ -0,0 +1,143 @@
+//! Two-phase changes: find a key once with `locate`, then remove it or
+//! insert next to it without searching the tree again.
+//!
+//! An `EntryHandle` remembers the child indexes down to the node holding a
+//! key, as a `Finger` remembers the way to a node. It stays good while only
+//! leaves change. Once keys move between nodes (splits, merges, borrows),
+//! or the key itself has gone, using it costs the search it was meant to
+//! save but gives the same result as `delete` or `insert` would.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{BTree, DuplicatePolicy, Node};
+
+/// Where a key was found by `BTree::locate`. See the module docs.
+#[derive(Clone, Debug)]
+pub struct EntryHandle<T> {
+    version: u64,
+    path: Vec<usize>,
+    index: usize,
+    key: T,
+}
+
+impl<T: Copy> EntryHandle<T> {
+    /// The key as it was stored when found.
+    pub fn key(&self) -> T {
+        self.key
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// A handle on the stored key equal to `key`, or `None` if there is
+    /// none: `search` and `get_key` that can be acted on afterwards.
+    pub fn locate(&self, key: &T) -> Option<EntryHandle<T>> {
+        let result = self.root.locate(*key, Some(&self.props.counters));
+        if !result.found {
+            return None;
+        }
+        let key = self.root.node_at(&result.path).keys[result.index];
+        Some(EntryHandle { version: self.ctx.version, path: result.path, index: result.index, key })
+    }
+
+    /// `delete(handle.key())`, straight from where the handle points while
+    /// it is good. Returns whether a key was removed.
+    pub fn remove_at(&mut self, handle: EntryHandle<T>) -> bool {
+        if self.found_at(&handle).is_none() {
+            return self.delete(handle.key);
+        }
+        self.delete_found(&handle.path, handle.index, handle.key);
+        true
+    }
+
+    /// `insert(key)` for a key no smaller than the handle's, put straight
+    /// into the handle's leaf when the handle is good, the leaf has room and
+    /// no separator comes between the two keys. Panics if `key` is smaller.
+    pub fn insert_after(&mut self, handle: &EntryHandle<T>, key: T) {
+        assert!(handle.key <= key, "insert_after: {key:?} is smaller than the handle's key {:?}", handle.key);
+        if self.props.duplicates != DuplicatePolicy::Allow || !self.fits_after(handle, key) {
+            self.insert(key);
+            return;
+        }
+        self.ctx.begin(&self.root);
+        self.insert_down(&handle.path, key);
+    }
+
+    // The node holding the handle's key, if nothing has moved it.
+    fn found_at(&self, handle: &EntryHandle<T>) -> Option<&Node<T>> {
+        if handle.version != self.ctx.version {
+            return None;
+        }
+        let node = handle.path.iter().try_fold(&*self.root, |node, &index| node.children.get(index).map(|child| &**child))?;
+        self.props.counters.compare(1);
+        (node.keys.get(handle.index) == Some(&handle.key)).then_some(node)
+    }
+
+    // Whether `key` belongs in the handle's leaf and fits there.
+    fn fits_after(&self, handle: &EntryHandle<T>, key: T) -> bool {
+        match self.found_at(handle) {
+            Some(node) if node.is_leaf() && !self.props.is_maxed_out(node) => {}
+            _ => return false,
+        }
+        // the separator just above the leaf's keys, the last on the way
+        // down that has one
+        let (mut node, mut high) = (&*self.root, None);
+        for &index in &handle.path {
+            high = node.keys.get(index).or(high);
+            node = &node.children[index];
+        }
+        self.props.counters.compare(usize::from(high.is_some()));
+        high.is_none_or(|high| key <= *high)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_entry_handle() {
+        let mut tree = BTree::new(3);
+        for key in (0..500u32).map(|key| key * 4) {
+            tree.insert(key);
+        }
+        assert!(tree.locate(&1).is_none());
+        let handle = tree.locate(&400).unwrap();
+        assert_eq!(handle.key(), 400);
+        #[cfg(feature = "metrics")]
+        {
+            tree.reset_metrics();
+            assert!(tree.remove_at(handle.clone()));
+            // checking the handle is good is the only comparison
+            assert_eq!(tree.metrics().comparisons, 1);
+            tree.insert(400);
+        }
+
+        // leaves change, the handles stay good
+        let mut handle = tree.locate(&1000).unwrap();
+        for key in 1001..1004 {
+            tree.insert_after(&handle, key);
+            handle = tree.locate(&key).unwrap();
+        }
+        assert!(tree.remove_at(tree.locate(&1002).unwrap()));
+        assert!(tree.iter_snapshot().skip_while(|&key| key < 996).take(5).eq([996, 1000, 1001, 1003, 1004]));
+        check_node(&tree.root, &tree.props, true);
+
+        // a stale handle still does the right thing
+        let handle = tree.locate(&4).unwrap();
+        for key in 0..200 {
+            tree.delete(key * 4 + 800);
+        }
+        tree.insert_after(&handle, 5);
+        assert!(tree.remove_at(handle));
+        let gone = tree.locate(&8).unwrap();
+        tree.delete(8);
+        assert!(!tree.remove_at(gone));
+        assert!(tree.iter_snapshot().take(3).eq([0, 5, 12]));
+        check_node(&tree.root, &tree.props, true);
+    }
+}
@@ -0,0 +1,230 @@
+//! Undo and redo, for editors and other applications that let people take
+//! changes back.
+//!
+//! The tree before each change is kept the way a snapshot keeps it, by
+//! holding on to its root: the next change copies the nodes it touches
+//! instead of changing them in place, so a step costs the nodes on one path
+//! rather than a copy of the tree. Undoing puts the old root back.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+
+use crate::allocator::{Allocator, ArcIn, Global};
+use crate::diff::diff_roots;
+use crate::{Aggregate, BTree, Context, Node};
+
+pub(crate) struct History<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    // The tree before each change still done, oldest first.
+    undo: Vec<ArcIn<Node<T, A, M>, M>>,
+    // The tree before each undo, most recently undone last.
+    redo: Vec<ArcIn<Node<T, A, M>, M>>,
+    // Named points in `undo`, by how many steps were done when they were set.
+    checkpoints: Vec<(String, usize)>,
+    // The tree as the operation in progress found it, until it changes
+    // something.
+    before: Option<ArcIn<Node<T, A, M>, M>>,
+    // How many operations made of other operations are in progress: what
+    // they do is one step, not one per part.
+    nested: usize,
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> Context<T, A, M> {
+    // Called by each operation that may change the tree, before it does.
+    pub(crate) fn begin(&mut self, root: &ArcIn<Node<T, A, M>, M>) {
+        if let Some(history) = &mut self.history {
+            if history.nested == 0 {
+                history.before = Some(Arc::clone(root));
+            }
+        }
+    }
+
+    pub(crate) fn begin_nested(&mut self, root: &ArcIn<Node<T, A, M>, M>) {
+        self.begin(root);
+        if let Some(history) = &mut self.history {
+            history.nested += 1;
+        }
+    }
+
+    pub(crate) fn end_nested(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.nested -= 1;
+        }
+    }
+
+    // Called with each key inserted or removed: the first one makes the
+    // operation in progress a step.
+    pub(crate) fn changed(&mut self) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        if let Some(before) = history.before.take() {
+            history.undo.push(before);
+            history.redo.clear();
+            let done = history.undo.len();
+            history.checkpoints.retain(|&(_, at)| at < done);
+        }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// Start keeping the tree as it was before each change, for `undo`.
+    /// Each call of `insert`, `delete`, `replace`, `insert_batch`,
+    /// `keep_largest` and the like is one step, and so is a whole
+    /// `drain_range`. Does nothing if history is already kept.
+    pub fn enable_history(&mut self) {
+        self.ctx.history.get_or_insert_with(|| History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            checkpoints: Vec::new(),
+            before: None,
+            nested: 0,
+        });
+    }
+
+    /// Stop keeping history and let go of the old trees.
+    pub fn disable_history(&mut self) {
+        self.ctx.history = None;
+    }
+
+    /// Take back the last change still done, returning false if there is
+    /// none. The keys it put back and took out are reported to hooks and
+    /// the journal as inserts and deletes.
+    pub fn undo(&mut self) -> bool {
+        let Some(history) = &mut self.ctx.history else {
+            return false;
+        };
+        let Some(root) = history.undo.pop() else {
+            return false;
+        };
+        history.before = None;
+        let current = mem::replace(&mut self.root, root);
+        history.redo.push(Arc::clone(&current));
+        self.restored(&current);
+        true
+    }
+
+    /// Do again the change last taken back by `undo`, returning false if
+    /// there is none. Any other change since then leaves nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(history) = &mut self.ctx.history else {
+            return false;
+        };
+        let Some(root) = history.redo.pop() else {
+            return false;
+        };
+        history.before = None;
+        let current = mem::replace(&mut self.root, root);
+        history.undo.push(Arc::clone(&current));
+        self.restored(&current);
+        true
+    }
+
+    /// Name the tree as it is now, for `undo_to`. A name used before now
+    /// means this point. Does nothing unless history is kept.
+    pub fn checkpoint(&mut self, name: &str) {
+        if let Some(history) = &mut self.ctx.history {
+            history.checkpoints.retain(|(checkpoint, _)| checkpoint != name);
+            history.checkpoints.push((String::from(name), history.undo.len()));
+        }
+    }
+
+    /// Undo every change since the checkpoint `name`, or redo back to it if
+    /// it has itself been undone. Returns false, changing nothing, if there
+    /// is no such checkpoint: it was never set, or a change made after
+    /// undoing past it took its place.
+    pub fn undo_to(&mut self, name: &str) -> bool {
+        let Some(history) = &self.ctx.history else {
+            return false;
+        };
+        let Some(&(_, at)) = history.checkpoints.iter().find(|(checkpoint, _)| checkpoint == name) else {
+            return false;
+        };
+        while self.ctx.history.as_ref().is_some_and(|history| history.undo.len() > at) {
+            self.undo();
+        }
+        while self.ctx.history.as_ref().is_some_and(|history| history.undo.len() < at) {
+            self.redo();
+        }
+        true
+    }
+
+    // After the root has been swapped for an older or newer one.
+    fn restored(&mut self, old: &ArcIn<Node<T, A, M>, M>) {
+        self.ctx.version += 1;
+        if self.ctx.listening() {
+            let diff = diff_roots(old, &self.root);
+            for key in &diff.only_in_self {
+                self.ctx.removed(key);
+            }
+            for key in &diff.only_in_other {
+                self.ctx.inserted(key);
+            }
+        }
+        self.record_whole(|| String::from("restore from history"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut tree = BTree::new(2);
+        tree.insert(100);
+        tree.enable_history();
+        tree.enable_journal();
+        for key in 0..50u32 {
+            tree.insert(key);
+        }
+        tree.checkpoint("filled");
+        assert!(!tree.delete(1000));
+        tree.drain_range(10..40).for_each(drop);
+        tree.insert_batch((200..300).collect());
+        tree.keep_smallest(30);
+        assert_eq!(tree.len(), 30);
+
+        assert!(tree.undo());
+        assert_eq!(tree.len(), 121);
+        assert!(tree.undo() && tree.undo());
+        assert!(tree.iter_snapshot().eq((0..50).chain([100])));
+        assert!(tree.redo());
+        assert_eq!(tree.len(), 21);
+        check_node(&tree.root, &tree.props, true);
+
+        // undoing and redoing are told to the journal like any change
+        let mut replica = BTree::new(3);
+        replica.insert(100);
+        replica.replay(tree.journal().unwrap().records());
+        assert!(replica.iter_snapshot().eq(tree.iter_snapshot()));
+
+        tree.insert(7);
+        assert!(!tree.redo());
+        assert!(tree.undo_to("filled"));
+        assert!(tree.iter_snapshot().eq((0..50).chain([100])));
+        assert!(tree.undo_to("filled"));
+        for _ in 0..50 {
+            assert!(tree.undo());
+        }
+        assert!(!tree.undo());
+        assert!(tree.iter_snapshot().eq([100]));
+        assert!(tree.undo_to("filled"));
+        assert_eq!(tree.len(), 51);
+
+        // nothing kept for undo means nothing to undo to
+        tree.undo();
+        tree.delete(100);
+        assert!(!tree.undo_to("filled"));
+        tree.disable_history();
+        assert!(!tree.undo());
+    }
+}
@@ -0,0 +1,122 @@
+//! Callbacks run after keys go into or come out of a tree, for keeping
+//! caches, counters or other indexes in step with it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::allocator::Allocator;
+use crate::journal::Change;
+use crate::{Aggregate, BTree, Context};
+
+type Hook<T> = Box<dyn FnMut(&T) + Send + Sync>;
+
+// The registered callbacks, run in the order they were added.
+pub(crate) struct Hooks<T> {
+    insert: Vec<Hook<T>>,
+    remove: Vec<Hook<T>>,
+}
+
+impl<T> Hooks<T> {
+    pub(crate) fn new() -> Self {
+        Hooks { insert: Vec::new(), remove: Vec::new() }
+    }
+}
+
+// Hooks and the journal hear of every change.
+impl<T: Copy, A: Aggregate<T>, M: Allocator + Clone> Context<T, A, M> {
+    pub(crate) fn inserted(&mut self, key: &T) {
+        self.changed();
+        if let Some(journal) = &mut self.journal {
+            journal.push(Change::Insert(*key));
+        }
+        for hook in &mut self.hooks.insert {
+            hook(key);
+        }
+    }
+
+    // Whether anything listens for removals, so work done only to tell
+    // hooks and the journal about them can be skipped.
+    pub(crate) fn removing(&self) -> bool {
+        !self.hooks.remove.is_empty() || self.journal.is_some()
+    }
+
+    // Whether anything hears of changes at all.
+    pub(crate) fn listening(&self) -> bool {
+        !self.hooks.insert.is_empty() || self.removing()
+    }
+
+    pub(crate) fn removed(&mut self, key: &T) {
+        self.changed();
+        if let Some(journal) = &mut self.journal {
+            journal.push(Change::Delete(*key));
+        }
+        for hook in &mut self.hooks.remove {
+            hook(key);
+        }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// Call `hook` with every key stored from now on, by any insert. A key
+    /// that replaces an equal one counts as the old one removed and the new
+    /// one inserted.
+    pub fn on_insert(&mut self, hook: impl FnMut(&T) + Send + Sync + 'static) {
+        self.ctx.hooks.insert.push(Box::new(hook));
+    }
+
+    /// Call `hook` with every key removed from now on, as it was stored: by
+    /// `delete`, `drain_range` or a replacement.
+    pub fn on_remove(&mut self, hook: impl FnMut(&T) + Send + Sync + 'static) {
+        self.ctx.hooks.remove.push(Box::new(hook));
+    }
+
+    /// Drop every hook added by `on_insert` and `on_remove`.
+    pub fn clear_hooks(&mut self) {
+        self.ctx.hooks = Hooks::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::test::Tagged;
+    use crate::{BTree, DuplicatePolicy, Finger};
+
+    #[test]
+    fn test_hooks() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = BTree::new(2);
+        let inserted = Arc::clone(&log);
+        tree.on_insert(move |key: &Tagged| inserted.lock().unwrap().push(format!("+{}{}", key.0, key.1)));
+        let removed = Arc::clone(&log);
+        tree.on_remove(move |key: &Tagged| removed.lock().unwrap().push(format!("-{}{}", key.0, key.1)));
+
+        tree.insert(Tagged(1, 'a'));
+        tree.insert_max(Tagged(5, 'a')).unwrap();
+        tree.insert_batch(vec![Tagged(3, 'a'), Tagged(2, 'a')]);
+        let mut finger: Finger<Tagged> = tree.finger(Tagged(4, '?'));
+        tree.insert_hint(&mut finger, Tagged(4, 'a'));
+        tree.replace(Tagged(3, 'b'));
+        assert!(tree.delete(Tagged(1, '?')) && !tree.delete(Tagged(1, '?')));
+        tree.set_duplicate_policy(DuplicatePolicy::Reject);
+        tree.insert(Tagged(2, 'b'));
+        tree.set_duplicate_policy(DuplicatePolicy::Replace);
+        tree.insert(Tagged(2, 'c'));
+        assert_eq!(tree.drain_range(Tagged(4, '?')..).count(), 2);
+        assert_eq!(
+            log.lock().unwrap().join(" "),
+            "+1a +5a +2a +3a +4a -3a +3b -1a -2a +2c -4a -5a"
+        );
+
+        tree.clear_hooks();
+        tree.insert(Tagged(9, 'a'));
+        assert_eq!(log.lock().unwrap().len(), 12);
+    }
+}
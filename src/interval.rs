@@ -0,0 +1,105 @@
+use core::fmt::Debug;
+
+use crate::{Aggregate, BTree};
+
+/// Closed intervals `[start, end]`, found by the points or ranges they
+/// overlap.
+///
+/// Intervals are kept in a `BTree` ordered by start, with the largest end
+/// in each subtree as its summary: a subtree whose intervals all end
+/// before the range asked about is skipped whole, and the scan stops at the
+/// first interval starting after it.
+pub struct IntervalTree<T: Ord + Copy + Debug + Default> {
+    tree: BTree<(T, T), MaxEnd>,
+}
+
+// The summary: the largest end in a subtree.
+struct MaxEnd;
+
+impl<T: Ord + Copy> Aggregate<(T, T)> for MaxEnd {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+    fn key(interval: &(T, T)) -> Option<T> {
+        Some(interval.1)
+    }
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        match (left, right) {
+            (Some(left), Some(right)) => Some(*left.max(right)),
+            _ => left.or(*right),
+        }
+    }
+}
+
+impl<T: Ord + Copy + Debug + Default> IntervalTree<T> {
+    pub fn new(branch_factor: usize) -> Self {
+        IntervalTree { tree: BTree::with_aggregate(branch_factor) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Panics if `start` is after `end`.
+    pub fn insert(&mut self, start: T, end: T) {
+        assert!(start <= end, "an interval can't start after it ends");
+        self.tree.insert((start, end));
+    }
+
+    pub fn delete(&mut self, start: T, end: T) -> bool {
+        self.tree.delete((start, end))
+    }
+
+    /// The intervals holding `point`, by start.
+    pub fn query_point(&self, point: T) -> impl Iterator<Item = (T, T)> + '_ {
+        self.query_overlap(point, point)
+    }
+
+    /// The intervals sharing at least one point with `[low, high]`, by
+    /// start.
+    pub fn query_overlap(&self, low: T, high: T) -> impl Iterator<Item = (T, T)> + '_ {
+        self.tree
+            .pruned(move |max_end| max_end.is_some_and(|end| end >= low))
+            .take_while(move |interval| interval.0 <= high)
+            .filter(move |interval| interval.1 >= low)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IntervalTree;
+
+    #[test]
+    fn test_interval_queries() {
+        let mut tree = IntervalTree::new(2);
+        let mut intervals = Vec::new();
+        for index in 0..400u32 {
+            let start = (index * 7919) % 1000;
+            let interval = (start, start + (index * 31) % 50);
+            tree.insert(interval.0, interval.1);
+            intervals.push(interval);
+        }
+        for &(start, end) in intervals.iter().step_by(4) {
+            assert!(tree.delete(start, end));
+        }
+        intervals = intervals.into_iter().enumerate().filter(|(index, _)| index % 4 != 0).map(|(_, i)| i).collect();
+        intervals.sort();
+        assert_eq!(tree.len(), 300);
+
+        for point in (0..1100).step_by(7) {
+            let expected: Vec<_> = intervals.iter().copied().filter(|&(start, end)| start <= point && point <= end).collect();
+            assert_eq!(tree.query_point(point).collect::<Vec<_>>(), expected, "point {point}");
+        }
+        for (low, high) in [(0, 10), (100, 120), (990, 2000), (500, 500)] {
+            let expected: Vec<_> = intervals.iter().copied().filter(|&(start, end)| start <= high && low <= end).collect();
+            assert_eq!(tree.query_overlap(low, high).collect::<Vec<_>>(), expected);
+        }
+    }
+}
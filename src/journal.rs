@@ -0,0 +1,158 @@
+//! A journal of the changes made to a tree: every key inserted or removed,
+//! numbered in order, to replay on another tree (a replica, or a copy
+//! restored from an older snapshot) or to look back through when a tree
+//! ends up holding what it shouldn't.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::BTree;
+
+/// One change to a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change<T> {
+    Insert(T),
+    Delete(T),
+}
+
+/// A change and its place in the journal, counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record<T> {
+    pub seq: u64,
+    pub change: Change<T>,
+}
+
+/// The changes recorded since `BTree::enable_journal`, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct Journal<T> {
+    records: Vec<Record<T>>,
+    // The sequence number of the last change, kept when records are
+    // truncated away.
+    last_seq: u64,
+}
+
+impl<T> Journal<T> {
+    pub fn records(&self) -> &[Record<T>] {
+        &self.records
+    }
+
+    /// The records after `seq`, which a replica that has applied everything
+    /// up to `seq` still needs.
+    pub fn since(&self, seq: u64) -> &[Record<T>] {
+        let start = self.records.partition_point(|record| record.seq <= seq);
+        &self.records[start..]
+    }
+
+    /// The sequence number of the last change, 0 if there has been none.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    pub(crate) fn push(&mut self, change: Change<T>) {
+        self.last_seq += 1;
+        self.records.push(Record { seq: self.last_seq, change });
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Start recording every insert and delete that changes the tree, as
+    /// listed under `on_insert` and `on_remove`. Does nothing if a journal
+    /// is already being kept.
+    pub fn enable_journal(&mut self) {
+        self.ctx.journal.get_or_insert_with(Journal::default);
+    }
+
+    /// Stop recording and hand back what was recorded.
+    pub fn disable_journal(&mut self) -> Option<Journal<T>> {
+        self.ctx.journal.take()
+    }
+
+    pub fn journal(&self) -> Option<&Journal<T>> {
+        self.ctx.journal.as_ref()
+    }
+
+    /// Drop the records up to and including `upto`, once everything that
+    /// replays them has, or once the tree has been saved as of `upto`.
+    pub fn truncate_journal(&mut self, upto: u64) {
+        if let Some(journal) = &mut self.ctx.journal {
+            let end = journal.records.partition_point(|record| record.seq <= upto);
+            journal.records.drain(..end);
+        }
+    }
+
+    /// Apply `records` in order. Replaying a whole journal onto an empty
+    /// tree rebuilds the tree it was kept for, with the same keys.
+    pub fn replay(&mut self, records: &[Record<T>]) {
+        for record in records {
+            match record.change {
+                Change::Insert(key) => self.insert(key),
+                Change::Delete(key) => _ = self.delete(key),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, Record};
+    use crate::test::Tagged;
+    use crate::BTree;
+
+    #[test]
+    fn test_journal_replay() {
+        let mut tree = BTree::new(2);
+        tree.insert(1000);
+        tree.enable_journal();
+        for key in 0..500u32 {
+            tree.insert((key * 7919) % 500);
+        }
+        for key in (0..500u32).step_by(3) {
+            tree.delete(key);
+        }
+        assert!(!tree.delete(9999));
+        tree.replace(1);
+        tree.keep_smallest(300);
+        let journal = tree.journal().unwrap();
+        assert_eq!(journal.records()[0], Record { seq: 1, change: Change::Insert(0) });
+        assert_eq!(journal.records()[500], Record { seq: 501, change: Change::Delete(0) });
+        assert_eq!(journal.last_seq(), journal.records().len() as u64);
+
+        let mut replica = BTree::new(3);
+        replica.insert(1000);
+        replica.replay(&journal.records()[..600]);
+        let seen = 600;
+        tree.insert(7000);
+        replica.replay(tree.journal().unwrap().since(seen));
+        assert!(replica.iter_snapshot().eq(tree.iter_snapshot()));
+
+        let last = tree.journal().unwrap().last_seq();
+        tree.truncate_journal(last - 1);
+        let journal = tree.disable_journal().unwrap();
+        assert_eq!(journal.records(), [Record { seq: last, change: Change::Insert(7000) }]);
+        tree.insert(1);
+        assert!(tree.journal().is_none());
+    }
+
+    #[test]
+    fn test_journal_records_stored_key() {
+        let mut tree = BTree::new(2);
+        tree.enable_journal();
+        tree.insert(Tagged(1, 'a'));
+        assert!(tree.delete(Tagged(1, '?')));
+        // `Tagged` compares by number alone, so the tags are what tell
+        let tags: Vec<_> = tree
+            .journal()
+            .unwrap()
+            .records()
+            .iter()
+            .map(|record| match record.change {
+                Change::Insert(key) => ('+', key.1),
+                Change::Delete(key) => ('-', key.1),
+            })
+            .collect();
+        assert_eq!(tags, [('+', 'a'), ('-', 'a')]);
+    }
+}
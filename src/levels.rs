@@ -0,0 +1,115 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{BTree, Node};
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// The tree in level order: one item per depth, root first, holding the
+    /// keys of each node at that depth from left to right.
+    pub fn levels(&self) -> Levels<'_, T> {
+        Levels { nodes: vec![&*self.root] }
+    }
+
+    /// How many leaves there are at each depth, the root at depth 0. In a
+    /// sound tree every leaf is at the same depth, so only the last entry is
+    /// nonzero.
+    pub fn depth_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        let mut nodes = vec![&*self.root];
+        while !nodes.is_empty() {
+            histogram.push(nodes.iter().filter(|node| node.is_leaf()).count());
+            nodes = nodes.iter().flat_map(|node| node.children.iter().map(|child| &**child)).collect();
+        }
+        histogram
+    }
+
+    /// Panic, showing the `depth_histogram`, unless every leaf is at the
+    /// same depth.
+    pub fn assert_uniform_depth(&self) {
+        let histogram = self.depth_histogram();
+        let depths = histogram.iter().filter(|&&leaves| leaves > 0).count();
+        assert!(depths == 1, "leaves at {depths} different depths, counts by depth {histogram:?}");
+    }
+}
+
+/// Level-order iterator over a tree. See `BTree::levels`.
+pub struct Levels<'a, T> {
+    // The nodes of the next level.
+    nodes: Vec<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Levels<'a, T> {
+    type Item = Vec<&'a [T]>;
+
+    fn next(&mut self) -> Option<Vec<&'a [T]>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let level = self.nodes.iter().map(|node| node.keys.as_slice()).collect();
+        self.nodes = self.nodes.iter().flat_map(|node| node.children.iter().map(|child| &**child)).collect();
+        Some(level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+    use std::panic::AssertUnwindSafe;
+
+    use crate::{BTree, Node};
+
+    #[test]
+    fn test_levels() {
+        let mut tree = BTree::new(2);
+        for key in [10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+            tree.insert(key);
+        }
+        let levels: Vec<Vec<&[i32]>> = tree.levels().collect();
+        assert_eq!(
+            levels,
+            [
+                vec![&[40][..]],
+                vec![&[20][..], &[60, 80]],
+                vec![&[10][..], &[30], &[50], &[70], &[90, 100]],
+            ]
+        );
+
+        // every node above the last level has a child per gap between its
+        // keys, so the leaves are all on the last level
+        let mut tree = BTree::new(3);
+        for key in 0..500 {
+            tree.insert(key);
+        }
+        let levels: Vec<Vec<&[i32]>> = tree.levels().collect();
+        let keys: usize = levels.iter().flatten().map(|keys| keys.len()).sum();
+        assert_eq!(keys, 500);
+        for pair in levels.windows(2) {
+            assert_eq!(pair[1].len(), pair[0].iter().map(|keys| keys.len() + 1).sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn test_depth_histogram() {
+        let mut tree = BTree::new(2);
+        assert_eq!(tree.depth_histogram(), [1]);
+        for key in [10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+            tree.insert(key);
+        }
+        assert_eq!(tree.depth_histogram(), [0, 0, 5]);
+        tree.assert_uniform_depth();
+
+        // a leaf hung where a subtree of height 2 belongs
+        let root = Arc::get_mut(&mut tree.root).unwrap();
+        root.children[0] = Arc::new(Node::new(4, Some(vec![10, 20, 30]), None));
+        assert_eq!(tree.depth_histogram(), [0, 1, 3]);
+        let panic = std::panic::catch_unwind(AssertUnwindSafe(|| tree.assert_uniform_depth())).unwrap_err();
+        assert_eq!(
+            panic.downcast_ref::<String>().unwrap(),
+            "leaves at 2 different depths, counts by depth [0, 1, 3]"
+        );
+    }
+}
@@ -0,0 +1,1149 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+
+use allocator::{arc_in, vec_in, Allocator, ArcIn, Global, VecIn};
+use free_list::FreeList;
+use history::History;
+use hooks::Hooks;
+use metrics::Counters;
+use record::Recording;
+#[cfg(feature = "tracing")]
+use trace::{key_range, node_id};
+use trace::event;
+
+pub mod aggregate;
+pub mod allocator;
+#[cfg(feature = "tokio")]
+pub mod async_disk;
+#[cfg(feature = "std")]
+pub mod background;
+mod batch;
+pub mod bounded;
+#[cfg(feature = "std")]
+pub mod buffer_pool;
+pub mod builder;
+pub mod buffered;
+mod bulk;
+pub mod bytes;
+pub mod codec;
+pub mod composite;
+#[cfg(feature = "std")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+mod crc32;
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod disk;
+pub mod drain;
+pub mod error;
+pub mod expiring;
+pub mod extent;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod finger;
+pub mod float;
+mod free_list;
+pub mod frozen;
+mod gaps;
+pub mod handle;
+mod history;
+mod hooks;
+pub mod interval;
+pub mod journal;
+pub mod levels;
+pub mod memory;
+pub mod merge;
+#[cfg(feature = "merkle")]
+pub mod merkle;
+pub mod metrics;
+pub mod multi_index;
+pub mod multimap;
+#[cfg(feature = "std")]
+pub mod optimistic;
+#[cfg(feature = "std")]
+pub mod pager;
+#[cfg(feature = "rayon")]
+mod parallel;
+pub mod policy;
+pub mod prefixed;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+mod pretty;
+pub mod priority_queue;
+mod rank;
+pub mod record;
+mod search;
+#[cfg(feature = "std")]
+pub mod sharded;
+#[cfg(feature = "mmap")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod sort;
+#[cfg(feature = "std")]
+pub mod sync;
+pub mod testing;
+pub mod tombstone;
+mod top_k;
+mod trace;
+#[cfg(feature = "std")]
+pub mod value_log;
+#[cfg(feature = "std")]
+pub mod verify;
+#[cfg(feature = "mmap")]
+pub mod view;
+pub mod visit;
+#[cfg(feature = "std")]
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use aggregate::Aggregate;
+#[cfg(feature = "tokio")]
+pub use async_disk::AsyncDiskBTree;
+#[cfg(feature = "std")]
+pub use background::BackgroundDiskBTree;
+pub use bounded::{BoundedBTree, Eviction};
+pub use buffered::BufferedBTree;
+pub use builder::BTreeBuilder;
+pub use codec::{KeyCodec, Ordered, Varint};
+pub use composite::Composite;
+#[cfg(feature = "std")]
+pub use compress::Compression;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentBTree;
+pub use diff::TreeDiff;
+#[cfg(feature = "std")]
+pub use disk::{DiskBTree, DiskOptions};
+pub use drain::DrainRange;
+pub use error::{ConfigError, Error, OccupiedError};
+pub use expiring::ExpiringBTree;
+pub use extent::{ExtentTree, Fit};
+pub use finger::Finger;
+pub use float::{NanPolicy, OrdF32, OrdF64};
+pub use frozen::{FrozenBTree, SnapshotIter};
+pub use handle::EntryHandle;
+pub use interval::IntervalTree;
+pub use journal::{Change, Journal, Record};
+pub use levels::Levels;
+pub use memory::{LevelUsage, MemoryUsage};
+pub use merge::{MergeIter, Source};
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "profile")]
+pub use metrics::Profile;
+pub use multi_index::MultiIndex;
+pub use multimap::BTreeMultiMap;
+#[cfg(feature = "std")]
+pub use optimistic::OptimisticBTree;
+pub use policy::{DuplicatePolicy, RebalancePolicy, SplitPolicy, Strategy};
+pub use prefixed::PrefixBTree;
+pub use priority_queue::TreePriorityQueue;
+pub use rank::CountMismatch;
+pub use record::{Recorder, Step};
+#[cfg(feature = "std")]
+pub use sharded::ShardedBTree;
+#[cfg(feature = "mmap")]
+pub use shared::BTreeReader;
+#[cfg(feature = "std")]
+pub use sort::{sort_file, SortOptions};
+#[cfg(feature = "std")]
+pub use sync::SyncBTree;
+pub use tombstone::TombstoneBTree;
+#[cfg(feature = "std")]
+pub use value_log::{DiskMap, DiskMapOptions};
+#[cfg(feature = "std")]
+pub use verify::verify_file;
+#[cfg(feature = "mmap")]
+pub use view::BTreeView;
+pub use visit::{NodeInfo, Order, TreeVisitor};
+
+// Children are shared between a tree and its snapshots; a node is only
+// copied (`Arc::make_mut`) when a change has to go through it while it is
+// shared.
+struct Node<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    keys: VecIn<T, M>,
+    children: VecIn<ArcIn<Node<T, A, M>, M>, M>,
+    // Keys in the subtree, for finding keys by position.
+    len: usize,
+    // `A`'s summary of the subtree, kept up to date by `summarize`; nothing
+    // for a plain tree.
+    summary: A::Value,
+    // Worked out on first use and dropped by `node_mut`.
+    #[cfg(feature = "merkle")]
+    hash: std::sync::OnceLock<merkle::Hash>,
+}
+
+impl<T: Clone, A: Aggregate<T>, M: Allocator + Clone> Clone for Node<T, A, M> {
+    fn clone(&self) -> Self {
+        Node {
+            keys: self.keys.clone(),
+            children: self.children.clone(),
+            len: self.len,
+            summary: self.summary.clone(),
+            #[cfg(feature = "merkle")]
+            hash: self.hash.clone(),
+        }
+    }
+}
+
+// Every change to a node goes through here: it copies the node if it is
+// shared, and drops the hash cached for what the node held.
+fn node_mut<T: Clone, A: Aggregate<T>, M: Allocator + Clone>(node: &mut ArcIn<Node<T, A, M>, M>) -> &mut Node<T, A, M> {
+    let node = Arc::make_mut(node);
+    #[cfg(feature = "merkle")]
+    node.hash.take();
+    node
+}
+
+/// A B-tree of keys `T`, keeping `A`'s summary of every subtree if it is
+/// made `with_aggregate`, and with its nodes in `M` if it is made `new_in`.
+pub struct BTree<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    root: ArcIn<Node<T, A, M>, M>,
+    props: BTreeProps,
+    ctx: Context<T, A, M>,
+}
+
+// What insert and delete carry down besides the nodes: spare nodes to
+// reuse, the recording in progress if a `Recorder` is attached, and a count
+// of the times keys have moved between nodes, which tells a `Finger` it is
+// stale.
+struct Context<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    // Where new nodes are allocated.
+    alloc: M,
+    free: FreeList<T, A, M>,
+    recording: Option<Recording<T, A, M>>,
+    version: u64,
+    hooks: Hooks<T>,
+    journal: Option<Journal<T>>,
+    history: Option<History<T, A, M>>,
+}
+
+impl<T, A: Aggregate<T>> Context<T, A> {
+    fn new() -> Self {
+        Context::new_in(Global)
+    }
+}
+
+impl<T, A: Aggregate<T>, M: Allocator + Clone> Context<T, A, M> {
+    fn new_in(alloc: M) -> Self {
+        Context {
+            alloc,
+            free: FreeList::new(),
+            recording: None,
+            version: 0,
+            hooks: Hooks::new(),
+            journal: None,
+            history: None,
+        }
+    }
+}
+
+// Why to need a different Struct for props...
+// Check - http://smallcultfollowing.com/babysteps/blog/2018/11/01/after-nll-interprocedural-conflicts/#fnref:improvement
+struct BTreeProps {
+    degree: usize,
+    max_keys: usize,
+    // Lowered by `RebalancePolicy`; splits leave `(degree - 1) / 2` unless
+    // the `SplitPolicy` moves them.
+    min_keys: usize,
+    mid_key_index: usize,
+    strategy: Strategy,
+    duplicates: DuplicatePolicy,
+    split: SplitPolicy,
+    // Set by `set_relaxed`.
+    relaxed: bool,
+    // Set by `set_shrink_on_merge`.
+    shrink_on_merge: bool,
+    counters: Counters,
+}
+
+impl<T, A> Node<T, A>
+where
+    T: Ord,
+    A: Aggregate<T>,
+{
+   fn new(degree: usize, _keys: Option<Vec<T>>, _children: Option<Vec<Arc<Node<T, A>>>>) -> Self {
+        Node::from_parts(
+            match _keys {
+                Some(_keys) => _keys,
+                None => Vec::with_capacity(degree - 1),
+            },
+            match _children {
+                Some(_children) => _children,
+                None => Vec::with_capacity(degree),
+            },
+        )
+   }
+}
+
+impl<T, A, M> Node<T, A, M>
+where
+    T: Ord,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    // An empty node with room for a full one, in `alloc`.
+    fn new_in(degree: usize, alloc: &M) -> Self {
+        Node::from_parts(vec_in(degree - 1, alloc), vec_in(degree, alloc))
+    }
+
+    fn from_parts(keys: VecIn<T, M>, children: VecIn<ArcIn<Node<T, A, M>, M>, M>) -> Self {
+        let mut node = Node {
+            keys,
+            children,
+            len: 0,
+            summary: A::empty(),
+            #[cfg(feature = "merkle")]
+            hash: std::sync::OnceLock::new(),
+        };
+        node.recount();
+        node
+    }
+
+    // Recompute `len` after keys or children have moved in or out.
+    fn recount(&mut self) {
+        self.len = self.keys.len() + self.children.iter().map(|child| child.len).sum::<usize>();
+        self.summarize();
+    }
+
+    // Recompute the summary from the keys and the children's summaries,
+    // which are up to date. Goes with every change of a node's keys or
+    // children on the way back up; for a plain tree it compiles to nothing.
+    fn summarize(&mut self) {
+        let mut summary = match self.children.first() {
+            Some(child) => child.summary.clone(),
+            None => A::empty(),
+        };
+        for (index, key) in self.keys.iter().enumerate() {
+            summary = A::combine(&summary, &A::key(key));
+            if let Some(child) = self.children.get(index + 1) {
+                summary = A::combine(&summary, &child.summary);
+            }
+        }
+        self.summary = summary;
+    }
+
+   fn is_leaf(&self) -> bool {
+		self.children.is_empty()
+   }
+
+    fn search(&self, key: T, counters: Option<&Counters>) -> bool {
+        self.get(key, counters).is_some()
+    }
+
+    // Swap `key` in for the stored key `locate` found, copying the nodes on
+    // the way that are shared with a snapshot.
+    fn replace_at(&mut self, path: &[usize], index: usize, key: T) -> T
+    where
+        T: Clone,
+    {
+        let old = match path.split_first() {
+            Some((&child, rest)) => node_mut(&mut self.children[child]).replace_at(rest, index, key),
+            None => mem::replace(&mut self.keys[index], key),
+        };
+        self.summarize();
+        old
+    }
+
+    // The stored key equal to `key`, which may differ from it in whatever
+    // the ordering ignores.
+    fn get(&self, key: T, counters: Option<&Counters>) -> Option<&T> {
+        let result = self.locate(key, counters);
+        result.found.then(|| &self.node_at(&result.path).keys[result.index])
+    }
+
+    // Look for `key` from this node down, stopping at the first node that
+    // holds it: the one descent that lookups, deletes, replacing inserts
+    // and fingers all start with.
+    fn locate(&self, key: T, counters: Option<&Counters>) -> SearchResult {
+        let mut node = self;
+        let mut path = Vec::new();
+        loop {
+            let (index, comparisons) = search::partition(&node.keys, |stored| *stored < key);
+            if let Some(counters) = counters {
+                counters.visit();
+                // and the equality check against the key after them
+                counters.compare(comparisons + usize::from(index < node.keys.len()));
+            }
+            let found = index < node.keys.len() && node.keys[index] == key;
+            if found || node.is_leaf() {
+                return SearchResult { path, index, found };
+            }
+            path.push(index);
+            node = &node.children[index];
+        }
+    }
+
+    // The node reached by taking the child indexes of `path` from this one.
+    fn node_at(&self, path: &[usize]) -> &Node<T, A, M> {
+        path.iter().fold(self, |node, &index| &node.children[index])
+    }
+}
+
+// Where `Node::locate` stopped: the child indexes taken from the node it
+// started at, and in the node they lead to, the index of the key if it was
+// found there, or else, at a leaf, the index it would go at.
+pub(crate) struct SearchResult {
+    path: Vec<usize>,
+    index: usize,
+    found: bool,
+}
+
+// What `refill_from` picks for an underfull child.
+enum Refill {
+    FromRight,
+    FromLeft,
+    MergeRight,
+    MergeLeft,
+}
+
+impl BTreeProps {
+    fn new(degree: usize) -> Self {
+        BTreeProps {
+            degree,
+            max_keys: degree - 1,
+            min_keys: (degree - 1) / 2,
+            mid_key_index: (degree - 1) / 2,
+            strategy: Strategy::default(),
+            duplicates: DuplicatePolicy::default(),
+            split: SplitPolicy::default(),
+            relaxed: false,
+            shrink_on_merge: false,
+            counters: Counters::default(),
+        }
+    }
+
+    fn is_maxed_out<T: Ord + Copy, A: Aggregate<T>, M: Allocator + Clone>(&self, node: &Node<T, A, M>) -> bool {
+        node.keys.len() == self.max_keys
+    }
+
+    // The fewest keys a delete may leave in a node other than the root:
+    // relaxed, only a node left with none has to be fixed right away.
+    fn fewest_keys(&self) -> usize {
+        if self.relaxed { 1 } else { self.min_keys }
+    }
+
+    // How many keys the left half of a split of a node whose keys run from
+    // `first` to `last` keeps, as the split policy says for an insert of
+    // `key`. Both halves keep `min_keys`.
+    fn split_index<T: Ord + ?Sized>(&self, first: &T, last: &T, key: &T) -> usize {
+        let (fewest, most) = (self.min_keys, self.max_keys - 1 - self.min_keys);
+        let index = match self.split {
+            SplitPolicy::Middle => self.mid_key_index,
+            // rounded by hand: `f64::round` needs std
+            SplitPolicy::Ratio(ratio) => ((self.max_keys - 1) as f64 * ratio.clamp(0.0, 1.0) + 0.5) as usize,
+            SplitPolicy::TowardInsert if last <= key => most,
+            SplitPolicy::TowardInsert if key < first => fewest,
+            SplitPolicy::TowardInsert => self.mid_key_index,
+        };
+        index.clamp(fewest, most)
+    }
+
+    // Split Child expects the Child Node to be full
+    /// Move the middle_key to parent node and split the child_node's
+    /// keys/chilren_nodes in two, where the split policy says for `key`
+    fn split_child<T: Ord + Copy + Debug + Default, A: Aggregate<T>, M: Allocator + Clone>(
+        &self,
+        parent: &mut Node<T, A, M>,
+        child_index: usize,
+        key: &T,
+        ctx: &mut Context<T, A, M>,
+    ) {
+        ctx.version += 1;
+        let mut new_child_node = ctx.free.take(self.degree, &ctx.alloc);
+        let right = Arc::get_mut(&mut new_child_node).unwrap();
+        let keys = &parent.children[child_index].keys;
+        let at = self.split_index(&keys[0], &keys[keys.len() - 1], key);
+        let child = node_mut(&mut parent.children[child_index]);
+        right.keys.extend(child.keys.drain(at + 1..));
+        // What's left past the right half is the middle key, which moves to
+        // the parent node.
+        let middle_key = child.keys.pop().unwrap();
+        if !child.is_leaf() {
+            right.children.extend(child.children.drain(at + 1..));
+        }
+        child.recount();
+        right.recount();
+
+        event!(
+            "split",
+            parent = node_id(parent),
+            left = node_id(&parent.children[child_index]),
+            right = node_id(&new_child_node),
+            middle = ?middle_key,
+            left_keys = ?key_range(&parent.children[child_index]),
+            right_keys = ?key_range(&new_child_node),
+        );
+        parent.keys.insert(child_index, middle_key);
+        parent.children.insert(child_index + 1, new_child_node);
+        self.counters.split(1);
+    }
+
+    fn insert_non_full<T: Ord + Copy + Debug + Default, A: Aggregate<T>, M: Allocator + Clone>(&mut self, node: &mut Node<T, A, M>, key: T, ctx: &mut Context<T, A, M>) {
+        let (mut u_index, comparisons) = search::partition(&node.keys, |stored| *stored < key);
+        self.counters.compare(comparisons);
+        self.counters.visit();
+        node.len += 1;
+        if node.is_leaf() {
+            // Just insert it, as we know this method will be called only when node is not full
+            node.keys.insert(u_index, key);
+        } else {
+            if self.is_maxed_out(&node.children[u_index]) {
+                self.split_child(node, u_index, &key, ctx);
+                ctx.change(node, |at| format!("split child {u_index} of {at}"));
+                self.counters.compare(1);
+                if node.keys[u_index] < key {
+                    u_index += 1;
+                }
+            }
+
+            ctx.descend(u_index);
+            self.insert_non_full(node_mut(&mut node.children[u_index]), key, ctx);
+            ctx.ascend();
+        }
+        node.summarize();
+    }
+
+    // `insert_non_full` for a key no smaller than any in the subtree, which
+    // belongs at the end of its rightmost leaf.
+    fn append<T: Ord + Copy + Debug + Default, A: Aggregate<T>, M: Allocator + Clone>(&mut self, node: &mut Node<T, A, M>, key: T, ctx: &mut Context<T, A, M>) {
+        self.counters.visit();
+        node.len += 1;
+        if node.is_leaf() {
+            node.keys.push(key);
+            node.summarize();
+            return;
+        }
+        let mut last = node.children.len() - 1;
+        if self.is_maxed_out(&node.children[last]) {
+            self.split_child(node, last, &key, ctx);
+            ctx.change(node, |at| format!("split child {last} of {at}"));
+            last += 1;
+        }
+        ctx.descend(last);
+        self.append(node_mut(&mut node.children[last]), key, ctx);
+        ctx.ascend();
+        node.summarize();
+    }
+
+    #[cfg(feature = "std")]
+    fn traverse_node<T: Ord + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, node: &Node<T, A, M>, depth: usize) {
+        if node.is_leaf() {
+            print!(" {0:{<1$}{2:?}{0:}<1$} ", "", depth, node.keys);
+        } else {
+            let _depth = depth + 1;
+            for (index, key) in node.keys.iter().enumerate() {
+                self.traverse_node(&node.children[index], _depth);
+                // Check https://doc.rust-lang.org/std/fmt/index.html
+                // And https://stackoverflow.com/a/35280799/2849127
+                print!("{0:{<1$}{2:?}{0:}<1$}", "", depth, key);
+            }
+            self.traverse_node(node.children.last().unwrap(), _depth);
+        }
+    }
+
+    // Removes the key at `index` of the node that `path` leads to, as
+    // `locate` found it. Nodes on the way back up are rebalanced by their
+    // parent, so only the root may be left underfull.
+    fn delete_key<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, node: &mut Node<T, A, M>, path: &[usize], index: usize, ctx: &mut Context<T, A, M>) {
+        self.counters.visit();
+        node.len -= 1;
+        match path.split_first() {
+            Some((&child, rest)) => {
+                ctx.descend(child);
+                self.delete_key(node_mut(&mut node.children[child]), rest, index, ctx);
+                ctx.ascend();
+                self.rebalance_child(node, child, ctx);
+            }
+            None if node.is_leaf() => {
+                node.keys.remove(index);
+            }
+            None => {
+                // An internal key is replaced by its predecessor, the largest
+                // key of its left subtree.
+                ctx.version += 1;
+                ctx.descend(index);
+                node.keys[index] = self.delete_max(node_mut(&mut node.children[index]), ctx);
+                ctx.ascend();
+                self.rebalance_child(node, index, ctx);
+            }
+        }
+        node.summarize();
+    }
+
+    fn delete_max<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, node: &mut Node<T, A, M>, ctx: &mut Context<T, A, M>) -> T {
+        self.counters.visit();
+        node.len -= 1;
+        if node.is_leaf() {
+            let key = node.keys.pop().unwrap();
+            node.summarize();
+            return key;
+        }
+        let last = node.children.len() - 1;
+        ctx.descend(last);
+        let key = self.delete_max(node_mut(&mut node.children[last]), ctx);
+        ctx.ascend();
+        self.rebalance_child(node, last, ctx);
+        node.summarize();
+        key
+    }
+
+    // How to refill the child at `index` of a node with `count` children,
+    // `keys` giving how many keys each holds: from a sibling that can spare
+    // one or by merging with one, as the strategy prefers. `None` for an
+    // only child.
+    fn refill_from(&self, index: usize, count: usize, keys: impl Fn(usize) -> usize) -> Option<Refill> {
+        let (right, left) = (index + 1 < count, index > 0);
+        // whether the child and `sibling` fit in one node
+        let fits = |sibling: usize| keys(index) + keys(sibling) < self.max_keys;
+        let prefer_merge = self.strategy == Strategy::PreferMerge;
+        if prefer_merge && right && fits(index + 1) {
+            Some(Refill::MergeRight)
+        } else if prefer_merge && left && fits(index - 1) {
+            Some(Refill::MergeLeft)
+        } else if right && keys(index + 1) > self.min_keys {
+            Some(Refill::FromRight)
+        } else if left && keys(index - 1) > self.min_keys {
+            Some(Refill::FromLeft)
+        } else if right {
+            Some(Refill::MergeRight)
+        } else if left {
+            Some(Refill::MergeLeft)
+        } else {
+            None
+        }
+    }
+
+    fn rebalance_child<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize, ctx: &mut Context<T, A, M>) {
+        if parent.children[index].keys.len() < self.fewest_keys() {
+            self.refill_child(parent, index, ctx);
+        }
+    }
+
+    // Borrow into or merge away the child at `index`, which is underfull.
+    fn refill_child<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize, ctx: &mut Context<T, A, M>) {
+        ctx.version += 1;
+
+        let children = &parent.children;
+        match self.refill_from(index, children.len(), |child| children[child].keys.len()) {
+            Some(Refill::MergeRight) => {
+                self.merge_with_right(parent, index, ctx);
+                ctx.change(parent, |at| format!("merge child {} into child {index} of {at}", index + 1));
+            }
+            Some(Refill::MergeLeft) => {
+                self.merge_with_left(parent, index, ctx);
+                ctx.change(parent, |at| format!("merge child {index} into child {} of {at}", index - 1));
+            }
+            Some(Refill::FromRight) => {
+                self.donate_from_right(parent, index);
+                ctx.change(parent, |at| format!("borrow from child {} into child {index} of {at}", index + 1));
+            }
+            Some(Refill::FromLeft) => {
+                self.donate_from_left(parent, index);
+                ctx.change(parent, |at| format!("borrow from child {} into child {index} of {at}", index - 1));
+            }
+            None => {}
+        }
+    }
+
+    fn donate_from_right<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize) {
+        let sibling = node_mut(&mut parent.children[index + 1]);
+        let sibling_key = sibling.keys.remove(0);
+        let sibling_child = if sibling.is_leaf() { None } else { Some(sibling.children.remove(0)) };
+        let parent_key = mem::replace(&mut parent.keys[index], sibling_key);
+        sibling.recount();
+        let node = node_mut(&mut parent.children[index]);
+        node.keys.push(parent_key);
+        node.children.extend(sibling_child);
+        node.recount();
+        self.counters.right_donation();
+        event!(
+            "borrow from right",
+            parent = node_id(parent),
+            node = node_id(&parent.children[index]),
+            sibling = node_id(&parent.children[index + 1]),
+            key = ?parent_key,
+            node_keys = ?key_range(&parent.children[index]),
+        );
+    }
+
+    fn donate_from_left<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize) {
+        let sibling = node_mut(&mut parent.children[index - 1]);
+        let sibling_key = sibling.keys.pop().unwrap();
+        let sibling_child = sibling.children.pop();
+        let parent_key = mem::replace(&mut parent.keys[index - 1], sibling_key);
+        sibling.recount();
+        let node = node_mut(&mut parent.children[index]);
+        node.keys.insert(0, parent_key);
+        if let Some(child) = sibling_child {
+            node.children.insert(0, child);
+        }
+        node.recount();
+        self.counters.left_donation();
+        event!(
+            "borrow from left",
+            parent = node_id(parent),
+            node = node_id(&parent.children[index]),
+            sibling = node_id(&parent.children[index - 1]),
+            key = ?parent_key,
+            node_keys = ?key_range(&parent.children[index]),
+        );
+    }
+
+    fn merge_with_right<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize, ctx: &mut Context<T, A, M>) {
+        let mut right_sibling = parent.children.remove(index + 1);
+        let separator = parent.keys.remove(index);
+        let node = node_mut(&mut parent.children[index]);
+        node.keys.push(separator);
+        node.keys.extend_from_slice(&right_sibling.keys);
+        node.len += 1 + right_sibling.len;
+        match Arc::get_mut(&mut right_sibling) {
+            Some(sibling) => node.children.append(&mut sibling.children),
+            None => node.children.extend(right_sibling.children.iter().cloned()),
+        }
+        node.summarize();
+        event!(
+            "merge",
+            parent = node_id(parent),
+            node = node_id(&parent.children[index]),
+            removed = node_id(&right_sibling),
+            separator = ?separator,
+            node_keys = ?key_range(&parent.children[index]),
+        );
+        if self.shrink_on_merge {
+            parent.keys.shrink_to_fit();
+            parent.children.shrink_to_fit();
+        } else {
+            ctx.free.put(right_sibling);
+        }
+        self.counters.merge();
+    }
+
+    fn merge_with_left<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(&self, parent: &mut Node<T, A, M>, index: usize, ctx: &mut Context<T, A, M>) {
+        self.merge_with_right(parent, index - 1, ctx);
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// A tree whose nodes hold up to `2 * branch_factor - 1` keys.
+    pub fn new(branch_factor: usize) -> Self {
+        let degree = 2 * branch_factor;
+        BTree {
+            root: Arc::new(Node::new(degree, None, None)),
+            props: BTreeProps::new(degree),
+            ctx: Context::new(),
+        }
+    }
+
+    /// `new`, but rejecting branch factors below 2 instead of making a
+    /// tree that breaks on first delete or panics.
+    pub fn with_branch_factor(branch_factor: usize) -> Result<Self, ConfigError> {
+        match branch_factor {
+            0 | 1 => Err(ConfigError::BranchFactorTooSmall(branch_factor)),
+            _ => Ok(BTree::new(branch_factor)),
+        }
+    }
+
+    /// A tree whose nodes have up to `degree` children, which must be even
+    /// and at least 4.
+    pub fn with_degree(degree: usize) -> Result<Self, ConfigError> {
+        if degree < 4 {
+            Err(ConfigError::DegreeTooSmall(degree))
+        } else if !degree.is_multiple_of(2) {
+            Err(ConfigError::OddDegree(degree))
+        } else {
+            Ok(BTree::new(degree / 2))
+        }
+    }
+}
+
+impl<T, M> BTree<T, (), M>
+where
+    T: Ord + Copy + Debug + Default,
+    M: Allocator + Clone,
+{
+    /// `new`, with every node and the key and child buffers in it allocated
+    /// in `alloc`.
+    pub fn new_in(branch_factor: usize, alloc: M) -> Self {
+        let degree = 2 * branch_factor;
+        BTree {
+            root: arc_in(Node::new_in(degree, &alloc), &alloc),
+            props: BTreeProps::new(degree),
+            ctx: Context::new_in(alloc),
+        }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// The allocator the nodes are in.
+    pub fn allocator(&self) -> &M {
+        &self.ctx.alloc
+    }
+
+    /// The most children a node can have.
+    pub fn degree(&self) -> usize {
+        self.props.degree
+    }
+
+    /// The fewest keys a node other than the root holds after any insert or
+    /// delete.
+    pub fn min_keys(&self) -> usize {
+        self.props.min_keys
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.props.max_keys
+    }
+
+    pub fn insert(&mut self, key: T) {
+        // Keys that go after all the others, as in time series, skip the
+        // search and go down the right edge.
+        let append = self.last_key().is_some_and(|last| {
+            self.props.counters.compare(1);
+            *last <= key
+        });
+        self.insert_at(key, append);
+    }
+
+    /// Insert `key` if no key in the tree is greater, straight down the
+    /// right edge of the tree; otherwise hand it back.
+    pub fn insert_max(&mut self, key: T) -> Result<(), T> {
+        match self.last_key() {
+            Some(last) if *last > key => Err(key),
+            _ => {
+                self.insert_at(key, true);
+                Ok(())
+            }
+        }
+    }
+
+    fn insert_at(&mut self, key: T, append: bool) {
+        self.ctx.begin(&self.root);
+        if self.take_duplicate(key) {
+            return;
+        }
+        if self.props.is_maxed_out(&self.root) {
+            // Create an empty root and split the old root...
+            let new_root = self.ctx.free.take(self.props.degree, &self.ctx.alloc);
+            let old_root = mem::replace(&mut self.root, new_root);
+            let root = node_mut(&mut self.root);
+            root.children.insert(0, old_root);
+            self.props.split_child(root, 0, &key, &mut self.ctx);
+            root.recount();
+            self.ctx.change(&self.root, |_| String::from("split the root, adding a level"));
+            event!("root grew", root = node_id(&self.root), height = self.height());
+        }
+        match append {
+            true => self.props.append(node_mut(&mut self.root), key, &mut self.ctx),
+            false => self.props.insert_non_full(node_mut(&mut self.root), key, &mut self.ctx),
+        }
+        self.props.counters.depth(self.height());
+        if let Some(recording) = &mut self.ctx.recording {
+            recording.finish(format!("insert {key:?}"), &self.root, &self.root);
+        }
+        self.ctx.inserted(&key);
+    }
+
+    // Deal with `key` as the duplicate policy says if an equal key is
+    // already stored, returning whether that left nothing to insert.
+    fn take_duplicate(&mut self, key: T) -> bool {
+        match self.props.duplicates {
+            DuplicatePolicy::Allow => false,
+            DuplicatePolicy::Reject => self.search(key),
+            DuplicatePolicy::Replace => {
+                let result = self.root.locate(key, Some(&self.props.counters));
+                if result.found {
+                    self.replace_found(&result, key);
+                }
+                result.found
+            }
+        }
+    }
+
+    // Swap `key` in for the equal key `result` found.
+    fn replace_found(&mut self, result: &SearchResult, key: T) -> T {
+        self.ctx.begin(&self.root);
+        let old = node_mut(&mut self.root).replace_at(&result.path, result.index, key);
+        self.ctx.removed(&old);
+        self.ctx.inserted(&key);
+        old
+    }
+
+    #[cfg(feature = "std")]
+    pub fn traverse(&self) {
+        self.props.traverse_node(&self.root, 0);
+        println!();
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        self.root.search(key, Some(&self.props.counters))
+    }
+
+    /// The key stored in the tree that is equal to `key`, for key types
+    /// whose equal keys can still be told apart. With duplicates, any one of
+    /// them.
+    pub fn get_key(&self, key: T) -> Option<&T> {
+        self.root.get(key, Some(&self.props.counters))
+    }
+
+    /// Insert `key` unless an equal key is already stored, in which case
+    /// the error hands `key` back along with the stored one.
+    pub fn try_insert(&mut self, key: T) -> Result<(), OccupiedError<'_, T>> {
+        // Looked up twice: returning the reference from the first lookup
+        // would keep `self` borrowed through the insert below.
+        if self.get_key(key).is_some() {
+            let existing = self.get_key(key).unwrap();
+            return Err(OccupiedError { existing, key });
+        }
+        self.insert(key);
+        Ok(())
+    }
+
+    /// Insert `key`, unless an equal key is already stored, in which case
+    /// `key` takes its place and the old one is returned, like
+    /// `BTreeSet::replace`.
+    pub fn replace(&mut self, key: T) -> Option<T> {
+        // a miss mustn't copy nodes shared with a snapshot
+        let result = self.root.locate(key, Some(&self.props.counters));
+        if !result.found {
+            self.insert(key);
+            return None;
+        }
+        Some(self.replace_found(&result, key))
+    }
+
+	pub fn delete(&mut self, key: T) -> bool {
+        // Found first so that a miss doesn't copy nodes shared with a
+        // snapshot; the descent that removes the key then follows the path
+        // without comparing keys again.
+        let result = self.root.locate(key, Some(&self.props.counters));
+        if !result.found {
+            return false;
+        }
+        self.delete_found(&result.path, result.index, key);
+        true
+	}
+
+    // Remove the key at `index` of the node `path` leads to, which is equal
+    // to `key`.
+    fn delete_found(&mut self, path: &[usize], index: usize, key: T) {
+        self.ctx.begin(&self.root);
+        self.props.counters.depth(self.height());
+        // Nodes above a change are as they were before the delete started.
+        let base = self.ctx.recording.is_some().then(|| Arc::clone(&self.root));
+        // what hooks and the journal hear of: the stored key, not `key`
+        let removed = self.root.node_at(path).keys[index];
+        self.props.delete_key(node_mut(&mut self.root), path, index, &mut self.ctx);
+        if self.root.keys.is_empty() && !self.root.is_leaf() {
+            /* if root is left with 0 keys, then its one and only child becomes the new root */
+            // A leaf root with no keys is just the empty tree, and with no
+            // parent links there is nothing below to update.
+            let child = Arc::clone(&self.root.children[0]);
+            let old_root = mem::replace(&mut self.root, child);
+            self.ctx.version += 1;
+            event!("root shrank", old_root = node_id(&old_root), root = node_id(&self.root), height = self.height());
+            if !self.props.shrink_on_merge {
+                self.ctx.free.put(old_root);
+            }
+        }
+        if let (Some(recording), Some(base)) = (&mut self.ctx.recording, base) {
+            recording.finish(format!("delete {key:?}"), &base, &self.root);
+        }
+        self.ctx.removed(&removed);
+    }
+
+    /// `delete`, with a missing key reported as `Error::NotFound`.
+    pub fn try_delete(&mut self, key: T) -> Result<(), Error> {
+        match self.delete(key) {
+            true => Ok(()),
+            false => Err(Error::NotFound),
+        }
+    }
+
+    // The largest key, at the end of the rightmost leaf.
+    fn last_key(&self) -> Option<&T> {
+        let mut node = &self.root;
+        while let Some(child) = node.children.last() {
+            node = child;
+        }
+        node.keys.last()
+    }
+
+    // Levels from the root down to the leaves, the root counting as 1.
+    fn height(&self) -> usize {
+        let mut node = &self.root;
+        let mut height = 1;
+        while let Some(child) = node.children.first() {
+            node = child;
+            height += 1;
+        }
+        height
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BTree, ConfigError, Error};
+    pub(crate) use crate::testing::check_node;
+
+    #[test]
+    fn test_search() {
+        let mut tree = BTree::new(2);
+        tree.insert(10);
+        tree.insert(20);
+        tree.insert(30);
+        tree.insert(5);
+        tree.insert(6);
+        tree.insert(7);
+        tree.insert(11);
+        tree.insert(12);
+        tree.insert(15);
+        assert!(tree.search(15));
+        assert!(!tree.search(16));
+        //tree.delete(15);
+        //assert_eq!(tree.search(15), false);
+        //assert!(tree.search(12));
+        //tree.delete(12);
+        //assert_eq!(tree.search(12), false);
+        tree.delete(10);
+        assert!(!tree.search(10));
+        assert!(tree.search(5));
+        assert!(tree.search(7));
+        assert!(tree.search(11));
+        assert!(tree.search(12));
+        assert!(tree.search(15));
+        assert!(tree.search(30));
+    }
+
+    // ordered by the number alone
+    #[derive(Clone, Copy, Debug, Default)]
+    pub(crate) struct Tagged(pub(crate) u32, pub(crate) char);
+
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for Tagged {}
+
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Tagged {
+        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn test_get_replace_and_try_insert() {
+        let mut tree = BTree::new(2);
+        for key in 0..100 {
+            tree.insert(Tagged(key, char::from(b'a' + (key % 26) as u8)));
+        }
+        assert_eq!(tree.get_key(Tagged(30, '?')).map(|key| key.1), Some('e'));
+        assert_eq!(tree.get_key(Tagged(99, '?')).map(|key| key.1), Some('v'));
+        assert!(tree.get_key(Tagged(100, '?')).is_none());
+
+        assert_eq!(tree.replace(Tagged(30, '!')).map(|key| key.1), Some('e'));
+        assert_eq!(tree.get_key(Tagged(30, '?')).map(|key| key.1), Some('!'));
+        assert!(tree.replace(Tagged(100, '!')).is_none());
+        assert_eq!(tree.get_key(Tagged(100, '?')).map(|key| key.1), Some('!'));
+        check_node(&tree.root, &tree.props, true);
+
+        let error = tree.try_insert(Tagged(31, '?')).unwrap_err();
+        assert_eq!((error.existing.1, error.key.1), ('f', '?'));
+        assert!(tree.try_insert(Tagged(101, '?')).is_ok());
+        assert_eq!(tree.get_key(Tagged(101, '!')).map(|key| key.1), Some('?'));
+    }
+
+    #[test]
+    fn test_try_methods() {
+        assert_eq!(BTree::<u32>::with_branch_factor(1).err(), Some(ConfigError::BranchFactorTooSmall(1)));
+        assert_eq!(BTree::<u32>::with_degree(2).err(), Some(ConfigError::DegreeTooSmall(2)));
+        assert_eq!(BTree::<u32>::with_degree(7).err(), Some(ConfigError::OddDegree(7)));
+        let tree = BTree::<u32>::with_degree(8).unwrap();
+        assert_eq!((tree.degree(), tree.min_keys(), tree.max_keys()), (8, 3, 7));
+
+        let mut tree = BTree::with_branch_factor(2).unwrap();
+        for key in 0..50u32 {
+            tree.try_insert(key).unwrap();
+        }
+        assert_eq!(tree.try_delete(20), Ok(()));
+        assert_eq!(tree.try_delete(20), Err(Error::NotFound));
+        assert!(tree.try_insert(10).is_err());
+    }
+
+    #[test]
+    fn test_insert_max() {
+        let mut tree = BTree::new(2);
+        for key in 0..300u32 {
+            assert_eq!(tree.insert_max(key * 2), Ok(()));
+            check_node(&tree.root, &tree.props, true);
+        }
+        assert_eq!(tree.insert_max(597), Err(597));
+        assert_eq!(tree.insert_max(598), Ok(()));
+        tree.insert(599);
+        tree.insert(1);
+        let mut expected: Vec<u32> = (0..300).map(|key| key * 2).chain([598, 599, 1]).collect();
+        expected.sort();
+        assert!(tree.iter_snapshot().eq(expected));
+        check_node(&tree.root, &tree.props, true);
+    }
+
+    #[test]
+    fn test_delete_rebalances() {
+        let mut tree = BTree::new(2);
+        for key in 0..500u32 {
+            tree.insert((key * 7919) % 500);
+        }
+        for key in (0..500u32).map(|key| (key * 4099) % 500) {
+            assert!(tree.delete(key));
+            assert!(!tree.search(key));
+            check_node(&tree.root, &tree.props, true);
+        }
+        assert!(tree.root.keys.is_empty() && tree.root.is_leaf());
+        assert!(tree.is_empty() && tree.audit_counts().is_empty());
+        assert!(!tree.delete(0));
+
+        // each delete that empties the root hands the tree to its one child
+        for key in 0..20u32 {
+            tree.insert(key);
+        }
+        let mut height = tree.height();
+        for key in 0..20u32 {
+            assert!(tree.delete(key));
+            assert!(tree.height() <= height && (tree.root.is_leaf() || !tree.root.keys.is_empty()));
+            height = tree.height();
+        }
+        assert!(tree.is_empty() && tree.root.is_leaf() && !tree.delete(0));
+        tree.insert(7);
+        assert!(tree.search(7) && tree.len() == 1);
+    }
+}
@@ -1,489 +1,313 @@
-use std::convert::TryFrom;
-use std::fmt::Debug;
-use std::cmp::PartialEq;
-use std::mem;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-struct Node<T> {
-    keys: Vec<T>,
-    children: Vec<Node<T>>,
-    parent: Option<Rc<RefCell<Node<T>>>>,
-    parent_index: usize,
-}
+use b_trees_with_delete::{sort_file, BTree, SortOptions};
 
-pub struct BTree<T> {
-    root: Node<T>,
-    props: BTreeProps,
-}
+mod bench;
+
+const HELP: &str = "\
+commands:
+  insert <key>...   add keys (duplicates are kept)
+  delete <key>...   remove one copy of each key
+  search <key>      report whether a key is in the tree
+  print             draw the tree level by level
+  stats             node and key counts per level, and memory used
+  help              show this message
+  quit              leave (so does end of input)";
+
+const USAGE: &str = "\
+usage:
+  btree [shell] [--degree <n>]
+  btree load <keys file> [--degree <n>] [--db <file>]
+  btree query (--range <a>..<b> | --key <k>) [--db <file>]
+  btree delete-file <keys file> [--db <file>]
+  btree sort <keys file> <output file>
+  btree bench [--degrees <n>,...] [--sizes <n>,...] [--mix <i>:<s>:<d>]
+
+Key files hold one whole number per line. The tree is kept in --db between
+commands (default tree.btree); `load` replaces it. --degree is the most
+children a node can have, an even number of at least 4 (default 4). `sort`
+sorts a key file of any size through a tree on disk, without --db.";
 
-// Why to need a different Struct for props...
-// Check - http://smallcultfollowing.com/babysteps/blog/2018/11/01/after-nll-interprocedural-conflicts/#fnref:improvement
-struct BTreeProps {
+struct Args {
+    command: String,
+    file: Option<PathBuf>,
+    // Where `sort` writes.
+    output: Option<PathBuf>,
     degree: usize,
-    max_keys: usize,
-    min_keys: usize,
-    mid_key_index: usize,
+    db: PathBuf,
+    range: Option<Range<i64>>,
 }
 
-impl<T> Node<T>
-where
-    T: Ord,
-{
-   fn new(degree: usize, _keys: Option<Vec<T>>, _children: Option<Vec<Node<T>>>, _parent: Option<Rc<RefCell<Node<T>>>>, _parent_index: usize) -> Self {
-        Node {
-            keys: match _keys {
-                Some(_keys) => _keys,
-                None => Vec::with_capacity(degree - 1),
-            },
-            children: match _children {
-                Some(_children) => _children,
-                None => Vec::with_capacity(degree),
-            },
-            parent: _parent,
-            parent_index: _parent_index,
-        }
-   }
-
-   fn is_leaf(&self) -> bool {
-		return self.children.len() == 0
-   }
-	 
-	fn has_right_sibling(&self) -> bool {
-        match self.parent {
-            Some(ref node) => node.borrow().children.len() > self.parent_index + 1,
-            None => false,
+fn main() -> ExitCode {
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        let config = match bench::parse_args(std::env::args().skip(2)) {
+            Ok(config) => config,
+            Err(message) => {
+                eprintln!("{message}\n\n{}", bench::USAGE);
+                return ExitCode::from(2);
+            }
+        };
+        return match bench::run(&config, &mut io::stdout().lock()) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("btree: {error}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
         }
-	}
-	 
-	fn has_left_sibling(&self) -> bool {
-        match self.parent {
-            None => false,
-            Some(_) => self.parent_index > 0,
+    };
+    let mut out = io::stdout().lock();
+    let result = match (args.command.as_str(), &args.file, &args.range) {
+        ("shell", None, None) => shell(args.degree),
+        ("load", Some(file), None) => load(file, args.degree, &args.db)
+            .and_then(|count| writeln!(out, "loaded {count} keys into {}", args.db.display())),
+        ("query", None, Some(range)) => query(&args.db, range.clone(), &mut out),
+        ("delete-file", Some(file), None) => delete_file(file, &args.db)
+            .and_then(|(deleted, count)| writeln!(out, "deleted {deleted} of {count} keys")),
+        ("sort", Some(file), None) if args.output.is_some() => {
+            let output = args.output.as_ref().unwrap();
+            sort_file(file, output, SortOptions::default())
+                .and_then(|count| writeln!(out, "sorted {count} keys into {}", output.display()))
         }
-	}
-	
-	fn is_root(&self) -> bool {
-		match self.parent {
-            None => true,
-            Some(_) => false,
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
         }
-	}
-
-    // caller must already check existence of right sibling
-    /*
-    fn right_sibling(&self) -> &mut Node<T> {
-        match self.parent {
-            None => panic!("Called right_sibling method of root node"),
-            Some(ref p) => &mut (p.borrow_mut().children[self.parent_index + 1])
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("btree: {error}");
+            ExitCode::FAILURE
         }
     }
-    */
-
-    // caller must already check existence of left sibling
-    /*
-    fn left_sibling(&self) -> &mut Node<T> {
-        match self.parent {
-            None => panic!("left_sibling method called on root"),
-            Some(ref p) => &mut (p.borrow_mut().children[self.parent_index - 1])
-        }
-	}
-    */
 }
 
-impl BTreeProps {
-    fn new(degree: usize) -> Self {
-        BTreeProps {
-            degree,
-            max_keys: degree - 1,
-            min_keys: (degree - 1) / 2,
-            mid_key_index: (degree - 1) / 2,
+fn parse_args(mut words: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args {
+        command: String::from("shell"),
+        file: None,
+        output: None,
+        degree: 4,
+        db: PathBuf::from("tree.btree"),
+        range: None,
+    };
+    let mut positional = Vec::new();
+    while let Some(word) = words.next() {
+        let mut value = || words.next().ok_or(format!("{word} needs a value"));
+        match word.as_str() {
+            "--degree" => {
+                args.degree = match value()?.parse() {
+                    Ok(degree) if degree >= 4 && degree % 2 == 0 => degree,
+                    _ => return Err(String::from("--degree must be an even number of at least 4")),
+                }
+            }
+            "--db" => args.db = PathBuf::from(value()?),
+            "--range" => args.range = Some(parse_range(&value()?)?),
+            "--key" => {
+                let key: i64 = value()?.parse().map_err(|_| String::from("--key must be a whole number"))?;
+                args.range = Some(key..key.saturating_add(1));
+            }
+            "-h" | "--help" => return Err(String::from("btree: a B-tree to experiment with")),
+            _ if word.starts_with("--") => return Err(format!("unknown option {word}")),
+            _ => positional.push(word),
         }
     }
-
-    fn is_maxed_out<T: Ord + Copy>(&self, node: &Node<T>) -> bool {
-        node.keys.len() == self.max_keys
+    let mut positional = positional.into_iter();
+    if let Some(command) = positional.next() {
+        args.command = command;
     }
-	 
-	 fn can_donate_from_left_sibling<T: Ord + Copy>(&self, node: &Node<T>) -> bool {
-         if !node.has_left_sibling() {
-             return false;
-         }
-         match node.parent {
-             None => false,
-             Some(ref p) => p.borrow().children[node.parent_index - 1].keys.len() > self.min_keys,
-         }
-	 }
-	 
-	 fn can_donate_from_right_sibling<T: Ord + Copy>(&self, node: &Node<T>) -> bool {
-         if !node.has_right_sibling() {
-             return false;
-         }
-         match node.parent {
-             None => false,
-             Some(ref p) => p.borrow().children[node.parent_index + 1].keys.len() > self.min_keys,
-         }
-	 }
+    args.file = positional.next().map(PathBuf::from);
+    if args.command == "sort" {
+        args.output = positional.next().map(PathBuf::from);
+    }
+    match positional.next() {
+        Some(extra) => Err(format!("unexpected argument {extra}")),
+        None => Ok(args),
+    }
+}
+
+// `a..b`, with `b` left out.
+fn parse_range(text: &str) -> Result<Range<i64>, String> {
+    let bad = || format!("--range must look like 100..200, not {text}");
+    let (start, end) = text.split_once("..").ok_or_else(bad)?;
+    Ok(start.parse().map_err(|_| bad())?..end.parse().map_err(|_| bad())?)
+}
 
-    // Split Child expects the Child Node to be full
-    /// Move the middle_key to parent node and split the child_node's
-    /// keys/chilren_nodes into half
-    fn split_child<T: Ord + Copy + Default>(&self, parent: &mut Node<T>, child_index: usize) {
-        let child = &mut parent.children[child_index];
-        let middle_key = child.keys[self.mid_key_index];
-        let right_keys = match child.keys.split_off(self.mid_key_index).split_first() {
-            Some((_first, _others)) => {
-                // We don't need _first, as it will move to parent node.
-                _others.to_vec()
+fn read_keys(path: &Path) -> io::Result<Vec<i64>> {
+    let mut keys = Vec::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse() {
+            Ok(key) => keys.push(key),
+            Err(_) => {
+                let message = format!("{}:{}: not a whole number: {line}", path.display(), number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
             }
-            None => Vec::with_capacity(self.max_keys),
-        };
-        let mut right_children = None;
-        if !child.is_leaf() {
-            right_children = Some(child.children.split_off(self.mid_key_index + 1));
         }
-        let new_child_node: Node<T> = Node::new(self.degree, Some(right_keys), right_children, child.parent.clone(), child_index + 1);
-
-        parent.keys.insert(child_index, middle_key);
-        parent.children.insert(child_index + 1, new_child_node);
     }
+    Ok(keys)
+}
 
-    fn insert_non_full<T: Ord + Copy + Default>(&mut self, node: &mut Node<T>, key: T) {
-        let mut index: isize = isize::try_from(node.keys.len()).ok().unwrap() - 1;
-        while index >= 0 && node.keys[index as usize] >= key {
-            index -= 1;
-        }
-
-        let mut u_index: usize = usize::try_from(index + 1).ok().unwrap();
-        if node.is_leaf() {
-            // Just insert it, as we know this method will be called only when node is not full
-            node.keys.insert(u_index, key);
-        } else {
-            if self.is_maxed_out(&node.children[u_index]) {
-                self.split_child(node, u_index);
-                if node.keys[u_index] < key {
-                    u_index += 1;
-                }
-            }
+fn load(keys: &Path, degree: usize, db: &Path) -> io::Result<usize> {
+    let keys = read_keys(keys)?;
+    let count = keys.len();
+    let mut tree = BTree::new(degree / 2);
+    tree.insert_batch(keys);
+    tree.save_to(db)?;
+    Ok(count)
+}
 
-            self.insert_non_full(&mut node.children[u_index], key);
-        }
+fn query(db: &Path, range: Range<i64>, out: &mut impl Write) -> io::Result<()> {
+    let tree: BTree<i64> = BTree::load_from(db)?;
+    for key in tree.range(range) {
+        writeln!(out, "{key}")?;
     }
+    Ok(())
+}
 
-    fn traverse_node<T: Ord + Debug>(&self, node: &Node<T>, depth: usize) {
-        if node.is_leaf() {
-            print!(" {0:{<1$}{2:?}{0:}<1$} ", "", depth, node.keys);
-        } else {
-            let _depth = depth + 1;
-            for (index, key) in node.keys.iter().enumerate() {
-                self.traverse_node(&node.children[index], _depth);
-                // Check https://doc.rust-lang.org/std/fmt/index.html
-                // And https://stackoverflow.com/a/35280799/2849127
-                print!("{0:{<1$}{2:?}{0:}<1$}", "", depth, key);
-            }
-            self.traverse_node(&node.children.last().unwrap(), _depth);
+// Returns how many of the file's keys were found and deleted, out of how
+// many it has.
+fn delete_file(keys: &Path, db: &Path) -> io::Result<(usize, usize)> {
+    let keys = read_keys(keys)?;
+    let mut tree: BTree<i64> = BTree::load_from(db)?;
+    let deleted = keys.iter().filter(|&&key| tree.delete(key)).count();
+    tree.save_to(db)?;
+    Ok((deleted, keys.len()))
+}
+
+fn shell(degree: usize) -> io::Result<()> {
+    let mut tree = BTree::new(degree / 2);
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut out = io::stdout();
+    if interactive {
+        writeln!(out, "B-tree of degree {degree}; type `help` for commands.")?;
+    }
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        let Some(line) = lines.next() else { break };
+        if !run_command(&mut tree, &line?, &mut out)? {
+            break;
         }
     }
-	 
-	 
-	fn delete_key<T: Ord + Copy + Debug + PartialEq>(&self, node: &mut Node<T>, key: T) {
-		if node.is_leaf() {
-			self.remove_key_from_node(node, key);
-			self.rebalance_after_deletion(node);
-		}
-		else {		
-			
-		    let key_index = node.keys.iter().position(|&e| e == key).unwrap();
-		
-            {
-		        let mut leaf_left = &mut node.children[key_index];
-		        while !leaf_left.is_leaf() {
-			        leaf_left = leaf_left.children.last_mut().unwrap();
-		        }
-                if leaf_left.keys.len() > self.min_keys {
-                    let new_sep = leaf_left.keys.pop().unwrap();
-                    self.rebalance_after_deletion(leaf_left);
-                    self.replace_keys(node, key, new_sep);
-                    return;
-                }
-            }
+    Ok(())
+}
 
-            {
-		
-                let mut leaf_right = &mut node.children[key_index + 1];
-                while !leaf_right.is_leaf() {
-                    leaf_right = leaf_right.children.first_mut().unwrap();
-                }
-				let new_sep = leaf_right.keys.remove(0);
-				self.rebalance_after_deletion(leaf_right);
-				self.replace_keys(node, key, new_sep);
-            }
+// Runs one line of input, returning false when it asks to quit.
+fn run_command(tree: &mut BTree<i64>, line: &str, out: &mut impl Write) -> io::Result<bool> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else { return Ok(true) };
+    let keys: Result<Vec<i64>, _> = words.map(str::parse).collect();
+    let Ok(keys) = keys else {
+        writeln!(out, "keys must be whole numbers")?;
+        return Ok(true);
+    };
 
-		}
-	}
-	
-	fn remove_key_from_node<T: PartialEq>(&self, node: &mut Node<T>, key: T) {
-		if let Some(pos) = node.keys.iter().position(|x| *x == key) {
-			node.keys.remove(pos);
-		}
-	}
-	
-    /*
-	fn get_donor_leafs<T: Ord, PartialEq>(&self, node: &Node<T>, key: T) -> (&mut Node<T>, &mut Node<T>) {
-		let key_index = node.keys.iter().position(|&e| e == key).unwrap();
-		
-		let left_leaf = &mut node.children[key_index];
-		while !left_leaf.is_leaf() {
-			left_leaf = left_leaf.children.last_mut().unwrap();
-		}
-		
-		let right_leaf = &mut node.children[key_index + 1];
-		while !right_leaf.is_leaf() {
-			right_leaf = right_leaf.children.first_mut().unwrap();
-		}
-		
-		return (left_leaf, right_leaf);
-		
-	}
-    */
-	
-	fn replace_keys<T: PartialEq>(&self, node: &mut Node<T>, old_key: T, new_key: T) {
-		let index = node.keys.iter().position(|e| *e == old_key).unwrap();
-		node.keys[index] = new_key;
-	}
-	
-	fn rebalance_after_deletion<T: Ord + Copy>(&self, node: &mut Node<T>) {
-		
-		if node.is_root() || node.keys.len() >= self.min_keys {
-			return;
-		}
-		
-						
-		if self.can_donate_from_right_sibling(&node) {
-			self.donate_from_right(node);
-		}
-		else if self.can_donate_from_left_sibling(&node) {
-			self.donate_from_left(node);
-		}
-		else if node.has_right_sibling() {
-			self.merge_with_right(node);
-            match node.parent {
-                None => return, // panic here, parent can't be none
-                Some(ref parent) => self.rebalance_after_deletion(&mut parent.borrow_mut()), // parent lost one key during merge, check if she needs rebalance.
-            }
-				
-		}
-		else if node.has_left_sibling() {
-			self.merge_with_left(node);
-            match node.parent {
-                None => return, // panic, parent can't be None
-                Some(ref parent) => self.rebalance_after_deletion(&mut parent.borrow_mut()), // parent lost one key during merge, check if she needs rebalance.
-            }
-		}
-	}
-	
-	fn donate_from_right<T: Ord + Copy>(&self, node: &mut Node<T>) {
-        match node.parent {
-            None => return, // panic, parent can't be None
-            Some(ref parent_cell) => {
-                let parent = &mut parent_cell.borrow_mut();
-    		    //let sibling = node.right_sibling();
-                let sibling = &mut parent.children[node.parent_index + 1];
-	         	let sibling_key = sibling.keys.remove(0);
-                if !node.is_leaf() {
-                    let sibling_child = sibling.children.remove(0);
-    	    	    node.children.push(sibling_child);
-                }
-	    	    let parent_key = std::mem::replace(&mut parent.keys[node.parent_index], sibling_key);
-	    	    node.keys.push(parent_key);
+    match (command, keys.as_slice()) {
+        ("insert", [_, ..]) => {
+            for &key in &keys {
+                tree.insert(key);
             }
         }
-	}
-	
-	fn donate_from_left<T: Ord + Copy>(&self, node: &mut Node<T>) {
-        match node.parent {
-            None => return, // panic, parent can't be None
-            Some(ref n) => {
-                let parent = &mut n.borrow_mut();
-		        let sibling = &mut parent.children[node.parent_index - 1];
-	        	let sibling_key = sibling.keys.pop().unwrap();
-                if !node.is_leaf() {
-                    let sibling_child = sibling.children.pop().unwrap();
-    		        node.children.insert(0, sibling_child);
+        ("delete", [_, ..]) => {
+            for &key in &keys {
+                if !tree.delete(key) {
+                    writeln!(out, "{key} not found")?;
                 }
-		        let parent_key = std::mem::replace(&mut parent.keys[node.parent_index - 1], sibling_key);
-		        node.keys.insert(0, parent_key);
             }
         }
-	}
-
-    fn merge_with_right<T: Ord>(&self, node: &mut Node<T>) {
-        match node.parent {
-            None => panic!("trying to merge root with right sibling"),
-            Some(ref n) => {
-                let parent = &mut n.borrow_mut();
-                let right_sibling = &mut parent.children[node.parent_index + 1];
-                let keys = &mut right_sibling.keys;
-                let children = &mut right_sibling.children;
-                node.children.append(children);
-                node.keys.append(keys);
-                parent.keys.remove(node.parent_index);
-                parent.children.remove(node.parent_index + 1);
-            }
+        ("search", [key]) => {
+            let found = if tree.search(*key) { "found" } else { "not found" };
+            writeln!(out, "{key} {found}")?;
         }
-    }
-
-    fn merge_with_left<T: Ord>(&self, node: &mut Node<T>) {
-        match node.parent {
-            None => panic!("trying to merge root with left sibling"),
-            Some(ref n) => {
-                let parent = &mut n.borrow_mut();
-                let left_sibling = &mut parent.children[node.parent_index - 1];
-                let keys = &mut node.keys;
-                let children = &mut node.children;
-                left_sibling.keys.append(keys);
-                left_sibling.children.append(children);
-                parent.keys.remove(node.parent_index - 1);
-                parent.children.remove(node.parent_index);
+        ("print", []) => {
+            tree.pretty_print(&mut *out)?;
+        }
+        ("stats", []) => {
+            let usage = tree.memory_usage();
+            for (depth, level) in usage.levels.iter().enumerate() {
+                writeln!(out, "level {depth}: {} nodes, {} keys, {} bytes", level.nodes, level.keys, level.bytes())?;
             }
+            writeln!(out, "total: {} nodes, {} bytes", usage.nodes(), usage.bytes())?;
         }
+        ("help", []) => writeln!(out, "{HELP}")?,
+        ("quit" | "exit", []) => return Ok(false),
+        _ => writeln!(out, "unknown command or wrong arguments: {line} (try `help`)")?,
     }
-
+    Ok(true)
 }
 
-impl<T> BTree<T>
-where
-    T: Ord + Copy + Debug + Default,
-{
-    pub fn new(branch_factor: usize) -> Self {
-        let degree = 2 * branch_factor;
-        BTree {
-            root: Node::new(degree, None, None, None, 0),
-            props: BTreeProps::new(degree),
-        }
-    }
-
-    pub fn insert(&mut self, key: T) {
-        if self.props.is_maxed_out(&self.root) {
-            // Create an empty root and split the old root...
-            let mut new_root = Node::new(self.props.degree, None, None, None, 0);
-            mem::swap(&mut new_root, &mut self.root);
-            self.root.children.insert(0, new_root);
-            self.props.split_child(&mut self.root, 0);
-        }
-        self.props.insert_non_full(&mut self.root, key);
-    }
-
-    pub fn traverse(&self) {
-        self.props.traverse_node(&self.root, 0);
-        println!("");
-    }
+#[cfg(test)]
+mod test {
+    use std::fs;
 
-    pub fn search(&self, key: T) -> bool {
-        let mut current_node = &self.root;
-        let mut index: isize;
-        loop {
-            index = isize::try_from(current_node.keys.len()).ok().unwrap() - 1;
-            while index >= 0 && current_node.keys[index as usize] > key {
-                index -= 1;
-            }
+    use super::{delete_file, load, parse_args, query, run_command};
+    use b_trees_with_delete::BTree;
 
-            let u_index: usize = usize::try_from(index + 1).ok().unwrap();
-            if index >= 0 && current_node.keys[u_index - 1] == key {
-                break true;
-            } else if current_node.is_leaf() {
-                break false;
-            } else {
-                current_node = &current_node.children[u_index];
-            }
+    #[test]
+    fn test_commands() {
+        let mut tree = BTree::new(3);
+        let mut out = Vec::new();
+        for line in ["insert 5 3 8 1", "delete 3 4", "search 5", "search 3", "", "insert x", "stats", "search"] {
+            assert!(run_command(&mut tree, line, &mut out).unwrap());
         }
+        assert!(!run_command(&mut tree, "quit", &mut out).unwrap());
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[..4], ["4 not found", "5 found", "3 not found", "keys must be whole numbers"]);
+        assert!(lines[4].starts_with("level 0: 1 nodes, 3 keys"));
+        assert!(lines.last().unwrap().starts_with("unknown command"));
     }
-	
-	pub fn delete(&mut self, key: T) -> bool {
-        let mut node: Option<&mut Node<T>> = None;
-		let mut current_node = &mut self.root;
-        let mut index: isize;
-		loop {
-			index = isize::try_from(current_node.keys.len()).ok().unwrap() - 1;
-			while index >= 0 && current_node.keys[index as usize] > key {
-				 index -= 1;
-			}
 
-			let u_index: usize = usize::try_from(index + 1).ok().unwrap();
-			if index >= 0 && current_node.keys[u_index - 1] == key {
-                 node = Some(current_node);
-                 break;
-			} else if current_node.is_leaf() {
-				 break;
-			} else {
-				 current_node = &mut current_node.children[u_index];
-			}
-		}
+    #[test]
+    fn test_load_query_delete() {
+        let dir = std::env::temp_dir();
+        let keys = dir.join(format!("btree-cli-keys-{}.txt", std::process::id()));
+        let deletes = dir.join(format!("btree-cli-deletes-{}.txt", std::process::id()));
+        let db = dir.join(format!("btree-cli-{}.btree", std::process::id()));
+        fs::write(&keys, (0..1000).map(|key| format!("{}\n", key * 3)).collect::<String>()).unwrap();
+        fs::write(&deletes, "150\n\n 153 \n154\n").unwrap();
 
-		match node {
-			None => false,
-			Some(node) => {
-				self.props.delete_key(node, key);
-				if self.root.keys.len() == 0 {
-                    /* if root is left with 0 keys, then its one and only child becomes the new root */
-					self.root = self.root.children.pop().unwrap();
-				}
-				true
-			}
-		}
-	}
-	
-	//fn find_node_with_key(&mut self, key: T) -> Option<&mut Node<T>> {
-	//	let mut current_node = &mut self.root;
-    //    let mut index: isize;
-	//	loop {
-	//		index = isize::try_from(current_node.keys.len()).ok().unwrap() - 1;
-	//		while index >= 0 && current_node.keys[index as usize] > key {
-	//			 index -= 1;
-	//		}
+        let words = ["load", keys.to_str().unwrap(), "--degree", "8", "--db", db.to_str().unwrap()];
+        let args = parse_args(words.into_iter().map(String::from)).unwrap();
+        assert_eq!((args.command.as_str(), args.degree), ("load", 8));
+        assert_eq!(load(args.file.as_ref().unwrap(), args.degree, &args.db).unwrap(), 1000);
+        assert_eq!(delete_file(&deletes, &db).unwrap(), (2, 3));
 
-	//		let u_index: usize = usize::try_from(index + 1).ok().unwrap();
-	//		if index >= 0 && current_node.keys[u_index - 1] == key {
-	//			 break Some(current_node);
-	//		} else if current_node.is_leaf() {
-	//			 break None;
-	//		} else {
-	//			 current_node = &mut current_node.children[u_index];
-	//		}
-	//	}
-	//}
-}
+        let args = parse_args(["query", "--range", "140..160"].into_iter().map(String::from)).unwrap();
+        let mut out = Vec::new();
+        query(&db, args.range.unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "141\n144\n147\n156\n159\n");
+        assert!(parse_args(["query", "--range", "1-2"].into_iter().map(String::from)).is_err());
+        assert!(parse_args(["load", "--degree", "5"].into_iter().map(String::from)).is_err());
+        let args = parse_args(["sort", "in.txt", "out.txt"].into_iter().map(String::from)).unwrap();
+        assert_eq!(args.output.unwrap().to_str(), Some("out.txt"));
+        assert!(parse_args(["load", "in.txt", "out.txt"].into_iter().map(String::from)).is_err());
 
-#[cfg(test)]
-mod test {
-    use super::BTree;
-
-    #[test]
-    fn test_search() {
-        let mut tree = BTree::new(2);
-        tree.insert(10);
-        tree.insert(20);
-        tree.insert(30);
-        tree.insert(5);
-        tree.insert(6);
-        tree.insert(7);
-        tree.insert(11);
-        tree.insert(12);
-        tree.insert(15);
-        assert!(tree.search(15));
-        assert_eq!(tree.search(16), false);
-        //tree.delete(15);
-        //assert_eq!(tree.search(15), false);
-        //assert!(tree.search(12));
-        //tree.delete(12);
-        //assert_eq!(tree.search(12), false);
-        tree.delete(10);
-        assert_eq!(tree.search(10), false);
-        assert!(tree.search(5));
-        assert!(tree.search(7));
-        assert!(tree.search(11));
-        assert!(tree.search(12));
-        assert!(tree.search(15));
-        assert!(tree.search(30));
+        fs::write(&deletes, "12\nabc\n").unwrap();
+        assert!(delete_file(&deletes, &db).unwrap_err().to_string().ends_with(":2: not a whole number: abc"));
+        for path in [keys, deletes, db] {
+            fs::remove_file(path).unwrap();
+        }
     }
 }
@@ -0,0 +1,195 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+
+use crate::free_list::FreeList;
+use crate::{node_mut, BTree, Node};
+
+/// Approximate heap usage of a `BTree`, from the root level down. See
+/// `BTree::memory_usage`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub levels: Vec<LevelUsage>,
+}
+
+/// Heap usage of one level of a tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelUsage {
+    pub nodes: usize,
+    pub keys: usize,
+    /// The `Arc` allocations holding the nodes themselves.
+    pub node_bytes: usize,
+    /// Key buffers, by capacity rather than length.
+    pub key_bytes: usize,
+    /// Child pointer buffers, by capacity rather than length.
+    pub child_bytes: usize,
+}
+
+impl LevelUsage {
+    pub fn bytes(&self) -> usize {
+        self.node_bytes + self.key_bytes + self.child_bytes
+    }
+}
+
+impl MemoryUsage {
+    pub fn nodes(&self) -> usize {
+        self.levels.iter().map(|level| level.nodes).sum()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.levels.iter().map(LevelUsage::bytes).sum()
+    }
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Walk the tree and add up what its nodes hold on the heap.
+    ///
+    /// The figures don't include allocator overhead, and nodes shared with a
+    /// snapshot are counted as if this tree owned them.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        add_node(&self.root, 0, &mut usage);
+        usage
+    }
+
+    /// An empty tree with nodes allocated up front for `expected_keys`
+    /// inserts; see `reserve`.
+    pub fn with_capacity(expected_keys: usize, branch_factor: usize) -> Self {
+        let mut tree = BTree::new(branch_factor);
+        tree.reserve(expected_keys);
+        tree
+    }
+
+    /// Allocate enough spare nodes now that `additional` more inserts make
+    /// no allocator calls for new nodes. Splits leave nodes with at least
+    /// `min_keys` keys, half full unless the split policy says otherwise, so
+    /// that is taken as the worst case; nodes still spare after the inserts
+    /// are kept until `shrink_node_capacity`.
+    pub fn reserve(&mut self, additional: usize) {
+        let nodes = additional.div_ceil(self.props.min_keys.max(1));
+        self.ctx.free.reserve(nodes, self.props.degree, &self.ctx.alloc);
+    }
+
+    /// Give back the spare room in every node's key and child buffers,
+    /// keeping the tree's shape, and drop the nodes kept for reuse. Unlike
+    /// `shrink_to_fit` nothing moves, but the next insert into a trimmed
+    /// node has to grow its buffer again. Nodes shared with a snapshot are
+    /// copied, which trims them too.
+    pub fn shrink_node_capacity(&mut self) {
+        shrink(&mut self.root);
+        self.ctx.free = FreeList::new();
+    }
+
+    /// Whether merges trim the node they take a key out of, as
+    /// `shrink_node_capacity` would, and drop emptied nodes (the merged
+    /// sibling, or a root left with one child) rather than keep them for
+    /// reuse. Off by default: it makes deletes give memory back
+    /// as they go, at the cost of allocating again when inserts refill the
+    /// tree.
+    pub fn set_shrink_on_merge(&mut self, shrink: bool) {
+        self.props.shrink_on_merge = shrink;
+    }
+}
+
+fn shrink<T: Ord + Copy>(node: &mut Arc<Node<T>>) {
+    let node = node_mut(node);
+    node.keys.shrink_to_fit();
+    node.children.shrink_to_fit();
+    for child in &mut node.children {
+        shrink(child);
+    }
+}
+
+fn add_node<T>(node: &Node<T>, depth: usize, usage: &mut MemoryUsage) {
+    if usage.levels.len() == depth {
+        usage.levels.push(LevelUsage::default());
+    }
+    let level = &mut usage.levels[depth];
+    level.nodes += 1;
+    level.keys += node.keys.len();
+    // an `Arc` allocation holds the two reference counts next to the value
+    level.node_bytes += 2 * mem::size_of::<usize>() + mem::size_of::<Node<T>>();
+    level.key_bytes += node.keys.capacity() * mem::size_of::<T>();
+    level.child_bytes += node.children.capacity() * mem::size_of::<Arc<Node<T>>>();
+    for child in &node.children {
+        add_node(child, depth + 1, usage);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_memory_usage() {
+        let mut tree = BTree::new(3);
+        for key in 0..1000u64 {
+            tree.insert(key);
+        }
+        let usage = tree.memory_usage();
+        assert_eq!(usage.levels[0].nodes, 1);
+        assert_eq!(usage.levels.iter().map(|level| level.keys).sum::<usize>(), 1000);
+        // new nodes reserve room for a full set of keys
+        let leaves = usage.levels.last().unwrap();
+        assert!(leaves.key_bytes >= leaves.nodes * 5 * 8);
+
+        tree.shrink_to_fit();
+        let packed = tree.memory_usage();
+        assert!(packed.nodes() < usage.nodes() && packed.bytes() < usage.bytes());
+        assert_eq!(packed.levels.last().unwrap().key_bytes, packed.levels.last().unwrap().keys * 8);
+    }
+
+    #[test]
+    fn test_shrink_node_capacity() {
+        let build = |shrink_on_merge| {
+            let mut tree = BTree::new(8);
+            tree.set_shrink_on_merge(shrink_on_merge);
+            for key in 0..4000u32 {
+                tree.insert((key * 7919) % 4000);
+            }
+            for key in (0..4000u32).filter(|key| key % 4 != 0) {
+                tree.delete(key);
+            }
+            check_node(&tree.root, &tree.props, true);
+            tree
+        };
+        let mut tree = build(false);
+        let (nodes, usage) = (tree.levels().flatten().count(), tree.memory_usage());
+        assert!(tree.ctx.free.len() > 0);
+        tree.shrink_node_capacity();
+        let trimmed = tree.memory_usage();
+        assert_eq!(tree.levels().flatten().count(), nodes);
+        assert!(trimmed.bytes() < usage.bytes() && tree.ctx.free.len() == 0);
+        let leaves = trimmed.levels.last().unwrap();
+        assert_eq!((leaves.key_bytes, leaves.child_bytes), (leaves.keys * 4, 0));
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.iter_snapshot().eq((0..4000).step_by(4)));
+
+        let merged = build(true);
+        assert_eq!(merged.ctx.free.len(), 0);
+        assert!(merged.memory_usage().bytes() < usage.bytes());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut tree = BTree::with_capacity(1000, 3);
+        assert_eq!(tree.ctx.free.len(), 500);
+        for key in 0..1000u32 {
+            tree.insert((key * 7919) % 1000);
+        }
+        check_node(&tree.root, &tree.props, true);
+        // every node but the first root came from the reserve
+        assert_eq!(tree.ctx.free.len(), 500 + 1 - tree.levels().flatten().count());
+
+        let spare = tree.ctx.free.len();
+        tree.reserve(2 * spare + 100);
+        assert_eq!(tree.ctx.free.len(), spare + 50);
+        tree.shrink_node_capacity();
+        assert_eq!(tree.ctx.free.len(), 0);
+    }
+}
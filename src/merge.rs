@@ -0,0 +1,157 @@
+//! Merging trees: two into one sorted stream, or many into one tree.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::fmt::Debug;
+use core::iter::{FusedIterator, Peekable};
+
+use crate::{BTree, SnapshotIter};
+
+/// Which tree a key from `MergeIter` came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Left,
+    Right,
+    /// Equal keys from both trees, given once; the key is the left one.
+    Both,
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// The keys of this tree (left) and `other` (right) in one sorted
+    /// stream, each tagged with where it came from. A key in both trees
+    /// comes once as `Source::Both`, paired copy for copy, so mapping away
+    /// the tags gives the union with duplicates across the trees removed;
+    /// keeping those from one side gives the intersection or a difference.
+    pub fn merge_iter(&self, other: &BTree<T>) -> MergeIter<T> {
+        MergeIter {
+            left: self.iter_snapshot().peekable(),
+            right: other.iter_snapshot().peekable(),
+        }
+    }
+
+    /// One tree holding every key of `trees`, duplicates included: their
+    /// keys merged k ways through a heap, then bulk loaded into full nodes
+    /// of the first tree's branch factor (the default if there are none).
+    /// Costs O(n log k) for n keys in k trees.
+    pub fn merge_all<I: IntoIterator<Item = BTree<T>>>(trees: I) -> BTree<T> {
+        let trees: Vec<BTree<T>> = trees.into_iter().collect();
+        let mut merged = match trees.first() {
+            Some(first) => BTree::new(first.props.degree / 2),
+            None => BTree::default(),
+        };
+        let keys = merge_runs(trees.iter().map(BTree::iter_snapshot));
+        merged.rebuild_from(keys, 1.0);
+        merged
+    }
+
+    /// `merge_all` over runs of keys from anywhere, each of which must be
+    /// sorted; panics at the first key out of order. The result has the
+    /// default branch factor.
+    pub fn merge_sorted<I, R>(runs: I) -> BTree<T>
+    where
+        I: IntoIterator<Item = R>,
+        R: IntoIterator<Item = T>,
+    {
+        BTree::from_sorted_vec(merge_runs(runs.into_iter().map(IntoIterator::into_iter)))
+    }
+}
+
+// The keys of sorted `runs` in one sorted vector, equal keys in run order.
+fn merge_runs<T: Ord + Copy, R: Iterator<Item = T>>(runs: impl Iterator<Item = R>) -> Vec<T> {
+    let mut runs: Vec<R> = runs.collect();
+    let mut heap: BinaryHeap<Reverse<(T, usize)>> =
+        runs.iter_mut().enumerate().filter_map(|(index, run)| Some(Reverse((run.next()?, index)))).collect();
+    let mut keys = Vec::with_capacity(runs.iter().map(|run| run.size_hint().0).sum::<usize>() + heap.len());
+    while let Some(Reverse((key, index))) = heap.pop() {
+        keys.push(key);
+        if let Some(next) = runs[index].next() {
+            assert!(next >= key, "run {index} is not sorted");
+            heap.push(Reverse((next, index)));
+        }
+    }
+    keys
+}
+
+/// See `BTree::merge_iter`. Like `iter_snapshot`, it holds snapshots of
+/// both trees.
+pub struct MergeIter<T: Copy> {
+    left: Peekable<SnapshotIter<T>>,
+    right: Peekable<SnapshotIter<T>>,
+}
+
+impl<T: Ord + Copy> Iterator for MergeIter<T> {
+    type Item = (T, Source);
+
+    fn next(&mut self) -> Option<(T, Source)> {
+        match (self.left.peek(), self.right.peek()) {
+            (Some(left), Some(right)) if left < right => Some((self.left.next()?, Source::Left)),
+            (Some(left), Some(right)) if right < left => Some((self.right.next()?, Source::Right)),
+            (Some(_), Some(_)) => {
+                self.right.next();
+                Some((self.left.next()?, Source::Both))
+            }
+            (Some(_), None) => Some((self.left.next()?, Source::Left)),
+            (None, _) => Some((self.right.next()?, Source::Right)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (left, right) = (self.left.len(), self.right.len());
+        (left.max(right), Some(left + right))
+    }
+}
+
+impl<T: Ord + Copy> FusedIterator for MergeIter<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::Source::{self, Both, Left, Right};
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_merge_iter() {
+        let mut left = BTree::new(2);
+        let mut right = BTree::new(3);
+        for key in [1, 3, 5, 5, 7, 9] {
+            left.insert(key);
+        }
+        for key in [2, 3, 5, 8, 9, 10] {
+            right.insert(key);
+        }
+        let merged: Vec<(i32, Source)> = left.merge_iter(&right).collect();
+        assert_eq!(
+            merged,
+            [(1, Left), (2, Right), (3, Both), (5, Both), (5, Left), (7, Left), (8, Right), (9, Both), (10, Right)]
+        );
+        assert_eq!(left.merge_iter(&right).size_hint(), (6, Some(12)));
+        let both: Vec<i32> = left.merge_iter(&right).filter(|(_, source)| *source == Both).map(|(key, _)| key).collect();
+        assert_eq!(both, [3, 5, 9]);
+        assert!(left.merge_iter(&BTree::new(2)).map(|(key, _)| key).eq(left.iter_snapshot()));
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let trees = (0..5u32).map(|run| {
+            let mut tree = BTree::new(3);
+            for key in (0..200).filter(|key| key % 5 == run || key % 7 == 0) {
+                tree.insert(key);
+            }
+            tree
+        });
+        let merged = BTree::merge_all(trees);
+        check_node(&merged.root, &merged.props, true);
+        assert_eq!(merged.degree(), 6);
+        let mut expected: Vec<u32> = (0..200).chain((0..200).filter(|key| key % 7 == 0).flat_map(|key| [key; 4])).collect();
+        expected.sort();
+        assert!(merged.iter_snapshot().eq(expected));
+        assert_eq!(BTree::<u32>::merge_all([]).iter_snapshot().len(), 0);
+
+        let sorted = BTree::merge_sorted([vec![1, 4, 9], vec![], vec![2, 3, 10, 11], vec![4]]);
+        assert!(sorted.iter_snapshot().eq([1, 2, 3, 4, 4, 9, 10, 11]));
+    }
+}
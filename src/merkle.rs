@@ -0,0 +1,104 @@
+//! Merkle hashes: every node has a SHA-256 hash of its keys and of its
+//! children's hashes, so the root's hash stands for the whole tree and
+//! `BTree::diff` can skip subtrees whose hashes match.
+//!
+//! Hashes are worked out when first asked for and kept in the node until
+//! the node changes, so after a few inserts or deletes only the nodes on
+//! their paths are hashed again. Keys are fed to the hash through their
+//! `Hash` impls, which for the standard types write native-endian bytes:
+//! hashes agree between machines of the same endianness.
+//!
+//! A hash covers the shape of the tree as well as its keys. Trees built by
+//! the same operations from the same start hash the same; the same keys
+//! arranged differently usually don't.
+
+use core::fmt::Debug;
+use core::hash::Hasher;
+
+use sha2::{Digest, Sha256};
+
+use crate::{BTree, Node};
+
+/// A SHA-256 digest.
+pub type Hash = [u8; 32];
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default + core::hash::Hash,
+{
+    /// The hash of the root, standing for every key and the tree's shape.
+    pub fn root_hash(&self) -> Hash {
+        node_hash(&self.root)
+    }
+}
+
+fn node_hash<T: Ord + core::hash::Hash>(node: &Node<T>) -> Hash {
+    *node.hash.get_or_init(|| {
+        let mut hasher = Feed(Sha256::new());
+        hasher.0.update([u8::from(node.is_leaf())]);
+        for (index, key) in node.keys.iter().enumerate() {
+            if let Some(child) = node.children.get(index) {
+                hasher.0.update(node_hash(child));
+            }
+            key.hash(&mut hasher);
+        }
+        if let Some(child) = node.children.get(node.keys.len()) {
+            hasher.0.update(node_hash(child));
+        }
+        hasher.0.finalize().into()
+    })
+}
+
+// Passes what a key's `Hash` impl writes on to SHA-256.
+struct Feed(Sha256);
+
+impl Hasher for Feed {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        unreachable!("only written to")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BTree;
+
+    #[test]
+    fn test_root_hash_and_diff() {
+        let build = |keys: &mut dyn Iterator<Item = u32>| {
+            let mut tree = BTree::new(2);
+            keys.for_each(|key| tree.insert(key));
+            tree
+        };
+        let mut ours = build(&mut (0..2000).map(|key| (key * 7919) % 2000));
+        let mut theirs = build(&mut (0..2000).map(|key| (key * 7919) % 2000));
+        assert_eq!(ours.root_hash(), theirs.root_hash());
+        assert!(ours.diff(&theirs).only_in_self.is_empty());
+
+        let before = ours.snapshot();
+        ours.delete(1234);
+        ours.insert(1500);
+        theirs.insert(5000);
+        assert_ne!(ours.root_hash(), theirs.root_hash());
+        // every subtree hashed, and only the changed ones walked
+        let diff = ours.diff(&theirs);
+        assert_eq!((diff.only_in_self, diff.only_in_other), (vec![1500], vec![1234, 5000]));
+
+        theirs.delete(5000);
+        theirs.delete(1234);
+        theirs.insert(1500);
+        assert!(ours.diff(&theirs) == Default::default());
+        drop(before);
+
+        // a tree of another shape, with no hashes in common
+        let sorted = build(&mut (0..2000u32).filter(|key| !key.is_multiple_of(3)));
+        let diff = ours.diff(&sorted);
+        // `ours` holds 1500 twice, and `sorted` 1234, which `ours` lost
+        let mut expected: Vec<u32> = (0..2000).step_by(3).collect();
+        expected.insert(501, 1500);
+        assert_eq!((diff.only_in_self, diff.only_in_other), (expected, vec![1234]));
+    }
+}
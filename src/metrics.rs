@@ -0,0 +1,264 @@
+//! Counts of the structural work a `BTree` does, with the `metrics` feature,
+//! and of the work of single operations, with the `profile` feature.
+//!
+//! Without the features the counters are empty and every update compiles to
+//! nothing, so uninstrumented trees don't pay for them.
+
+#[cfg(any(feature = "metrics", feature = "profile"))]
+use core::fmt::Debug;
+#[cfg(any(feature = "metrics", feature = "profile"))]
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+#[cfg(any(feature = "metrics", feature = "profile"))]
+use crate::BTree;
+
+/// What a tree has done since it was made or `BTree::reset_metrics` was
+/// last called. See `BTree::metrics`.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Nodes split in two, or into more pieces by `insert_batch`, counted
+    /// once for each new node.
+    pub splits: u64,
+    pub merges: u64,
+    /// Keys borrowed from a left sibling to refill a node.
+    pub left_donations: u64,
+    /// Keys borrowed from a right sibling to refill a node.
+    pub right_donations: u64,
+    /// Key comparisons made by `search`, `insert` and `delete`.
+    pub comparisons: u64,
+    /// The tallest the tree has been, counting the root as 1.
+    pub max_depth: u64,
+}
+
+/// The work one `search`, `insert` or `delete` did, from
+/// `BTree::search_profiled` and the like: enough to check that it grows
+/// with the log of the tree's size, and to weigh a larger branch factor
+/// (fewer nodes, more comparisons in each) against what a comparison of
+/// your keys costs.
+#[cfg(feature = "profile")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub comparisons: u64,
+    /// Nodes whose keys were searched, counting a node again each time an
+    /// operation comes back to it.
+    pub nodes_visited: u64,
+}
+
+// Kept in `BTreeProps`. Atomic so that `search`, through a shared
+// reference, can count too, and the tree stays `Sync`.
+#[derive(Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "metrics")]
+    counts: [AtomicU64; 6],
+    // Comparisons and node visits since the profiled operation started.
+    #[cfg(feature = "profile")]
+    operation: [AtomicU64; 2],
+}
+
+#[cfg(feature = "metrics")]
+const SPLITS: usize = 0;
+#[cfg(feature = "metrics")]
+const MERGES: usize = 1;
+#[cfg(feature = "metrics")]
+const LEFT_DONATIONS: usize = 2;
+#[cfg(feature = "metrics")]
+const RIGHT_DONATIONS: usize = 3;
+#[cfg(feature = "metrics")]
+const COMPARISONS: usize = 4;
+#[cfg(feature = "metrics")]
+const MAX_DEPTH: usize = 5;
+
+impl Counters {
+    #[cfg(feature = "metrics")]
+    fn add(&self, counter: usize, count: usize) {
+        self.counts[counter].fetch_add(count as u64, Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn split(&self, _new_nodes: usize) {
+        #[cfg(feature = "metrics")]
+        self.add(SPLITS, _new_nodes);
+    }
+
+    #[inline]
+    pub(crate) fn merge(&self) {
+        #[cfg(feature = "metrics")]
+        self.add(MERGES, 1);
+    }
+
+    #[inline]
+    pub(crate) fn left_donation(&self) {
+        #[cfg(feature = "metrics")]
+        self.add(LEFT_DONATIONS, 1);
+    }
+
+    #[inline]
+    pub(crate) fn right_donation(&self) {
+        #[cfg(feature = "metrics")]
+        self.add(RIGHT_DONATIONS, 1);
+    }
+
+    #[inline]
+    pub(crate) fn compare(&self, _comparisons: usize) {
+        #[cfg(feature = "metrics")]
+        self.add(COMPARISONS, _comparisons);
+        #[cfg(feature = "profile")]
+        self.operation[0].fetch_add(_comparisons as u64, Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn visit(&self) {
+        #[cfg(feature = "profile")]
+        self.operation[1].fetch_add(1, Relaxed);
+    }
+
+    #[cfg(feature = "profile")]
+    fn start_operation(&self) {
+        for count in &self.operation {
+            count.store(0, Relaxed);
+        }
+    }
+
+    #[cfg(feature = "profile")]
+    fn operation(&self) -> Profile {
+        let [comparisons, nodes_visited] = self.operation.each_ref().map(|count| count.load(Relaxed));
+        Profile { comparisons, nodes_visited }
+    }
+
+    #[inline]
+    pub(crate) fn depth(&self, _depth: usize) {
+        #[cfg(feature = "metrics")]
+        self.counts[MAX_DEPTH].fetch_max(_depth as u64, Relaxed);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn read(&self) -> Metrics {
+        let [splits, merges, left_donations, right_donations, comparisons, max_depth] =
+            self.counts.each_ref().map(|count| count.load(Relaxed));
+        Metrics {
+            splits,
+            merges,
+            left_donations,
+            right_donations,
+            comparisons,
+            max_depth,
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn reset(&self) {
+        for count in &self.counts {
+            count.store(0, Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn metrics(&self) -> Metrics {
+        self.props.counters.read()
+    }
+
+    pub fn reset_metrics(&self) {
+        self.props.counters.reset();
+    }
+}
+
+#[cfg(feature = "profile")]
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// `search`, and the work it did. Searches running on other threads at
+    /// the same time are counted in too.
+    pub fn search_profiled(&self, key: T) -> (bool, Profile) {
+        self.props.counters.start_operation();
+        let found = self.search(key);
+        (found, self.props.counters.operation())
+    }
+
+    /// `insert`, and the work it did, including any search the duplicate
+    /// policy calls for.
+    pub fn insert_profiled(&mut self, key: T) -> Profile {
+        self.props.counters.start_operation();
+        self.insert(key);
+        self.props.counters.operation()
+    }
+
+    /// `delete`, and the work it did: the search that checks `key` is
+    /// there, then the descent that removes it.
+    pub fn delete_profiled(&mut self, key: T) -> (bool, Profile) {
+        self.props.counters.start_operation();
+        let deleted = self.delete(key);
+        (deleted, self.props.counters.operation())
+    }
+}
+
+#[cfg(all(test, any(feature = "metrics", feature = "profile")))]
+mod test {
+    use crate::BTree;
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        let mut tree = BTree::new(2);
+        for key in 0..1000u32 {
+            tree.insert(key);
+        }
+        let metrics = tree.metrics();
+        assert!(metrics.splits > 300);
+        // in order, each insert after the first is one comparison with the
+        // largest key
+        assert_eq!(metrics.comparisons, 999);
+        assert_eq!((metrics.merges, metrics.max_depth), (0, 9));
+
+        tree.reset_metrics();
+        assert!(tree.search(500));
+        let lookup = tree.metrics().comparisons;
+        assert!(lookup > 0 && lookup < 40);
+
+        for key in 0..1000u32 {
+            tree.delete(key);
+        }
+        let metrics = tree.metrics();
+        assert!(metrics.merges > 0 && metrics.left_donations + metrics.right_donations > 0);
+        assert_eq!((metrics.splits, metrics.max_depth), (0, 9));
+    }
+
+    #[cfg(feature = "profile")]
+    #[test]
+    fn test_profile() {
+        let mut tree = BTree::new(2);
+        for key in (0..1000u32).rev() {
+            let profile = tree.insert_profiled(key * 2);
+            assert!(profile.nodes_visited <= 9 && profile.comparisons <= 40, "{profile:?}");
+        }
+        // one node for each level, root to leaf
+        let (found, profile) = tree.search_profiled(1);
+        assert!(!found);
+        assert_eq!(profile.nodes_visited, tree.height() as u64);
+        assert!(profile.comparisons >= profile.nodes_visited);
+
+        let (_, search) = tree.search_profiled(1000);
+        let (deleted, profile) = tree.delete_profiled(1000);
+        assert!(deleted && profile.nodes_visited > tree.height() as u64);
+        // the descent that removes the key follows the search's path
+        assert_eq!(profile.comparisons, search.comparisons);
+        assert_eq!(tree.delete_profiled(1).1, tree.search_profiled(1).1);
+
+        let mut wide = BTree::new(32);
+        for key in 0..1000u32 {
+            wide.insert(key * 2);
+        }
+        let (_, narrow) = tree.search_profiled(1);
+        let (_, profile) = wide.search_profiled(1);
+        assert!(profile.nodes_visited < narrow.nodes_visited);
+        // scanned key by key, one wide node costs more than several narrow
+        #[cfg(not(feature = "branchless"))]
+        assert!(profile.comparisons > narrow.comparisons);
+    }
+}
@@ -0,0 +1,246 @@
+//! A table of values by primary key, with secondary indexes kept in step.
+//!
+//! Values live in a `BTreeMap` by primary key. Each secondary index is a
+//! `BTree` of `(secondary key, primary key)` pairs, scanned with
+//! `range_prefix`, so many values can share a secondary key.
+//!
+//! Values handed out by `values_mut` or `iter_mut` may be changed so they
+//! no longer match their index entries, so their secondary keys are noted
+//! as they go out. The next `insert`, `remove` or `add_index` re-indexes
+//! those whose keys did change, and until then `find` checks just them
+//! against the value rather than the index.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+
+use crate::BTree;
+
+/// Values by primary key `K`, also findable by secondary keys `S` worked
+/// out from them. See the module docs.
+///
+/// ```
+/// use b_trees_with_delete::MultiIndex;
+///
+/// let mut users = MultiIndex::new();
+/// let by_age = users.add_index(|user: &(&str, u32)| user.1);
+/// users.insert(1, ("ann", 30));
+/// users.insert(2, ("bob", 25));
+/// users.insert(3, ("cy", 30));
+/// assert_eq!(users.find(by_age, 30).map(|(id, _)| id).collect::<Vec<u32>>(), [1, 3]);
+/// ```
+pub struct MultiIndex<K, V, S> {
+    primary: BTreeMap<K, V>,
+    secondary: Vec<Secondary<K, V, S>>,
+    // Entries handed out to be changed since the indexes were last brought
+    // up to date, with their secondary keys then, one per index.
+    touched: BTreeMap<K, Vec<S>>,
+}
+
+struct Secondary<K, V, S> {
+    extract: Box<dyn Fn(&V) -> S + Send + Sync>,
+    tree: BTree<(S, K)>,
+}
+
+impl<K, V, S> MultiIndex<K, V, S>
+where
+    K: Ord + Copy + Debug + Default,
+    S: Ord + Copy + Debug + Default,
+{
+    pub fn new() -> Self {
+        MultiIndex {
+            primary: BTreeMap::new(),
+            secondary: Vec::new(),
+            touched: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.primary.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary.is_empty()
+    }
+
+    /// Index every value, those already in and those to come, by
+    /// `extract(value)`, returning the number `find` takes to use the index.
+    pub fn add_index(&mut self, extract: impl Fn(&V) -> S + Send + Sync + 'static) -> usize {
+        self.refresh();
+        let mut keys: Vec<(S, K)> = self.primary.iter().map(|(&key, value)| (extract(value), key)).collect();
+        keys.sort_unstable();
+        self.secondary.push(Secondary {
+            extract: Box::new(extract),
+            tree: BTree::from_sorted_vec(keys),
+        });
+        self.secondary.len() - 1
+    }
+
+    pub fn get(&self, key: K) -> Option<&V> {
+        self.primary.get(&key)
+    }
+
+    /// Store `value` under `key`, re-indexing it, and return the value it
+    /// replaces.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.refresh();
+        let old = self.remove(key);
+        for index in &mut self.secondary {
+            index.tree.insert(((index.extract)(&value), key));
+        }
+        self.primary.insert(key, value);
+        old
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        self.refresh();
+        let value = self.primary.remove(&key)?;
+        for index in &mut self.secondary {
+            index.tree.delete(((index.extract)(&value), key));
+        }
+        Some(value)
+    }
+
+    /// The entries whose secondary key in index `index` is `secondary`, by
+    /// primary key. Panics if there is no such index.
+    pub fn find(&self, index: usize, secondary: S) -> impl Iterator<Item = (K, &V)> {
+        let index = &self.secondary[index];
+        let mut indexed =
+            index.tree.range_prefix(secondary).map(|(_, key)| key).filter(|key| !self.touched.contains_key(key)).peekable();
+        let mut touched = self
+            .touched
+            .keys()
+            .copied()
+            .filter(move |key| (index.extract)(&self.primary[key]) == secondary)
+            .peekable();
+        // both by primary key
+        core::iter::from_fn(move || {
+            let key = match (indexed.peek(), touched.peek()) {
+                (Some(a), Some(b)) if b < a => touched.next(),
+                (Some(_), _) => indexed.next(),
+                (None, _) => touched.next(),
+            }?;
+            Some((key, &self.primary[&key]))
+        })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.primary.keys().copied()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.primary.values()
+    }
+
+    /// Every entry, by primary key.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
+        self.primary.iter().map(|(&key, value)| (key, value))
+    }
+
+    /// The values by primary key, to change in place. See the module docs
+    /// for what that does to the indexes.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    /// `iter` with the values to change in place, as `values_mut`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (K, &mut V)> {
+        let (secondary, touched) = (&self.secondary, &mut self.touched);
+        self.primary.iter_mut().map(move |(&key, value)| {
+            if !secondary.is_empty() {
+                touched.entry(key).or_insert_with(|| secondary.iter().map(|index| (index.extract)(value)).collect());
+            }
+            (key, value)
+        })
+    }
+
+    // Re-index the entries handed out to be changed whose secondary keys
+    // did change.
+    fn refresh(&mut self) {
+        for (key, old) in mem::take(&mut self.touched) {
+            let value = &self.primary[&key];
+            for (index, old) in self.secondary.iter_mut().zip(old) {
+                let new = (index.extract)(value);
+                if new != old {
+                    index.tree.delete((old, key));
+                    index.tree.insert((new, key));
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, S> Default for MultiIndex<K, V, S>
+where
+    K: Ord + Copy + Debug + Default,
+    S: Ord + Copy + Debug + Default,
+{
+    fn default() -> Self {
+        MultiIndex::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+
+    use super::MultiIndex;
+
+    #[test]
+    fn test_multi_index() {
+        // orders: (customer, total)
+        let mut orders = MultiIndex::new();
+        for id in 0..300u32 {
+            orders.insert(id, (id % 7, id * 10 % 97));
+        }
+        let by_customer = orders.add_index(|order: &(u32, u32)| order.0);
+        let by_total = orders.add_index(|order| order.1);
+        for id in (0..300).step_by(3) {
+            assert_eq!(orders.remove(id), Some((id % 7, id * 10 % 97)));
+        }
+        assert_eq!(orders.insert(1, (100, 1)), Some((1, 10)));
+        assert_eq!(orders.remove(0), None);
+
+        let live = |id: &u32| !id.is_multiple_of(3) && *id != 1;
+        for customer in 0..7 {
+            let ids: Vec<u32> = orders.find(by_customer, customer).map(|(id, _)| id).collect();
+            assert_eq!(ids, (0..300).filter(|id| live(id) && id % 7 == customer).collect::<Vec<_>>());
+        }
+        assert!(orders.find(by_customer, 100).map(|(id, order)| (id, *order)).eq([(1, (100, 1))]));
+        let totals: Vec<(u32, u32)> = orders.find(by_total, 10).map(|(id, order)| (id, order.1)).collect();
+        assert_eq!(totals, (0..300).filter(|id| live(id) && id * 10 % 97 == 10).map(|id| (id, 10)).collect::<Vec<_>>());
+        assert_eq!(orders.len(), 200);
+
+        assert!(orders.keys().take(3).eq([1, 2, 4]));
+        assert_eq!(orders.values().count(), 200);
+        // move every order of customer 3 to customer 4
+        for (_, order) in orders.iter_mut().filter(|(_, order)| order.0 == 3) {
+            order.0 = 4;
+        }
+        assert_eq!(orders.find(by_customer, 3).count(), 0);
+        let moved: Vec<u32> = orders.find(by_customer, 4).map(|(id, _)| id).collect();
+        assert_eq!(moved, (0..300).filter(|id| live(id) && [3, 4].contains(&(id % 7))).collect::<Vec<_>>());
+        orders.remove(2);
+        assert_eq!(orders.find(by_customer, 4).count(), moved.len());
+        for total in orders.values_mut() {
+            total.1 += 1;
+        }
+        assert_eq!(orders.find(by_total, 11).count(), totals.len());
+        orders.refresh();
+        assert!(orders.touched.is_empty());
+        assert_eq!(orders.find(by_total, 11).count(), totals.len());
+        assert_eq!(orders.secondary[by_total].tree.len(), orders.len());
+
+        // handing out values that stay the same re-indexes nothing
+        let before = Arc::clone(&orders.secondary[by_customer].tree.root);
+        for (id, order) in orders.iter_mut() {
+            order.1 += id % 2;
+        }
+        assert_eq!(orders.touched.len(), orders.len());
+        orders.refresh();
+        assert!(Arc::ptr_eq(&orders.secondary[by_customer].tree.root, &before));
+        assert!(orders.find(by_customer, 4).map(|(id, _)| id).eq(moved.into_iter().filter(|&id| id != 2)));
+    }
+}
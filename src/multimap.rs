@@ -0,0 +1,338 @@
+//! A map from keys to any number of values each.
+//!
+//! The tree holds one `(key, arrival, slot)` entry per value, so a key's
+//! values sit next to each other in the order they were inserted and are
+//! found with `range_prefix`. The values themselves live in a vector of
+//! slots, each with a copy of its key, which lets them be of any type and
+//! borrowed from the map along with their keys; freed slots are reused by
+//! later inserts.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::mem;
+use core::ops::{Bound, Index, IndexMut, RangeBounds};
+
+use crate::{BTree, SnapshotIter};
+
+/// Values `V` by key `K`, any number per key. See the module docs.
+///
+/// ```
+/// use b_trees_with_delete::BTreeMultiMap;
+///
+/// let mut tags = BTreeMultiMap::new(8);
+/// tags.insert("rust", "fast");
+/// tags.insert("rust", "safe");
+/// tags.insert("c", "fast");
+/// assert_eq!(tags.get_all(&"rust").collect::<Vec<_>>(), [&"fast", &"safe"]);
+/// assert!(tags.remove_one("rust", &"fast"));
+/// assert_eq!(tags.remove_all("rust"), ["safe"]);
+/// ```
+pub struct BTreeMultiMap<K, V> {
+    tree: BTree<(K, u64, usize)>,
+    values: Vec<Option<(K, V)>>,
+    // Slots of `values` emptied by removals.
+    free: Vec<usize>,
+    inserted: u64,
+}
+
+impl<K, V> BTreeMultiMap<K, V>
+where
+    K: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize) -> Self {
+        BTreeMultiMap { tree: BTree::new(branch_factor), values: Vec::new(), free: Vec::new(), inserted: 0 }
+    }
+
+    /// The number of values, counting each value of a key.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Add `value` to those of `key`, after any it already has.
+    pub fn insert(&mut self, key: K, value: V) {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.values[slot] = Some((key, value));
+                slot
+            }
+            None => {
+                self.values.push(Some((key, value)));
+                self.values.len() - 1
+            }
+        };
+        self.tree.insert((key, self.inserted, slot));
+        self.inserted += 1;
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.tree.range_prefix(*key).next().is_some()
+    }
+
+    /// The first value inserted for `key`, as `map[&key]` gives it.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_all(key).next()
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let (_, _, slot) = self.tree.range_prefix(*key).next()?;
+        self.values[slot].as_mut().map(|(_, value)| value)
+    }
+
+    /// The values of `key` in the order they were inserted.
+    pub fn get_all(&self, key: &K) -> impl Iterator<Item = &V> + '_ {
+        self.tree.range_prefix(*key).map(move |(_, _, slot)| &self.slot(slot).1)
+    }
+
+    /// The keys with at least one value, each once, in order.
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        let mut last = None;
+        self.tree.iter_snapshot().map(|(key, _, _)| key).filter(move |&key| last.replace(key) != Some(key))
+    }
+
+    /// Every value, in key order and then insertion order.
+    pub fn values(&self) -> impl Iterator<Item = &V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// `values`, to change in place.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    /// Every value with its key, in the order of `values`.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.range(..)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> + '_ {
+        self.range_mut(..)
+    }
+
+    /// Every value of the keys in `range`, with its key, in key order and
+    /// then insertion order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.entries(bounds(&range)).map(move |(_, _, slot)| {
+            let (key, value) = self.slot(slot);
+            (key, value)
+        })
+    }
+
+    /// `range` with the values mutable, so they can be changed in place.
+    pub fn range_mut<R: RangeBounds<K>>(&mut self, range: R) -> impl Iterator<Item = (&K, &mut V)> + '_ {
+        let slots: Vec<usize> = self.entries(bounds(&range)).map(|(_, _, slot)| slot).collect();
+        // Borrow the slots apart in slot order, then put them in key order.
+        let mut by_slot: Vec<(usize, usize)> = slots.iter().enumerate().map(|(index, &slot)| (slot, index)).collect();
+        by_slot.sort_unstable();
+        let mut found: Vec<Option<&mut (K, V)>> = slots.iter().map(|_| None).collect();
+        let (mut rest, mut skipped) = (&mut self.values[..], 0);
+        for (slot, index) in by_slot {
+            let (item, tail) = mem::take(&mut rest)[slot - skipped..].split_first_mut().unwrap();
+            found[index] = item.as_mut();
+            (rest, skipped) = (tail, slot + 1);
+        }
+        found.into_iter().map(|item| {
+            let (key, value) = item.expect("tree entry for an empty slot");
+            (&*key, value)
+        })
+    }
+
+    /// Remove the first value of `key` equal to `value`, returning whether
+    /// there was one.
+    pub fn remove_one(&mut self, key: K, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        match self.tree.range_prefix(key).find(|&(_, _, slot)| self.slot(slot).1 == *value) {
+            Some(entry) => {
+                self.remove_entry(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove every value of `key`, returning them in the order they were
+    /// inserted.
+    pub fn remove_all(&mut self, key: K) -> Vec<V> {
+        let entries: Vec<(K, u64, usize)> = self.tree.range_prefix(key).collect();
+        entries.into_iter().map(|entry| self.remove_entry(entry)).collect()
+    }
+
+    // The tree's entries for the keys from `start` to `end`.
+    fn entries(&self, (start, end): (Bound<K>, Bound<K>)) -> impl Iterator<Item = (K, u64, usize)> {
+        let before = move |entry: &(K, u64, usize)| match start {
+            Bound::Included(start) => entry.0 < start,
+            Bound::Excluded(start) => entry.0 <= start,
+            Bound::Unbounded => false,
+        };
+        SnapshotIter::seek(Arc::clone(&self.tree.root), before).take_while(move |entry| match end {
+            Bound::Included(end) => entry.0 <= end,
+            Bound::Excluded(end) => entry.0 < end,
+            Bound::Unbounded => true,
+        })
+    }
+
+    fn slot(&self, slot: usize) -> &(K, V) {
+        self.values[slot].as_ref().expect("tree entry for an empty slot")
+    }
+
+    fn remove_entry(&mut self, entry: (K, u64, usize)) -> V {
+        self.tree.delete(entry);
+        self.free.push(entry.2);
+        self.values[entry.2].take().expect("tree entry for an empty slot").1
+    }
+}
+
+impl<K, V> Default for BTreeMultiMap<K, V>
+where
+    K: Ord + Copy + Debug + Default,
+{
+    fn default() -> Self {
+        BTreeMultiMap { tree: BTree::default(), values: Vec::new(), free: Vec::new(), inserted: 0 }
+    }
+}
+
+fn bounds<K: Copy>(range: &impl RangeBounds<K>) -> (Bound<K>, Bound<K>) {
+    (range.start_bound().cloned(), range.end_bound().cloned())
+}
+
+/// The first value of a key, panicking if it has none, as std maps do.
+impl<K, V> Index<&K> for BTreeMultiMap<K, V>
+where
+    K: Ord + Copy + Debug + Default,
+{
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        match self.get(key) {
+            Some(value) => value,
+            None => panic!("no value for key {key:?}; use get(&key) if it may be missing"),
+        }
+    }
+}
+
+impl<K, V> IndexMut<&K> for BTreeMultiMap<K, V>
+where
+    K: Ord + Copy + Debug + Default,
+{
+    fn index_mut(&mut self, key: &K) -> &mut V {
+        match self.get_mut(key) {
+            Some(value) => value,
+            None => panic!("no value for key {key:?}; use get_mut(&key) if it may be missing"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use core::ops::Bound;
+    use std::panic::AssertUnwindSafe;
+
+    use super::BTreeMultiMap;
+    use crate::test::check_node;
+
+    #[test]
+    fn test_multimap() {
+        let mut map = BTreeMultiMap::new(2);
+        for number in 0..300u32 {
+            map.insert(number % 7, String::from(char::from(b'a' + (number / 7 % 26) as u8)));
+        }
+        assert_eq!(map.len(), 300);
+        let first: String = map.get_all(&3).take(5).map(String::as_str).collect();
+        assert_eq!(first, "abcde");
+        assert_eq!(map.get_all(&9).count(), 0);
+
+        assert!(map.remove_one(3, &String::from("b")));
+        assert!(!map.remove_one(3, &String::from("?")));
+        assert!(!map.remove_one(9, &String::from("a")));
+        let first: String = map.get_all(&3).take(5).map(String::as_str).collect();
+        assert_eq!(first, "acdef");
+
+        let removed = map.remove_all(5);
+        assert_eq!(removed.len(), 43);
+        assert_eq!(removed[..3], ["a", "b", "c"]);
+        assert!(!map.contains_key(&5) && map.contains_key(&6));
+        assert_eq!(map.len(), 300 - 44);
+        check_node(&map.tree.root, &map.tree.props, true);
+
+        // freed slots are reused, and new values go after the old ones
+        map.insert(3, String::from("new"));
+        assert_eq!(map.values.len(), 300);
+        assert_eq!(map.get_all(&3).last().map(String::as_str), Some("new"));
+        let counts: Vec<usize> = (0..7).map(|key| map.get_all(&key).count()).collect();
+        assert_eq!(counts, [43, 43, 43, 43, 43, 0, 42]);
+    }
+
+    #[test]
+    fn test_index() {
+        let mut map = BTreeMultiMap::new(2);
+        for key in 0..40u32 {
+            map.insert(key / 2, key);
+        }
+        assert_eq!((map[&0], map[&7], map.get(&20)), (0, 14, None));
+        map[&7] += 100;
+        assert_eq!(map.get_all(&7).copied().collect::<Vec<u32>>(), [114, 15]);
+
+        let panic = std::panic::catch_unwind(AssertUnwindSafe(|| map[&20])).unwrap_err();
+        assert_eq!(panic.downcast_ref::<String>().unwrap(), "no value for key 20; use get(&key) if it may be missing");
+    }
+
+    #[test]
+    fn test_range() {
+        let mut map = BTreeMultiMap::new(2);
+        for number in 0..200u32 {
+            map.insert(number * 37 % 50, number);
+        }
+        // free some slots and reuse them, so slot order differs from key order
+        for key in [3, 10, 44] {
+            map.remove_all(key);
+        }
+        for number in 0..12 {
+            map.insert(number % 4 * 15, 1000 + number);
+        }
+
+        let window: Vec<(u32, u32)> = map.range(9..=15).map(|(&key, &value)| (key, value)).collect();
+        let mut expected: Vec<(u32, u32)> =
+            (0..200).map(|number| (number * 37 % 50, number)).filter(|&(key, _)| (9..=15).contains(&key) && key != 10).collect();
+        expected.sort_by_key(|&(key, _)| key);
+        expected.extend([(15, 1001), (15, 1005), (15, 1009)]);
+        assert_eq!(window, expected);
+        assert_eq!(map.range(..).count(), map.len());
+        assert_eq!(map.range((Bound::Excluded(49), Bound::Unbounded)).count(), 0);
+
+        for (key, value) in map.range_mut(9..=15) {
+            *value += *key * 10_000;
+        }
+        let changed: Vec<(u32, u32)> = map.range(9..=15).map(|(&key, &value)| (key, value - key * 10_000)).collect();
+        assert_eq!(changed, expected);
+        assert_eq!(map.get_all(&16).count(), 4);
+        assert!(map.get_all(&16).all(|&value| value < 200));
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut map = BTreeMultiMap::new(2);
+        for number in 0..60u32 {
+            map.insert(number % 5 * 2, number);
+        }
+        map.remove_all(4);
+        assert!(map.keys().eq([0, 2, 6, 8]));
+        assert!(map.values().take(3).eq(&[0, 5, 10]));
+        assert_eq!(map.iter().count(), 48);
+        for (key, value) in map.iter_mut() {
+            *value += *key * 100;
+        }
+        for value in map.values_mut().filter(|value| **value >= 800) {
+            *value = 0;
+        }
+        assert!(map.get_all(&6).take(2).eq(&[603, 608]));
+        assert!(map.get_all(&8).all(|&value| value == 0));
+    }
+}
@@ -0,0 +1,666 @@
+use std::hint;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned};
+
+// A version counter that doubles as a writer lock. `LOCKED` is set while a
+// writer holds it and `DELETING` too if that writer is a delete; the rest
+// counts the changes deletes have made, so readers can tell whether what
+// they read is still current. Inserts change nodes without counting: the
+// keys a split moves stay reachable through the right link, so readers that
+// race with one follow the link instead of starting over.
+struct VersionLock(AtomicU64);
+
+const LOCKED: u64 = 1;
+const DELETING: u64 = 2;
+const CHANGE: u64 = 4;
+
+impl VersionLock {
+    fn new() -> Self {
+        VersionLock(AtomicU64::new(0))
+    }
+
+    // Start an optimistic read; None while a delete holds the lock.
+    fn read(&self) -> Option<u64> {
+        let version = self.0.load(Ordering::Acquire) & !LOCKED;
+        (version & DELETING == 0).then_some(version)
+    }
+
+    fn validate(&self, version: u64) -> bool {
+        self.0.load(Ordering::Acquire) & !LOCKED == version
+    }
+
+    fn lock(&self, deleting: bool) {
+        let held = if deleting { LOCKED | DELETING } else { LOCKED };
+        let mut spins = 0u32;
+        loop {
+            let version = self.0.load(Ordering::Relaxed);
+            if version & LOCKED == 0
+                && self
+                    .0
+                    .compare_exchange_weak(version, version | held, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    // Releasing a lock without having changed anything restores the old
+    // version, so readers that overlapped it don't restart.
+    fn unlock(&self, changed: bool) {
+        let version = self.0.load(Ordering::Relaxed);
+        let counted = if changed && version & DELETING != 0 { CHANGE } else { 0 };
+        self.0.store((version & !(LOCKED | DELETING)) + counted, Ordering::Release);
+    }
+}
+
+fn backoff(spins: &mut u32) {
+    *spins += 1;
+    if *spins < 64 {
+        hint::spin_loop();
+    } else {
+        std::thread::yield_now();
+    }
+}
+
+// Bodies are never changed in place: a writer swaps in an edited copy, so a
+// reader holding an old one can still look at it safely, and the version
+// check tells it whether the copy was current. Readers hold bodies, and the
+// nodes they link to, by plain reference inside a pinned epoch rather than
+// by reference count, so a read writes to no shared memory; a body swapped
+// out is only freed once every thread pinned when it was swapped out has
+// unpinned.
+//
+// As in Lehman and Yao's B-link tree, each node also links to the next node
+// on its level, and its high key is the separator between the two in their
+// parents: the keys below the node are no greater, those below the next
+// node no smaller. A split gives the new right half the node's old link and
+// high key, and the left half the middle key as its high key.
+#[derive(Clone)]
+struct Body<T> {
+    keys: Vec<T>,
+    children: Vec<Arc<Node<T>>>,
+    // `None` for the last node on a level.
+    high_key: Option<T>,
+    right: Option<Arc<Node<T>>>,
+}
+
+impl<T> Body<T> {
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+struct Node<T> {
+    version: VersionLock,
+    body: Atomic<Body<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(body: Body<T>) -> Arc<Self> {
+        Arc::new(Node {
+            version: VersionLock::new(),
+            body: Atomic::new(body),
+        })
+    }
+
+    fn body<'g>(&self, guard: &'g Guard) -> &'g Body<T> {
+        // SAFETY: a node always has a body, and one swapped out by `set` is
+        // not freed while `guard` is pinned.
+        unsafe { self.body.load(Ordering::Acquire, guard).deref() }
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        // SAFETY: a node is dropped once no body links to it any more, and
+        // the last such body was freed after every reader that could have
+        // reached this node through it had unpinned, so no one else can
+        // see its body.
+        unsafe { drop(self.body.load(Ordering::Relaxed, epoch::unprotected()).into_owned()) }
+    }
+}
+
+// A node whose version lock is held by the current writer.
+struct Locked<T> {
+    node: Arc<Node<T>>,
+    changed: bool,
+    guard: Guard,
+}
+
+impl<T> Locked<T> {
+    // `deleting` for a delete, which moves keys in ways right links don't
+    // cover, so readers that cross the node meanwhile have to start over.
+    fn new(node: Arc<Node<T>>, deleting: bool) -> Self {
+        node.version.lock(deleting);
+        Locked { node, changed: false, guard: epoch::pin() }
+    }
+
+    fn body(&self) -> &Body<T> {
+        self.node.body(&self.guard)
+    }
+
+    fn set(&mut self, body: Body<T>) {
+        let old = self.node.body.swap(Owned::new(body), Ordering::AcqRel, &self.guard);
+        // SAFETY: `old` is no longer reachable from the node, so only
+        // threads pinned now can still be reading it.
+        unsafe { self.guard.defer_destroy(old) };
+        self.changed = true;
+    }
+}
+
+impl<T> Drop for Locked<T> {
+    fn drop(&mut self) {
+        self.node.version.unlock(self.changed);
+    }
+}
+
+struct LockedRoot<'a> {
+    lock: &'a VersionLock,
+    changed: bool,
+}
+
+impl<'a> LockedRoot<'a> {
+    fn new(lock: &'a VersionLock, deleting: bool) -> Self {
+        lock.lock(deleting);
+        LockedRoot { lock, changed: false }
+    }
+}
+
+impl Drop for LockedRoot<'_> {
+    fn drop(&mut self) {
+        self.lock.unlock(self.changed);
+    }
+}
+
+/// A concurrent B-tree whose readers take no locks.
+///
+/// Every node carries a version counter. Readers note the version of a node
+/// before reading it and check it again once they have picked the child to
+/// go to; if a delete got in between they restart from the root. An insert
+/// that splits a node on the way doesn't make them restart: they find any
+/// key it moved by following the node's link to its right sibling. Writers lock
+/// nodes hand over hand through the same counters, splitting and topping up
+/// nodes on the way down like `ConcurrentBTree`, and replace node contents
+/// with edited copies. Suits read-heavy workloads, where readers never block
+/// each other or bounce a lock's cache line between cores.
+pub struct OptimisticBTree<T> {
+    // Held by writers that might replace the root.
+    root_lock: VersionLock,
+    root: ArcSwap<Node<T>>,
+    max_keys: usize,
+    min_keys: usize,
+    len: AtomicUsize,
+}
+
+// `Send + Sync` because a body is freed by whichever thread next collects
+// the epoch's garbage, which drops its keys and may drop the nodes it links
+// to.
+impl<T: Ord + Clone + Send + Sync> OptimisticBTree<T> {
+    pub fn new(branch_factor: usize) -> Self {
+        let degree = 2 * branch_factor;
+        OptimisticBTree {
+            root_lock: VersionLock::new(),
+            root: ArcSwap::new(Node::new(Body {
+                keys: Vec::new(),
+                children: Vec::new(),
+                high_key: None,
+                right: None,
+            })),
+            max_keys: degree - 1,
+            min_keys: (degree - 1) / 2,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        let guard = &epoch::pin();
+        let mut spins = 0;
+        'restart: loop {
+            if spins > 0 {
+                backoff(&mut spins);
+            }
+            spins += 1;
+            let Some(root_version) = self.root_lock.read() else { continue };
+            let root = self.root.load();
+            let mut node: &Node<T> = &root;
+            let Some(mut version) = node.version.read() else { continue };
+            if !self.root_lock.validate(root_version) {
+                continue;
+            }
+            loop {
+                let body = node.body(guard);
+                let next = match &body.high_key {
+                    // split off since the way here was picked
+                    Some(high) if key > *high => body.right.as_ref().unwrap(),
+                    // the middle key of that split, now in the parent
+                    Some(high) if key == *high => continue 'restart,
+                    _ => {
+                        let index = body.keys.partition_point(|k| *k < key);
+                        let found = index < body.keys.len() && body.keys[index] == key;
+                        if found || body.is_leaf() {
+                            if node.version.validate(version) {
+                                return found;
+                            }
+                            continue 'restart;
+                        }
+                        &body.children[index]
+                    }
+                };
+                let Some(next_version) = next.version.read() else { continue 'restart };
+                if !node.version.validate(version) {
+                    continue 'restart;
+                }
+                node = next;
+                version = next_version;
+            }
+        }
+    }
+
+    pub fn insert(&self, key: T) {
+        let mut root = LockedRoot::new(&self.root_lock, false);
+        let mut node = Locked::new(self.root.load_full(), false);
+        let body = node.body();
+        if body.keys.len() == self.max_keys {
+            let (left, middle, right) = self.split(body);
+            node.set(left);
+            let new_root = Node::new(Body {
+                keys: vec![middle],
+                children: vec![Arc::clone(&node.node), right],
+                high_key: None,
+                right: None,
+            });
+            self.root.store(Arc::clone(&new_root));
+            root.changed = true;
+            node = Locked::new(new_root, false);
+        }
+        drop(root);
+
+        loop {
+            let body = node.body();
+            let index = body.keys.partition_point(|k| *k < key);
+            if body.is_leaf() {
+                let mut body = Body::clone(body);
+                body.keys.insert(index, key);
+                node.set(body);
+                break;
+            }
+            let mut child = Locked::new(Arc::clone(&body.children[index]), false);
+            let child_body = child.body();
+            if child_body.keys.len() == self.max_keys {
+                let (left, middle, right) = self.split(child_body);
+                child.set(left);
+                let go_right = middle < key;
+                let mut body = Body::clone(body);
+                body.keys.insert(index, middle);
+                body.children.insert(index + 1, Arc::clone(&right));
+                node.set(body);
+                if go_right {
+                    child = Locked::new(right, false);
+                }
+            }
+            node = child;
+        }
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Split a full node's body into its lower half, middle key and a new
+    // node holding the upper half, linked from the lower.
+    fn split(&self, body: &Body<T>) -> (Body<T>, T, Arc<Node<T>>) {
+        let mid = self.max_keys / 2;
+        let (left_children, right_children) = if body.is_leaf() {
+            (Vec::new(), Vec::new())
+        } else {
+            (body.children[..=mid].to_vec(), body.children[mid + 1..].to_vec())
+        };
+        let right = Node::new(Body {
+            keys: body.keys[mid + 1..].to_vec(),
+            children: right_children,
+            high_key: body.high_key.clone(),
+            right: body.right.clone(),
+        });
+        let left = Body {
+            keys: body.keys[..mid].to_vec(),
+            children: left_children,
+            high_key: Some(body.keys[mid].clone()),
+            right: Some(Arc::clone(&right)),
+        };
+        (left, body.keys[mid].clone(), right)
+    }
+
+    pub fn delete(&self, key: T) -> bool {
+        let mut root = Some(LockedRoot::new(&self.root_lock, true));
+        let mut node = Locked::new(self.root.load_full(), true);
+        loop {
+            let body = node.body();
+            let index = body.keys.partition_point(|k| *k < key);
+            let found = index < body.keys.len() && body.keys[index] == key;
+            if body.is_leaf() {
+                if found {
+                    let mut body = Body::clone(body);
+                    body.keys.remove(index);
+                    node.set(body);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                }
+                return found;
+            }
+
+            let child = if found {
+                let mut left = Locked::new(Arc::clone(&body.children[index]), true);
+                let mut right = Locked::new(Arc::clone(&body.children[index + 1]), true);
+                let (left_body, right_body) = (left.body(), right.body());
+                if left_body.keys.len() > self.min_keys {
+                    drop((right, root));
+                    let replacement = self.pop_max(left);
+                    let mut body = Body::clone(body);
+                    body.keys[index] = replacement;
+                    node.set(body);
+                    self.len.fetch_sub(1, Ordering::Relaxed);
+                    return true;
+                }
+                if right_body.keys.len() > self.min_keys {
+                    // Move the key down into the left child and delete it
+                    // there. Putting the right child's smallest key in its
+                    // place would leave the high keys down the left child's
+                    // right edge below the new separator.
+                    self.borrow_from_right(&mut node, index, &mut left, right);
+                    left
+                } else {
+                    // Both children are minimal: merge them around the key and
+                    // delete it from the merged node.
+                    let mut body = Body::clone(body);
+                    let separator = body.keys.remove(index);
+                    body.children.remove(index + 1);
+                    node.set(body);
+                    left.set(merged(left_body, separator, right_body));
+                    right.changed = true;
+                    left
+                }
+            } else {
+                self.lock_child_for_delete(&mut node, index)
+            };
+
+            if let Some(mut root) = root.take() {
+                let body = node.body();
+                if body.keys.is_empty() {
+                    self.root.store(Arc::clone(&body.children[0]));
+                    root.changed = true;
+                }
+            }
+            node = child;
+        }
+    }
+
+    // Remove the largest key below `node`, the child left of a separator
+    // being deleted, to take the separator's place. It also becomes the high
+    // key of every node down the child's right edge, where the separator
+    // was, so they stay locked until the end.
+    fn pop_max(&self, node: Locked<T>) -> T {
+        let mut edge = vec![node];
+        loop {
+            let node = edge.last_mut().unwrap();
+            let body = node.body();
+            if body.is_leaf() {
+                break;
+            }
+            let last = body.children.len() - 1;
+            let child = self.lock_child_for_delete(node, last);
+            edge.push(child);
+        }
+        let key = edge.last().unwrap().body().keys.last().unwrap().clone();
+        for node in &mut edge {
+            let mut body = Body::clone(node.body());
+            if body.is_leaf() {
+                body.keys.pop();
+            }
+            body.high_key = Some(key.clone());
+            node.set(body);
+        }
+        key
+    }
+
+    // Lock the child at `index` for a descent that may remove a key from it,
+    // first giving it a key from a sibling or merging it with one if it only
+    // has the minimum. Returns the node to continue in.
+    fn lock_child_for_delete(&self, node: &mut Locked<T>, index: usize) -> Locked<T> {
+        let body = node.body();
+        let mut child = Locked::new(Arc::clone(&body.children[index]), true);
+        let child_body = child.body();
+        if child_body.keys.len() > self.min_keys {
+            return child;
+        }
+        let mut parent = Body::clone(body);
+        if index > 0 {
+            let mut left = Locked::new(Arc::clone(&body.children[index - 1]), true);
+            let mut left_body = Body::clone(left.body());
+            if left_body.keys.len() > self.min_keys {
+                let mut child_body = Body::clone(child_body);
+                let borrowed = left_body.keys.pop().unwrap();
+                left_body.high_key = Some(borrowed.clone());
+                child_body.keys.insert(0, std::mem::replace(&mut parent.keys[index - 1], borrowed));
+                if let Some(grandchild) = left_body.children.pop() {
+                    child_body.children.insert(0, grandchild);
+                }
+                left.set(left_body);
+                child.set(child_body);
+                node.set(parent);
+                return child;
+            }
+            let separator = parent.keys.remove(index - 1);
+            parent.children.remove(index);
+            left.set(merged(&left_body, separator, child_body));
+            child.changed = true;
+            node.set(parent);
+            return left;
+        }
+        let right = Locked::new(Arc::clone(&body.children[index + 1]), true);
+        if right.body().keys.len() > self.min_keys {
+            self.borrow_from_right(node, index, &mut child, right);
+            return child;
+        }
+        let separator = parent.keys.remove(index);
+        parent.children.remove(index + 1);
+        child.set(merged(child_body, separator, right.body()));
+        let mut right = right;
+        right.changed = true;
+        node.set(parent);
+        child
+    }
+
+    // Move the separator after the child at `index` down to the end of the
+    // child, and the first key of the child's right sibling up in its place.
+    fn borrow_from_right(&self, node: &mut Locked<T>, index: usize, child: &mut Locked<T>, mut right: Locked<T>) {
+        let mut parent = Body::clone(node.body());
+        let mut child_body = Body::clone(child.body());
+        let mut right_body = Body::clone(right.body());
+        let borrowed = right_body.keys.remove(0);
+        child_body.high_key = Some(borrowed.clone());
+        child_body.keys.push(std::mem::replace(&mut parent.keys[index], borrowed));
+        if !right_body.is_leaf() {
+            child_body.children.push(right_body.children.remove(0));
+        }
+        right.set(right_body);
+        child.set(child_body);
+        node.set(parent);
+    }
+}
+
+// The node that `left` and `right`, siblings, make with the separator
+// between them. It takes over `right`'s place on the level.
+fn merged<T: Clone>(left: &Body<T>, separator: T, right: &Body<T>) -> Body<T> {
+    let mut body = left.clone();
+    body.keys.push(separator);
+    body.keys.extend_from_slice(&right.keys);
+    body.children.extend_from_slice(&right.children);
+    body.high_key = right.high_key.clone();
+    body.right = right.right.clone();
+    body
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Node, OptimisticBTree};
+    use crossbeam_epoch as epoch;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    #[test]
+    fn test_readers_during_writes() {
+        let tree = Arc::new(OptimisticBTree::new(2));
+        for key in (1..2000u64).step_by(2) {
+            tree.insert(key);
+        }
+        let writers: Vec<_> = (0..2u64)
+            .map(|t| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for _ in 0..3 {
+                        for key in (2 * t..2000).step_by(4) {
+                            tree.insert(key);
+                        }
+                        for key in (2 * t..2000).step_by(4) {
+                            assert!(tree.delete(key));
+                        }
+                    }
+                })
+            })
+            .collect();
+        // Writers only touch even keys, so readers must see every odd key
+        // throughout, however the nodes around it are split and merged.
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for key in (1..2000u64).step_by(2) {
+                        assert!(tree.search(key));
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(tree.len(), 1000);
+        for key in 0..2000u64 {
+            assert_eq!(tree.search(key), key % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn test_right_links() {
+        let tree = OptimisticBTree::new(2);
+        for key in 0..500u64 {
+            tree.insert(key * 7 % 500);
+        }
+        for key in (0..500u64).rev().filter(|key| key % 3 != 0) {
+            assert!(tree.delete(key));
+        }
+        check_links(&tree);
+        // Deleted separators were replaced by smaller keys; these land
+        // between the new separators and the old ones.
+        for key in (0..500u64).filter(|key| key % 3 == 1) {
+            tree.insert(key);
+        }
+        check_links(&tree);
+        for key in 0..500u64 {
+            assert_eq!(tree.search(key), key % 3 != 2);
+        }
+    }
+
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    // A key that counts its copies, to see when bodies holding them go.
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    struct Counted(u64);
+
+    impl Counted {
+        fn new(key: u64) -> Self {
+            CREATED.fetch_add(1, Ordering::Relaxed);
+            Counted(key)
+        }
+    }
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            Counted::new(self.0)
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_bodies_outlive_readers() {
+        let tree = OptimisticBTree::new(2);
+        let (pinned_tx, pinned) = mpsc::channel();
+        let (unpin, unpin_rx) = mpsc::channel::<()>();
+        let reader = thread::spawn(move || {
+            let _guard = epoch::pin();
+            pinned_tx.send(()).unwrap();
+            unpin_rx.recv().unwrap();
+        });
+        pinned.recv().unwrap();
+        // Inserts drop no keys of their own, so any drop would be a body
+        // swapped out from under the pinned reader.
+        for key in 0..300 {
+            tree.insert(Counted::new(key));
+        }
+        epoch::pin().flush();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+        assert!(CREATED.load(Ordering::Relaxed) > 300);
+
+        unpin.send(()).unwrap();
+        reader.join().unwrap();
+        drop(tree);
+        for _ in 0..10_000 {
+            if DROPPED.load(Ordering::Relaxed) == CREATED.load(Ordering::Relaxed) {
+                break;
+            }
+            epoch::pin().flush();
+            thread::yield_now();
+        }
+        assert_eq!(DROPPED.load(Ordering::Relaxed), CREATED.load(Ordering::Relaxed));
+    }
+
+    // Each level, read left to right, is a chain of right links, and a
+    // node's high key falls between its keys and those of the next node.
+    fn check_links(tree: &OptimisticBTree<u64>) {
+        let guard = epoch::pin();
+        let mut level = vec![tree.root.load_full()];
+        while !level.is_empty() {
+            for pair in level.windows(2) {
+                let body = pair[0].body(&guard);
+                assert!(Arc::ptr_eq(body.right.as_ref().unwrap(), &pair[1]));
+                let high = body.high_key.unwrap();
+                assert!(keys_below(&pair[0], &guard).iter().all(|&key| key <= high));
+                assert!(keys_below(&pair[1], &guard).iter().all(|&key| key >= high));
+            }
+            let last = level.last().unwrap().body(&guard);
+            assert!(last.right.is_none() && last.high_key.is_none());
+            level = level.iter().flat_map(|node| node.body(&guard).children.clone()).collect();
+        }
+    }
+
+    fn keys_below(node: &Node<u64>, guard: &epoch::Guard) -> Vec<u64> {
+        let body = node.body(guard);
+        body.keys.iter().copied().chain(body.children.iter().flat_map(|child| keys_below(child, guard))).collect()
+    }
+}
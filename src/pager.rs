@@ -0,0 +1,241 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{AeadInOut, KeyInit};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+
+use crate::crc32::crc32;
+
+pub const PAGE_SIZE: usize = 4096;
+/// Bytes at the end of a page the pager keeps for itself, stamped on write
+/// and checked on read: a mode byte, then a CRC32 of the rest of the page
+/// at the very end, or with encryption the page's nonce and tag.
+pub const PAGE_TRAILER: usize = 32;
+/// Bytes of a page available to callers.
+pub const PAGE_PAYLOAD: usize = PAGE_SIZE - PAGE_TRAILER;
+
+const MODE_PLAIN: u8 = 0;
+const MODE_ENCRYPTED: u8 = 1;
+// The random part of a nonce, stored in the trailer; the page id makes up
+// the rest.
+#[cfg(feature = "encryption")]
+const NONCE_RANDOM: usize = 15;
+
+pub type PageId = u64;
+pub type Page = [u8; PAGE_SIZE];
+
+/// A file split into fixed-size pages. Page `n` lives at byte offset
+/// `n * PAGE_SIZE`; the pager does no caching of its own.
+///
+/// With a key, from `open_encrypted`, every page is encrypted with
+/// XChaCha20-Poly1305 under a fresh random nonce each time it is written,
+/// and a page that was tampered with, moved to another offset or written
+/// under another key fails to read. Page 0, the file header, is
+/// authenticated the same way but left readable, so tools can still tell
+/// what a file is.
+pub struct Pager {
+    file: File,
+    page_count: u64,
+    #[cfg(feature = "encryption")]
+    cipher: Option<XChaCha20Poly1305>,
+}
+
+impl Pager {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        if len % PAGE_SIZE as u64 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file length is not a multiple of the page size",
+            ));
+        }
+        Ok(Pager {
+            file,
+            page_count: len / PAGE_SIZE as u64,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+        })
+    }
+
+    /// `open` a file whose pages are encrypted with `key`, or a new file
+    /// whose pages will be. An unencrypted file fails to read.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted<P: AsRef<Path>>(path: P, key: &[u8; 32]) -> io::Result<Self> {
+        let mut pager = Pager::open(path)?;
+        pager.cipher = Some(XChaCha20Poly1305::new(key.into()));
+        Ok(pager)
+    }
+
+    pub fn page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    pub fn read_page(&mut self, id: PageId, buf: &mut Page) -> io::Result<()> {
+        if id >= self.page_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} is past the end of the file", id),
+            ));
+        }
+        self.file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+        self.file.read_exact(buf)?;
+        self.unseal(id, buf)
+    }
+
+    pub fn write_page(&mut self, id: PageId, buf: &Page) -> io::Result<()> {
+        if id > self.page_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} would leave a hole in the file", id),
+            ));
+        }
+        let mut page = *buf;
+        self.seal(id, &mut page)?;
+        self.file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+        self.file.write_all(&page)?;
+        if id == self.page_count {
+            self.page_count += 1;
+        }
+        Ok(())
+    }
+
+    /// Extend the file by one zeroed page and return its id.
+    pub fn allocate(&mut self) -> io::Result<PageId> {
+        let id = self.page_count;
+        self.write_page(id, &[0; PAGE_SIZE])?;
+        Ok(id)
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// What the WAL should log for a page: the page as it would be written,
+    /// when the file is encrypted, so nothing reaches the log in the clear.
+    pub(crate) fn log_image(&self, id: PageId, page: &Page) -> io::Result<Box<Page>> {
+        let mut image = Box::new(*page);
+        if self.encrypted() {
+            self.seal(id, &mut image)?;
+        }
+        Ok(image)
+    }
+
+    /// Write a page the WAL logged with `log_image`.
+    pub(crate) fn write_logged(&mut self, id: PageId, image: &Page) -> io::Result<()> {
+        let mut page = *image;
+        if self.encrypted() {
+            self.unseal(id, &mut page)?;
+        }
+        self.write_page(id, &page)
+    }
+
+    fn encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        return self.cipher.is_some();
+        #[cfg(not(feature = "encryption"))]
+        false
+    }
+
+    // Fill in the trailer of a page about to be written, encrypting the
+    // rest if the file is encrypted.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn seal(&self, id: PageId, page: &mut Page) -> io::Result<()> {
+        page[PAGE_PAYLOAD..].fill(0);
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            let (payload, trailer) = page.split_at_mut(PAGE_PAYLOAD);
+            trailer[0] = MODE_ENCRYPTED;
+            getrandom::fill(&mut trailer[1..1 + NONCE_RANDOM]).map_err(io::Error::other)?;
+            let nonce = nonce(id, &trailer[1..1 + NONCE_RANDOM]);
+            let tag = match id {
+                0 => cipher.encrypt_inout_detached(&nonce, payload, (&mut [][..]).into()),
+                _ => cipher.encrypt_inout_detached(&nonce, &[], payload.into()),
+            };
+            trailer[1 + NONCE_RANDOM..].copy_from_slice(&tag.expect("a page is well within the cipher's limits"));
+            return Ok(());
+        }
+        let checksum = crc32(&page[..PAGE_SIZE - 4]);
+        page[PAGE_SIZE - 4..].copy_from_slice(&checksum.to_le_bytes());
+        Ok(())
+    }
+
+    // Check the trailer of a page just read, decrypting it if the file is
+    // encrypted.
+    fn unseal(&self, id: PageId, page: &mut Page) -> io::Result<()> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("page {} {}", id, message));
+        match (page[PAGE_PAYLOAD], self.encrypted()) {
+            (MODE_PLAIN, false) => match crc32(&page[..PAGE_SIZE - 4]).to_le_bytes() == page[PAGE_SIZE - 4..] {
+                true => Ok(()),
+                false => Err(invalid("failed its checksum")),
+            },
+            #[cfg(feature = "encryption")]
+            (MODE_ENCRYPTED, true) => {
+                let cipher = self.cipher.as_ref().unwrap();
+                let (payload, trailer) = page.split_at_mut(PAGE_PAYLOAD);
+                let nonce = nonce(id, &trailer[1..1 + NONCE_RANDOM]);
+                let tag = Tag::try_from(&trailer[1 + NONCE_RANDOM..]).unwrap();
+                let opened = match id {
+                    0 => cipher.decrypt_inout_detached(&nonce, payload, (&mut [][..]).into(), &tag),
+                    _ => cipher.decrypt_inout_detached(&nonce, &[], payload.into(), &tag),
+                };
+                opened.map_err(|_| invalid("failed authentication: the key is wrong or the page is corrupt"))
+            }
+            // the caller's mistake, not the file's
+            (MODE_PLAIN, true) => Err(io::Error::new(io::ErrorKind::InvalidInput, "file is not encrypted, but a key was given")),
+            (MODE_ENCRYPTED, false) => Err(io::Error::new(io::ErrorKind::InvalidInput, "file is encrypted: open it with its key")),
+            _ => Err(invalid("failed its checksum")),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn nonce(id: PageId, random: &[u8]) -> XNonce {
+    let mut nonce = XNonce::default();
+    nonce[..NONCE_RANDOM].copy_from_slice(random);
+    nonce[NONCE_RANDOM..NONCE_RANDOM + 8].copy_from_slice(&id.to_le_bytes());
+    nonce
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Pager, PAGE_PAYLOAD, PAGE_SIZE};
+
+    #[test]
+    fn test_read_write() {
+        let path = std::env::temp_dir().join(format!("pager-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut pager = Pager::open(&path).unwrap();
+            assert_eq!(pager.page_count(), 0);
+            let first = pager.allocate().unwrap();
+            let second = pager.allocate().unwrap();
+            assert_eq!((first, second), (0, 1));
+            pager.write_page(second, &[7; PAGE_SIZE]).unwrap();
+            assert!(pager.write_page(5, &[0; PAGE_SIZE]).is_err());
+        }
+        let mut pager = Pager::open(&path).unwrap();
+        assert_eq!(pager.page_count(), 2);
+        let mut page = [0; PAGE_SIZE];
+        pager.read_page(1, &mut page).unwrap();
+        assert!(page[..PAGE_PAYLOAD].iter().all(|&b| b == 7));
+        assert!(pager.read_page(2, &mut page).is_err());
+        drop(pager);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[PAGE_SIZE + 100] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let mut pager = Pager::open(&path).unwrap();
+        pager.read_page(0, &mut page).unwrap();
+        assert!(pager.read_page(1, &mut page).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::{BTree, BTreeProps, Context, Node};
+
+// Below this many keys a subtree is built on the current thread.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default + Send + Sync,
+{
+    /// Build a tree from sorted keys, building the subtrees of large inputs
+    /// on the rayon thread pool.
+    ///
+    /// Nodes are filled as evenly as the key count allows, with every leaf at
+    /// the same depth.
+    pub fn from_sorted_slice_parallel(branch_factor: usize, keys: &[T]) -> Self {
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]), "keys must be sorted");
+        let degree = 2 * branch_factor;
+        let props = BTreeProps::new(degree);
+        let mut height = 1;
+        while capacity(&props, height) < keys.len() {
+            height += 1;
+        }
+        BTree {
+            root: Arc::new(build(&props, keys, height)),
+            props,
+            ctx: Context::new(),
+        }
+    }
+
+    /// Parallel iterator over the keys, in order, with leaves as the units
+    /// of work.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = T> + '_ {
+        let mut chunks = Vec::new();
+        collect_chunks(&self.root, &mut chunks);
+        chunks.into_par_iter().flat_map_iter(|chunk| chunk.iter().copied())
+    }
+}
+
+// Most keys a subtree of the given height can hold.
+fn capacity(props: &BTreeProps, height: usize) -> usize {
+    (1..height).fold(props.max_keys, |below, _| (props.max_keys + 1) * below + props.max_keys)
+}
+
+fn build<T: Ord + Copy + Send + Sync>(props: &BTreeProps, keys: &[T], height: usize) -> Node<T> {
+    if height == 1 {
+        return Node::new(props.degree, Some(keys.to_vec()), None);
+    }
+    // As few children as will hold the keys, but at least two; spreading
+    // the keys evenly then leaves every child at least half full.
+    let below = capacity(props, height - 1);
+    let count = (keys.len() + 1).div_ceil(below + 1).max(2);
+    let per_child = (keys.len() + 1 - count) / count;
+    let extra = (keys.len() + 1 - count) % count;
+
+    let mut ranges = Vec::with_capacity(count);
+    let mut separators = Vec::with_capacity(count - 1);
+    let mut start = 0;
+    for index in 0..count {
+        let end = start + per_child + usize::from(index < extra);
+        ranges.push(start..end);
+        if index + 1 < count {
+            separators.push(keys[end]);
+        }
+        start = end + 1;
+    }
+
+    let children: Vec<Arc<Node<T>>> = if keys.len() >= PARALLEL_THRESHOLD {
+        ranges
+            .into_par_iter()
+            .map(|range| Arc::new(build(props, &keys[range], height - 1)))
+            .collect()
+    } else {
+        ranges
+            .into_iter()
+            .map(|range| Arc::new(build(props, &keys[range], height - 1)))
+            .collect()
+    };
+    Node::new(props.degree, Some(separators), Some(children))
+}
+
+// The tree's keys as in-order slices: whole leaves, and single keys from
+// internal nodes in between.
+fn collect_chunks<'a, T>(node: &'a Node<T>, chunks: &mut Vec<&'a [T]>) {
+    if node.children.is_empty() {
+        chunks.push(&node.keys);
+        return;
+    }
+    for (index, child) in node.children.iter().enumerate() {
+        collect_chunks(child, chunks);
+        if index < node.keys.len() {
+            chunks.push(&node.keys[index..index + 1]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{capacity, PARALLEL_THRESHOLD};
+    use crate::test::check_node;
+    use crate::{BTree, BTreeProps};
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_parallel_build_and_iter() {
+        let keys: Vec<u64> = (0..100_000).collect();
+        let tree = BTree::from_sorted_slice_parallel(3, &keys);
+        assert!(tree.search(0) && tree.search(99_999) && !tree.search(100_000));
+        assert_eq!(tree.par_iter().map(|key| key % 7).sum::<u64>(), keys.iter().map(|key| key % 7).sum::<u64>());
+        assert_eq!(tree.par_iter().collect::<Vec<_>>(), keys);
+
+        // a parallel build is a normal tree afterwards
+        let mut tree = BTree::from_sorted_slice_parallel(2, &keys[..10]);
+        for key in 0..10 {
+            assert!(tree.delete(key));
+        }
+        assert_eq!(tree.iter_snapshot().count(), 0);
+    }
+
+    #[test]
+    fn test_parallel_build_shape() {
+        for branch_factor in 2..=5 {
+            let props = BTreeProps::new(2 * branch_factor);
+            // every size a level fills up at, and the parallel threshold
+            let mut sizes: Vec<usize> = (0..=40).collect();
+            for at in (1..=4).map(|height| capacity(&props, height)).chain([PARALLEL_THRESHOLD]) {
+                sizes.extend([at - 1, at, at + 1]);
+            }
+            for len in sizes {
+                let keys: Vec<u64> = (0..len as u64).collect();
+                let tree = BTree::from_sorted_slice_parallel(branch_factor, &keys);
+                check_node(&tree.root, &tree.props, true);
+                assert!(tree.iter_snapshot().eq(keys.iter().copied()), "{branch_factor} {len}");
+                assert_eq!(tree.par_iter().collect::<Vec<_>>(), keys);
+            }
+        }
+    }
+}
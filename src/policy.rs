@@ -0,0 +1,305 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt::Debug;
+use core::mem;
+
+use crate::allocator::Allocator;
+use crate::{node_mut, Aggregate, BTree, BTreeProps, ConfigError, Context, Node};
+
+/// How deletes keep a tree's nodes filled. See
+/// `BTree::set_rebalance_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RebalancePolicy {
+    /// The fewest keys a node other than the root may be left with: a node
+    /// with fewer is refilled from a sibling or merged into one. At least 1
+    /// and at most the default, `(degree - 1) / 2`; lower means less
+    /// rebalancing work but emptier nodes.
+    pub min_keys: usize,
+    pub strategy: Strategy,
+}
+
+/// What to do first with an underfull node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Borrow a key from a sibling that can spare one, and merge only when
+    /// neither can. Keeps deletes from cascading up the tree.
+    #[default]
+    PreferBorrow,
+    /// Merge into a sibling whenever the two fit in one node, and borrow
+    /// only when they don't. Fewer, fuller nodes at the cost of more
+    /// merges, and of splits when inserts come back.
+    PreferMerge,
+}
+
+/// Where a full node splits, as set by `BTree::set_split_policy`. Either
+/// way each half keeps at least the rebalance policy's `min_keys`, which at
+/// its default leaves room only for the middle: lower it to let splits
+/// move.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SplitPolicy {
+    /// Half the keys each side.
+    #[default]
+    Middle,
+    /// This fraction of the keys stays in the left node, so a node split at
+    /// 2/3 is left two thirds full: denser for inserts that come mostly in
+    /// ascending order.
+    Ratio(f64),
+    /// Leave the node the new key doesn't go into as full as allowed when
+    /// the key goes past either end of the node, and split in the middle
+    /// otherwise. Ascending or descending runs then fill nodes almost to
+    /// the top, while random inserts split as they would in the middle.
+    TowardInsert,
+}
+
+/// What inserting a key equal to one already stored does. Applies to
+/// `insert`, `insert_max`, `insert_hint` and `insert_batch`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Store it alongside the others.
+    #[default]
+    Allow,
+    /// Leave the tree as it is, like `BTreeSet::insert`.
+    Reject,
+    /// Put it in the stored key's place, like `BTreeSet::replace`.
+    Replace,
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.props.duplicates
+    }
+
+    /// Change what later inserts do with duplicates. Duplicates already in
+    /// the tree stay.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.props.duplicates = policy;
+    }
+
+    pub fn split_policy(&self) -> SplitPolicy {
+        self.props.split
+    }
+
+    /// Change where later splits happen. Nodes already in the tree stay.
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.props.split = policy;
+    }
+
+    pub fn is_relaxed(&self) -> bool {
+        self.props.relaxed
+    }
+
+    /// In relaxed mode a delete leaves the nodes it empties underfull, down
+    /// to one key, and only fixes a node it leaves with none, so a burst of
+    /// deletes does a fraction of the borrowing and merging. The tree stays
+    /// searchable and ordered throughout; `settle` restores the
+    /// rebalance policy's `min_keys` everywhere afterwards, and so does
+    /// turning relaxed mode off.
+    pub fn set_relaxed(&mut self, relaxed: bool) {
+        self.props.relaxed = relaxed;
+        if !relaxed {
+            self.settle();
+        }
+    }
+
+    /// Bring every node other than the root back up to `min_keys`, bottom
+    /// up, after deletes in relaxed mode. Walks the whole tree; does nothing
+    /// to a tree that is already settled.
+    pub fn settle(&mut self) {
+        if !needs_settling(&self.root, &self.props, true) {
+            return;
+        }
+        self.ctx.begin(&self.root);
+        let base = self.ctx.recording.is_some().then(|| Arc::clone(&self.root));
+        // A pass can merge all of a node's children into one that is still
+        // underfull, and that child is only reached again once the level
+        // above has merged its parent away. Every pass borrows or merges at
+        // least once, so this ends.
+        while needs_settling(&self.root, &self.props, true) {
+            settle_node(&self.props, node_mut(&mut self.root), &mut self.ctx);
+            while self.root.keys.is_empty() && !self.root.is_leaf() {
+                let child = Arc::clone(&self.root.children[0]);
+                let old_root = mem::replace(&mut self.root, child);
+                if !self.props.shrink_on_merge {
+                    self.ctx.free.put(old_root);
+                }
+            }
+        }
+        if let (Some(recording), Some(base)) = (&mut self.ctx.recording, base) {
+            recording.finish(String::from("settle"), &base, &self.root);
+        }
+    }
+
+    pub fn rebalance_policy(&self) -> RebalancePolicy {
+        RebalancePolicy {
+            min_keys: self.props.min_keys,
+            strategy: self.props.strategy,
+        }
+    }
+
+    /// Change how later deletes rebalance. Lowering `min_keys` leaves the
+    /// nodes as they are; raising it rebuilds the tree half full, since
+    /// nodes may have been left with fewer keys than the new minimum.
+    pub fn set_rebalance_policy(&mut self, policy: RebalancePolicy) -> Result<(), ConfigError> {
+        let most = (self.props.degree - 1) / 2;
+        if policy.min_keys == 0 || policy.min_keys > most {
+            return Err(ConfigError::MinKeysOutOfRange { min_keys: policy.min_keys, most });
+        }
+        let raised = policy.min_keys > self.props.min_keys;
+        self.props.min_keys = policy.min_keys;
+        self.props.strategy = policy.strategy;
+        if raised {
+            self.rebuild(0.5);
+        }
+        Ok(())
+    }
+}
+
+fn needs_settling<T, A: Aggregate<T>, M: Allocator + Clone>(node: &Node<T, A, M>, props: &BTreeProps, is_root: bool) -> bool {
+    (!is_root && node.keys.len() < props.min_keys)
+        || node.children.iter().any(|child| needs_settling(child, props, false))
+}
+
+// Settles the subtrees under `node`, then refills its underfull children.
+fn settle_node<T: Ord + Copy + Debug, A: Aggregate<T>, M: Allocator + Clone>(props: &BTreeProps, node: &mut Node<T, A, M>, ctx: &mut Context<T, A, M>) {
+    for index in 0..node.children.len() {
+        if needs_settling(&node.children[index], props, true) {
+            ctx.descend(index);
+            settle_node(props, node_mut(&mut node.children[index]), ctx);
+            ctx.ascend();
+        }
+    }
+    // Each refill gives the child a key or merges it away, and a merge
+    // leaves settled subtrees settled.
+    let mut index = 0;
+    while index < node.children.len() {
+        if node.children[index].keys.len() < props.min_keys && node.children.len() > 1 {
+            props.refill_child(node, index, ctx);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RebalancePolicy, SplitPolicy, Strategy};
+    use crate::test::check_node;
+    use crate::{BTree, ConfigError};
+
+    #[test]
+    fn test_rebalance_policy() {
+        let policies = [
+            RebalancePolicy { min_keys: 3, strategy: Strategy::PreferBorrow },
+            RebalancePolicy { min_keys: 3, strategy: Strategy::PreferMerge },
+            RebalancePolicy { min_keys: 1, strategy: Strategy::PreferBorrow },
+        ];
+        let mut nodes = Vec::new();
+        for policy in policies {
+            let mut tree = BTree::new(4);
+            tree.set_rebalance_policy(policy).unwrap();
+            for key in 0..2000u32 {
+                tree.insert((key * 7919) % 2000);
+            }
+            for key in (0..1500u32).map(|key| (key * 4099) % 2000) {
+                assert!(tree.delete(key));
+                check_node(&tree.root, &tree.props, true);
+            }
+            nodes.push(tree.memory_usage().nodes());
+
+            tree.set_rebalance_policy(RebalancePolicy { min_keys: 3, ..policy }).unwrap();
+            check_node(&tree.root, &tree.props, true);
+            assert_eq!(tree.iter_snapshot().count(), 500);
+        }
+        // merging eagerly packs the keys left into fewer nodes, and a lower
+        // minimum lets more nodes stay around half empty
+        assert!(nodes[1] < nodes[0] && nodes[0] < nodes[2], "{nodes:?}");
+
+        let mut tree = BTree::<u32>::new(4);
+        let error = tree.set_rebalance_policy(RebalancePolicy { min_keys: 4, strategy: Strategy::PreferBorrow });
+        assert_eq!(error, Err(ConfigError::MinKeysOutOfRange { min_keys: 4, most: 3 }));
+    }
+
+    #[test]
+    fn test_split_policy() {
+        let policies = [SplitPolicy::Middle, SplitPolicy::Ratio(2.0 / 3.0), SplitPolicy::TowardInsert];
+        let mut nodes = Vec::new();
+        for policy in policies {
+            let tree = || {
+                let mut tree = BTree::new(4);
+                tree.set_rebalance_policy(RebalancePolicy { min_keys: 1, strategy: Strategy::PreferBorrow }).unwrap();
+                tree.set_split_policy(policy);
+                tree
+            };
+            let (mut ascending, mut descending, mut random) = (tree(), tree(), tree());
+            for key in 0..2000u32 {
+                ascending.insert(key);
+                descending.insert(2000 - key);
+                random.insert((key * 7919) % 2000);
+            }
+            for tree in [&ascending, &descending, &random] {
+                check_node(&tree.root, &tree.props, true);
+                assert_eq!(tree.len(), 2000);
+            }
+            nodes.push([&ascending, &descending, &random].map(|tree| tree.memory_usage().nodes()));
+        }
+        let [middle, two_thirds, toward] = [nodes[0], nodes[1], nodes[2]];
+        assert!(two_thirds[0] < middle[0] && toward[0] < two_thirds[0], "{nodes:?}");
+        // 2/3 splits leave the left side full, which descending keys don't fill
+        assert!(two_thirds[1] > middle[1] && toward[1] < middle[1], "{nodes:?}");
+        assert!(toward[2].abs_diff(middle[2]) < middle[2] / 10, "{nodes:?}");
+
+        // at the default minimum there is only the middle to split at
+        let mut tree = BTree::new(4);
+        tree.set_split_policy(SplitPolicy::TowardInsert);
+        for key in 0..2000u32 {
+            tree.insert(key);
+        }
+        assert_eq!(tree.split_policy(), SplitPolicy::TowardInsert);
+        let mut middle = BTree::new(4);
+        for key in 0..2000u32 {
+            middle.insert(key);
+        }
+        assert!(tree.levels().eq(middle.levels()));
+    }
+
+    #[test]
+    fn test_relaxed() {
+        let deletes = |relaxed| {
+            let mut tree = BTree::new(4);
+            tree.set_relaxed(relaxed);
+            for key in 0..3000u32 {
+                tree.insert(key);
+            }
+            for key in (0..3000u32).map(|key| (key * 7919) % 3000).filter(|key| key % 5 != 0) {
+                assert!(tree.delete(key));
+            }
+            tree
+        };
+        let strict = deletes(false);
+        let mut relaxed = deletes(true);
+        assert!(relaxed.is_relaxed());
+        assert!(relaxed.memory_usage().nodes() > strict.memory_usage().nodes());
+        assert!(relaxed.iter_snapshot().eq((0..3000).step_by(5)));
+        assert!(relaxed.search(2995) && !relaxed.search(2996));
+        assert_eq!(relaxed.depth_histogram().iter().filter(|&&leaves| leaves > 0).count(), 1);
+
+        relaxed.settle();
+        check_node(&relaxed.root, &relaxed.props, true);
+        assert!(relaxed.iter_snapshot().eq((0..3000).step_by(5)));
+        assert_eq!((relaxed.len(), relaxed.nth(599)), (600, Some(&2995)));
+
+        // down to nothing and back
+        let mut tree = deletes(true);
+        for key in (0..3000u32).step_by(5) {
+            assert!(tree.delete(key));
+        }
+        tree.set_relaxed(false);
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.is_empty() && tree.root.is_leaf());
+    }
+}
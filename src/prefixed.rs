@@ -0,0 +1,482 @@
+//! A B-tree of byte strings that stores each node's keys as one shared
+//! prefix plus the remaining suffix of every key, for keys such as URLs or
+//! file paths where neighbours have long beginnings in common.
+//!
+//! The prefix is always the longest one the node's keys share, which for
+//! sorted keys is the one the first and last share. Searches check it once
+//! per node and then compare suffixes only. Keys come back out whole.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::mem;
+
+use crate::record::write_string;
+use crate::{BTreeProps, Refill};
+
+/// A set of byte strings with per-node prefix compression. See the module
+/// docs.
+///
+/// Nodes are owned outright and keys are not `Copy`, so unlike `BTree`
+/// there are no snapshots. Keys are kept once each.
+pub struct PrefixBTree {
+    root: Node,
+    props: BTreeProps,
+    len: usize,
+}
+
+struct Node {
+    keys: Keys,
+    children: Vec<Node>,
+}
+
+// A node's keys, each one `prefix` followed by its suffix.
+#[derive(Default)]
+struct Keys {
+    prefix: Vec<u8>,
+    suffixes: Vec<Box<[u8]>>,
+}
+
+impl Keys {
+    fn len(&self) -> usize {
+        self.suffixes.len()
+    }
+
+    fn get(&self, index: usize) -> Vec<u8> {
+        [&self.prefix[..], &self.suffixes[index]].concat()
+    }
+
+    fn compare(&self, index: usize, key: &[u8]) -> Ordering {
+        self.prefix.iter().chain(self.suffixes[index].iter()).cmp(key.iter())
+    }
+
+    // The index of the first key not below `key`, and whether it is `key`.
+    fn find(&self, key: &[u8]) -> (usize, bool) {
+        let shared = key.len().min(self.prefix.len());
+        match key[..shared].cmp(&self.prefix[..shared]) {
+            Ordering::Less => return (0, false),
+            Ordering::Greater => return (self.len(), false),
+            // `key` is a prefix of the prefix, so below every key
+            Ordering::Equal if key.len() < self.prefix.len() => return (0, false),
+            Ordering::Equal => {}
+        }
+        let rest = &key[self.prefix.len()..];
+        let index = self.suffixes.partition_point(|suffix| **suffix < *rest);
+        (index, index < self.len() && *self.suffixes[index] == *rest)
+    }
+
+    fn insert(&mut self, index: usize, key: &[u8]) {
+        if self.suffixes.is_empty() {
+            self.prefix = key.to_vec();
+            self.suffixes.push(Box::default());
+            return;
+        }
+        let shared = common_prefix(&self.prefix, key);
+        self.set_prefix_len(shared);
+        self.suffixes.insert(index, key[shared..].into());
+    }
+
+    fn push(&mut self, key: &[u8]) {
+        self.insert(self.len(), key);
+    }
+
+    fn remove(&mut self, index: usize) -> Vec<u8> {
+        let key = self.get(index);
+        self.suffixes.remove(index);
+        self.fit_prefix();
+        key
+    }
+
+    fn pop(&mut self) -> Vec<u8> {
+        self.remove(self.len() - 1)
+    }
+
+    fn replace(&mut self, index: usize, key: &[u8]) -> Vec<u8> {
+        let old = self.remove(index);
+        self.insert(index, key);
+        old
+    }
+
+    // Moves the keys from `at` on into a new set.
+    fn split_off(&mut self, at: usize) -> Keys {
+        let mut right = Keys {
+            prefix: self.prefix.clone(),
+            suffixes: self.suffixes.split_off(at),
+        };
+        right.fit_prefix();
+        self.fit_prefix();
+        right
+    }
+
+    // Moves every key of `other`, all of which sort after these, to the end.
+    fn append(&mut self, other: &mut Keys) {
+        if other.suffixes.is_empty() {
+            return;
+        }
+        if self.suffixes.is_empty() {
+            mem::swap(self, other);
+            return;
+        }
+        let shared = common_prefix(&self.prefix, &other.prefix);
+        self.set_prefix_len(shared);
+        other.set_prefix_len(shared);
+        self.suffixes.append(&mut other.suffixes);
+    }
+
+    // Lengthens the prefix to the longest one the keys share, after keys
+    // have been taken away.
+    fn fit_prefix(&mut self) {
+        let (Some(first), Some(last)) = (self.suffixes.first(), self.suffixes.last()) else {
+            self.prefix.clear();
+            return;
+        };
+        let len = self.prefix.len() + common_prefix(first, last);
+        self.set_prefix_len(len);
+    }
+
+    // Moves bytes between the prefix and the front of every suffix; growing
+    // the prefix is only right if every suffix starts with the same bytes.
+    fn set_prefix_len(&mut self, len: usize) {
+        if len < self.prefix.len() {
+            let moved = self.prefix.split_off(len);
+            for suffix in &mut self.suffixes {
+                *suffix = [&moved[..], suffix].concat().into();
+            }
+        } else if len > self.prefix.len() {
+            let extra = len - self.prefix.len();
+            self.prefix.extend_from_slice(&self.suffixes[0][..extra]);
+            for suffix in &mut self.suffixes {
+                *suffix = suffix[extra..].into();
+            }
+        }
+    }
+
+    fn stored_bytes(&self) -> usize {
+        self.prefix.len() + self.suffixes.iter().map(|suffix| suffix.len()).sum::<usize>()
+    }
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+impl Node {
+    fn new() -> Self {
+        Node {
+            keys: Keys::default(),
+            children: Vec::new(),
+        }
+    }
+}
+
+impl PrefixBTree {
+    pub fn new(branch_factor: usize) -> Self {
+        PrefixBTree {
+            root: Node::new(),
+            props: BTreeProps::new(2 * branch_factor),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn search(&self, key: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
+        let mut node = &self.root;
+        loop {
+            let (index, found) = node.keys.find(key);
+            if found {
+                return true;
+            }
+            match node.children.get(index) {
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+    }
+
+    /// Adds `key`, returning false if it was already there.
+    pub fn insert(&mut self, key: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
+        if self.search(key) {
+            return false;
+        }
+        if self.root.keys.len() == self.props.max_keys {
+            let old_root = mem::replace(&mut self.root, Node::new());
+            self.root.children.push(old_root);
+            split_child(&self.props, &mut self.root, 0, key);
+        }
+        insert_non_full(&self.props, &mut self.root, key);
+        self.len += 1;
+        true
+    }
+
+    pub fn delete(&mut self, key: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
+        if !self.search(key) {
+            return false;
+        }
+        delete_key(&self.props, &mut self.root, key);
+        if self.root.keys.len() == 0 && !self.root.children.is_empty() {
+            self.root = self.root.children.pop().unwrap();
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Every key, in order, rebuilt from its node's prefix and its suffix.
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.descend(&self.root);
+        iter
+    }
+
+    /// The bytes of key data held, prefixes and suffixes, against what the
+    /// keys would take stored whole.
+    pub fn key_bytes(&self) -> (usize, usize) {
+        let mut stored = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            stored += node.keys.stored_bytes();
+            stack.extend(node.children.iter());
+        }
+        (stored, self.iter().map(|key| key.len()).sum())
+    }
+
+    /// The nodes as JSON in the format of `Recorder` steps, each key
+    /// written whole as a string, with any invalid UTF-8 replaced.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write_node(&mut json, &self.root);
+        json
+    }
+}
+
+fn write_node(json: &mut String, node: &Node) {
+    json.push_str("{\"keys\":[");
+    for index in 0..node.keys.len() {
+        if index > 0 {
+            json.push(',');
+        }
+        write_string(json, &String::from_utf8_lossy(&node.keys.get(index)));
+    }
+    json.push(']');
+    if !node.children.is_empty() {
+        json.push_str(",\"children\":[");
+        for (index, child) in node.children.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            write_node(json, child);
+        }
+        json.push(']');
+    }
+    json.push('}');
+}
+
+/// The keys of a `PrefixBTree` in order. See `PrefixBTree::iter`.
+pub struct Iter<'a> {
+    // Nodes on the path to the next key, with the index of that key.
+    stack: Vec<(&'a Node, usize)>,
+}
+
+impl<'a> Iter<'a> {
+    fn descend(&mut self, mut node: &'a Node) {
+        loop {
+            self.stack.push((node, 0));
+            match node.children.first() {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        while let Some((node, index)) = self.stack.pop() {
+            if index < node.keys.len() {
+                self.stack.push((node, index + 1));
+                if let Some(child) = node.children.get(index + 1) {
+                    self.descend(child);
+                }
+                return Some(node.keys.get(index));
+            }
+        }
+        None
+    }
+}
+
+// Splits the full child at `index` where the split policy says for `key`.
+fn split_child(props: &BTreeProps, parent: &mut Node, index: usize, key: &[u8]) {
+    let mut right = Node::new();
+    let child = &mut parent.children[index];
+    let at = props.split_index(&child.keys.get(0)[..], &child.keys.get(child.keys.len() - 1)[..], key);
+    right.keys = child.keys.split_off(at + 1);
+    let middle_key = child.keys.pop();
+    if !child.children.is_empty() {
+        right.children.extend(child.children.drain(at + 1..));
+    }
+    parent.keys.insert(index, &middle_key);
+    parent.children.insert(index + 1, right);
+}
+
+fn insert_non_full(props: &BTreeProps, node: &mut Node, key: &[u8]) {
+    let (mut index, _) = node.keys.find(key);
+    if node.children.is_empty() {
+        node.keys.insert(index, key);
+    } else {
+        if node.children[index].keys.len() == props.max_keys {
+            split_child(props, node, index, key);
+            if node.keys.compare(index, key) == Ordering::Less {
+                index += 1;
+            }
+        }
+        insert_non_full(props, &mut node.children[index], key);
+    }
+}
+
+// `key` must be in the subtree. A key in an internal node gives way to its
+// predecessor, and every child left underfull on the way back up is
+// refilled.
+fn delete_key(props: &BTreeProps, node: &mut Node, key: &[u8]) {
+    let (index, found) = node.keys.find(key);
+    if node.children.is_empty() {
+        node.keys.remove(index);
+    } else {
+        if found {
+            let max = delete_max(props, &mut node.children[index]);
+            node.keys.replace(index, &max);
+        } else {
+            delete_key(props, &mut node.children[index], key);
+        }
+        rebalance_child(props, node, index);
+    }
+}
+
+fn delete_max(props: &BTreeProps, node: &mut Node) -> Vec<u8> {
+    if node.children.is_empty() {
+        return node.keys.pop();
+    }
+    let last = node.children.len() - 1;
+    let key = delete_max(props, &mut node.children[last]);
+    rebalance_child(props, node, last);
+    key
+}
+
+// Refills the child at `index` if it is underfull, the way `BTree` picks.
+fn rebalance_child(props: &BTreeProps, parent: &mut Node, index: usize) {
+    if parent.children[index].keys.len() >= props.min_keys {
+        return;
+    }
+    let children = &parent.children;
+    match props.refill_from(index, children.len(), |child| children[child].keys.len()) {
+        Some(Refill::FromRight) => donate_from_right(parent, index),
+        Some(Refill::FromLeft) => donate_from_left(parent, index),
+        Some(Refill::MergeRight) => merge_with_right(parent, index),
+        Some(Refill::MergeLeft) => merge_with_right(parent, index - 1),
+        None => {}
+    }
+}
+
+fn donate_from_right(parent: &mut Node, index: usize) {
+    let sibling = &mut parent.children[index + 1];
+    let sibling_key = sibling.keys.remove(0);
+    let sibling_child = if sibling.children.is_empty() { None } else { Some(sibling.children.remove(0)) };
+    let parent_key = parent.keys.replace(index, &sibling_key);
+    let node = &mut parent.children[index];
+    node.keys.push(&parent_key);
+    node.children.extend(sibling_child);
+}
+
+fn donate_from_left(parent: &mut Node, index: usize) {
+    let sibling = &mut parent.children[index - 1];
+    let sibling_key = sibling.keys.pop();
+    let sibling_child = sibling.children.pop();
+    let parent_key = parent.keys.replace(index - 1, &sibling_key);
+    let node = &mut parent.children[index];
+    node.keys.insert(0, &parent_key);
+    if let Some(child) = sibling_child {
+        node.children.insert(0, child);
+    }
+}
+
+fn merge_with_right(parent: &mut Node, index: usize) {
+    let mut right_sibling = parent.children.remove(index + 1);
+    let separator = parent.keys.remove(index);
+    let node = &mut parent.children[index];
+    node.keys.push(&separator);
+    node.keys.append(&mut right_sibling.keys);
+    node.children.append(&mut right_sibling.children);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{common_prefix, Node, PrefixBTree};
+    use crate::BTreeProps;
+
+    // Returns the depth of the subtree after checking fill, key order and
+    // that each prefix is as long as it can be.
+    fn check(node: &Node, props: &BTreeProps, is_root: bool) -> usize {
+        let keys: Vec<Vec<u8>> = (0..node.keys.len()).map(|index| node.keys.get(index)).collect();
+        assert!(keys.len() <= props.max_keys && (is_root || keys.len() >= props.min_keys));
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        if let (Some(first), Some(last)) = (keys.first(), keys.last()) {
+            assert_eq!(node.keys.prefix.len(), common_prefix(first, last));
+        }
+        if node.children.is_empty() {
+            return 1;
+        }
+        assert_eq!(node.children.len(), keys.len() + 1);
+        let depths: Vec<usize> = node.children.iter().map(|child| check(child, props, false)).collect();
+        assert!(depths.iter().all(|&depth| depth == depths[0]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn test_prefix_compression() {
+        let mut tree = PrefixBTree::new(8);
+        let mut urls = Vec::new();
+        for id in 0..1500u32 {
+            let id = (id * 7919) % 1500;
+            let url = format!("https://example.com/users/{:05}/profile/{}", id / 3, id % 3);
+            assert!(tree.insert(&url));
+            check(&tree.root, &tree.props, true);
+            urls.push(url);
+        }
+        assert!(!tree.insert(&urls[0]));
+        for url in urls.iter().step_by(4) {
+            assert!(tree.delete(url));
+            check(&tree.root, &tree.props, true);
+        }
+        assert!(!tree.delete(&urls[0]) && !tree.search(&urls[0]) && !tree.search("https://"));
+
+        let mut expected: Vec<&String> = urls.iter().enumerate().filter(|(i, _)| i % 4 != 0).map(|(_, url)| url).collect();
+        expected.sort();
+        assert_eq!(tree.len(), expected.len());
+        assert!(tree.iter().eq(expected.iter().map(|url| url.as_bytes().to_vec())));
+        assert!(expected.iter().all(|url| tree.search(url)));
+
+        let (stored, whole) = tree.key_bytes();
+        assert!(stored * 2 < whole, "{stored} of {whole}");
+
+        for url in &expected {
+            assert!(tree.delete(url));
+        }
+        assert!(tree.is_empty() && tree.iter().next().is_none());
+        for key in ["", "a", "ab", "b", "\u{ff}"] {
+            tree.insert(key);
+        }
+        assert!(tree.iter().eq(["", "a", "ab", "b", "\u{ff}"].map(|key| key.as_bytes().to_vec())));
+    }
+}
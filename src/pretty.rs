@@ -0,0 +1,146 @@
+use std::fmt::{Debug, Write as _};
+use std::io::{self, Write};
+
+use crate::{BTree, Node};
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Draw the tree one level per row of boxes, root first:
+    ///
+    /// ```text
+    /// level 0  ┌────┐
+    ///          │ 20 │
+    ///          └────┘
+    /// level 1  ┌────┐ ┌───────┐
+    ///          │ 10 │ │ 30 40 │
+    ///          └────┘ └───────┘
+    /// ```
+    ///
+    /// Siblings sit one space apart and the children of different parents
+    /// three, so which parent a node hangs from can be read off the row.
+    pub fn pretty_print<W: Write>(&self, mut out: W) -> io::Result<()> {
+        // each level's nodes, with the index of their parent in the level
+        // above
+        let mut level: Vec<(&Node<T>, usize)> = vec![(&self.root, 0)];
+        let mut depth = 0;
+        while !level.is_empty() {
+            let label = format!("level {depth}");
+            let mut rows = [String::new(), String::new(), String::new()];
+            for (index, (node, parent)) in level.iter().enumerate() {
+                if index > 0 {
+                    let gap = if level[index - 1].1 == *parent { " " } else { "   " };
+                    for row in &mut rows {
+                        row.push_str(gap);
+                    }
+                }
+                let keys: Vec<String> = node.keys.iter().map(|key| format!("{key:?}")).collect();
+                let text = format!(" {} ", keys.join(" "));
+                let bar = "─".repeat(text.chars().count());
+                rows[0].push_str(&format!("┌{bar}┐"));
+                rows[1].push_str(&format!("│{text}│"));
+                rows[2].push_str(&format!("└{bar}┘"));
+            }
+            writeln!(out, "{label:<9}{}", rows[0])?;
+            writeln!(out, "{:<9}{}", "", rows[1])?;
+            writeln!(out, "{:<9}{}", "", rows[2])?;
+
+            level = level
+                .iter()
+                .enumerate()
+                .flat_map(|(index, (node, _))| node.children.iter().map(move |child| (&**child, index)))
+                .collect();
+            depth += 1;
+        }
+        Ok(())
+    }
+
+    /// The whole shape of the tree as text that only changes when the
+    /// shape does, for tests that pin down exactly what a sequence of
+    /// inserts and deletes builds. One line per node, level by level from
+    /// the root and left to right within a level:
+    ///
+    /// ```text
+    /// depth 0 node 0: 20
+    /// depth 1 node 0 parent 0: 10
+    /// depth 1 node 1 parent 0: 30 40
+    /// ```
+    ///
+    /// A node is numbered by its place in its level, and its parent by the
+    /// parent's place in the level above. Keys are written with `Debug`.
+    pub fn structure_string(&self) -> String {
+        let mut out = String::new();
+        let mut level: Vec<(&Node<T>, Option<usize>)> = vec![(&self.root, None)];
+        let mut depth = 0;
+        while !level.is_empty() {
+            for (index, (node, parent)) in level.iter().enumerate() {
+                _ = write!(out, "depth {depth} node {index}");
+                if let Some(parent) = parent {
+                    _ = write!(out, " parent {parent}");
+                }
+                out.push(':');
+                for key in &node.keys {
+                    _ = write!(out, " {key:?}");
+                }
+                out.push('\n');
+            }
+            level = level
+                .iter()
+                .enumerate()
+                .flat_map(|(index, (node, _))| node.children.iter().map(move |child| (&**child, Some(index))))
+                .collect();
+            depth += 1;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BTree;
+
+    #[test]
+    fn test_pretty_print() {
+        let mut tree = BTree::new(2);
+        for key in [10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+            tree.insert(key);
+        }
+        let mut out = Vec::new();
+        tree.pretty_print(&mut out).unwrap();
+        let expected = "\
+level 0  ┌────┐
+         │ 40 │
+         └────┘
+level 1  ┌────┐ ┌───────┐
+         │ 20 │ │ 60 80 │
+         └────┘ └───────┘
+level 2  ┌────┐ ┌────┐   ┌────┐ ┌────┐ ┌────────┐
+         │ 10 │ │ 30 │   │ 50 │ │ 70 │ │ 90 100 │
+         └────┘ └────┘   └────┘ └────┘ └────────┘
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_structure_string() {
+        let mut tree = BTree::new(2);
+        assert_eq!(tree.structure_string(), "depth 0 node 0:\n");
+        for key in [10, 20, 30, 40, 50, 60, 70, 80, 90, 100] {
+            tree.insert(key);
+        }
+        for key in [10, 70] {
+            tree.delete(key);
+        }
+        let expected = "\
+depth 0 node 0: 60
+depth 1 node 0 parent 0: 40
+depth 1 node 1 parent 0: 90
+depth 2 node 0 parent 0: 20 30
+depth 2 node 1 parent 0: 50
+depth 2 node 2 parent 1: 80
+depth 2 node 3 parent 1: 100
+";
+        assert_eq!(tree.structure_string(), expected);
+    }
+}
@@ -0,0 +1,135 @@
+//! A double-ended priority queue kept in a tree: the smallest and the
+//! largest item are both a lookup away, and both can be popped in
+//! O(log n).
+
+use core::fmt::Debug;
+
+use crate::BTree;
+
+/// Items in priority order, popped from either end. Equal items come out
+/// in the order they were pushed, whichever end they are popped from.
+pub struct TreePriorityQueue<T> {
+    // Each item with the number of pushes before it, which orders equal
+    // items by arrival.
+    tree: BTree<(T, u64)>,
+    pushed: u64,
+}
+
+impl<T> TreePriorityQueue<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize) -> Self {
+        TreePriorityQueue { tree: BTree::new(branch_factor), pushed: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.tree.insert((item, self.pushed));
+        self.pushed += 1;
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.tree.nth(0).map(|(item, _)| item)
+    }
+
+    pub fn peek_max(&self) -> Option<&T> {
+        self.tree.nth(self.len().checked_sub(1)?).map(|(item, _)| item)
+    }
+
+    /// Remove the smallest item; of equal ones, the first pushed.
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.tree.delete_min(1).pop().map(|(item, _)| item)
+    }
+
+    /// Remove the largest item; of equal ones, the first pushed.
+    pub fn pop_max(&mut self) -> Option<T> {
+        let max = *self.peek_max()?;
+        // the first of the largest items to arrive is the first entry for
+        // `max` in the tree
+        let entry = *self.tree.nth(self.tree.rank((max, 0))).unwrap();
+        self.tree.delete(entry);
+        Some(entry.0)
+    }
+}
+
+impl<T> Default for TreePriorityQueue<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    fn default() -> Self {
+        TreePriorityQueue { tree: BTree::default(), pushed: 0 }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::cmp::Ordering;
+
+    use super::TreePriorityQueue;
+
+    // Ordered by priority alone, so equal priorities can be told apart by
+    // their label.
+    #[derive(Clone, Copy, Debug, Default)]
+    struct Job {
+        priority: u8,
+        label: char,
+    }
+
+    impl PartialEq for Job {
+        fn eq(&self, other: &Self) -> bool {
+            self.priority == other.priority
+        }
+    }
+
+    impl Eq for Job {}
+
+    impl PartialOrd for Job {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Job {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.priority.cmp(&other.priority)
+        }
+    }
+
+    #[test]
+    fn test_priority_queue() {
+        let mut queue = TreePriorityQueue::new(2);
+        assert_eq!((queue.pop_min(), queue.pop_max(), queue.peek_max()), (None, None, None));
+        for (priority, label) in [(3, 'a'), (1, 'b'), (3, 'c'), (1, 'd'), (2, 'e'), (3, 'f'), (1, 'g')] {
+            queue.push(Job { priority, label });
+        }
+        assert_eq!(queue.len(), 7);
+        assert_eq!((queue.peek_min().unwrap().priority, queue.peek_max().unwrap().priority), (1, 3));
+
+        let mut order = String::new();
+        order.extend([queue.pop_max(), queue.pop_min(), queue.pop_max(), queue.pop_min()].map(|job| job.unwrap().label));
+        assert_eq!(order, "abcd");
+        queue.push(Job { priority: 3, label: 'h' });
+        let rest: String = core::iter::from_fn(|| queue.pop_max()).map(|job| job.label).collect();
+        assert_eq!(rest, "fheg");
+        assert!(queue.is_empty());
+
+        let mut numbers = TreePriorityQueue::default();
+        for number in (0..1000u32).map(|number| (number * 7919) % 500) {
+            numbers.push(number);
+        }
+        let mut popped = Vec::new();
+        while let (Some(low), Some(high)) = (numbers.pop_min(), numbers.pop_max()) {
+            popped.push((low, high));
+        }
+        assert_eq!(popped.len(), 500);
+        assert!(popped.iter().enumerate().all(|(index, &pair)| pair == ((index / 2) as u32, 499 - (index / 2) as u32)));
+    }
+}
@@ -0,0 +1,147 @@
+//! A Python class over a tree of integer keys, through pyo3. Build the
+//! extension module with
+//! `cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib`
+//! and put the library on Python's path as `b_trees_with_delete.so`
+//! (`.pyd` on Windows).
+//!
+//! ```python
+//! from b_trees_with_delete import BTree
+//! tree = BTree(branch_factor=8)
+//! for key in [5, 1, 9, 3]:
+//!     tree.insert(key)
+//! 3 in tree, len(tree)        # True, 4
+//! list(tree.range(2, 9))      # [3, 5]
+//! tree.remove(5)
+//! ```
+//!
+//! Keys are Python ints that fit in 64 bits. Iterators see the tree as it
+//! was when they were made, so the tree can change while one is in use.
+
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::frozen::SnapshotIter;
+use crate::BTree;
+
+/// A `BTree` of `i64` keys, as the Python class `BTree`. Duplicates are
+/// kept, as in the Rust tree.
+#[pyclass(name = "BTree", module = "b_trees_with_delete")]
+pub struct PyBTree {
+    tree: BTree<i64>,
+}
+
+#[pymethods]
+impl PyBTree {
+    #[new]
+    #[pyo3(signature = (branch_factor = 16))]
+    fn new(branch_factor: usize) -> PyResult<Self> {
+        let tree = BTree::with_branch_factor(branch_factor).map_err(|error| PyValueError::new_err(error.to_string()))?;
+        Ok(PyBTree { tree })
+    }
+
+    fn insert(&mut self, key: i64) {
+        self.tree.insert(key);
+    }
+
+    /// Remove one copy of `key`, raising `KeyError` if there is none, like
+    /// `set.remove`.
+    fn remove(&mut self, key: i64) -> PyResult<()> {
+        match self.tree.delete(key) {
+            true => Ok(()),
+            false => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __contains__(&self, key: i64) -> bool {
+        self.tree.search(key)
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn __iter__(&self) -> PyKeys {
+        PyKeys { iter: self.tree.iter_snapshot(), end: None }
+    }
+
+    /// The keys from `lo` up to but not including `hi`, in order.
+    fn range(&self, lo: i64, hi: i64) -> PyKeys {
+        PyKeys {
+            iter: SnapshotIter::seek(Arc::clone(&self.tree.root), |key| *key < lo),
+            end: Some(hi),
+        }
+    }
+}
+
+/// An iterator over a `BTree`'s keys, from `iter(tree)` or `tree.range`.
+#[pyclass(name = "Keys", module = "b_trees_with_delete")]
+pub struct PyKeys {
+    iter: SnapshotIter<i64>,
+    // Where a range stops, exclusive.
+    end: Option<i64>,
+}
+
+#[pymethods]
+impl PyKeys {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<i64> {
+        let key = self.iter.next()?;
+        match self.end {
+            Some(end) if key >= end => None,
+            _ => Some(key),
+        }
+    }
+}
+
+/// The `b_trees_with_delete` extension module.
+#[pymodule]
+fn b_trees_with_delete(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBTree>()?;
+    module.add_class::<PyKeys>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::PyBTree;
+
+    #[test]
+    fn test_python_class() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("BTree", py.get_type::<PyBTree>()).unwrap();
+            let script = c"
+tree = BTree(2)
+for key in [50, 10, 40, 20, 30, 20]:
+    tree.insert(key)
+assert len(tree) == 6 and 40 in tree and 45 not in tree
+assert list(tree) == [10, 20, 20, 30, 40, 50]
+assert list(tree.range(15, 40)) == [20, 20, 30]
+tree.remove(20)
+try:
+    tree.remove(99)
+    raise AssertionError('removed a missing key')
+except KeyError:
+    pass
+keys = iter(tree)
+tree.insert(0)
+assert list(keys) == [10, 20, 30, 40, 50]
+try:
+    BTree(1)
+    raise AssertionError('accepted branch factor 1')
+except ValueError:
+    pass
+";
+            py.run(script, Some(&globals), None).unwrap();
+        });
+    }
+}
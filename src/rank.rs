@@ -0,0 +1,280 @@
+//! Keys by their position in sorted order, using the count of keys every
+//! node keeps for its subtree.
+
+#[cfg(feature = "rand")]
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+#[cfg(feature = "rand")]
+use rand_core::RngCore;
+
+use crate::allocator::{Allocator, ArcIn};
+use crate::{node_mut, Aggregate, BTree, Node, SnapshotIter};
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// The number of keys, duplicates included.
+    pub fn len(&self) -> usize {
+        self.root.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.len == 0
+    }
+
+    /// The key at position `rank` in sorted order, counting from 0.
+    pub fn nth(&self, mut rank: usize) -> Option<&T> {
+        if rank >= self.root.len {
+            return None;
+        }
+        let mut node: &Node<T, A, M> = &self.root;
+        'descend: loop {
+            if node.is_leaf() {
+                return Some(&node.keys[rank]);
+            }
+            for (index, child) in node.children.iter().enumerate() {
+                if rank < child.len {
+                    node = child;
+                    continue 'descend;
+                }
+                rank -= child.len;
+                if rank == 0 {
+                    return Some(&node.keys[index]);
+                }
+                rank -= 1;
+            }
+            unreachable!("subtree counts add up to less than the root's");
+        }
+    }
+
+    /// The number of keys less than `key`: where it is, or would go, in
+    /// sorted order.
+    pub fn rank(&self, key: T) -> usize {
+        let mut node: &Node<T, A, M> = &self.root;
+        let mut rank = 0;
+        loop {
+            let index = node.keys.partition_point(|stored| *stored < key);
+            rank += index + node.children[..index.min(node.children.len())].iter().map(|child| child.len).sum::<usize>();
+            match node.children.get(index) {
+                Some(child) => node = child,
+                None => return rank,
+            }
+        }
+    }
+
+    /// The key below which a fraction `q` of the keys fall, by nearest rank:
+    /// the median for 0.5, the smallest key for 0.0 and the largest for 1.0.
+    /// `None` for an empty tree or a `q` outside `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        if !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        // ceil(q * len) by hand, since `f64::ceil` needs std
+        let position = q * self.len() as f64;
+        let rank = position as usize + usize::from((position as usize as f64) < position);
+        self.nth(rank.saturating_sub(1))
+    }
+
+    /// `k` keys picked at random, each position equally likely and none
+    /// picked twice, in sorted order. All of them if there are no more than
+    /// `k`.
+    #[cfg(feature = "rand")]
+    pub fn sample<R: RngCore + ?Sized>(&self, rng: &mut R, k: usize) -> Vec<&T> {
+        let len = self.len();
+        let k = k.min(len);
+        // Floyd's algorithm: `k` distinct positions from `0..len`
+        let mut ranks = BTreeSet::new();
+        for end in len - k..len {
+            let rank = below(rng, end + 1);
+            if !ranks.insert(rank) {
+                ranks.insert(end);
+            }
+        }
+        ranks.into_iter().filter_map(|rank| self.nth(rank)).collect()
+    }
+
+    /// Up to `limit` keys in order, starting with the one at position
+    /// `offset`: one page of a listing, found without going through the
+    /// keys before it.
+    pub fn page(&self, offset: usize, limit: usize) -> Vec<T> {
+        SnapshotIter::at(Arc::clone(&self.root), offset).take(limit).collect()
+    }
+
+    /// Every node whose count of the keys under it is wrong, in pre-order.
+    /// Nodes don't point back at their parents, so these counts are the only
+    /// derived state a node keeps that can go stale, and everything above
+    /// works from them. Empty for a sound tree.
+    pub fn audit_counts(&self) -> Vec<CountMismatch> {
+        let mut mismatches = Vec::new();
+        audit(&self.root, &mut Vec::new(), &mut mismatches);
+        mismatches
+    }
+
+    /// Recount every node `audit_counts` would report, bottom up, returning
+    /// how many there were: for trees put together by hand or read from
+    /// somewhere that can't be trusted. Leaves the tree alone if it is
+    /// sound, so snapshots keep sharing its nodes.
+    pub fn repair_counts(&mut self) -> usize {
+        let broken = self.audit_counts().len();
+        if broken > 0 {
+            repair(&mut self.root);
+        }
+        broken
+    }
+}
+
+/// A node whose count disagrees with its subtree. See `BTree::audit_counts`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CountMismatch {
+    /// The child index taken at each level to reach the node from the
+    /// root; empty for the root.
+    pub path: Vec<usize>,
+    pub counted: usize,
+    pub actual: usize,
+}
+
+// Walks the subtree under `node`, at `path`, returning how many keys it has.
+fn audit<T, A: Aggregate<T>, M: Allocator + Clone>(node: &Node<T, A, M>, path: &mut Vec<usize>, mismatches: &mut Vec<CountMismatch>) -> usize {
+    let at = mismatches.len();
+    let mut actual = node.keys.len();
+    for (index, child) in node.children.iter().enumerate() {
+        path.push(index);
+        actual += audit(child, path, mismatches);
+        path.pop();
+    }
+    if node.len != actual {
+        // before its descendants, which were pushed while walking them
+        mismatches.insert(at, CountMismatch { path: path.clone(), counted: node.len, actual });
+    }
+    actual
+}
+
+fn repair<T: Ord + Copy, A: Aggregate<T>, M: Allocator + Clone>(node: &mut ArcIn<Node<T, A, M>, M>) {
+    let node = node_mut(node);
+    for child in &mut node.children {
+        repair(child);
+    }
+    node.recount();
+}
+
+// Uniform in `0..bound`, by the high half of a 128-bit product.
+#[cfg(feature = "rand")]
+fn below<R: RngCore + ?Sized>(rng: &mut R, bound: usize) -> usize {
+    ((u128::from(rng.next_u64()) * bound as u128) >> 64) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::sync::Arc;
+
+    use super::CountMismatch;
+    use crate::BTree;
+
+    #[test]
+    fn test_page() {
+        let mut tree = BTree::new(2);
+        assert!(tree.is_empty() && tree.page(0, 10).is_empty());
+        for key in 0..1000u32 {
+            tree.insert((key * 7919) % 1000);
+        }
+        for key in (0..1000u32).filter(|key| key % 3 == 0) {
+            tree.delete(key);
+        }
+        let keys: Vec<u32> = tree.iter_snapshot().collect();
+        assert_eq!(tree.len(), keys.len());
+        for offset in 0..keys.len() + 2 {
+            let end = (offset + 7).min(keys.len());
+            assert_eq!(tree.page(offset, 7), keys[offset.min(end)..end], "offset {offset}");
+            assert_eq!(tree.nth(offset), keys.get(offset));
+        }
+    }
+
+    #[test]
+    fn test_rank_and_quantile() {
+        let mut tree = BTree::new(2);
+        assert_eq!((tree.rank(5), tree.quantile(0.5)), (0, None));
+        for key in 1..=200u32 {
+            tree.insert(key * 2);
+        }
+        assert_eq!((tree.rank(0), tree.rank(2), tree.rank(3), tree.rank(401)), (0, 0, 1, 200));
+        for key in 1..=200u32 {
+            assert_eq!(tree.rank(key * 2), key as usize - 1);
+        }
+        assert_eq!(tree.quantile(0.0), Some(&2));
+        assert_eq!(tree.quantile(0.5), Some(&200));
+        assert_eq!(tree.quantile(0.99), Some(&396));
+        assert_eq!(tree.quantile(1.0), Some(&400));
+        assert_eq!(tree.quantile(1.5), None);
+        assert_eq!(tree.quantile(f64::NAN), None);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_sample() {
+        struct XorShift(u64);
+        impl rand_core::RngCore for XorShift {
+            fn next_u32(&mut self) -> u32 {
+                (self.next_u64() >> 32) as u32
+            }
+            fn next_u64(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                rand_core::impls::fill_bytes_via_next(self, dest);
+            }
+        }
+
+        let mut tree = BTree::new(3);
+        for key in 0..100u32 {
+            tree.insert(key);
+        }
+        let mut rng = XorShift(7);
+        let mut hits = [0u32; 100];
+        for _ in 0..2000 {
+            let sample = tree.sample(&mut rng, 10);
+            assert_eq!(sample.len(), 10);
+            assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+            for &&key in &sample {
+                hits[key as usize] += 1;
+            }
+        }
+        // 200 expected for each key
+        assert!(hits.iter().all(|&count| (120..280).contains(&count)), "{hits:?}");
+        assert_eq!(tree.sample(&mut rng, 500).len(), 100);
+    }
+
+    #[test]
+    fn test_audit_counts() {
+        let mut tree = BTree::new(2);
+        for key in 0..100u32 {
+            tree.insert(key);
+        }
+        assert!(tree.audit_counts().is_empty());
+        assert_eq!(tree.repair_counts(), 0);
+
+        let snapshot = tree.snapshot();
+        let root = Arc::make_mut(&mut tree.root);
+        Arc::make_mut(&mut root.children[1]).len += 3;
+        root.len = 7;
+        assert_eq!(
+            tree.audit_counts(),
+            [
+                CountMismatch { path: vec![], counted: 7, actual: 100 },
+                CountMismatch { path: vec![1], counted: tree.root.children[1].len, actual: tree.root.children[1].len - 3 },
+            ]
+        );
+        assert_eq!(tree.repair_counts(), 2);
+        assert!(tree.audit_counts().is_empty());
+        assert_eq!((tree.len(), tree.nth(99)), (100, Some(&99)));
+        assert!(snapshot.iter().eq(tree.iter_snapshot()));
+    }
+}
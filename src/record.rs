@@ -0,0 +1,272 @@
+//! Step-by-step recordings of a tree, for visualizations and teaching.
+//!
+//! With a `Recorder` attached, every split, merge and borrow adds a step
+//! holding a label such as "split child 2 of root" and the whole tree as it
+//! was right after that change, as JSON. Each insert and delete then adds a
+//! step for its final tree, and `insert_batch` and `rebuild` one step each.
+//!
+//! A tree is an object with the node's keys and, unless it is a leaf, its
+//! children: `{"keys": [4, 9], "children": [{"keys": [1, 2]}, ...]}`. Keys
+//! are written with their `Debug` format: as JSON numbers when that is a
+//! number, as JSON strings otherwise. Nodes are named by the child indexes
+//! on the way to them from the root, as in "node 0.2".
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Write};
+
+use crate::allocator::{Allocator, Global};
+use crate::{Aggregate, BTree, Context, Node};
+
+/// Steps recorded from a tree. See the module docs.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub label: String,
+    /// The tree after this step, as JSON.
+    pub tree: String,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// All steps as a JSON array of `{"label": ..., "tree": ...}` objects.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (index, step) in self.steps.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"label\":");
+            write_string(&mut json, &step.label);
+            let _ = write!(json, ",\"tree\":{}}}", step.tree);
+        }
+        json.push(']');
+        json
+    }
+}
+
+// A change's label, the path to the node it changed and a copy of it.
+type Change<T, A, M> = (String, Vec<usize>, Node<T, A, M>);
+// A node to put in place of the one at the end of a path.
+type Replacement<'a, T, A, M> = (&'a [usize], &'a Node<T, A, M>);
+
+// What an insert or delete in progress needs to turn its changes into
+// steps: the path from the root to the node it is working on, and the
+// changes so far, each with a copy of the node it changed. The copy holds
+// on to that node's children, so later changes to them copy them
+// (`Arc::make_mut`) instead of rewriting the recorded state.
+pub(crate) struct Recording<T, A: Aggregate<T> = (), M: Allocator + Clone = Global> {
+    pub(crate) recorder: Recorder,
+    path: Vec<usize>,
+    changes: Vec<Change<T, A, M>>,
+}
+
+impl<T: Clone + Debug, A: Aggregate<T>, M: Allocator + Clone> Recording<T, A, M> {
+    pub(crate) fn new(recorder: Recorder) -> Self {
+        Recording {
+            recorder,
+            path: Vec::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    fn descend(&mut self, index: usize) {
+        self.path.push(index);
+    }
+
+    fn ascend(&mut self) {
+        self.path.pop();
+    }
+
+    // `label` gets the name of the changed node, which is the node the
+    // operation is at.
+    fn change(&mut self, node: &Node<T, A, M>, label: impl FnOnce(&str) -> String) {
+        let name = match self.path.is_empty() {
+            true => String::from("root"),
+            false => {
+                let path: Vec<String> = self.path.iter().map(|index| format!("{index}")).collect();
+                format!("node {}", path.join("."))
+            }
+        };
+        self.changes.push((label(&name), self.path.clone(), node.clone()));
+    }
+
+    // Turn the changes into steps, then add the final tree. Each change is
+    // shown in `base` with the changed node put back as it was then: the
+    // nodes above it are the same in `base` as they were at the time.
+    pub(crate) fn finish(&mut self, label: String, base: &Node<T, A, M>, root: &Node<T, A, M>) {
+        for (change, path, node) in self.changes.drain(..) {
+            let mut tree = String::new();
+            write_tree(&mut tree, base, Some((&path, &node)));
+            self.recorder.steps.push(Step { label: change, tree });
+        }
+        self.path.clear();
+        self.record(label, root);
+    }
+
+    pub(crate) fn record(&mut self, label: String, root: &Node<T, A, M>) {
+        let mut tree = String::new();
+        write_tree(&mut tree, root, None);
+        self.recorder.steps.push(Step { label, tree });
+    }
+}
+
+// Each does nothing unless a recorder is attached.
+impl<T: Clone + Debug, A: Aggregate<T>, M: Allocator + Clone> Context<T, A, M> {
+    pub(crate) fn descend(&mut self, index: usize) {
+        if let Some(recording) = &mut self.recording {
+            recording.descend(index);
+        }
+    }
+
+    pub(crate) fn ascend(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.ascend();
+        }
+    }
+
+    pub(crate) fn change(&mut self, node: &Node<T, A, M>, label: impl FnOnce(&str) -> String) {
+        if let Some(recording) = &mut self.recording {
+            recording.change(node, label);
+        }
+    }
+}
+
+impl<T, A, M> BTree<T, A, M>
+where
+    T: Ord + Copy + Debug + Default,
+    A: Aggregate<T>,
+    M: Allocator + Clone,
+{
+    /// Start recording steps into `recorder`, replacing any attached one.
+    pub fn attach_recorder(&mut self, recorder: Recorder) {
+        self.ctx.recording = Some(Recording::new(recorder));
+    }
+
+    pub fn detach_recorder(&mut self) -> Option<Recorder> {
+        self.ctx.recording.take().map(|recording| recording.recorder)
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.ctx.recording.as_ref().map(|recording| &recording.recorder)
+    }
+
+    // Called when an operation other than insert and delete has replaced
+    // the tree, which is recorded as a single step.
+    pub(crate) fn record_whole(&mut self, label: impl FnOnce() -> String) {
+        if let Some(recording) = &mut self.ctx.recording {
+            recording.record(label(), &self.root);
+        }
+    }
+}
+
+// Write `node`, with `replacement` put in where its path leads.
+fn write_tree<T: Debug, A: Aggregate<T>, M: Allocator + Clone>(json: &mut String, node: &Node<T, A, M>, replacement: Option<Replacement<'_, T, A, M>>) {
+    let (node, replacement) = match replacement {
+        Some(([], replacement)) => (replacement, None),
+        _ => (node, replacement),
+    };
+    json.push_str("{\"keys\":[");
+    for (index, key) in node.keys.iter().enumerate() {
+        if index > 0 {
+            json.push(',');
+        }
+        write_key(json, key);
+    }
+    json.push(']');
+    if !node.children.is_empty() {
+        json.push_str(",\"children\":[");
+        for (index, child) in node.children.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let below = match replacement {
+                Some((&[next, ref rest @ ..], replacement)) if next == index => Some((rest, replacement)),
+                _ => None,
+            };
+            write_tree(json, child, below);
+        }
+        json.push(']');
+    }
+    json.push('}');
+}
+
+fn write_key<T: Debug>(json: &mut String, key: &T) {
+    let text = format!("{key:?}");
+    let number = text.strip_prefix('-').unwrap_or(&text);
+    let is_number = number.starts_with(|c: char| c.is_ascii_digit())
+        && number.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '-' | '+'))
+        && !number.ends_with('.');
+    if is_number {
+        json.push_str(&text);
+    } else {
+        write_string(json, &text);
+    }
+}
+
+pub(crate) fn write_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::Recorder;
+    use crate::BTree;
+
+    #[test]
+    fn test_recorder_steps() {
+        let mut tree = BTree::new(2);
+        for key in [10, 20, 30] {
+            tree.insert(key);
+        }
+        tree.attach_recorder(Recorder::new());
+        for key in [40, 50, 60] {
+            tree.insert(key);
+        }
+        for key in [10, 60, 50] {
+            tree.delete(key);
+        }
+
+        let recorder = tree.detach_recorder().unwrap();
+        let steps: Vec<(&str, &str)> = recorder.steps().iter().map(|step| (step.label.as_str(), step.tree.as_str())).collect();
+        assert_eq!(
+            steps,
+            [
+                ("split the root, adding a level", r#"{"keys":[20],"children":[{"keys":[10]},{"keys":[30]}]}"#),
+                ("insert 40", r#"{"keys":[20],"children":[{"keys":[10]},{"keys":[30,40]}]}"#),
+                ("insert 50", r#"{"keys":[20],"children":[{"keys":[10]},{"keys":[30,40,50]}]}"#),
+                ("split child 1 of root", r#"{"keys":[20,40],"children":[{"keys":[10]},{"keys":[30]},{"keys":[50]}]}"#),
+                ("insert 60", r#"{"keys":[20,40],"children":[{"keys":[10]},{"keys":[30]},{"keys":[50,60]}]}"#),
+                ("merge child 1 into child 0 of root", r#"{"keys":[40],"children":[{"keys":[20,30]},{"keys":[50,60]}]}"#),
+                ("delete 10", r#"{"keys":[40],"children":[{"keys":[20,30]},{"keys":[50,60]}]}"#),
+                ("delete 60", r#"{"keys":[40],"children":[{"keys":[20,30]},{"keys":[50]}]}"#),
+                ("borrow from child 0 into child 1 of root", r#"{"keys":[30],"children":[{"keys":[20]},{"keys":[40]}]}"#),
+                ("delete 50", r#"{"keys":[30],"children":[{"keys":[20]},{"keys":[40]}]}"#),
+            ]
+        );
+        assert!(recorder.to_json().starts_with(r#"[{"label":"split the root, adding a level","tree":{"keys":[20],"#));
+    }
+}
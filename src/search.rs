@@ -0,0 +1,57 @@
+//! Finding a key's place among the keys of one node.
+//!
+//! By default the keys are scanned from the largest down, which is quick
+//! for the few keys of a narrow node. With the `branchless` feature it is a
+//! binary search whose steps pick the next half with a conditional move
+//! rather than a jump, so wide nodes take log2 of the branch factor steps
+//! with no mispredicted branches; that pays off from a few dozen keys on.
+
+// The number of leading keys of sorted `keys` that `before` holds for, like
+// `partition_point`, and how many times `before` was called.
+#[cfg(not(feature = "branchless"))]
+#[inline]
+pub(crate) fn partition<T>(keys: &[T], mut before: impl FnMut(&T) -> bool) -> (usize, usize) {
+    let mut index = keys.len();
+    while index > 0 && !before(&keys[index - 1]) {
+        index -= 1;
+    }
+    // the keys passed over, then the one that stopped the scan
+    (index, keys.len() - index + usize::from(index > 0))
+}
+
+#[cfg(feature = "branchless")]
+#[inline]
+pub(crate) fn partition<T>(keys: &[T], mut before: impl FnMut(&T) -> bool) -> (usize, usize) {
+    if keys.is_empty() {
+        return (0, 0);
+    }
+    // the answer is in `base..=base + size`
+    let (mut base, mut size, mut comparisons) = (0, keys.len(), 1);
+    while size > 1 {
+        let half = size / 2;
+        base = if before(&keys[base + half]) { base + half } else { base };
+        size -= half;
+        comparisons += 1;
+    }
+    (base + usize::from(before(&keys[base])), comparisons)
+}
+
+#[cfg(test)]
+mod test {
+    use super::partition;
+
+    #[test]
+    fn test_partition() {
+        for len in 0..40u32 {
+            let keys: Vec<u32> = (0..len).map(|key| key * 2).collect();
+            for probe in 0..2 * len + 2 {
+                let (index, comparisons) = partition(&keys, |key| *key < probe);
+                assert_eq!(index, keys.partition_point(|key| *key < probe), "{probe} in {keys:?}");
+                assert!(comparisons <= keys.len());
+            }
+        }
+        let duplicates = [1, 2, 2, 2, 3];
+        assert_eq!(partition(&duplicates, |key| *key < 2).0, 1);
+        assert_eq!(partition(&duplicates, |key| *key <= 2).0, 4);
+    }
+}
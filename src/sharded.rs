@@ -0,0 +1,103 @@
+use std::fmt::Debug;
+use std::iter::Flatten;
+use std::vec;
+
+use parking_lot::RwLock;
+
+use crate::{BTree, SnapshotIter};
+
+/// Splits the key space into ranges, each held by its own `BTree` behind its
+/// own lock, so writers to different ranges don't contend.
+///
+/// Pick boundaries that spread the expected keys evenly: with `n` boundaries
+/// there are `n + 1` shards, shard `i` holding keys in
+/// `boundaries[i - 1]..boundaries[i]`.
+pub struct ShardedBTree<T> {
+    boundaries: Vec<T>,
+    shards: Vec<RwLock<BTree<T>>>,
+}
+
+impl<T> ShardedBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize, boundaries: Vec<T>) -> Self {
+        assert!(
+            boundaries.windows(2).all(|pair| pair[0] < pair[1]),
+            "shard boundaries must be strictly increasing"
+        );
+        let shards = (0..=boundaries.len()).map(|_| RwLock::new(BTree::new(branch_factor))).collect();
+        ShardedBTree { boundaries, shards }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, key: T) -> &RwLock<BTree<T>> {
+        &self.shards[self.boundaries.partition_point(|b| *b <= key)]
+    }
+
+    pub fn insert(&self, key: T) {
+        self.shard(key).write().insert(key);
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        self.shard(key).read().search(key)
+    }
+
+    pub fn delete(&self, key: T) -> bool {
+        self.shard(key).write().delete(key)
+    }
+
+    /// Iterate over all keys in order. Every shard is read at the same
+    /// moment, and writes may continue while the iterator is in use.
+    pub fn iter(&self) -> ShardedIter<T> {
+        let guards: Vec<_> = self.shards.iter().map(|shard| shard.read()).collect();
+        let iters: Vec<_> = guards.iter().map(|tree| tree.iter_snapshot()).collect();
+        ShardedIter(iters.into_iter().flatten())
+    }
+}
+
+pub struct ShardedIter<T: Copy>(Flatten<vec::IntoIter<SnapshotIter<T>>>);
+
+impl<T: Copy> Iterator for ShardedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ShardedBTree;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_parallel_writes() {
+        let tree = Arc::new(ShardedBTree::new(4, vec![1000, 2000, 3000]));
+        assert_eq!(tree.shard_count(), 4);
+        let writers: Vec<_> = (0..4u32)
+            .map(|t| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for key in (t..4000).step_by(4) {
+                        tree.insert(key);
+                    }
+                    for key in (t..4000).step_by(8) {
+                        assert!(tree.delete(key));
+                    }
+                })
+            })
+            .collect();
+        for handle in writers {
+            handle.join().unwrap();
+        }
+        let expected: Vec<u32> = (0..4000).filter(|key| key % 8 >= 4).collect();
+        assert_eq!(tree.iter().collect::<Vec<_>>(), expected);
+        assert!(tree.search(3999));
+        assert!(!tree.search(3000));
+    }
+}
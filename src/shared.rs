@@ -0,0 +1,141 @@
+//! Sharing a tree with other processes through shared memory, for sidecar
+//! processes that only read it.
+//!
+//! `BTree::publish` saves a snapshot under a name in the shared memory
+//! directory, `/dev/shm` where there is one and the temporary directory
+//! elsewhere, as `<name>.btsn`. The file is an ordinary snapshot, laid out
+//! as the `snapshot` module describes. Each process that opens the name
+//! gets a `BTreeReader` mapping it read-only; the mapped pages are the
+//! kernel's, shared by every reader rather than copied into each.
+//!
+//! Publishing writes the new snapshot beside the old one and renames it
+//! into place, so a mapped snapshot is never changed or truncated under a
+//! reader. A reader keeps the snapshot it mapped until `refresh` maps the
+//! latest.
+
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+
+use crate::codec::{KeyCodec, Ordered};
+use crate::view::{BTreeView, Range};
+use crate::BTree;
+
+/// Where `publish` puts snapshots.
+pub fn shared_dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    match shm.is_dir() {
+        true => shm.to_path_buf(),
+        false => std::env::temp_dir(),
+    }
+}
+
+fn shared_path(name: &str) -> PathBuf {
+    shared_dir().join(format!("{name}.btsn"))
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Publish with the built-in `Ordered` codec.
+    pub fn publish(&self, name: &str) -> io::Result<PathBuf>
+    where
+        Ordered: KeyCodec<T>,
+    {
+        self.publish_with::<Ordered>(name)
+    }
+
+    /// Save a snapshot for `BTreeReader::open(name)` to map, replacing the
+    /// last one published under `name`. Returns the snapshot's path.
+    pub fn publish_with<C: KeyCodec<T>>(&self, name: &str) -> io::Result<PathBuf> {
+        let path = shared_path(name);
+        let staging = shared_dir().join(format!(".{name}.{}.tmp", std::process::id()));
+        self.save_with::<C, _>(&staging)?;
+        fs::rename(&staging, &path)?;
+        Ok(path)
+    }
+}
+
+/// One process's read-only handle on a published tree: a `BTreeView` of
+/// the snapshot last mapped.
+pub struct BTreeReader<T, C = Ordered> {
+    view: BTreeView<T, C>,
+    path: PathBuf,
+}
+
+impl<T, C> BTreeReader<T, C>
+where
+    T: Ord + Clone,
+    C: KeyCodec<T>,
+{
+    /// Map the snapshot published under `name`. `C` must be the codec it
+    /// was published with.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let path = shared_path(name);
+        Ok(BTreeReader { view: BTreeView::open(&path)?, path })
+    }
+
+    /// Map the latest snapshot published under this name.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        self.view = BTreeView::open(&self.path)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn view(&self) -> &BTreeView<T, C> {
+        &self.view
+    }
+
+    pub fn len(&self) -> u64 {
+        self.view.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view.is_empty()
+    }
+
+    pub fn search(&self, key: T) -> io::Result<bool> {
+        self.view.search(key)
+    }
+
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, C> {
+        self.view.range(range)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BTreeReader;
+    use crate::BTree;
+
+    #[test]
+    fn test_publish_and_refresh() {
+        let name = format!("shared-test-{}", std::process::id());
+        let mut tree = BTree::new(3);
+        for key in 0..500u32 {
+            tree.insert(key);
+        }
+        let path = tree.publish(&name).unwrap();
+
+        let mut reader: BTreeReader<u32> = BTreeReader::open(&name).unwrap();
+        let other: BTreeReader<u32> = BTreeReader::open(&name).unwrap();
+        for key in 500..800u32 {
+            tree.insert(key);
+        }
+        tree.publish(&name).unwrap();
+        // both keep the snapshot they mapped, still whole after the rename
+        assert_eq!(reader.len(), 500);
+        assert_eq!(other.range(490..).count(), 10);
+        reader.refresh().unwrap();
+        assert_eq!(reader.len(), 800);
+        assert!(reader.search(799).unwrap());
+        assert!(BTreeReader::<u64>::open(&name).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
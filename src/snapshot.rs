@@ -0,0 +1,508 @@
+//! Compact binary snapshots of an in-memory `BTree`.
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! header   magic "BTSN" | version u16 | degree u32 | codec id u8 | reserved u8
+//! nodes    post-order, children before their parent:
+//!          is_leaf u8 | key count u32 | key end offsets u32 | encoded keys
+//!          | child offsets u64 (internal only)
+//! trailer  node count u64 | key count u64 | root offset u64
+//! ```
+//!
+//! Keys are encoded with a `KeyCodec`; key `i` of a node spans from the end
+//! of key `i - 1` to its own end offset, so any key can be found without
+//! decoding the others. The header, every node record and the trailer are
+//! each followed by a CRC32 of their bytes. Child offsets are byte offsets
+//! from the start of the file, so a node can be read without touching the
+//! rest. Loading rebuilds nodes exactly as they were saved instead of
+//! re-inserting every key.
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::codec::{KeyCodec, Ordered};
+use crate::crc32::crc32;
+use crate::{BTree, BTreeProps, Context, Node};
+
+pub(crate) const MAGIC: &[u8; 4] = b"BTSN";
+pub(crate) const VERSION: u16 = 3;
+pub(crate) const CHECKSUM_LEN: usize = 4;
+pub(crate) const HEADER_LEN: usize = 12 + CHECKSUM_LEN;
+pub(crate) const TRAILER_LEN: usize = 24 + CHECKSUM_LEN;
+// is_leaf + key count
+pub(crate) const NODE_HEAD_LEN: usize = 5;
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Save with the built-in `Ordered` codec.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()>
+    where
+        Ordered: KeyCodec<T>,
+    {
+        self.save_with::<Ordered, P>(path)
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> io::Result<Self>
+    where
+        Ordered: KeyCodec<T>,
+    {
+        BTree::load_with::<Ordered, P>(path)
+    }
+
+    pub fn save_with<C: KeyCodec<T>, P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&(self.props.degree as u32).to_le_bytes());
+        header.push(C::ID);
+        header.push(0);
+        write_checked(&mut out, &header)?;
+
+        let mut writer = NodeWriter {
+            out: &mut out,
+            offset: HEADER_LEN as u64,
+            nodes: 0,
+            keys: 0,
+            buf: Vec::new(),
+            key_bytes: Vec::new(),
+        };
+        let root = writer.write::<T, C>(&self.root)?;
+        let mut trailer = Vec::with_capacity(TRAILER_LEN);
+        trailer.extend_from_slice(&writer.nodes.to_le_bytes());
+        trailer.extend_from_slice(&writer.keys.to_le_bytes());
+        trailer.extend_from_slice(&root.to_le_bytes());
+        write_checked(&mut out, &trailer)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+
+    pub fn load_with<C: KeyCodec<T>, P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+
+        let mut header = [0; HEADER_LEN];
+        input.read_exact(&mut header)?;
+        let degree = check_header::<T, C>(&header)?;
+        let props = BTreeProps::new(degree);
+
+        // every subtree whose parent hasn't been read yet
+        let mut pending: Vec<Subtree<T>> = Vec::new();
+        let mut offset = HEADER_LEN as u64;
+        let nodes_end = file_len
+            .checked_sub(TRAILER_LEN as u64)
+            .ok_or_else(|| invalid_data("snapshot is truncated"))?;
+        let mut keys_read = 0u64;
+        let mut underfull = false;
+        let mut buf = Vec::new();
+        while offset < nodes_end {
+            read_record(&mut input, &mut buf, nodes_end - offset)?;
+            let record = NodeRecord::parse(&buf)?;
+            if record.count > props.max_keys {
+                return Err(invalid_data("node holds more keys than the degree allows"));
+            }
+            let keys: Vec<T> = (0..record.count)
+                .map(|i| C::decode(record.key(i)))
+                .collect::<Option<_>>()
+                .ok_or_else(|| invalid_data("node holds a key its codec can't decode"))?;
+            if keys.windows(2).any(|pair| pair[0] > pair[1]) {
+                return Err(invalid_data("node keys are out of order"));
+            }
+
+            let mut children = Vec::new();
+            let mut height = 0;
+            let mut bounds = keys.first().zip(keys.last()).map(|(&first, &last)| (first, last));
+            if !record.is_leaf {
+                if record.count == 0 {
+                    return Err(invalid_data("internal node holds no keys"));
+                }
+                if pending.len() < record.count + 1 {
+                    return Err(invalid_data("node refers to children that were never written"));
+                }
+                let first = pending.len() - (record.count + 1);
+                height = pending[first].height + 1;
+                for (index, child) in pending.drain(first..).enumerate() {
+                    if record.child(index) != child.offset {
+                        return Err(invalid_data("child offset does not match the node layout"));
+                    }
+                    if child.height + 1 != height {
+                        return Err(invalid_data("leaves are at different depths"));
+                    }
+                    // Only the root may be empty, and it is never a child.
+                    let Some((low, high)) = child.bounds else {
+                        return Err(invalid_data("node other than the root holds no keys"));
+                    };
+                    let above = index.checked_sub(1).map(|index| keys[index]);
+                    let below = keys.get(index);
+                    if above.is_some_and(|above| low < above) || below.is_some_and(|&below| high > below) {
+                        return Err(invalid_data("child keys are out of order with their parent's"));
+                    }
+                    if index == 0 {
+                        bounds = bounds.map(|(_, last)| (low, last));
+                    }
+                    if index == record.count {
+                        bounds = bounds.map(|(first, _)| (first, high));
+                    }
+                    underfull |= child.node.keys.len() < props.min_keys;
+                    children.push(Arc::new(child.node));
+                }
+            }
+
+            keys_read += record.count as u64;
+            pending.push(Subtree { offset, node: Node::new(degree, Some(keys), Some(children)), height, bounds });
+            offset += (buf.len() + CHECKSUM_LEN) as u64;
+        }
+
+        let mut trailer = [0; TRAILER_LEN];
+        input.read_exact(&mut trailer)?;
+        let (_, key_count, root_offset) = check_trailer(&trailer)?;
+        if pending.len() != 1 || pending[0].offset != root_offset || key_count != keys_read {
+            return Err(invalid_data("trailer does not match the node layout"));
+        }
+        let root = pending.pop().unwrap().node;
+        let mut tree = BTree {
+            root: Arc::new(root),
+            props,
+            ctx: Context::new(),
+        };
+        // Relaxed deletes and a lowered `min_keys` leave nodes that are
+        // valid but below the default minimum the tree loads with.
+        if underfull {
+            tree.settle();
+        }
+        Ok(tree)
+    }
+}
+
+// A node read by `load_with`, waiting for its parent.
+struct Subtree<T> {
+    offset: u64,
+    node: Node<T>,
+    // 0 for a leaf.
+    height: usize,
+    // The least and greatest key anywhere below; `None` if empty.
+    bounds: Option<(T, T)>,
+}
+
+struct NodeWriter<'a, W: Write> {
+    out: &'a mut W,
+    offset: u64,
+    nodes: u64,
+    keys: u64,
+    buf: Vec<u8>,
+    key_bytes: Vec<u8>,
+}
+
+impl<W: Write> NodeWriter<'_, W> {
+    // Writes the subtree and returns the offset of its root record.
+    fn write<T, C: KeyCodec<T>>(&mut self, node: &Node<T>) -> io::Result<u64> {
+        let mut child_offsets = Vec::with_capacity(node.children.len());
+        for child in node.children.iter() {
+            child_offsets.push(self.write::<T, C>(child)?);
+        }
+
+        self.buf.clear();
+        self.key_bytes.clear();
+        self.buf.push(node.children.is_empty() as u8);
+        let count = u32::try_from(node.keys.len()).ok().unwrap();
+        self.buf.extend_from_slice(&count.to_le_bytes());
+        for key in node.keys.iter() {
+            C::encode(key, &mut self.key_bytes);
+            let end = u32::try_from(self.key_bytes.len())
+                .map_err(|_| invalid_input("node keys are larger than 4 GiB"))?;
+            self.buf.extend_from_slice(&end.to_le_bytes());
+        }
+        self.buf.extend_from_slice(&self.key_bytes);
+        for child_offset in child_offsets {
+            self.buf.extend_from_slice(&child_offset.to_le_bytes());
+        }
+        write_checked(self.out, &self.buf)?;
+
+        let offset = self.offset;
+        self.offset += (self.buf.len() + CHECKSUM_LEN) as u64;
+        self.nodes += 1;
+        self.keys += node.keys.len() as u64;
+        Ok(offset)
+    }
+}
+
+/// A node record split into its parts. Parsing checks that the parts fit.
+#[derive(Clone, Copy)]
+pub(crate) struct NodeRecord<'a> {
+    pub(crate) is_leaf: bool,
+    pub(crate) count: usize,
+    ends: &'a [u8],
+    keys: &'a [u8],
+    children: &'a [u8],
+}
+
+impl<'a> NodeRecord<'a> {
+    pub(crate) fn parse(record: &'a [u8]) -> io::Result<Self> {
+        let (is_leaf, count) = record_head(record);
+        let len = record_len(record)?;
+        if len != record.len() {
+            return Err(invalid_data("node record has the wrong length"));
+        }
+        let (ends, rest) = record[NODE_HEAD_LEN..].split_at(4 * count);
+        let (keys, children) = rest.split_at(keys_len(ends));
+        let parsed = NodeRecord {
+            is_leaf,
+            count,
+            ends,
+            keys,
+            children,
+        };
+        if (1..count).any(|i| parsed.end(i - 1) > parsed.end(i)) {
+            return Err(invalid_data("node key offsets are out of order"));
+        }
+        Ok(parsed)
+    }
+
+    pub(crate) fn key(&self, index: usize) -> &'a [u8] {
+        let start = if index == 0 { 0 } else { self.end(index - 1) };
+        &self.keys[start..self.end(index)]
+    }
+
+    pub(crate) fn child(&self, index: usize) -> u64 {
+        u64::from_le_bytes(self.children[index * 8..index * 8 + 8].try_into().unwrap())
+    }
+
+    fn end(&self, index: usize) -> usize {
+        u32::from_le_bytes(self.ends[index * 4..index * 4 + 4].try_into().unwrap()) as usize
+    }
+}
+
+fn write_checked<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    out.write_all(bytes)?;
+    out.write_all(&crc32(bytes).to_le_bytes())
+}
+
+fn checksum_matches(bytes: &[u8], checksum: &[u8]) -> bool {
+    crc32(bytes).to_le_bytes() == checksum
+}
+
+// Returns the degree recorded in the header.
+pub(crate) fn check_header<T, C: KeyCodec<T>>(header: &[u8]) -> io::Result<usize> {
+    if check_header_bytes(header)? != C::ID {
+        return Err(invalid_data("snapshot was written with a different key codec"));
+    }
+    let degree = u32::from_le_bytes(header[6..10].try_into().unwrap()) as usize;
+    // what `BTree::with_degree` accepts
+    if degree < 4 || !degree.is_multiple_of(2) {
+        return Err(invalid_data("snapshot has an invalid degree"));
+    }
+    Ok(degree)
+}
+
+// Checks magic, version and checksum; returns the codec id.
+pub(crate) fn check_header_bytes(header: &[u8]) -> io::Result<u8> {
+    if &header[0..4] != MAGIC {
+        return Err(invalid_data("not a b-tree snapshot"));
+    }
+    if u16::from_le_bytes([header[4], header[5]]) != VERSION {
+        return Err(invalid_data("unsupported snapshot version"));
+    }
+    if !checksum_matches(&header[..HEADER_LEN - CHECKSUM_LEN], &header[HEADER_LEN - CHECKSUM_LEN..]) {
+        return Err(invalid_data("snapshot header failed its checksum"));
+    }
+    Ok(header[10])
+}
+
+// Returns (node count, key count, root offset).
+pub(crate) fn check_trailer(trailer: &[u8]) -> io::Result<(u64, u64, u64)> {
+    if !checksum_matches(&trailer[..TRAILER_LEN - CHECKSUM_LEN], &trailer[TRAILER_LEN - CHECKSUM_LEN..]) {
+        return Err(invalid_data("snapshot trailer failed its checksum"));
+    }
+    let field = |index: usize| u64::from_le_bytes(trailer[index * 8..index * 8 + 8].try_into().unwrap());
+    Ok((field(0), field(1), field(2)))
+}
+
+fn record_head(record: &[u8]) -> (bool, usize) {
+    let count = u32::from_le_bytes(record[1..NODE_HEAD_LEN].try_into().unwrap()) as usize;
+    (record[0] == 1, count)
+}
+
+fn keys_len(ends: &[u8]) -> usize {
+    match ends.len() {
+        0 => 0,
+        n => u32::from_le_bytes(ends[n - 4..].try_into().unwrap()) as usize,
+    }
+}
+
+// Length of the record (without its checksum) that starts with `prefix`,
+// which must hold at least the head and the key end offsets.
+fn record_len(prefix: &[u8]) -> io::Result<usize> {
+    let (is_leaf, count) = record_head(prefix);
+    let ends = prefix
+        .get(NODE_HEAD_LEN..NODE_HEAD_LEN + 4 * count)
+        .ok_or_else(|| invalid_data("node record is truncated"))?;
+    let children = if is_leaf { 0 } else { 8 * (count + 1) };
+    Ok(NODE_HEAD_LEN + ends.len() + keys_len(ends) + children)
+}
+
+/// Read one node record into `record` (without its checksum) and verify it.
+/// `remaining` is how many bytes of nodes are left in the file.
+pub(crate) fn read_record<R: Read>(input: &mut R, record: &mut Vec<u8>, remaining: u64) -> io::Result<()> {
+    // A corrupt count or key length shouldn't make us allocate gigabytes
+    // before the checksum fails.
+    let fits = |len: usize| len as u64 + CHECKSUM_LEN as u64 <= remaining;
+    if !fits(NODE_HEAD_LEN) {
+        return Err(invalid_data("node record runs past the end of the snapshot"));
+    }
+    record.resize(NODE_HEAD_LEN, 0);
+    input.read_exact(record)?;
+    let (_, count) = record_head(record);
+    if count > u16::MAX as usize || !fits(NODE_HEAD_LEN + 4 * count) {
+        return Err(invalid_data("node record has an impossible key count"));
+    }
+    record.resize(NODE_HEAD_LEN + 4 * count, 0);
+    input.read_exact(&mut record[NODE_HEAD_LEN..])?;
+    let len = record_len(record)?;
+    if !fits(len) {
+        return Err(invalid_data("node record runs past the end of the snapshot"));
+    }
+    let read = record.len();
+    record.resize(len, 0);
+    input.read_exact(&mut record[read..])?;
+    let mut checksum = [0; CHECKSUM_LEN];
+    input.read_exact(&mut checksum)?;
+    if !checksum_matches(record, &checksum) {
+        return Err(invalid_data("snapshot node failed its checksum"));
+    }
+    Ok(())
+}
+
+/// The node record starting at `offset` in an in-memory copy of a snapshot,
+/// bounds checked and verified.
+#[cfg(feature = "mmap")]
+pub(crate) fn record_at(bytes: &[u8], offset: u64) -> io::Result<NodeRecord<'_>> {
+    let out_of_range = || invalid_data("node runs past the end of the snapshot");
+    let start = usize::try_from(offset).map_err(|_| out_of_range())?;
+    let rest = bytes.get(start..).ok_or_else(out_of_range)?;
+    if rest.len() < NODE_HEAD_LEN {
+        return Err(out_of_range());
+    }
+    let len = record_len(rest)?;
+    let record = rest.get(..len).ok_or_else(out_of_range)?;
+    let checksum = rest.get(len..len + CHECKSUM_LEN).ok_or_else(out_of_range)?;
+    if !checksum_matches(record, checksum) {
+        return Err(invalid_data("snapshot node failed its checksum"));
+    }
+    let node = NodeRecord::parse(record)?;
+    // Children are written before their parent, so one at or after it
+    // would send a walk down the tree round in a loop.
+    if !node.is_leaf && (0..=node.count).any(|index| node.child(index) >= offset) {
+        return Err(invalid_data("node points at a child that isn't before it"));
+    }
+    Ok(node)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::crc32::crc32;
+    use crate::test::check_node;
+    use crate::{BTree, Node};
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("snapshot-{}.btsn", std::process::id()));
+        let mut tree = BTree::new(3);
+        for key in 0..1000u32 {
+            tree.insert((key * 7) % 1000);
+        }
+        tree.save_to(&path).unwrap();
+
+        let loaded: BTree<u32> = BTree::load_from(&path).unwrap();
+        assert_eq!(loaded.props.degree, tree.props.degree);
+        for key in 0..1000u32 {
+            assert!(loaded.search(key));
+        }
+        assert!(!loaded.search(1000));
+
+        // Same bytes, wrong key type.
+        assert!(BTree::<u64>::load_from(&path).is_err());
+
+        // A flipped bit anywhere in a node is caught on load.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[super::HEADER_LEN + 7] ^= 0x10;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(BTree::<u32>::load_from(&path).is_err());
+
+        BTree::<u32>::new(2).save_to(&path).unwrap();
+        let empty: BTree<u32> = BTree::load_from(&path).unwrap();
+        assert!(!empty.search(0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_broken_trees() {
+        let path = std::env::temp_dir().join(format!("snapshot-broken-{}.btsn", std::process::id()));
+        let leaf = |keys: &[u32]| Arc::new(Node::new(8, Some(keys.to_vec()), Some(Vec::new())));
+        let internal = |keys: &[u32], children| Node::new(8, Some(keys.to_vec()), Some(children));
+        let load = |root: Node<u32>| {
+            let mut tree = BTree::new(4);
+            tree.root = Arc::new(root);
+            tree.save_to(&path).unwrap();
+            BTree::<u32>::load_from(&path)
+        };
+        let rejected = |root| load(root).err().map(|error| error.to_string());
+
+        assert_eq!(rejected(internal(&[3, 1], Vec::new())).unwrap(), "node keys are out of order");
+        let wrong_side = internal(&[5], vec![leaf(&[1, 2, 3]), leaf(&[4, 6, 7])]);
+        assert_eq!(rejected(wrong_side).unwrap(), "child keys are out of order with their parent's");
+        let deeper = internal(&[5], vec![leaf(&[1, 2, 3]), Arc::new(internal(&[7], vec![leaf(&[6]), leaf(&[8])]))]);
+        assert_eq!(rejected(deeper).unwrap(), "leaves are at different depths");
+        let empty = internal(&[5], vec![leaf(&[]), leaf(&[6, 7, 8])]);
+        assert_eq!(rejected(empty).unwrap(), "node other than the root holds no keys");
+
+        // an underfull node, as relaxed deletes leave, is refilled on load
+        let loaded = load(internal(&[10], vec![leaf(&[1]), leaf(&[11, 12, 13, 14])])).unwrap();
+        check_node(&loaded.root, &loaded.props, true);
+        assert!(loaded.iter_snapshot().eq([1, 10, 11, 12, 13, 14]));
+
+        // a degree `BTree` can't have, with the header's checksum fixed up
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[6..10].copy_from_slice(&7u32.to_le_bytes());
+        let checksum = crc32(&bytes[..12]);
+        bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(BTree::<u32>::load_from(&path).err().unwrap().to_string(), "snapshot has an invalid degree");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_oversized_record() {
+        let path = std::env::temp_dir().join(format!("snapshot-oversized-{}.btsn", std::process::id()));
+        let mut tree = BTree::new(2);
+        for key in 0..3u32 {
+            tree.insert(key);
+        }
+        tree.save_to(&path).unwrap();
+
+        // the leaf's key bytes said to run to 4 GiB, checksum left stale
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last_end = super::HEADER_LEN + super::NODE_HEAD_LEN + 4 * 2;
+        bytes[last_end..last_end + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let error = BTree::<u32>::load_from(&path).err().unwrap();
+        assert_eq!(error.to_string(), "node record runs past the end of the snapshot");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
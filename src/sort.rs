@@ -0,0 +1,120 @@
+//! Sorting a file of keys too big for memory, with a `DiskBTree` doing the
+//! merging. The input is read in runs of `run_keys` keys; each run is
+//! sorted in memory and inserted into a tree in a scratch file next to the
+//! output. Because a sorted run goes into the tree leaf after leaf, its
+//! inserts mostly touch pages already in the buffer pool, and the tree
+//! merges each new run into the ones before it. Reading the tree back in
+//! order writes the output.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{DiskBTree, DiskOptions};
+
+pub struct SortOptions {
+    /// How many keys to sort in memory at a time.
+    pub run_keys: usize,
+    /// Pages of the scratch tree to keep in memory, as `DiskOptions`.
+    pub cache_pages: usize,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        SortOptions { run_keys: 1 << 20, cache_pages: 1024 }
+    }
+}
+
+/// Sort the whole numbers in `input`, one per line (blank lines are
+/// skipped), into `output` in the same format, keeping duplicates. Returns
+/// how many keys were written. The scratch tree is `<output>.sort-tmp`,
+/// removed when done; a line that isn't a number fails with its line number.
+pub fn sort_file<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q, options: SortOptions) -> io::Result<u64> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let scratch = scratch_path(output);
+    if scratch.exists() {
+        fs::remove_file(&scratch)?;
+    }
+    let result = sort_through(input, output, &scratch, &options);
+    let removed = fs::remove_file(&scratch);
+    let count = result?;
+    removed?;
+    Ok(count)
+}
+
+fn sort_through(input: &Path, output: &Path, scratch: &Path, options: &SortOptions) -> io::Result<u64> {
+    let tree_options = DiskOptions { branch_factor: 127, cache_pages: options.cache_pages, ..DiskOptions::default() };
+    let mut tree: DiskBTree<i64> = DiskBTree::open(scratch, tree_options)?;
+    let mut run = Vec::with_capacity(options.run_keys.max(1));
+    for (number, line) in BufReader::new(File::open(input)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse() {
+            Ok(key) => run.push(key),
+            Err(_) => {
+                let message = format!("{}:{}: not a whole number: {line}", input.display(), number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+        if run.len() >= options.run_keys {
+            insert_run(&mut tree, &mut run)?;
+        }
+    }
+    insert_run(&mut tree, &mut run)?;
+
+    let mut out = BufWriter::new(File::create(output)?);
+    tree.for_each(|key| writeln!(out, "{key}"))?;
+    out.flush()?;
+    Ok(tree.len())
+}
+
+fn insert_run(tree: &mut DiskBTree<i64>, run: &mut Vec<i64>) -> io::Result<()> {
+    run.sort_unstable();
+    for key in run.drain(..) {
+        tree.insert(key)?;
+    }
+    Ok(())
+}
+
+fn scratch_path(output: &Path) -> PathBuf {
+    let mut path = OsString::from(output);
+    path.push(".sort-tmp");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{scratch_path, sort_file, SortOptions};
+
+    #[test]
+    fn test_sort_file() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("btree-sort-in-{}.txt", std::process::id()));
+        let output = dir.join(format!("btree-sort-out-{}.txt", std::process::id()));
+        let keys: Vec<i64> = (0..5000).map(|key| (key * 7919) % 5003 - 2500).chain([0, 0, -7]).collect();
+        let text: String = keys.iter().map(|key| format!("{key}\n\n")).collect();
+        fs::write(&input, text).unwrap();
+
+        let options = SortOptions { run_keys: 300, cache_pages: 4 };
+        assert_eq!(sort_file(&input, &output, options).unwrap(), 5003);
+        let mut expected = keys;
+        expected.sort();
+        let sorted: Vec<i64> = fs::read_to_string(&output).unwrap().lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(sorted, expected);
+        assert!(!scratch_path(&output).exists());
+
+        fs::write(&input, "3\n1\nx\n").unwrap();
+        let error = sort_file(&input, &output, SortOptions::default()).unwrap_err();
+        assert!(error.to_string().ends_with(":3: not a whole number: x"));
+        assert!(!scratch_path(&output).exists());
+        for path in [input, output] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! The simplest way to share a tree between threads: the whole tree behind
+//! one readers-writer lock. Any number of readers run at once, and a
+//! writer has the tree to itself. `parking_lot`'s lock has no poisoning,
+//! so a thread that panics while holding it leaves the tree usable, as the
+//! panicking operation left it.
+//!
+//! Writers wait for each other however far apart their keys are; for
+//! writers that don't, see `ShardedBTree` and `ConcurrentBTree`.
+
+use std::fmt::Debug;
+
+use parking_lot::RwLock;
+
+use crate::BTree;
+
+/// A `BTree` behind a `parking_lot::RwLock`. See the module docs.
+///
+/// The lock is held for the length of a call and no guard is handed out,
+/// so there is no lock to forget to release: `with_read` and `with_write`
+/// run a closure on the tree, and the common operations take the lock
+/// themselves.
+pub struct SyncBTree<T> {
+    tree: RwLock<BTree<T>>,
+}
+
+impl<T> SyncBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize) -> Self {
+        SyncBTree { tree: RwLock::new(BTree::new(branch_factor)) }
+    }
+
+    /// Run `read` on the tree, alongside any other readers.
+    pub fn with_read<R>(&self, read: impl FnOnce(&BTree<T>) -> R) -> R {
+        read(&self.tree.read())
+    }
+
+    /// Run `write` on the tree, with no other reader or writer.
+    pub fn with_write<R>(&self, write: impl FnOnce(&mut BTree<T>) -> R) -> R {
+        write(&mut self.tree.write())
+    }
+
+    pub fn insert(&self, key: T) {
+        self.tree.write().insert(key);
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        self.tree.read().search(key)
+    }
+
+    pub fn delete(&self, key: T) -> bool {
+        self.tree.write().delete(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.read().is_empty()
+    }
+
+    pub fn into_inner(self) -> BTree<T> {
+        self.tree.into_inner()
+    }
+}
+
+impl<T> From<BTree<T>> for SyncBTree<T> {
+    fn from(tree: BTree<T>) -> Self {
+        SyncBTree { tree: RwLock::new(tree) }
+    }
+}
+
+impl<T> Default for SyncBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    fn default() -> Self {
+        SyncBTree::from(BTree::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::AssertUnwindSafe;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::SyncBTree;
+    use crate::test::check_node;
+
+    #[test]
+    fn test_sync_btree() {
+        let tree = Arc::new(SyncBTree::new(3));
+        let writers: Vec<_> = (0..4u32)
+            .map(|thread| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for key in 0..500 {
+                        tree.insert(key * 4 + thread);
+                        if key % 5 == 0 {
+                            assert!(tree.with_read(|tree| tree.search(key * 4 + thread)));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(tree.len(), 2000);
+        let evens = tree.with_write(|tree| {
+            (0..2000).filter(|key| key % 2 == 0).map(|key| tree.delete(key)).filter(|&deleted| deleted).count()
+        });
+        assert_eq!(evens, 1000);
+
+        // a panic with the lock held doesn't poison it
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            tree.with_write(|tree| {
+                tree.insert(1_000_000);
+                panic!("writer failed");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(tree.search(1_000_000) && !tree.search(2));
+        let tree = Arc::into_inner(tree).unwrap().into_inner();
+        check_node(&tree.root, &tree.props, true);
+        assert!(tree.iter_snapshot().take(3).eq([1, 3, 5]));
+    }
+}
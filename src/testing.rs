@@ -0,0 +1,295 @@
+//! Differential testing: run the same operations on a `BTree` and on std's
+//! `BTreeMap`, and panic at the first difference or broken invariant.
+//!
+//! The tree keeps duplicate keys, so the model counts the copies of each key
+//! rather than being a set. Downstream crates can point this at their own
+//! key types from their tests:
+//!
+//! ```
+//! b_trees_with_delete::testing::run_oracle_test::<u32>(7, 2000);
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{BTree, BTreeProps, Node};
+
+/// One operation on a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Op<T> {
+    Insert(T),
+    Delete(T),
+    Search(T),
+}
+
+/// Run `ops` random operations, generated from `seed`, against a tree of a
+/// branch factor also picked by `seed`. Keys are drawn from a range small
+/// enough that most deletes and searches hit and keys get inserted again.
+pub fn run_oracle_test<T>(seed: u64, ops: usize)
+where
+    T: Ord + Copy + Debug + Default + From<u16>,
+{
+    let mut rng = Rng::new(seed);
+    let branch_factor = 2 + rng.below(4) as usize;
+    let keys = (ops as u64 / 4).clamp(8, u16::MAX as u64);
+    let ops: Vec<Op<T>> = (0..ops)
+        .map(|_| {
+            let key = T::from(rng.below(keys) as u16);
+            match rng.below(3) {
+                0 => Op::Insert(key),
+                1 => Op::Delete(key),
+                _ => Op::Search(key),
+            }
+        })
+        .collect();
+    check_ops(branch_factor, ops);
+}
+
+/// Decode `data` into a branch factor and a run of operations on byte keys,
+/// and check them as `check_ops` does, returning the tree they leave. A
+/// fuzz target only needs to call this; byte keys keep inserts, deletes and
+/// searches landing on each other.
+#[cfg(feature = "arbitrary")]
+pub fn fuzz_one(data: &[u8]) -> BTree<u8> {
+    let mut data = arbitrary::Unstructured::new(data);
+    let branch_factor = data.int_in_range(2..=6).unwrap_or(2);
+    check_ops(branch_factor, data.arbitrary::<Vec<Op<u8>>>().unwrap_or_default())
+}
+
+/// Apply `ops` to an empty tree and to the model, checking after each one
+/// that both gave the same answer, hold the same keys, and that the tree's
+/// nodes are within their bounds with every leaf at the same depth. Returns
+/// the tree.
+pub fn check_ops<T>(branch_factor: usize, ops: impl IntoIterator<Item = Op<T>>) -> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    check_ops_on(BTree::new(branch_factor), ops)
+}
+
+// `check_ops` from `tree` rather than an empty one.
+fn check_ops_on<T>(mut tree: BTree<T>, ops: impl IntoIterator<Item = Op<T>>) -> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    let mut model: BTreeMap<T, usize> = BTreeMap::new();
+    for key in tree.iter_snapshot() {
+        *model.entry(key).or_default() += 1;
+    }
+    for (step, op) in ops.into_iter().enumerate() {
+        match op {
+            Op::Insert(key) => {
+                tree.insert(key);
+                *model.entry(key).or_default() += 1;
+            }
+            Op::Delete(key) => {
+                let expected = match model.get_mut(&key) {
+                    Some(1) => model.remove(&key).is_some(),
+                    Some(count) => {
+                        *count -= 1;
+                        true
+                    }
+                    None => false,
+                };
+                assert_eq!(tree.delete(key), expected, "step {step}: {op:?}");
+            }
+            Op::Search(key) => assert_eq!(tree.search(key), model.contains_key(&key), "step {step}: {op:?}"),
+        }
+        check_node(&tree.root, &tree.props, true);
+        let keys = model.iter().flat_map(|(&key, &count)| core::iter::repeat_n(key, count));
+        assert!(tree.iter_snapshot().eq(keys), "step {step}: {op:?} left the tree with different keys");
+    }
+    tree
+}
+
+/// A reproducible random workload, weighted toward what tends to break
+/// trees: runs of one key inserted many times, deleting the smallest and
+/// largest keys, and long sorted runs in either direction.
+///
+/// ```
+/// b_trees_with_delete::testing::StressTest::new(42).ops(5000).degree(6).run();
+/// ```
+#[derive(Clone, Debug)]
+pub struct StressTest {
+    seed: u64,
+    ops: usize,
+    degree: usize,
+}
+
+impl StressTest {
+    /// 1000 operations on a tree of degree 4, unless changed.
+    pub fn new(seed: u64) -> Self {
+        StressTest { seed, ops: 1000, degree: 4 }
+    }
+
+    pub fn ops(mut self, ops: usize) -> Self {
+        self.ops = ops;
+        self
+    }
+
+    /// The most children a node may have, an even number of at least 4.
+    pub fn degree(mut self, degree: usize) -> Self {
+        assert!(degree >= 4 && degree.is_multiple_of(2), "degree must be an even number of at least 4");
+        self.degree = degree;
+        self
+    }
+
+    /// The operations `run` applies, the same every time for the same seed.
+    pub fn workload(&self) -> Vec<Op<u32>> {
+        let mut rng = Rng::new(self.seed);
+        // what the operations so far leave in the tree, to aim deletes at
+        // the ends
+        let mut keys: BTreeMap<u32, usize> = BTreeMap::new();
+        let mut ops = Vec::with_capacity(self.ops);
+        let span = (self.ops as u64).max(16);
+        while ops.len() < self.ops {
+            let run = 1 + rng.below(64) as usize;
+            let start = rng.below(span) as u32;
+            for offset in 0..run.min(self.ops - ops.len()) {
+                let op = match rng.below(6) {
+                    _ if keys.is_empty() => Op::Insert(start),
+                    0 => Op::Insert(start),
+                    1 => Op::Insert(start + offset as u32),
+                    2 => Op::Insert(start.saturating_sub(offset as u32)),
+                    3 => Op::Delete(*keys.keys().next().unwrap()),
+                    4 => Op::Delete(*keys.keys().next_back().unwrap()),
+                    _ => match rng.below(2) {
+                        0 => Op::Delete(rng.below(span) as u32),
+                        _ => Op::Search(rng.below(span) as u32),
+                    },
+                };
+                match op {
+                    Op::Insert(key) => *keys.entry(key).or_default() += 1,
+                    Op::Delete(key) => match keys.get_mut(&key) {
+                        Some(1) => _ = keys.remove(&key),
+                        Some(count) => *count -= 1,
+                        None => {}
+                    },
+                    Op::Search(_) => {}
+                }
+                ops.push(op);
+            }
+        }
+        ops
+    }
+
+    /// Check the workload as `check_ops` does, panicking at the first
+    /// failure.
+    pub fn run(&self) {
+        check_ops(self.degree / 2, self.workload());
+    }
+}
+
+// Returns the depth of the subtree after checking key order and fill.
+pub(crate) fn check_node<T: Ord>(node: &Node<T>, props: &BTreeProps, is_root: bool) -> usize {
+    assert!(node.keys.len() <= props.max_keys);
+    assert!(is_root || node.keys.len() >= props.min_keys);
+    assert!(node.keys.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(node.len, node.keys.len() + node.children.iter().map(|child| child.len).sum::<usize>());
+    if node.is_leaf() {
+        return 1;
+    }
+    assert_eq!(node.children.len(), node.keys.len() + 1);
+    let depths: Vec<usize> = node.children.iter().map(|child| check_node(child, props, false)).collect();
+    assert!(depths.iter().all(|&depth| depth == depths[0]));
+    depths[0] + 1
+}
+
+// xorshift64*, enough to spread keys and operations about.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // zero would stay zero forever
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::panic::AssertUnwindSafe;
+
+    use super::{check_ops, check_ops_on, run_oracle_test, Op, StressTest};
+    use crate::{node_mut, BTree};
+
+    #[test]
+    fn test_oracle() {
+        for seed in 0..8 {
+            run_oracle_test::<u32>(seed, 1500);
+        }
+        run_oracle_test::<i64>(99, 300);
+        check_ops(2, [Op::Insert(1), Op::Insert(1), Op::Delete(1), Op::Search(1), Op::Delete(1), Op::Delete(1)]);
+        let ops = (0..40).map(Op::Insert).chain((0..40).step_by(3).map(Op::Delete)).chain([Op::Insert(7)]);
+        let tree = check_ops(2, ops);
+        let mut expected: Vec<u32> = (0..40).filter(|key| key % 3 != 0).chain([7]).collect();
+        expected.sort();
+        assert!(tree.iter_snapshot().eq(expected));
+
+        // a tree with two keys of a leaf swapped is caught at the first step
+        let mut tree = BTree::new(3);
+        for key in 0..20u32 {
+            tree.insert(key);
+        }
+        let mut node = node_mut(&mut tree.root);
+        while !node.is_leaf() {
+            node = node_mut(&mut node.children[0]);
+        }
+        node.keys.swap(0, 1);
+        let panic = std::panic::catch_unwind(AssertUnwindSafe(|| _ = check_ops_on(tree, [Op::Search(100)]))).unwrap_err();
+        assert!(panic.downcast_ref::<&str>().unwrap().contains("pair[0] <= pair[1]"));
+    }
+
+    #[test]
+    fn test_stress() {
+        let test = StressTest::new(3).ops(3000).degree(4);
+        assert_eq!(test.workload(), StressTest::new(3).ops(3000).workload());
+        assert_ne!(test.workload(), StressTest::new(4).ops(3000).workload());
+        test.run();
+        StressTest::new(5).degree(8).run();
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_fuzz_one() {
+        let mut data = Vec::new();
+        let mut state = 1u32;
+        for _ in 0..4096 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.push((state >> 16) as u8);
+        }
+        for start in 0..16 {
+            // the keys left are those of the decoded operations, replayed
+            let input = &data[start * 100..];
+            let tree = super::fuzz_one(input);
+            let mut input = arbitrary::Unstructured::new(input);
+            let branch_factor: usize = input.int_in_range(2..=6).unwrap();
+            let mut keys: Vec<u8> = Vec::new();
+            for op in input.arbitrary::<Vec<Op<u8>>>().unwrap() {
+                match op {
+                    Op::Insert(key) => keys.insert(keys.partition_point(|&k| k <= key), key),
+                    Op::Delete(key) => keys.retain({
+                        let mut first = true;
+                        move |&k| !(k == key && core::mem::take(&mut first))
+                    }),
+                    Op::Search(_) => {}
+                }
+            }
+            assert_eq!(tree.degree(), 2 * branch_factor);
+            assert!(tree.iter_snapshot().eq(keys));
+        }
+        assert_eq!(super::fuzz_one(&[]).len(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use crate::{node_mut, BTree, Node};
+
+// Entries compare by key alone, so a tombstone sits exactly where its key
+// did and flipping `dead` never moves it.
+#[derive(Clone, Copy, Debug, Default)]
+struct Entry<T> {
+    key: T,
+    dead: bool,
+}
+
+impl<T: Ord> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T: Ord> Eq for Entry<T> {}
+
+impl<T: Ord> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+/// A `BTree` whose deletes only mark keys as dead.
+///
+/// A delete finds the key and flips a flag in place: no keys move and no
+/// nodes are merged, which keeps bursts of deletes cheap. Dead keys still
+/// take space and are skipped by lookups until `compact` rebuilds the tree
+/// from the live ones; inserting a key that has a tombstone revives it.
+pub struct TombstoneBTree<T> {
+    tree: BTree<Entry<T>>,
+    live: usize,
+    dead: usize,
+}
+
+impl<T> TombstoneBTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    pub fn new(branch_factor: usize) -> Self {
+        TombstoneBTree {
+            tree: BTree::new(branch_factor),
+            live: 0,
+            dead: 0,
+        }
+    }
+
+    /// Live keys.
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// Dead keys waiting for `compact`.
+    pub fn tombstones(&self) -> usize {
+        self.dead
+    }
+
+    pub fn insert(&mut self, key: T) {
+        if self.set_dead(key, true, false) {
+            self.dead -= 1;
+        } else {
+            self.tree.insert(Entry { key, dead: false });
+        }
+        self.live += 1;
+    }
+
+    pub fn search(&self, key: T) -> bool {
+        let mut path = Vec::new();
+        find(&self.tree.root, key, false, &mut path).is_some()
+    }
+
+    pub fn delete(&mut self, key: T) -> bool {
+        if !self.set_dead(key, false, true) {
+            return false;
+        }
+        self.live -= 1;
+        self.dead += 1;
+        true
+    }
+
+    /// Drop every tombstone by rebuilding the tree from the live keys, which
+    /// also leaves its nodes evenly filled.
+    pub fn compact(&mut self) {
+        let live: Vec<Entry<T>> = self.tree.iter_snapshot().filter(|entry| !entry.dead).collect();
+        self.tree = BTree::new(self.tree.props.degree / 2);
+        self.tree.insert_batch(live);
+        self.dead = 0;
+    }
+
+    // Flip the first entry for `key` whose flag is `from` to `to`. Only the
+    // nodes on the path to it are written (and copied if shared).
+    fn set_dead(&mut self, key: T, from: bool, to: bool) -> bool {
+        let mut path = Vec::new();
+        let Some(index) = find(&self.tree.root, key, from, &mut path) else { return false };
+        let mut node = node_mut(&mut self.tree.root);
+        for child in path {
+            node = node_mut(&mut node.children[child]);
+        }
+        node.keys[index].dead = to;
+        true
+    }
+}
+
+// Find an entry for `key` with the given flag, recording the child indexes
+// on the way to it. Equal keys can span several children, all of which are
+// tried in order.
+fn find<T: Ord + Copy>(node: &Node<Entry<T>>, key: T, dead: bool, path: &mut Vec<usize>) -> Option<usize> {
+    let first = node.keys.partition_point(|entry| entry.key < key);
+    let last = node.keys.partition_point(|entry| entry.key <= key);
+    for index in first..=last {
+        if !node.children.is_empty() {
+            path.push(index);
+            if let Some(found) = find(&node.children[index], key, dead, path) {
+                return Some(found);
+            }
+            path.pop();
+        }
+        if index < last && node.keys[index].dead == dead {
+            return Some(index);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::TombstoneBTree;
+
+    #[test]
+    fn test_tombstones_and_compact() {
+        let mut tree = TombstoneBTree::new(2);
+        for key in 0..300 {
+            tree.insert(key);
+        }
+        for key in (0..300).step_by(3) {
+            assert!(tree.delete(key));
+        }
+        assert!(!tree.delete(0));
+        assert_eq!((tree.len(), tree.tombstones()), (200, 100));
+        tree.insert(3);
+        assert!(tree.search(3));
+        assert_eq!(tree.tombstones(), 99);
+
+        tree.compact();
+        assert_eq!((tree.len(), tree.tombstones()), (201, 0));
+        for key in 0..300 {
+            assert_eq!(tree.search(key), key % 3 != 0 || key == 3);
+        }
+    }
+}
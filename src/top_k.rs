@@ -0,0 +1,107 @@
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::BTree;
+
+// How full `keep_largest` and `keep_smallest` leave the nodes when they
+// rebuild, with room for the inserts a leaderboard keeps getting.
+const KEEP_FILL: f64 = 0.7;
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Remove all but the `k` largest keys.
+    pub fn keep_largest(&mut self, k: usize) {
+        let len = self.len();
+        if len > k {
+            self.keep(len - k, k);
+        }
+    }
+
+    /// Remove all but the `k` smallest keys.
+    pub fn keep_smallest(&mut self, k: usize) {
+        if self.len() > k {
+            self.keep(0, k);
+        }
+    }
+
+    /// Insert `key`, then if that leaves more than `k` keys remove the
+    /// smallest and return it, which may be `key` itself: keeps the top `k`
+    /// of a stream of keys.
+    pub fn push_bounded(&mut self, key: T, k: usize) -> Option<T> {
+        self.ctx.begin_nested(&self.root);
+        self.insert(key);
+        let evicted = (self.len() > k).then(|| *self.nth(0).unwrap());
+        if let Some(smallest) = evicted {
+            self.delete(smallest);
+        }
+        self.ctx.end_nested();
+        evicted
+    }
+
+    // Keep the `k` keys from position `offset` on and remove the others.
+    // Few to remove are deleted one by one; otherwise the keys to keep are
+    // read out by rank and rebuilt into a new tree, which costs nothing for
+    // the keys removed beyond telling any hooks about them.
+    fn keep(&mut self, offset: usize, k: usize) {
+        self.ctx.begin_nested(&self.root);
+        let len = self.len();
+        let removed = || self.page(0, offset).into_iter().chain(self.page(offset + k, len - offset - k));
+        if len - k <= k {
+            for key in removed().collect::<Vec<T>>() {
+                self.delete(key);
+            }
+        } else {
+            if self.ctx.removing() {
+                for key in removed().collect::<Vec<T>>() {
+                    self.ctx.removed(&key);
+                }
+            }
+            // in case nothing heard of the keys removed
+            self.ctx.changed();
+            let kept = self.page(offset, k);
+            self.rebuild_from(kept, KEEP_FILL);
+            self.record_whole(|| format!("keep the {k} keys from position {offset}"));
+        }
+        self.ctx.end_nested();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::test::check_node;
+    use crate::BTree;
+
+    #[test]
+    fn test_keep_and_push_bounded() {
+        for k in [0, 10, 700, 999, 2000] {
+            let mut largest = BTree::new(2);
+            let mut smallest = BTree::new(3);
+            for key in 0..1000u32 {
+                largest.insert((key * 7919) % 1000);
+                smallest.insert((key * 7919) % 1000);
+            }
+            let removed = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&removed);
+            largest.on_remove(move |_| _ = counter.fetch_add(1, Ordering::Relaxed));
+            largest.keep_largest(k);
+            smallest.keep_smallest(k);
+            check_node(&largest.root, &largest.props, true);
+            check_node(&smallest.root, &smallest.props, true);
+            let k = k.min(1000) as u32;
+            assert!(largest.iter_snapshot().eq(1000 - k..1000), "{k}");
+            assert!(smallest.iter_snapshot().eq(0..k), "{k}");
+            assert_eq!(removed.load(Ordering::Relaxed), 1000 - k as usize);
+        }
+
+        let mut top = BTree::new(2);
+        let evicted: Vec<Option<u32>> = [5, 1, 9, 7, 3, 9].into_iter().map(|key| top.push_bounded(key, 3)).collect();
+        assert_eq!(evicted, [None, None, None, Some(1), Some(3), Some(5)]);
+        assert!(top.iter_snapshot().eq([7, 9, 9]));
+    }
+}
@@ -0,0 +1,103 @@
+//! Structural events (splits, merges, borrows, the root growing or
+//! shrinking) sent to `tracing` at debug level with the `tracing` feature.
+//!
+//! Nodes are identified by address. A node copied because a snapshot still
+//! shares it shows up under a new id after the copy.
+
+#[cfg(feature = "tracing")]
+use core::fmt::Debug;
+
+#[cfg(feature = "tracing")]
+use crate::allocator::Allocator;
+#[cfg(feature = "tracing")]
+use crate::{Aggregate, Node};
+
+// `event!("split", field = value, ...)` with `tracing`'s field syntax. The
+// arguments aren't even evaluated without the feature.
+macro_rules! event {
+    ($message:literal, $($fields:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message = $message, $($fields)*);
+    };
+}
+pub(crate) use event;
+
+#[cfg(feature = "tracing")]
+pub(crate) fn node_id<T, A: Aggregate<T>, M: Allocator + Clone>(node: &Node<T, A, M>) -> usize {
+    node as *const Node<T, A, M> as usize
+}
+
+// The smallest and largest keys of a node, for event fields.
+#[cfg(feature = "tracing")]
+pub(crate) fn key_range<T: Debug, A: Aggregate<T>, M: Allocator + Clone>(node: &Node<T, A, M>) -> Option<(&T, &T)> {
+    Some((node.keys.first()?, node.keys.last()?))
+}
+
+#[cfg(all(test, feature = "tracing", feature = "std"))]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::BTree;
+
+    // Collects the messages of every event.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Message<'a>(&'a mut String);
+
+    impl Visit for Message<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                *self.0 = value.to_string();
+            }
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "message" {
+                *self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.0.lock().unwrap().push(message);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_structural_events() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut tree = BTree::new(2);
+            for key in 0..20 {
+                tree.insert(key);
+            }
+            for key in 0..20 {
+                tree.delete(key);
+            }
+        });
+        let events = recorder.0.lock().unwrap();
+        for message in ["split", "root grew", "merge", "borrow from right", "root shrank"] {
+            assert!(events.iter().any(|event| event == message), "no {message} event");
+        }
+        assert_eq!(events.iter().filter(|event| *event == "root grew").count(), 3);
+    }
+}
@@ -0,0 +1,448 @@
+//! Key–value separation for disk trees, after WiscKey: a `DiskMap` keeps
+//! values over a size threshold in an append-only value log beside the tree
+//! file and stores only a pointer to each in the tree, so large values
+//! don't crowd the keys out of its pages and cut its fan-out.
+//!
+//! The log is a run of files `<path>-values-<generation>`, each a sequence
+//! of records: the key's and the value's length, 4 bytes little endian
+//! each, then the key and the value. Values that are overwritten or removed
+//! stay in the log until `collect_garbage` copies the live ones into a new
+//! generation and deletes the old files.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::codec::KeyCodec;
+use crate::disk::{DiskBTree, DiskOptions};
+use crate::snapshot::invalid_data;
+
+// tag (1) + generation (4) + offset (8) + length (4)
+const POINTER_LEN: usize = 17;
+// the end of the escaped key (2) + tag (1)
+const ENTRY_OVERHEAD: usize = 3;
+
+const TAG_INLINE: u8 = 0;
+const TAG_LOGGED: u8 = 1;
+
+/// Settings for `DiskMap::open`.
+pub struct DiskMapOptions {
+    /// Options for the tree file. Its keys are the map's keys stored with
+    /// their values or with pointers into the log, so its branch factor
+    /// bounds how long keys can be; see `DiskOptions::branch_factor`.
+    pub tree: DiskOptions,
+    /// Values longer than this go to the value log, as do shorter ones that
+    /// would make an entry too long for the tree.
+    pub value_threshold: usize,
+}
+
+impl Default for DiskMapOptions {
+    /// A branch factor of 16, which leaves 120 bytes per entry, and values
+    /// over 32 bytes in the log.
+    fn default() -> Self {
+        DiskMapOptions {
+            tree: DiskOptions {
+                branch_factor: 16,
+                ..DiskOptions::default()
+            },
+            value_threshold: 32,
+        }
+    }
+}
+
+// A key and where its value is. Entries compare by key alone, so looking
+// one up takes an entry with any value.
+struct Entry {
+    key: Vec<u8>,
+    value: Value,
+}
+
+enum Value {
+    Inline(Vec<u8>),
+    Logged(Pointer),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Pointer {
+    generation: u32,
+    // Of the value itself, past its record's lengths and key.
+    offset: u64,
+    len: u32,
+}
+
+impl Entry {
+    fn probe(key: &[u8]) -> Self {
+        Entry { key: key.to_vec(), value: Value::Inline(Vec::new()) }
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+struct EntryCodec;
+
+impl KeyCodec<Entry> for EntryCodec {
+    const ID: u8 = 23;
+    const FIXED_WIDTH: Option<usize> = None;
+
+    // The key with each zero byte escaped as 0x00 0xff and ended by
+    // 0x00 0x00, which keeps a key ordered before its extensions, then the
+    // value or the pointer to it.
+    fn encode(entry: &Entry, out: &mut Vec<u8>) {
+        for &byte in &entry.key {
+            out.push(byte);
+            if byte == 0 {
+                out.push(0xff);
+            }
+        }
+        out.extend_from_slice(&[0, 0]);
+        match &entry.value {
+            Value::Inline(value) => {
+                out.push(TAG_INLINE);
+                out.extend_from_slice(value);
+            }
+            Value::Logged(pointer) => {
+                out.push(TAG_LOGGED);
+                out.extend_from_slice(&pointer.generation.to_be_bytes());
+                out.extend_from_slice(&pointer.offset.to_be_bytes());
+                out.extend_from_slice(&pointer.len.to_be_bytes());
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Entry> {
+        let mut key = Vec::new();
+        let mut at = 0;
+        loop {
+            let byte = *bytes.get(at)?;
+            if byte == 0 {
+                match *bytes.get(at + 1)? {
+                    0 => break,
+                    0xff => at += 1,
+                    _ => return None,
+                }
+            }
+            key.push(byte);
+            at += 1;
+        }
+        let (&tag, rest) = bytes[at + 2..].split_first()?;
+        let value = match tag {
+            TAG_INLINE => Value::Inline(rest.to_vec()),
+            TAG_LOGGED if rest.len() == POINTER_LEN - 1 => Value::Logged(Pointer {
+                generation: u32::from_be_bytes(rest[..4].try_into().unwrap()),
+                offset: u64::from_be_bytes(rest[4..12].try_into().unwrap()),
+                len: u32::from_be_bytes(rest[12..].try_into().unwrap()),
+            }),
+            _ => return None,
+        };
+        Some(Entry { key, value })
+    }
+}
+
+/// A map from byte strings to byte strings on disk, with large values kept
+/// out of the tree. See the module docs.
+pub struct DiskMap {
+    tree: DiskBTree<Entry, EntryCodec>,
+    path: PathBuf,
+    // Every generation of the log; values are appended to the last.
+    logs: BTreeMap<u32, File>,
+    // Bytes in the last generation.
+    log_len: u64,
+    value_threshold: usize,
+    // With a WAL the tree is durable after every operation, so each value
+    // is synced before the tree points at it.
+    sync: bool,
+}
+
+impl DiskMap {
+    /// Open the tree and every generation of its log, cutting off a record
+    /// left part written at the end of the newest one.
+    pub fn open<P: AsRef<Path>>(path: P, options: DiskMapOptions) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let sync = options.tree.wal;
+        let tree = DiskBTree::open(&path, options.tree)?;
+
+        let mut logs = BTreeMap::new();
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let prefix = log_path(&path, "");
+        let prefix = prefix.file_name().unwrap().to_string_lossy();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let generation = name.to_str().and_then(|name| name.strip_prefix(&*prefix)?.parse::<u32>().ok());
+            if let Some(generation) = generation {
+                logs.insert(generation, open_log(&log_path(&path, generation))?);
+            }
+        }
+        if logs.is_empty() {
+            logs.insert(0, open_log(&log_path(&path, 0))?);
+        }
+        // a crash while appending can leave the last record cut short, and
+        // new ones go after the whole records
+        let newest = logs.values().next_back().unwrap();
+        let log_len = whole_records_len(newest)?;
+        if log_len < newest.metadata()?.len() {
+            newest.set_len(log_len)?;
+        }
+        Ok(DiskMap {
+            tree,
+            path,
+            logs,
+            log_len,
+            value_threshold: options.value_threshold,
+            sync,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        match self.tree.get(Entry::probe(key))? {
+            None => Ok(None),
+            Some(Entry { value: Value::Inline(value), .. }) => Ok(Some(value)),
+            Some(Entry { value: Value::Logged(pointer), .. }) => self.read(pointer).map(Some),
+        }
+    }
+
+    /// Set `key` to `value`. Replacing a value deletes the old entry and
+    /// inserts the new one, each durable on its own with a WAL.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let key_len = key.len() + key.iter().filter(|&&byte| byte == 0).count() + ENTRY_OVERHEAD;
+        if key_len - 1 + POINTER_LEN > self.tree.max_key_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("key is {} bytes, too long for this tree's entries", key.len()),
+            ));
+        }
+        let value = if value.len() > self.value_threshold || key_len + value.len() > self.tree.max_key_len() {
+            Value::Logged(self.append(key, value)?)
+        } else {
+            Value::Inline(value.to_vec())
+        };
+        self.tree.delete(Entry::probe(key))?;
+        self.tree.insert(Entry { key: key.to_vec(), value })
+    }
+
+    /// Remove `key`, returning whether it was there. Its value stays in the
+    /// log until the next `collect_garbage`.
+    pub fn remove(&mut self, key: &[u8]) -> io::Result<bool> {
+        self.tree.delete(Entry::probe(key))
+    }
+
+    /// Write the log and then the tree to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.logs.values().next_back().unwrap().sync_data()?;
+        self.tree.flush()
+    }
+
+    /// Bytes in all generations of the value log, live values or not.
+    pub fn log_bytes(&self) -> io::Result<u64> {
+        self.logs.values().map(|file| Ok(file.metadata()?.len())).sum()
+    }
+
+    /// Copy the values the tree still points at into a new generation of
+    /// the log, then delete the older ones. Returns the bytes freed.
+    ///
+    /// The tree is flushed, and checked to point only into the new
+    /// generation, before any file is deleted, so a crash part way through
+    /// leaves every value reachable, at worst with some stored twice until
+    /// the next run.
+    pub fn collect_garbage(&mut self) -> io::Result<u64> {
+        let before = self.log_bytes()?;
+        let old: Vec<u32> = self.logs.keys().copied().collect();
+        let generation = old.last().map_or(0, |last| last + 1);
+        self.logs.insert(generation, open_log(&log_path(&self.path, generation))?);
+        self.log_len = 0;
+
+        let mut logged = Vec::new();
+        self.tree.for_each(|entry| {
+            if let Value::Logged(pointer) = entry.value {
+                logged.push((entry.key, pointer));
+            }
+            Ok(())
+        })?;
+        for (key, pointer) in logged {
+            let value = self.read(pointer)?;
+            let moved = self.append(&key, &value)?;
+            self.tree.delete(Entry::probe(&key))?;
+            self.tree.insert(Entry { key, value: Value::Logged(moved) })?;
+        }
+
+        self.flush()?;
+        let mut in_use = false;
+        self.tree.for_each(|entry| {
+            in_use |= matches!(entry.value, Value::Logged(pointer) if pointer.generation != generation);
+            Ok(())
+        })?;
+        if in_use {
+            return Err(invalid_data("an old value log generation is still pointed at"));
+        }
+        for old_generation in old {
+            self.logs.remove(&old_generation);
+            fs::remove_file(log_path(&self.path, old_generation))?;
+        }
+        Ok(before.saturating_sub(self.log_len))
+    }
+
+    fn append(&mut self, key: &[u8], value: &[u8]) -> io::Result<Pointer> {
+        let (&generation, file) = self.logs.iter_mut().next_back().unwrap();
+        let mut record = Vec::with_capacity(8 + key.len() + value.len());
+        record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        file.write_all(&record)?;
+        if self.sync {
+            file.sync_data()?;
+        }
+        let pointer = Pointer {
+            generation,
+            offset: self.log_len + 8 + key.len() as u64,
+            len: value.len() as u32,
+        };
+        self.log_len += record.len() as u64;
+        Ok(pointer)
+    }
+
+    fn read(&self, pointer: Pointer) -> io::Result<Vec<u8>> {
+        let mut file = self
+            .logs
+            .get(&pointer.generation)
+            .ok_or_else(|| invalid_data("value log generation is missing"))?;
+        file.seek(SeekFrom::Start(pointer.offset))?;
+        let mut value = vec![0; pointer.len as usize];
+        file.read_exact(&mut value)?;
+        Ok(value)
+    }
+}
+
+fn log_path(path: &Path, generation: impl std::fmt::Display) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!("-values-{generation}"));
+    PathBuf::from(name)
+}
+
+fn open_log(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).append(true).create(true).open(path)
+}
+
+// Bytes in the run of whole records at the start of `file`. Each record's
+// lengths are checked against what is left of the file, so one cut short
+// ends the run.
+fn whole_records_len(file: &File) -> io::Result<u64> {
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(0))?;
+    let mut len = 0;
+    let mut lens = [0; 8];
+    while file_len - len >= 8 {
+        reader.read_exact(&mut lens)?;
+        let body = u64::from(u32::from_le_bytes(lens[..4].try_into().unwrap())) + u64::from(u32::from_le_bytes(lens[4..].try_into().unwrap()));
+        if body > file_len - len - 8 {
+            break;
+        }
+        reader.seek_relative(body as i64)?;
+        len += 8 + body;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::{log_path, DiskMap, DiskMapOptions};
+
+    #[test]
+    fn test_large_values_in_log() {
+        let path = std::env::temp_dir().join(format!("disk-map-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let value = |key: u32, round: u32| match key % 2 {
+            0 => vec![(key + round) as u8; 1000],
+            _ => vec![(key + round) as u8; 4],
+        };
+        {
+            let mut map = DiskMap::open(&path, DiskMapOptions::default()).unwrap();
+            for round in 0..3 {
+                for key in 0..200u32 {
+                    map.insert(&key.to_be_bytes(), &value(key, round)).unwrap();
+                }
+            }
+            for key in 100..200u32 {
+                assert!(map.remove(&key.to_be_bytes()).unwrap());
+            }
+            assert_eq!(map.len(), 100);
+            // three rounds of 100 large values, of which 50 are live
+            assert!(map.log_bytes().unwrap() > 300 * 500);
+            let freed = map.collect_garbage().unwrap();
+            assert!(freed > 250 * 1000, "{freed}");
+            assert_eq!(map.log_bytes().unwrap(), 50 * (8 + 4 + 1000));
+            assert_eq!(map.get(&7u32.to_be_bytes()).unwrap(), Some(value(7, 2)));
+            assert!(map.insert(&[0; 200], b"x").is_err());
+        }
+        let mut map = DiskMap::open(&path, DiskMapOptions::default()).unwrap();
+        for key in 0..200u32 {
+            let expected = (key < 100).then(|| value(key, 2));
+            assert_eq!(map.get(&key.to_be_bytes()).unwrap(), expected, "{key}");
+        }
+        drop(map);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(log_path(&path, 1)).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let path = std::env::temp_dir().join(format!("disk-map-torn-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut map = DiskMap::open(&path, DiskMapOptions::default()).unwrap();
+            map.insert(b"first", &[1; 100]).unwrap();
+            map.flush().unwrap();
+        }
+        // a record whose header made it to disk but only 4 bytes of its value
+        let mut log = std::fs::OpenOptions::new().append(true).open(log_path(&path, 0)).unwrap();
+        log.write_all(&1u32.to_le_bytes()).unwrap();
+        log.write_all(&100u32.to_le_bytes()).unwrap();
+        log.write_all(b"x\0\0\0\0").unwrap();
+        drop(log);
+
+        let mut map = DiskMap::open(&path, DiskMapOptions::default()).unwrap();
+        assert_eq!(map.log_bytes().unwrap(), 8 + 5 + 100);
+        for key in 0..20u32 {
+            map.insert(&key.to_be_bytes(), &[key as u8; 200]).unwrap();
+        }
+        map.collect_garbage().unwrap();
+        assert_eq!(map.get(b"first").unwrap(), Some(vec![1; 100]));
+        for key in 0..20u32 {
+            assert_eq!(map.get(&key.to_be_bytes()).unwrap(), Some(vec![key as u8; 200]), "{key}");
+        }
+        drop(map);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(log_path(&path, 1)).unwrap();
+    }
+}
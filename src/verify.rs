@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::disk;
+use crate::pager::{Pager, PAGE_SIZE};
+use crate::snapshot::{self, CHECKSUM_LEN, HEADER_LEN, TRAILER_LEN};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Snapshot,
+    Disk,
+}
+
+#[derive(Debug)]
+pub struct VerifyReport {
+    pub kind: FileKind,
+    /// Pages (disk files) or header, node and trailer records (snapshots) checked.
+    pub checked: u64,
+    /// Ids of corrupt pages, or byte offsets of corrupt snapshot records.
+    pub corrupt: Vec<u64>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// Check every checksum in a snapshot or disk tree file.
+///
+/// All pages of a disk file are checked. A snapshot is scanned up to its
+/// first corrupt record, since the lengths of the records after it can't be
+/// trusted.
+pub fn verify_file<P: AsRef<Path>>(path: P) -> io::Result<VerifyReport> {
+    let mut magic = [0; 4];
+    File::open(&path)?.read_exact(&mut magic)?;
+    if &magic == snapshot::MAGIC {
+        verify_snapshot(path)
+    } else if &magic == disk::MAGIC {
+        verify_disk(path)
+    } else {
+        Err(snapshot::invalid_data("not a b-tree snapshot or disk file"))
+    }
+}
+
+fn verify_disk<P: AsRef<Path>>(path: P) -> io::Result<VerifyReport> {
+    let mut pager = Pager::open(path)?;
+    let mut report = VerifyReport {
+        kind: FileKind::Disk,
+        checked: 0,
+        corrupt: Vec::new(),
+    };
+    let mut page = [0; PAGE_SIZE];
+    for id in 0..pager.page_count() {
+        report.checked += 1;
+        match pager.read_page(id, &mut page) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => report.corrupt.push(id),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(report)
+}
+
+fn verify_snapshot<P: AsRef<Path>>(path: P) -> io::Result<VerifyReport> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut input = BufReader::new(file);
+    let mut report = VerifyReport {
+        kind: FileKind::Snapshot,
+        checked: 1,
+        corrupt: Vec::new(),
+    };
+
+    let mut header = [0; HEADER_LEN];
+    if input.read_exact(&mut header).and_then(|_| snapshot::check_header_bytes(&header)).is_err() {
+        report.corrupt.push(0);
+        return Ok(report);
+    }
+
+    let nodes_end = file_len.saturating_sub(TRAILER_LEN as u64);
+    let mut offset = HEADER_LEN as u64;
+    let mut record = Vec::new();
+    while offset < nodes_end {
+        report.checked += 1;
+        if snapshot::read_record(&mut input, &mut record, nodes_end - offset).is_err() {
+            report.corrupt.push(offset);
+            return Ok(report);
+        }
+        offset += (record.len() + CHECKSUM_LEN) as u64;
+    }
+
+    report.checked += 1;
+    let mut trailer = [0; TRAILER_LEN];
+    let trailer_ok = offset == nodes_end
+        && input.read_exact(&mut trailer).is_ok()
+        && snapshot::check_trailer(&trailer).is_ok();
+    if !trailer_ok {
+        report.corrupt.push(offset);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify_file, FileKind};
+    use crate::pager::PAGE_SIZE;
+    use crate::{BTree, DiskBTree, DiskOptions};
+
+    #[test]
+    fn test_reports_corrupt_pages() {
+        let dir = std::env::temp_dir();
+        let disk_path = dir.join(format!("verify-disk-{}.db", std::process::id()));
+        let snapshot_path = dir.join(format!("verify-snapshot-{}.btsn", std::process::id()));
+        let _ = std::fs::remove_file(&disk_path);
+
+        let mut disk: DiskBTree = DiskBTree::open(&disk_path, DiskOptions { branch_factor: 2, ..DiskOptions::default() }).unwrap();
+        for key in 0..100u64 {
+            disk.insert(key).unwrap();
+        }
+        drop(disk);
+        let report = verify_file(&disk_path).unwrap();
+        assert_eq!(report.kind, FileKind::Disk);
+        assert!(report.is_ok());
+
+        let mut bytes = std::fs::read(&disk_path).unwrap();
+        bytes[3 * PAGE_SIZE + 10] ^= 0xFF;
+        bytes[5 * PAGE_SIZE + 20] ^= 0xFF;
+        std::fs::write(&disk_path, &bytes).unwrap();
+        assert_eq!(verify_file(&disk_path).unwrap().corrupt, vec![3, 5]);
+
+        let mut tree = BTree::new(2);
+        for key in 0..100u16 {
+            tree.insert(key);
+        }
+        tree.save_to(&snapshot_path).unwrap();
+        let report = verify_file(&snapshot_path).unwrap();
+        assert_eq!(report.kind, FileKind::Snapshot);
+        assert!(report.is_ok());
+
+        let mut bytes = std::fs::read(&snapshot_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&snapshot_path, &bytes).unwrap();
+        assert_eq!(verify_file(&snapshot_path).unwrap().corrupt.len(), 1);
+
+        std::fs::remove_file(&disk_path).unwrap();
+        std::fs::remove_file(&snapshot_path).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::codec::{KeyCodec, Ordered};
+use crate::snapshot::{self, NodeRecord, HEADER_LEN, TRAILER_LEN};
+
+/// Read-only tree answering queries straight from a memory-mapped snapshot
+/// (see `BTree::save_to`).
+///
+/// Opening only checks the header and trailer, so it takes the same time for
+/// any file size. Nodes are never turned into `Node` structs: each lookup
+/// decodes just the keys it compares, and verifies the checksum of every
+/// node record it visits. `C` must be the codec the snapshot was saved with.
+pub struct BTreeView<T, C = Ordered> {
+    map: Mmap,
+    root: u64,
+    len: u64,
+    _keys: PhantomData<(T, C)>,
+}
+
+// Index of the first key of `node` for which `goes_left` is false.
+fn partition_point<T, C: KeyCodec<T>>(node: &NodeRecord<'_>, goes_left: impl Fn(&T) -> bool) -> io::Result<usize> {
+    let (mut low, mut high) = (0, node.count);
+    while low < high {
+        let mid = (low + high) / 2;
+        if goes_left(&key_at::<T, C>(node, mid)?) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
+fn key_at<T, C: KeyCodec<T>>(node: &NodeRecord<'_>, index: usize) -> io::Result<T> {
+    C::decode(node.key(index)).ok_or_else(|| snapshot::invalid_data("node holds a key its codec can't decode"))
+}
+
+impl<T, C> BTreeView<T, C>
+where
+    T: Ord + Clone,
+    C: KeyCodec<T>,
+{
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read, and every read is bounds checked
+        // against its length. Like any mmap, it assumes the file isn't
+        // truncated by another process while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN + TRAILER_LEN {
+            return Err(snapshot::invalid_data("snapshot is truncated"));
+        }
+        snapshot::check_header::<T, C>(&map[..HEADER_LEN])?;
+        let (_, len, root) = snapshot::check_trailer(&map[map.len() - TRAILER_LEN..])?;
+        Ok(BTreeView {
+            map,
+            root,
+            len,
+            _keys: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn search(&self, key: T) -> io::Result<bool> {
+        let mut node = self.node(self.root)?;
+        loop {
+            let index = partition_point::<T, C>(&node, |k| *k < key)?;
+            if index < node.count && key_at::<T, C>(&node, index)? == key {
+                return Ok(true);
+            }
+            if node.is_leaf {
+                return Ok(false);
+            }
+            node = self.node(node.child(index))?;
+        }
+    }
+
+    /// Keys within `range` in ascending order. A corrupt node ends the
+    /// iteration with an error.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, C> {
+        let mut iter = Range {
+            view: self,
+            stack: Vec::new(),
+            end: range.end_bound().cloned(),
+            failed: None,
+        };
+        let start = range.start_bound().cloned();
+        let mut offset = self.root;
+        loop {
+            let node = match self.node(offset) {
+                Ok(node) => node,
+                Err(e) => {
+                    iter.failed = Some(e);
+                    break;
+                }
+            };
+            let index = match start {
+                Bound::Included(ref lo) => partition_point::<T, C>(&node, |k| k < lo),
+                Bound::Excluded(ref lo) => partition_point::<T, C>(&node, |k| k <= lo),
+                Bound::Unbounded => Ok(0),
+            };
+            let index = match index {
+                Ok(index) => index,
+                Err(e) => {
+                    iter.failed = Some(e);
+                    break;
+                }
+            };
+            iter.stack.push((node, index));
+            if node.is_leaf {
+                break;
+            }
+            offset = node.child(index);
+        }
+        iter
+    }
+
+    fn node(&self, offset: u64) -> io::Result<NodeRecord<'_>> {
+        snapshot::record_at(&self.map, offset)
+    }
+}
+
+pub struct Range<'a, T, C = Ordered> {
+    view: &'a BTreeView<T, C>,
+    // Path to the next key: each node with the index of its next key.
+    stack: Vec<(NodeRecord<'a>, usize)>,
+    end: Bound<T>,
+    failed: Option<io::Error>,
+}
+
+impl<T, C> Iterator for Range<'_, T, C>
+where
+    T: Ord + Clone,
+    C: KeyCodec<T>,
+{
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.failed.take() {
+            self.stack.clear();
+            return Some(Err(e));
+        }
+        loop {
+            let (node, index) = self.stack.last_mut()?;
+            if *index == node.count {
+                self.stack.pop();
+                continue;
+            }
+            let node = *node;
+            let key = match key_at::<T, C>(&node, *index) {
+                Ok(key) => key,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            };
+            *index += 1;
+            let past_end = match self.end {
+                Bound::Included(ref hi) => key > *hi,
+                Bound::Excluded(ref hi) => key >= *hi,
+                Bound::Unbounded => false,
+            };
+            if past_end {
+                self.stack.clear();
+                return None;
+            }
+            if !node.is_leaf {
+                // The next key after this one is the leftmost of the right child.
+                let mut offset = node.child(*index);
+                loop {
+                    match self.view.node(offset) {
+                        Ok(child) => {
+                            self.stack.push((child, 0));
+                            if child.is_leaf {
+                                break;
+                            }
+                            offset = child.child(0);
+                        }
+                        Err(e) => {
+                            self.failed = Some(e);
+                            break;
+                        }
+                    }
+                }
+            }
+            return Some(Ok(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BTreeView;
+    use crate::crc32::crc32;
+    use crate::snapshot::TRAILER_LEN;
+    use crate::BTree;
+
+    #[test]
+    fn test_search_and_range() {
+        let path = std::env::temp_dir().join(format!("view-{}.btsn", std::process::id()));
+        let mut tree = BTree::new(2);
+        for key in (0..2000i64).rev() {
+            tree.insert(key * 2);
+        }
+        tree.save_to(&path).unwrap();
+
+        let view: BTreeView<i64> = BTreeView::open(&path).unwrap();
+        assert_eq!(view.len(), 2000);
+        assert!(view.search(1000).unwrap());
+        assert!(!view.search(1001).unwrap());
+        let keys: Vec<i64> = view.range(11..=21).map(Result::unwrap).collect();
+        assert_eq!(keys, vec![12, 14, 16, 18, 20]);
+        assert_eq!(view.range(..).count(), 2000);
+        assert_eq!(view.range(3990..).map(Result::unwrap).collect::<Vec<_>>(), vec![3990, 3992, 3994, 3996, 3998]);
+        assert!(BTreeView::<u64>::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_child_cycle() {
+        let path = std::env::temp_dir().join(format!("view-cycle-{}.btsn", std::process::id()));
+        let mut tree = BTree::new(2);
+        for key in 0..20i64 {
+            tree.insert(key);
+        }
+        tree.save_to(&path).unwrap();
+
+        // point the root's first child back at the root, checksum fixed up
+        let mut bytes = std::fs::read(&path).unwrap();
+        let trailer = bytes.len() - TRAILER_LEN;
+        let root = u64::from_le_bytes(bytes[trailer + 16..trailer + 24].try_into().unwrap());
+        let start = root as usize;
+        let count = u32::from_le_bytes(bytes[start + 1..start + 5].try_into().unwrap()) as usize;
+        let ends = start + 5;
+        let keys_len = u32::from_le_bytes(bytes[ends + 4 * (count - 1)..ends + 4 * count].try_into().unwrap()) as usize;
+        let children = ends + 4 * count + keys_len;
+        bytes[children..children + 8].copy_from_slice(&root.to_le_bytes());
+        let end = children + 8 * (count + 1);
+        let checksum = crc32(&bytes[start..end]);
+        bytes[end..end + 4].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        let view: BTreeView<i64> = BTreeView::open(&path).unwrap();
+        let error = view.search(-1).unwrap_err();
+        assert_eq!(error.to_string(), "node points at a child that isn't before it");
+        assert!(view.range(..).next().unwrap().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
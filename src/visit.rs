@@ -0,0 +1,121 @@
+//! Walking a tree with a `TreeVisitor`, for analyses that want to see each
+//! node as well as the keys: statistics, exporters, validators.
+
+use core::fmt::Debug;
+
+use crate::{BTree, Node};
+
+/// When `BTree::walk` visits a node's keys relative to its children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// All of a node's keys before any of its children.
+    PreOrder,
+    /// Each key between the children on either side of it, which visits
+    /// every key in sorted order.
+    InOrder,
+    /// All of a node's keys after all of its children.
+    PostOrder,
+}
+
+/// A node as the visitor sees it.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeInfo<'a, T> {
+    pub keys: &'a [T],
+    /// The root is at depth 0.
+    pub depth: usize,
+    /// Zero for a leaf, otherwise one more than `keys.len()`.
+    pub children: usize,
+}
+
+/// Callbacks for `BTree::walk`. Each does nothing unless overridden.
+pub trait TreeVisitor<T> {
+    /// Called before anything inside the node.
+    fn enter_node(&mut self, _node: NodeInfo<'_, T>) {}
+
+    fn visit_key(&mut self, _key: &T, _depth: usize) {}
+
+    /// Called after everything inside the node, children included.
+    fn leave_node(&mut self, _node: NodeInfo<'_, T>) {}
+}
+
+impl<T> BTree<T>
+where
+    T: Ord + Copy + Debug + Default,
+{
+    /// Walk the whole tree depth first, left to right, calling `visitor`
+    /// for every node and key.
+    pub fn walk<V: TreeVisitor<T>>(&self, order: Order, visitor: &mut V) {
+        walk_node(&self.root, 0, order, visitor);
+    }
+}
+
+fn walk_node<T: Copy, V: TreeVisitor<T>>(node: &Node<T>, depth: usize, order: Order, visitor: &mut V) {
+    let info = NodeInfo {
+        keys: &node.keys,
+        depth,
+        children: node.children.len(),
+    };
+    visitor.enter_node(info);
+    if order == Order::PreOrder {
+        for key in &node.keys {
+            visitor.visit_key(key, depth);
+        }
+    }
+    for (index, child) in node.children.iter().enumerate() {
+        walk_node(child, depth + 1, order, visitor);
+        if order == Order::InOrder && index < node.keys.len() {
+            visitor.visit_key(&node.keys[index], depth);
+        }
+    }
+    if order == Order::InOrder && node.children.is_empty() {
+        for key in &node.keys {
+            visitor.visit_key(key, depth);
+        }
+    }
+    if order == Order::PostOrder {
+        for key in &node.keys {
+            visitor.visit_key(key, depth);
+        }
+    }
+    visitor.leave_node(info);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NodeInfo, Order, TreeVisitor};
+    use crate::BTree;
+
+    // Writes "(" and ")" around nodes and the keys between them.
+    #[derive(Default)]
+    struct Outline(String);
+
+    impl TreeVisitor<i32> for Outline {
+        fn enter_node(&mut self, _node: NodeInfo<'_, i32>) {
+            self.0.push('(');
+        }
+
+        fn visit_key(&mut self, key: &i32, _depth: usize) {
+            self.0.push_str(&format!(" {key} "));
+        }
+
+        fn leave_node(&mut self, _node: NodeInfo<'_, i32>) {
+            self.0.push(')');
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut tree = BTree::new(2);
+        for key in [10, 20, 30, 40, 50, 60] {
+            tree.insert(key);
+        }
+        let outline = |order| {
+            let mut outline = Outline::default();
+            tree.walk(order, &mut outline);
+            outline.0
+        };
+        assert_eq!(outline(Order::PreOrder), "( 20  40 ( 10 )( 30 )( 50  60 ))");
+        assert_eq!(outline(Order::InOrder), "(( 10 ) 20 ( 30 ) 40 ( 50  60 ))");
+        assert_eq!(outline(Order::PostOrder), "(( 10 )( 30 )( 50  60 ) 20  40 )");
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::crc32::Crc32;
+use crate::pager::{Page, PageId, Pager, PAGE_SIZE};
+
+const RECORD_PAGE: u8 = 1;
+const RECORD_COMMIT: u8 = 2;
+
+// kind (1) + page id or page count (8)
+const RECORD_HEADER: usize = 9;
+const RECORD_CHECKSUM: usize = 4;
+
+/// Write-ahead log of full page images.
+///
+/// Pages changed by one tree operation are appended as a batch closed by a
+/// commit record, and the log is synced before any of those pages may reach
+/// the data file. On open, `recover` copies every committed batch into the
+/// data file; a batch without its commit record (a torn write) is dropped.
+pub struct Wal {
+    file: File,
+    len: u64,
+    pending: u64,
+}
+
+impl Wal {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(Wal {
+            file,
+            len,
+            pending: 0,
+        })
+    }
+
+    /// Size of the log in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn append_page(&mut self, id: PageId, page: &Page) -> io::Result<()> {
+        self.append(RECORD_PAGE, id, page)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Close the current batch and make it durable.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.append(RECORD_COMMIT, self.pending, &[])?;
+        self.pending = 0;
+        self.file.sync_data()
+    }
+
+    /// Replay committed batches into `pager`, then empty the log.
+    /// Returns the number of batches applied.
+    pub fn recover(&mut self, pager: &mut Pager) -> io::Result<usize> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&mut self.file);
+        let mut batch: Vec<(PageId, Box<Page>)> = Vec::new();
+        let mut committed = Vec::new();
+        let mut page = Box::new([0; PAGE_SIZE]);
+        loop {
+            let mut header = [0; RECORD_HEADER];
+            if reader.read_exact(&mut header).is_err() {
+                break;
+            }
+            let kind = header[0];
+            let value = u64::from_le_bytes(header[1..].try_into().unwrap());
+            let payload: &mut [u8] = match kind {
+                RECORD_PAGE => &mut page[..],
+                RECORD_COMMIT => &mut [],
+                _ => break,
+            };
+            let mut checksum = [0; RECORD_CHECKSUM];
+            if reader.read_exact(payload).is_err() || reader.read_exact(&mut checksum).is_err() {
+                break;
+            }
+            let mut crc = Crc32::new();
+            crc.update(&header);
+            crc.update(payload);
+            if crc.finish() != u32::from_le_bytes(checksum) {
+                break;
+            }
+            if kind == RECORD_PAGE {
+                batch.push((value, page.clone()));
+            } else if value == batch.len() as u64 {
+                committed.push(std::mem::take(&mut batch));
+            } else {
+                break;
+            }
+        }
+
+        for (id, page) in committed.iter().flatten() {
+            while pager.page_count() < *id {
+                pager.allocate()?;
+            }
+            pager.write_logged(*id, page)?;
+        }
+        if !committed.is_empty() {
+            pager.sync()?;
+        }
+        self.truncate()?;
+        Ok(committed.len())
+    }
+
+    /// Drop every record. Only safe once the data file holds all of them.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_data()?;
+        self.len = 0;
+        self.pending = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, value: u64, payload: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER + payload.len() + RECORD_CHECKSUM);
+        record.push(kind);
+        record.extend_from_slice(&value.to_le_bytes());
+        record.extend_from_slice(payload);
+        let mut crc = Crc32::new();
+        crc.update(&record);
+        record.extend_from_slice(&crc.finish().to_le_bytes());
+        self.file.write_all(&record)?;
+        self.len += record.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Wal;
+    use crate::pager::{Pager, PAGE_SIZE};
+    use std::io::Write;
+
+    #[test]
+    fn test_recover_drops_torn_batch() {
+        let dir = std::env::temp_dir();
+        let data_path = dir.join(format!("wal-data-{}.db", std::process::id()));
+        let wal_path = dir.join(format!("wal-log-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&data_path);
+        let _ = std::fs::remove_file(&wal_path);
+        {
+            let mut wal = Wal::open(&wal_path).unwrap();
+            wal.append_page(0, &[1; PAGE_SIZE]).unwrap();
+            wal.append_page(2, &[2; PAGE_SIZE]).unwrap();
+            wal.commit().unwrap();
+            // never committed
+            wal.append_page(1, &[3; PAGE_SIZE]).unwrap();
+        }
+        // and a half-written record after it
+        let mut file = std::fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+        file.write_all(&[1, 0, 0]).unwrap();
+        drop(file);
+
+        let mut pager = Pager::open(&data_path).unwrap();
+        let mut wal = Wal::open(&wal_path).unwrap();
+        assert_eq!(wal.recover(&mut pager).unwrap(), 1);
+        assert!(wal.is_empty());
+        assert_eq!(pager.page_count(), 3);
+        let mut page = [0; PAGE_SIZE];
+        pager.read_page(0, &mut page).unwrap();
+        assert_eq!(page[0], 1);
+        pager.read_page(1, &mut page).unwrap();
+        assert_eq!(page[0], 0);
+        pager.read_page(2, &mut page).unwrap();
+        assert_eq!(page[0], 2);
+        std::fs::remove_file(&data_path).unwrap();
+        std::fs::remove_file(&wal_path).unwrap();
+    }
+}
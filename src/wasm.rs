@@ -0,0 +1,191 @@
+//! Trees for JavaScript through `wasm-bindgen`, for in-browser
+//! visualizations and demos: `NumberTree` of JS numbers and `StringTree` of
+//! strings.
+//!
+//! ```js
+//! import { NumberTree } from "b_trees_with_delete";
+//! const tree = new NumberTree(2);
+//! [5, 1, 9, 3].forEach((key) => tree.insert(key));
+//! tree.range(2, 9);               // Float64Array [3, 5]
+//! JSON.parse(tree.toJSON());      // {keys: [5], children: [...]}
+//! ```
+//!
+//! `toJSON` writes the nodes in the format of `Recorder` steps. Numbers
+//! that JSON can't hold, NaN and the infinities, are written as the strings
+//! "NaN", "Infinity" and "-Infinity".
+
+use std::fmt::Write;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::visit::{NodeInfo, Order, TreeVisitor};
+use crate::{BTree, OrdF64, PrefixBTree};
+
+/// A tree of numbers, ordered as `OrdF64` orders them. Duplicates are kept.
+#[wasm_bindgen]
+pub struct NumberTree {
+    tree: BTree<OrdF64>,
+}
+
+#[wasm_bindgen]
+impl NumberTree {
+    /// Branch factors below 2 are taken as 2.
+    #[wasm_bindgen(constructor)]
+    pub fn new(branch_factor: usize) -> NumberTree {
+        NumberTree { tree: BTree::new(branch_factor.max(2)) }
+    }
+
+    pub fn insert(&mut self, key: f64) {
+        self.tree.insert(OrdF64(key));
+    }
+
+    pub fn search(&self, key: f64) -> bool {
+        self.tree.search(OrdF64(key))
+    }
+
+    /// Remove one copy of `key`, returning whether there was one.
+    pub fn delete(&mut self, key: f64) -> bool {
+        self.tree.delete(OrdF64(key))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// The keys from `from` up to but not including `to`, in order.
+    pub fn range(&self, from: f64, to: f64) -> Vec<f64> {
+        let (from, to) = (OrdF64(from), OrdF64(to));
+        self.tree.iter_snapshot().skip_while(|key| *key < from).take_while(|key| *key < to).map(OrdF64::get).collect()
+    }
+
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> String {
+        let mut writer = JsonWriter::default();
+        self.tree.walk(Order::PreOrder, &mut writer);
+        writer.json
+    }
+}
+
+/// A set of strings, kept in a `PrefixBTree`.
+#[wasm_bindgen]
+pub struct StringTree {
+    tree: PrefixBTree,
+}
+
+#[wasm_bindgen]
+impl StringTree {
+    /// Branch factors below 2 are taken as 2.
+    #[wasm_bindgen(constructor)]
+    pub fn new(branch_factor: usize) -> StringTree {
+        StringTree { tree: PrefixBTree::new(branch_factor.max(2)) }
+    }
+
+    /// Add `key`, returning false if it was already there.
+    pub fn insert(&mut self, key: &str) -> bool {
+        self.tree.insert(key)
+    }
+
+    pub fn search(&self, key: &str) -> bool {
+        self.tree.search(key)
+    }
+
+    pub fn delete(&mut self, key: &str) -> bool {
+        self.tree.delete(key)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// The keys from `from` up to but not including `to`, in order.
+    pub fn range(&self, from: &str, to: &str) -> Vec<String> {
+        let (from, to) = (from.as_bytes(), to.as_bytes());
+        self.tree
+            .iter()
+            .skip_while(|key| &key[..] < from)
+            .take_while(|key| &key[..] < to)
+            .map(|key| String::from_utf8_lossy(&key).into_owned())
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> String {
+        self.tree.to_json()
+    }
+}
+
+// Writes a tree visited in pre-order as JSON: a node's keys come before
+// its children, so each node can be written as it is entered.
+#[derive(Default)]
+struct JsonWriter {
+    json: String,
+    // For each node being written, whether a child has been written yet.
+    open: Vec<bool>,
+}
+
+impl TreeVisitor<OrdF64> for JsonWriter {
+    fn enter_node(&mut self, node: NodeInfo<'_, OrdF64>) {
+        if let Some(written) = self.open.last_mut() {
+            if std::mem::replace(written, true) {
+                self.json.push(',');
+            }
+        }
+        self.json.push_str("{\"keys\":[");
+        for (index, key) in node.keys.iter().enumerate() {
+            if index > 0 {
+                self.json.push(',');
+            }
+            match key.get() {
+                key if key.is_finite() => _ = write!(self.json, "{key}"),
+                key if key.is_nan() => self.json.push_str("\"NaN\""),
+                key if key > 0.0 => self.json.push_str("\"Infinity\""),
+                _ => self.json.push_str("\"-Infinity\""),
+            }
+        }
+        self.json.push(']');
+        if node.children > 0 {
+            self.json.push_str(",\"children\":[");
+        }
+        self.open.push(false);
+    }
+
+    fn leave_node(&mut self, node: NodeInfo<'_, OrdF64>) {
+        self.open.pop();
+        if node.children > 0 {
+            self.json.push(']');
+        }
+        self.json.push('}');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NumberTree, StringTree};
+
+    #[test]
+    fn test_js_trees() {
+        let mut numbers = NumberTree::new(2);
+        for key in [10.0, 20.0, 30.0, 40.0, 2.5, f64::INFINITY] {
+            numbers.insert(key);
+        }
+        assert!(numbers.search(2.5) && !numbers.search(3.0));
+        assert!(numbers.delete(40.0));
+        assert_eq!(numbers.size(), 5);
+        assert_eq!(numbers.range(5.0, 30.0), [10.0, 20.0]);
+        assert_eq!(
+            numbers.to_json(),
+            r#"{"keys":[20],"children":[{"keys":[2.5,10]},{"keys":[30,"Infinity"]}]}"#
+        );
+
+        let mut strings = StringTree::new(2);
+        for key in ["pear", "apple", "plum", "fig"] {
+            assert!(strings.insert(key));
+        }
+        assert!(!strings.insert("fig"));
+        assert!(strings.delete("plum"));
+        assert_eq!(strings.range("b", "q"), ["fig", "pear"]);
+        assert_eq!(strings.to_json(), r#"{"keys":["fig"],"children":[{"keys":["apple"]},{"keys":["pear"]}]}"#);
+    }
+}